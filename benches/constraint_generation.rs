@@ -5,7 +5,12 @@
 
 use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
 use hbcn::constrain::hbcn::{constrain_cycle_time_proportional, constrain_cycle_time_pseudoclock};
+use hbcn::hbcn::DelayedPlace;
+use hbcn::hbcn::parser::parse_hbcn;
+use hbcn::hbcn::serialisation::serialise_hbcn_transition;
+use hbcn::structural_graph::parse;
 use hbcn::{from_structural_graph, read_file};
+use std::fmt::Write;
 use std::path::Path;
 
 /// Graph files available for benchmarking
@@ -18,6 +23,38 @@ const GRAPH_FILES: &[(&str, &str)] = &[
     ("test", "examples/structural_graphs/test.graph"),
 ];
 
+/// Register counts of the synthetic graphs used to benchmark parsing of large inputs
+const SYNTHETIC_SIZES: &[usize] = &[1_000, 20_000];
+
+/// A synthetic structural graph of `registers` registers: a pipeline of alternating
+/// `DataReg` and `NullReg` stages between two ports, each stage also fed back to the one
+/// before it
+fn synthetic_graph(registers: usize) -> String {
+    let mut text = String::from("Port \"port:in\" [(\"r0\", 20)]\n");
+    for i in 0..registers {
+        let kind = if i % 2 == 0 { "DataReg" } else { "NullReg" };
+        let next = if i + 1 == registers {
+            "port:out".to_string()
+        } else {
+            format!("r{}", i + 1)
+        };
+        write!(text, "{} \"r{}\" [(\"{}\", 20)", kind, i, next).unwrap();
+        if i > 0 {
+            write!(text, ", (\"r{}\", 35)", i - 1).unwrap();
+        }
+        text.push_str("]\n");
+    }
+    text.push_str("Port \"port:out\" []\n");
+    text
+}
+
+/// The `.hbcn` expansion of [`synthetic_graph`]
+fn synthetic_hbcn(registers: usize) -> String {
+    let graph = parse(&synthetic_graph(registers)).expect("synthetic graph parses");
+    let hbcn = from_structural_graph(&graph, false).expect("synthetic graph expands");
+    serialise_hbcn_transition(&hbcn.map(|_, t| t.clone(), |_, p| DelayedPlace::from(p.clone())))
+}
+
 /// Standard benchmark parameters
 #[derive(Debug, Clone, Copy)]
 struct BenchmarkParams {
@@ -268,6 +305,30 @@ fn bench_graph_parsing(c: &mut Criterion) {
     }
 
     group.finish();
+
+    // Large synthetic inputs, measured in bytes parsed per second
+    let mut group = c.benchmark_group("graph_parsing_large");
+    group.sample_size(10);
+
+    for &registers in SYNTHETIC_SIZES {
+        let graph = synthetic_graph(registers);
+        group.throughput(Throughput::Bytes(graph.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("parse_graph", registers),
+            &graph,
+            |b, graph| b.iter(|| black_box(parse(black_box(graph)))),
+        );
+
+        let hbcn = synthetic_hbcn(registers);
+        group.throughput(Throughput::Bytes(hbcn.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("parse_hbcn", registers),
+            &hbcn,
+            |b, hbcn| b.iter(|| black_box(parse_hbcn(black_box(hbcn)))),
+        );
+    }
+
+    group.finish();
 }
 
 /// Benchmark parameter sensitivity (different cycle times and margins)
@@ -0,0 +1,34 @@
+// A dual-rail pipeline a -> r -> q -> b, as a synthesis tool would write it after a hand
+// ECO: `r` is a DataReg flattened into escaped cell names with its stages `r/s0` and
+// `r/s1`, `q` an instance of a uniquified DataReg module, and the logic between them an
+// AO22 and an inverter. Acknowledges run back through the `*_ack` nets.
+module DataReg_1 (d_t, d_f, q_t, q_f, ack_in, ack);
+  input d_t, d_f, ack_in;
+  output q_t, q_f, ack;
+  wire ack_n;
+  C2 ct (.A(d_t), .B(ack_n), .Q(q_t));
+  C2 cf (.A(d_f), .B(ack_n), .Q(q_f));
+  INV ai (.A(ack_in), .Y(ack_n));
+  NOR2 cd (.A(q_t), .B(q_f), .Y(ack));
+endmodule
+
+module pipeline (a_t, a_f, a_ack, b_t, b_f, b_ack);
+  input a_t, a_f, b_ack;
+  output a_ack, b_t, b_f;
+  wire r_ack, q_ack, x_t, x_f, y_t, y_f, s0_t, s0_f, s1_t, s1_f;
+
+  C2 \r/ct (.A(a_t), .B(r_ack), .Q(x_t));
+  C2 \r/cf (.A(a_f), .B(r_ack), .Q(x_f));
+  C2 \r/s0/ct (.A(x_t), .B(r_ack), .Q(s0_t));
+  C2 \r/s0/cf (.A(x_f), .B(r_ack), .Q(s0_f));
+  C2 \r/s1/ct (.A(s0_t), .B(r_ack), .Q(s1_t));
+  C2 \r/s1/cf (.A(s0_f), .B(r_ack), .Q(s1_f));
+  INV \r/ai (.A(q_ack), .Y(r_ack));
+  NOR2 \r/cd (.A(x_t), .B(x_f), .Y(a_ack));
+
+  AO22 g0 (.A(s1_t), .B(s1_f), .Y(n0));
+  INV g1 (.A(n0), .Y(y_t));
+  INV g2 (.A(s1_f), .Y(y_f));
+
+  DataReg_1 q (.d_t(y_t), .d_f(y_f), .q_t(b_t), .q_f(b_f), .ack_in(b_ack), .ack(q_ack));
+endmodule
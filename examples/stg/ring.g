@@ -0,0 +1,18 @@
+# Two half-buffer stages between an input and an output port, as a Workcraft STG.
+# Rising transitions are the data phase of each stage and falling ones the spacer phase.
+.model ring
+.inputs in
+.outputs out
+.internal r0 r1
+.graph
+in+ r0+
+r0+ in- r1+
+in- r0-
+r0- in+ r1-
+r1+ r0- out+
+r1- r0+ out-
+out+ r1-
+out- p0
+p0 r1+
+.marking { <r0-,in+> <r1+,r0-> p0 }
+.end
@@ -0,0 +1,231 @@
+//! Cycle time of an HBCN across the corners of its places' delays.
+//!
+//! A characterised place carries a [`DelayPair`] whose `max` is its worst-case and whose
+//! `min` its best-case delay, while the rest of `analyse` only prices places at their `max`.
+//! [`analyse_corners`] solves the cycle time again with every place fixed at one end of its
+//! range, as selected by a [`Corner`]:
+//!
+//! - **min**: every place at its min delay, the fastest the circuit can run
+//! - **mixed**: forward (propagation) places at their max delay and backward (acknowledge)
+//!   places at their min, slow data paths with fast handshakes
+//! - **max**: every place at its max delay, the cycle time `analyse` reports
+//!
+//! A place without a min delay keeps its max delay in every corner. The cycle time only
+//! grows with the delays, so the min and max corners bound it over any assignment within
+//! the ranges, the mixed corner included; the gap between them is the performance range of
+//! the design across process variation.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::analyse::corners::{Corner, analyse_corners};
+//! use hbcn::analyse::hbcn::Engine;
+//! use hbcn::hbcn::parser::parse_hbcn;
+//!
+//! let hbcn = parse_hbcn("
+//!     * +{a} => +{b} : (6, 10)
+//!     +{b} => -{a} : (2, 5)
+//!     -{a} => -{b} : (8, 10)
+//!     -{b} => +{a} : (4, 5)
+//! ").unwrap();
+//!
+//! let corners = analyse_corners(&hbcn, Engine::Mcr, false).unwrap();
+//! let cycle_times: Vec<_> = corners.iter().map(|c| (c.corner, c.cycle_time)).collect();
+//! assert_eq!(
+//!     cycle_times,
+//!     [(Corner::Min, 20.0), (Corner::Mixed, 26.0), (Corner::Max, 30.0)]
+//! );
+//! assert_eq!(corners[0].critical_cycle.as_ref().unwrap().len(), 4);
+//! ```
+
+use std::fmt;
+
+use anyhow::Result;
+use ordered_float::OrderedFloat;
+use petgraph::{graph::NodeIndex, stable_graph::StableGraph};
+
+use super::hbcn::{Engine, compute_cycle_time_with, find_critical_cycles};
+use crate::hbcn::{
+    DelayPair, DelayedPlace, HasWeight, MarkablePlace, SlackablePlace, SolvedHBCN, StructuralHBCN,
+    Transition, WeightedPlace, is_backward_place,
+};
+
+/// End of their delay range at which places are priced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    /// Every place at its min delay.
+    Min,
+    /// Forward places at their max delay, backward places at their min delay.
+    Mixed,
+    /// Every place at its max delay.
+    Max,
+}
+
+impl Corner {
+    /// All corners, from the fastest to the slowest.
+    pub const ALL: [Corner; 3] = [Corner::Min, Corner::Mixed, Corner::Max];
+
+    /// Delay of a place at this corner, `backward` telling whether it is an acknowledge
+    /// place.
+    pub fn delay(self, delay: &DelayPair, backward: bool) -> f64 {
+        let min = delay.min.unwrap_or(delay.max);
+        match self {
+            Corner::Min => min,
+            Corner::Mixed if backward => min,
+            Corner::Mixed | Corner::Max => delay.max,
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corner::Min => write!(f, "min"),
+            Corner::Mixed => write!(f, "mixed"),
+            Corner::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// Cycle time of an HBCN at one corner.
+#[derive(Debug, Clone)]
+pub struct CornerAnalysis {
+    /// The corner the places were priced at.
+    pub corner: Corner,
+    /// Cycle time at this corner.
+    pub cycle_time: f64,
+    /// The HBCN solved with the places at this corner's delays.
+    pub solved: SolvedHBCN,
+    /// The cycle setting the cycle time, as `(source, target)` transition pairs, or `None`
+    /// if the HBCN has no cycle.
+    pub critical_cycle: Option<Vec<(NodeIndex, NodeIndex)>>,
+}
+
+/// Price every place of an HBCN at the delay of a corner.
+///
+/// The places of the result carry the corner's delay as their weight and no min delay.
+pub fn corner_hbcn<P: Clone + Into<DelayedPlace>>(
+    hbcn: &StableGraph<Transition, P>,
+    corner: Corner,
+) -> StructuralHBCN {
+    hbcn.filter_map(
+        |_, transition| Some(transition.clone()),
+        |ie, place| {
+            let (src, dst) = hbcn.edge_endpoints(ie)?;
+            let DelayedPlace { place, delay, .. } = place.clone().into();
+            Some(WeightedPlace {
+                place,
+                weight: corner.delay(&delay, is_backward_place(&hbcn[src], &hbcn[dst])),
+                min_delay: None,
+            })
+        },
+    )
+}
+
+/// Compute the weighted cycle time of an HBCN and its critical cycle at every corner.
+///
+/// Corners are solved in the order of [`Corner::ALL`] with the given engine, rounding the
+/// cycle time up to an integer if `integer` is set. The critical cycle is the one of
+/// [`find_critical_cycles`] with the largest ratio of delay to tokens.
+pub fn analyse_corners<P: Clone + Into<DelayedPlace>>(
+    hbcn: &StableGraph<Transition, P>,
+    engine: Engine,
+    integer: bool,
+) -> Result<Vec<CornerAnalysis>> {
+    Corner::ALL
+        .into_iter()
+        .map(|corner| {
+            let (cycle_time, solved) =
+                compute_cycle_time_with(&corner_hbcn(hbcn, corner), true, engine, integer)?;
+            let critical_cycle = critical_cycle(&solved);
+            Ok(CornerAnalysis {
+                corner,
+                cycle_time,
+                solved,
+                critical_cycle,
+            })
+        })
+        .collect()
+}
+
+/// The critical cycle of a solved HBCN with the largest ratio of delay to tokens.
+fn critical_cycle(solved: &SolvedHBCN) -> Option<Vec<(NodeIndex, NodeIndex)>> {
+    find_critical_cycles(solved)
+        .into_iter()
+        .max_by_key(|cycle| {
+            let (delay, tokens) = cycle.iter().fold((0.0, 0), |(delay, tokens), (is, it)| {
+                let place = &solved[solved.find_edge(*is, *it).unwrap()];
+                (
+                    delay + place.weight() - place.slack(),
+                    tokens + place.is_marked() as usize,
+                )
+            });
+            OrderedFloat(delay / tokens.max(1) as f64)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::Named;
+    use crate::hbcn::parser::parse_hbcn;
+
+    #[test]
+    fn places_without_a_min_keep_their_max() {
+        let hbcn = parse_hbcn(
+            "
+            * +{a} => +{b} : (6, 10)
+            +{b} => -{a} : 5
+            -{a} => -{b} : (8, 10)
+            -{b} => +{a} : (4, 5)
+            ",
+        )
+        .unwrap();
+        let weights = |corner| {
+            let priced = corner_hbcn(&hbcn, corner);
+            let mut weights: Vec<f64> = priced.edge_indices().map(|ie| priced[ie].weight).collect();
+            weights.sort_by(f64::total_cmp);
+            weights
+        };
+        assert_eq!(weights(Corner::Min), [4.0, 5.0, 6.0, 8.0]);
+        assert_eq!(weights(Corner::Mixed), [4.0, 5.0, 10.0, 10.0]);
+        assert_eq!(weights(Corner::Max), [5.0, 5.0, 10.0, 10.0]);
+    }
+
+    /// Each corner reports its own critical cycle: the fixed channel b-c at the min corner,
+    /// the wide-ranging channel a-b at the max corner.
+    #[test]
+    fn critical_cycle_moves_between_corners() {
+        let hbcn = parse_hbcn(
+            "
+            * +{a} => +{b} : (1, 10)
+              +{b} => -{a} : (1, 10)
+              -{a} => -{b} : (1, 10)
+              -{b} => +{a} : (1, 10)
+              +{b} => +{c} : 5
+              +{c} => -{b} : 5
+              -{b} => -{c} : 5
+            * -{c} => +{b} : 5
+            ",
+        )
+        .unwrap();
+        let corners = analyse_corners(&hbcn, Engine::Mcr, false).unwrap();
+        let nodes = |analysis: &CornerAnalysis| {
+            let mut nodes: Vec<String> = analysis
+                .critical_cycle
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|(is, _)| analysis.solved[*is].name().to_string())
+                .collect();
+            nodes.sort();
+            nodes.dedup();
+            nodes
+        };
+
+        assert_eq!(corners[0].cycle_time, 20.0);
+        assert_eq!(nodes(&corners[0]), ["b", "c"]);
+        assert_eq!(corners[2].cycle_time, 40.0);
+        assert_eq!(nodes(&corners[2]), ["a", "b"]);
+    }
+}
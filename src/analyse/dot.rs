@@ -0,0 +1,206 @@
+//! Graphviz (DOT) rendering of solved HBCNs.
+//!
+//! This module renders a solved HBCN as a Graphviz digraph that can be laid out with `dot`:
+//!
+//! - Transitions are labelled `+name` (data) or `-name` (spacer) with their arrival time, and
+//!   the two transitions of each circuit node are grouped in a cluster.
+//! - Places are labelled with their delay and slack, and marked places with a `●` token.
+//!   Forward places are drawn solid and backward places dashed.
+//! - The places and transitions of the given critical cycles are highlighted in red.
+//!
+//! # Usage
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hbcn::analyse::{dot::write_dot, hbcn::find_critical_cycles};
+//! use std::io::BufWriter;
+//! use std::fs::File;
+//! # let solved_hbcn = hbcn::hbcn::SolvedHBCN::default(); // Example only
+//!
+//! let cycles = find_critical_cycles(&solved_hbcn);
+//! let mut output = BufWriter::new(File::create("graph.dot")?);
+//! write_dot(&solved_hbcn, &cycles, &mut output)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use anyhow::Result;
+use petgraph::stable_graph::NodeIndex;
+
+use crate::hbcn::{
+    CircuitNode, HBCN, HasDelay, MarkablePlace, Named, SlackablePlace, TimedEvent, Transition,
+    is_backward_place,
+};
+use crate::structural_graph::dot::dot_escape;
+
+/// Write a solved HBCN in the DOT language, highlighting the `critical` cycles.
+///
+/// Each cycle is a list of `(source, target)` transition pairs, as returned by
+/// [`find_critical_cycles`](super::hbcn::find_critical_cycles).
+///
+/// # Arguments
+///
+/// * `hbcn` - The solved HBCN with arrival times, delays and slacks
+/// * `critical` - The cycles to highlight
+/// * `w` - Writer to output the DOT file to
+pub fn write_dot<T, P>(
+    hbcn: &HBCN<T, P>,
+    critical: &[Vec<(NodeIndex, NodeIndex)>],
+    w: &mut dyn io::Write,
+) -> Result<()>
+where
+    T: AsRef<Transition> + TimedEvent,
+    P: HasDelay + SlackablePlace + MarkablePlace,
+{
+    let critical_places: HashSet<(NodeIndex, NodeIndex)> =
+        critical.iter().flatten().copied().collect();
+    let critical_transitions: HashSet<NodeIndex> = critical_places
+        .iter()
+        .flat_map(|&(src, dst)| [src, dst])
+        .collect();
+
+    // Clusters are written in the order their circuit nodes first appear
+    let mut clusters: Vec<(&CircuitNode, Vec<NodeIndex>)> = Vec::new();
+    let mut cluster_of: HashMap<&CircuitNode, usize> = HashMap::new();
+    for ix in hbcn.node_indices() {
+        let node: &CircuitNode = hbcn[ix].as_ref().as_ref();
+        let i = *cluster_of.entry(node).or_insert_with(|| {
+            clusters.push((node, Vec::new()));
+            clusters.len() - 1
+        });
+        clusters[i].1.push(ix);
+    }
+
+    writeln!(w, "digraph hbcn {{")?;
+    writeln!(w, "  rankdir=LR;")?;
+    writeln!(w, "  node [shape=circle, fontname=\"Helvetica\"];")?;
+    writeln!(w, "  edge [fontname=\"Helvetica\"];")?;
+
+    for (i, (node, transitions)) in clusters.iter().enumerate() {
+        writeln!(w, "  subgraph \"cluster_{}\" {{", i)?;
+        writeln!(w, "    label=\"{}\";", dot_escape(&node.to_string()))?;
+        writeln!(w, "    style=rounded;")?;
+        for &ix in transitions {
+            let event = &hbcn[ix];
+            let sign = match event.as_ref() {
+                Transition::Data(_) => '+',
+                Transition::Spacer(_) => '-',
+            };
+            write!(
+                w,
+                "    t{} [label=\"{}{}\\nt={}\"",
+                ix.index(),
+                sign,
+                dot_escape(event.as_ref().name()),
+                event.time()
+            )?;
+            if critical_transitions.contains(&ix) {
+                write!(w, ", color=red, penwidth=2")?;
+            }
+            writeln!(w, "];")?;
+        }
+        writeln!(w, "  }}")?;
+    }
+
+    for ie in hbcn.edge_indices() {
+        let (src, dst) = hbcn
+            .edge_endpoints(ie)
+            .expect("Edge should have valid endpoints");
+        let place = &hbcn[ie];
+        let delay = place.delay();
+
+        let mut label = String::new();
+        if place.is_marked() {
+            label += "● ";
+        }
+        label += &match delay.min {
+            Some(min) => format!("({}, {})", min, delay.max),
+            None => format!("{}", delay.max),
+        };
+        label += &format!("\\nslack {}", place.slack());
+
+        write!(
+            w,
+            "  t{} -> t{} [label=\"{}\"",
+            src.index(),
+            dst.index(),
+            label
+        )?;
+        if is_backward_place(hbcn[src].as_ref(), hbcn[dst].as_ref()) {
+            write!(w, ", style=dashed")?;
+        }
+        if critical_places.contains(&(src, dst)) {
+            write!(w, ", color=red, penwidth=2")?;
+        }
+        writeln!(w, "];")?;
+    }
+
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{DelayPair, DelayedPlace, Place, TransitionEvent};
+    use string_cache::DefaultAtom;
+
+    fn event(transition: Transition, time: f64) -> TransitionEvent {
+        TransitionEvent { time, transition }
+    }
+
+    fn place(token: bool, min: Option<f64>, max: f64, slack: f64) -> DelayedPlace {
+        DelayedPlace {
+            place: Place {
+                token,
+                is_internal: false,
+            },
+            delay: DelayPair::new(min, max),
+            slack: Some(slack),
+        }
+    }
+
+    #[test]
+    fn renders_clusters_places_and_critical_cycle() {
+        let a = CircuitNode::Port(DefaultAtom::from("a"));
+        let b = CircuitNode::Register(DefaultAtom::from("b"));
+
+        let mut hbcn = HBCN::new();
+        let a_data = hbcn.add_node(event(Transition::Data(a.clone()), 0.0));
+        let a_spacer = hbcn.add_node(event(Transition::Spacer(a), 10.0));
+        let b_data = hbcn.add_node(event(Transition::Data(b.clone()), 5.0));
+        let b_spacer = hbcn.add_node(event(Transition::Spacer(b), 15.0));
+        hbcn.add_edge(a_data, b_data, place(false, Some(1.0), 5.0, 0.0));
+        hbcn.add_edge(b_data, a_spacer, place(false, None, 5.0, 0.0));
+        hbcn.add_edge(a_spacer, b_spacer, place(false, None, 5.0, 0.0));
+        hbcn.add_edge(b_spacer, a_data, place(true, None, 5.0, 0.0));
+        hbcn.add_edge(a_data, a_spacer, place(false, None, 2.0, 8.0));
+
+        let critical = vec![vec![
+            (a_data, b_data),
+            (b_data, a_spacer),
+            (a_spacer, b_spacer),
+            (b_spacer, a_data),
+        ]];
+        let mut out = Vec::new();
+        write_dot(&hbcn, &critical, &mut out).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+
+        assert!(dot.starts_with("digraph hbcn {"));
+        assert!(dot.contains("label=\"Port \\\"a\\\"\";"));
+        assert!(dot.contains("label=\"Register \\\"b\\\"\";"));
+        assert!(dot.contains("t1 [label=\"-a\\nt=10\", color=red, penwidth=2];"));
+        assert!(dot.contains("t0 -> t2 [label=\"(1, 5)\\nslack 0\", color=red, penwidth=2];"));
+        assert!(
+            dot.contains(
+                "t3 -> t0 [label=\"● 5\\nslack 0\", style=dashed, color=red, penwidth=2];"
+            )
+        );
+        assert!(dot.contains("t0 -> t1 [label=\"2\\nslack 8\", style=dashed];"));
+    }
+}
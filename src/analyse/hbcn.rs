@@ -14,16 +14,33 @@
 //! - Constraints: Timing relationships based on place weights and token markings
 //! - Objective: Minimise or maximise cycle time (depending on weighted flag)
 //!
+//! The cycle time is a continuous variable, so the LP finds the exact rational cycle time.
+//! [`compute_integer_cycle_time`] instead rounds it up to an integer, making the LP a MIP.
+//!
+//! The same cycle time is the maximum cycle ratio of the HBCN, which
+//! [`super::mcr::compute_cycle_time`] finds without an LP solver. [`compute_cycle_time_with`]
+//! runs either, as selected by an [`Engine`].
+//!
 //! ## Critical Cycle Detection
 //!
 //! The [`find_critical_cycles`] function uses a modified Bellman-Ford algorithm to find
 //! cycles that include marked places (tokens). These cycles represent critical paths
 //! through the circuit.
+//!
+//! It reconstructs at most one cycle per marked place, so it may repeat a cycle and miss
+//! near-critical ones. [`find_top_critical_cycles`] instead enumerates the distinct simple
+//! cycles of least total slack, each with its delay, tokens and ratio.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
 
-use std::collections::{HashMap, HashSet};
-
-use anyhow::Result;
+use anyhow::{Result, bail};
 use itertools::Itertools;
+use ordered_float::OrderedFloat;
 use petgraph::{
     graph::{EdgeIndex, NodeIndex},
     stable_graph::StableGraph,
@@ -33,7 +50,7 @@ use rayon::prelude::*;
 use crate::{
     AppError, Transition,
     hbcn::{
-        DelayPair, DelayedPlace, HasWeight, MarkablePlace, Place, SlackablePlace, SolvedHBCN,
+        DelayPair, DelayedPlace, HasWeight, MarkablePlace, SlackablePlace, SolvedHBCN,
         TransitionEvent, round_to_sig_digits,
     },
 };
@@ -141,10 +158,199 @@ pub fn find_critical_cycles<N: Sync + Send, P: MarkablePlace + SlackablePlace>(
     paths
 }
 
+/// A simple cycle of a solved HBCN with its totals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleSummary {
+    /// The places of the cycle as `(source, target)` transition pairs, in cycle order.
+    pub places: Vec<(NodeIndex, NodeIndex)>,
+    /// Total slack of the places.
+    pub slack: f64,
+    /// Total delay of the places, their weight less their slack.
+    pub delay: f64,
+    /// Number of marked places.
+    pub tokens: usize,
+    /// Delay per token, the cycle time this cycle alone would set.
+    pub ratio: f64,
+}
+
+/// Enumerate the distinct simple cycles of least total slack of a solved HBCN.
+///
+/// Returns at most `k` cycles, keeping only those whose total slack is at most
+/// `slack_window`, sorted by increasing slack and then decreasing ratio. Pass `usize::MAX`
+/// or `f64::INFINITY` to leave either bound open, but not both: the number of simple cycles
+/// grows exponentially with the size of the HBCN, so leaving both open is an error. Every
+/// cycle is listed once; of parallel places between the same transitions only the one with
+/// the least slack is followed.
+///
+/// The total slack of a cycle is the cycle time times its tokens less its delay, whatever
+/// the arrival times, so the cycles setting the cycle time come first with no slack. Each
+/// cycle is found from its lowest-indexed transition by a best-first search over simple
+/// paths, guided by the least slack back to that transition and pruned by the slack of the
+/// `k`th best cycle found so far.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::analyse::{hbcn::find_top_critical_cycles, mcr::compute_cycle_time};
+/// use hbcn::hbcn::parser::parse_hbcn;
+///
+/// let hbcn = parse_hbcn("
+///     * +{a} => +{b} : 10
+///       +{b} => -{a} : 10
+///       -{a} => -{b} : 10
+///       -{b} => +{a} : 10
+///       +{b} => +{c} : 5
+///       +{c} => -{b} : 5
+///       -{b} => -{c} : 5
+///     * -{c} => +{b} : 5
+/// ").unwrap();
+/// let (_, solved) = compute_cycle_time(&hbcn, true).unwrap();
+///
+/// let cycles = find_top_critical_cycles(&solved, 3, f64::INFINITY).unwrap();
+/// assert_eq!(cycles.len(), 3);
+/// assert_eq!((cycles[0].slack, cycles[0].ratio, cycles[0].tokens), (0.0, 40.0, 1));
+/// assert!(cycles[1].slack > 0.0);
+/// ```
+pub fn find_top_critical_cycles<N, P: MarkablePlace + SlackablePlace + HasWeight>(
+    hbcn: &StableGraph<N, P>,
+    k: usize,
+    slack_window: f64,
+) -> Result<Vec<CycleSummary>> {
+    if k == usize::MAX && slack_window == f64::INFINITY {
+        bail!("enumerating every simple cycle needs a bound on their number or slack");
+    }
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Least-slack place between each pair of transitions, within the window
+    let mut least_slack: HashMap<(NodeIndex, NodeIndex), EdgeIndex> = HashMap::new();
+    for ie in hbcn.edge_indices() {
+        if hbcn[ie].slack() > slack_window {
+            continue;
+        }
+        let endpoints = hbcn.edge_endpoints(ie).unwrap();
+        let best = least_slack.entry(endpoints).or_insert(ie);
+        if hbcn[ie].slack() < hbcn[*best].slack() {
+            *best = ie;
+        }
+    }
+    let mut successors: HashMap<NodeIndex, Vec<(NodeIndex, f64)>> = HashMap::new();
+    let mut predecessors: HashMap<NodeIndex, Vec<(NodeIndex, f64)>> = HashMap::new();
+    for (&(src, dst), &ie) in least_slack.iter().sorted_by_key(|(_, ie)| **ie) {
+        let slack = hbcn[ie].slack();
+        successors.entry(src).or_default().push((dst, slack));
+        predecessors.entry(dst).or_default().push((src, slack));
+    }
+
+    // The k best cycles found so far as (slack, cycles found before, path), worst on top
+    type Found = BinaryHeap<(OrderedFloat<f64>, usize, Vec<NodeIndex>)>;
+    let mut found = Found::new();
+    let mut count = 0;
+    // Whether a cycle of the given slack would make the list
+    let fits = |slack: f64, found: &Found| match found.peek() {
+        Some((worst, _, _)) if found.len() == k => slack < worst.0,
+        _ => slack <= slack_window,
+    };
+
+    for root in hbcn.node_indices() {
+        // Least slack from every transition back to the root, through higher transitions
+        let mut to_root: HashMap<NodeIndex, f64> = HashMap::from([(root, 0.0)]);
+        let mut queue = BinaryHeap::from([Reverse((OrderedFloat(0.0), root))]);
+        while let Some(Reverse((OrderedFloat(distance), ix))) = queue.pop() {
+            if distance > to_root[&ix] {
+                continue;
+            }
+            for &(pred, slack) in predecessors.get(&ix).into_iter().flatten() {
+                let distance = distance + slack;
+                if pred.index() > root.index()
+                    && fits(distance, &found)
+                    && to_root.get(&pred).is_none_or(|&d| distance < d)
+                {
+                    to_root.insert(pred, distance);
+                    queue.push(Reverse((OrderedFloat(distance), pred)));
+                }
+            }
+        }
+
+        // Best-first over the simple paths from the root, deepest first among equals
+        let mut paths = BinaryHeap::from([Reverse((
+            OrderedFloat(0.0),
+            Reverse(1),
+            OrderedFloat(0.0),
+            vec![root],
+        ))]);
+        while let Some(Reverse((OrderedFloat(estimate), _, OrderedFloat(cost), path))) = paths.pop()
+        {
+            if !fits(estimate, &found) {
+                break;
+            }
+            let last = *path.last().unwrap();
+            for &(next, slack) in successors.get(&last).into_iter().flatten() {
+                let cost = cost + slack;
+                if next == root {
+                    if fits(cost, &found) {
+                        if found.len() == k {
+                            found.pop();
+                        }
+                        found.push((OrderedFloat(cost), count, path.clone()));
+                        count += 1;
+                    }
+                } else if let Some(&remaining) = to_root.get(&next)
+                    && fits(cost + remaining, &found)
+                    && !path.contains(&next)
+                {
+                    let mut path = path.clone();
+                    path.push(next);
+                    paths.push(Reverse((
+                        OrderedFloat(cost + remaining),
+                        Reverse(path.len()),
+                        OrderedFloat(cost),
+                        path,
+                    )));
+                }
+            }
+        }
+    }
+
+    let mut cycles: Vec<CycleSummary> = found
+        .into_iter()
+        .map(|(_, _, path)| {
+            let places: Vec<_> = path
+                .iter()
+                .copied()
+                .zip(path.iter().skip(1).copied().chain(std::iter::once(path[0])))
+                .collect();
+            let (mut slack, mut delay, mut tokens) = (0.0, 0.0, 0);
+            for pair in &places {
+                let place = &hbcn[least_slack[pair]];
+                slack += place.slack();
+                delay += place.weight() - place.slack();
+                tokens += place.is_marked() as usize;
+            }
+            CycleSummary {
+                places,
+                slack,
+                delay,
+                tokens,
+                ratio: delay / tokens as f64,
+            }
+        })
+        .collect();
+    cycles.sort_by(|a, b| {
+        a.slack
+            .total_cmp(&b.slack)
+            .then(b.ratio.total_cmp(&a.ratio))
+            .then_with(|| a.places.cmp(&b.places))
+    });
+    Ok(cycles)
+}
+
 /// Compute cycle time for an HBCN using linear programming.
 ///
 /// This function formulates the cycle time problem as a linear programming problem
-/// and solves it to find the optimal cycle time. The problem considers:
+/// and solves it to find the optimal cycle time, exact rather than rounded to an integer.
+/// The problem considers:
 ///
 /// - Place weights (delays) between transitions
 /// - Token markings (which places are initially marked)
@@ -175,12 +381,31 @@ pub fn find_critical_cycles<N: Sync + Send, P: MarkablePlace + SlackablePlace>(
 /// # Ok(())
 /// # }
 /// ```
-pub fn compute_cycle_time<P: HasWeight + MarkablePlace + Into<Place> + Clone>(
+pub fn compute_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    solve_cycle_time(hbcn, weighted, VariableType::Continuous)
+}
+
+/// Compute the cycle time of an HBCN rounded up to an integer.
+///
+/// Solves the same LP as [`compute_cycle_time`] with an integer cycle-time variable, so the
+/// solver has to branch as for a MIP. The slacks of the solved HBCN absorb the rounding.
+pub fn compute_integer_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
     hbcn: &StableGraph<Transition, P>,
     weighted: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    solve_cycle_time(hbcn, weighted, VariableType::Integer)
+}
+
+fn solve_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+    cycle_time_type: VariableType,
 ) -> Result<(f64, SolvedHBCN)> {
     let mut builder = lp_model_builder!();
-    let cycle_time = builder.add_variable(VariableType::Integer, 0.0, f64::INFINITY);
+    let cycle_time = builder.add_variable(cycle_time_type, 0.0, f64::INFINITY);
 
     let arr_var: HashMap<NodeIndex, VariableId<_>> = hbcn
         .node_indices()
@@ -242,11 +467,11 @@ pub fn compute_cycle_time<P: HasWeight + MarkablePlace + Into<Place> + Clone>(
             },
             |ie, e| {
                 let (delay_var, slack_var) = &delay_slack_var[&ie];
-                let place: Place = e.clone().into();
+                let place: DelayedPlace = e.clone().into();
                 Some(DelayedPlace {
-                    place: place.clone(),
+                    place: place.place,
                     delay: DelayPair {
-                        min: None,
+                        min: place.delay.min,
                         max: round_to_sig_digits(
                             solution.get_value(*delay_var).unwrap_or(e.weight()),
                             8,
@@ -261,6 +486,75 @@ pub fn compute_cycle_time<P: HasWeight + MarkablePlace + Into<Place> + Clone>(
     ))
 }
 
+/// Algorithm computing the cycle time of an HBCN.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// The linear program of [`compute_cycle_time`], solved by the LP backend.
+    #[default]
+    Lp,
+    /// Howard's policy iteration for the maximum cycle ratio, see
+    /// [`super::mcr::compute_cycle_time`].
+    Mcr,
+}
+
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lp" => Ok(Engine::Lp),
+            "mcr" | "howard" => Ok(Engine::Mcr),
+            _ => bail!("unknown engine `{}` (expected `lp` or `mcr`)", s),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Engine::Lp => write!(f, "lp"),
+            Engine::Mcr => write!(f, "mcr"),
+        }
+    }
+}
+
+/// Compute the cycle time of an HBCN with the given engine, rounded up to an integer if
+/// `integer` is set.
+///
+/// Both engines find the same cycle time and solved HBCN, up to the arrival times, which
+/// the cycle time leaves free.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::analyse::hbcn::{Engine, compute_cycle_time_with};
+/// use hbcn::hbcn::parser::parse_hbcn;
+///
+/// let hbcn = parse_hbcn("
+///     * +{a} => +{b} : 10
+///     +{b} => -{a} : 5
+///     -{a} => -{b} : 10
+///     -{b} => +{a} : 5
+/// ").unwrap();
+///
+/// let engine = "mcr".parse().unwrap();
+/// assert_eq!(compute_cycle_time_with(&hbcn, true, engine, false).unwrap().0, 30.0);
+/// assert_eq!(compute_cycle_time_with(&hbcn, false, engine, false).unwrap().0, 4.0);
+/// ```
+pub fn compute_cycle_time_with<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+    engine: Engine,
+    integer: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    match (engine, integer) {
+        (Engine::Lp, false) => compute_cycle_time(hbcn, weighted),
+        (Engine::Lp, true) => compute_integer_cycle_time(hbcn, weighted),
+        (Engine::Mcr, false) => super::mcr::compute_cycle_time(hbcn, weighted),
+        (Engine::Mcr, true) => super::mcr::compute_integer_cycle_time(hbcn, weighted),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -480,4 +774,129 @@ mod tests {
             "all four place delays should survive analysis distinctly"
         );
     }
+
+    /// Fractional delays give a fractional cycle time, which is only rounded up on request.
+    #[test]
+    fn fractional_cycle_time_is_exact() {
+        use crate::hbcn::parser::parse_hbcn;
+
+        let hbcn = parse_hbcn(
+            r#"
+            * +{port:a} => +{reg1} : 1.5
+              +{reg1} => -{port:a} : 2
+              -{port:a} => -{reg1} : 3
+              -{reg1} => +{port:a} : 1.25
+        "#,
+        )
+        .expect("parses");
+
+        let (ct, solved) = compute_cycle_time(&hbcn, true).expect("solves");
+        assert!(
+            (ct - 7.75).abs() < 1e-6,
+            "cycle time should be 7.75, got {ct}"
+        );
+        let slack: f64 = solved.edge_indices().map(|ie| solved[ie].slack()).sum();
+        assert!(
+            slack.abs() < 1e-6,
+            "the only cycle should be tight, got {slack}"
+        );
+
+        let (ct, solved) = compute_integer_cycle_time(&hbcn, true).expect("solves");
+        assert_eq!(ct, 8.0);
+        let slack: f64 = solved.edge_indices().map(|ie| solved[ie].slack()).sum();
+        assert!(
+            (slack - 0.25).abs() < 1e-6,
+            "slack should absorb the rounding, got {slack}"
+        );
+    }
+
+    /// Every simple cycle of a graph, by depth-first search from its lowest transition.
+    fn all_simple_cycles(solved: &SolvedHBCN) -> Vec<Vec<(NodeIndex, NodeIndex)>> {
+        fn extend(
+            solved: &SolvedHBCN,
+            path: &mut Vec<NodeIndex>,
+            cycles: &mut Vec<Vec<(NodeIndex, NodeIndex)>>,
+        ) {
+            let root = path[0];
+            for next in solved.neighbors(*path.last().unwrap()) {
+                if next == root {
+                    cycles.push(
+                        path.iter()
+                            .copied()
+                            .zip(path[1..].iter().copied().chain([root]))
+                            .collect(),
+                    );
+                } else if next > root && !path.contains(&next) {
+                    path.push(next);
+                    extend(solved, path, cycles);
+                    path.pop();
+                }
+            }
+        }
+        let mut cycles = Vec::new();
+        for root in solved.node_indices() {
+            extend(solved, &mut vec![root], &mut cycles);
+        }
+        cycles
+    }
+
+    /// The top cycles are the distinct simple cycles in order of slack, each with totals
+    /// that agree with the cycle time.
+    #[test]
+    fn top_critical_cycles_are_distinct_and_ordered() {
+        let hbcn = crate::hbcn::parser::parse_hbcn(
+            "
+            * +{a} => +{b} : 10
+              +{b} => -{a} : 10
+              -{a} => -{b} : 10
+              -{b} => +{a} : 10
+              +{b} => +{c} : 5
+              +{c} => -{b} : 5
+              -{b} => -{c} : 5
+            * -{c} => +{b} : 5
+              +{c} => +{d} : 1
+              +{d} => -{c} : 1
+            * -{c} => -{d} : 1
+              -{d} => +{c} : 1
+            ",
+        )
+        .unwrap();
+        let (cycle_time, solved) = super::super::mcr::compute_cycle_time(&hbcn, true).unwrap();
+
+        // Leaving both bounds open is refused, so bound the count by the brute-force one
+        assert!(find_top_critical_cycles(&solved, usize::MAX, f64::INFINITY).is_err());
+        let count = all_simple_cycles(&solved).len();
+        let all = find_top_critical_cycles(&solved, count + 1, f64::INFINITY).unwrap();
+        assert_eq!(all.len(), count);
+        let distinct: HashSet<_> = all
+            .iter()
+            .map(|cycle| cycle.places.iter().copied().sorted().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(distinct.len(), all.len());
+        for (cycle, next) in all.iter().zip(&all[1..]) {
+            assert!(cycle.slack <= next.slack);
+        }
+        for cycle in &all {
+            assert!((cycle.tokens as f64 * cycle_time - cycle.delay - cycle.slack).abs() < 1e-6);
+            assert_eq!(cycle.ratio, cycle.delay / cycle.tokens as f64);
+        }
+        assert_eq!(all[0].slack, 0.0);
+        assert_eq!(all[0].ratio, cycle_time);
+
+        // Bounding either keeps a prefix of the full list
+        let top = find_top_critical_cycles(&solved, 3, f64::INFINITY).unwrap();
+        assert_eq!(top.len(), 3);
+        assert!(top.iter().zip(&all).all(|(a, b)| a.slack == b.slack));
+        let window = all[4].slack;
+        let within = find_top_critical_cycles(&solved, usize::MAX, window).unwrap();
+        assert_eq!(
+            within.len(),
+            all.iter().filter(|cycle| cycle.slack <= window).count()
+        );
+        assert!(
+            find_top_critical_cycles(&solved, 0, f64::INFINITY)
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
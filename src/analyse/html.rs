@@ -0,0 +1,410 @@
+//! Self-contained HTML reports of solved HBCNs.
+//!
+//! The text reports of `analyse` and `constrain` become hard to navigate once a circuit has
+//! hundreds of critical cycles. [`write_html`] renders the same data as a single static page,
+//! with its stylesheet and script embedded so that it can be opened offline:
+//!
+//! - A summary of the run, given by the caller
+//! - The critical cycles, in a table sortable by any column, each row expanding into its
+//!   places with their cost, min/max delay, slack and arrival time
+//! - The data and spacer arrival times of every circuit node, also sortable
+//! - Histograms of the place and cycle slacks
+//!
+//! # Usage
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hbcn::analyse::{hbcn::find_critical_cycles, html::{HtmlReport, write_html}};
+//! use hbcn::hbcn::{HasWeight, SlackablePlace};
+//! use std::io::BufWriter;
+//! use std::fs::File;
+//! # let solved_hbcn = hbcn::hbcn::SolvedHBCN::default(); // Example only
+//!
+//! let cycles = find_critical_cycles(&solved_hbcn);
+//! let report = HtmlReport {
+//!     title: "circuit.hbcn".into(),
+//!     summary: vec![("Worst cycle-time".into(), "42".into())],
+//! };
+//! let mut output = BufWriter::new(File::create("report.html")?);
+//! write_html(&mut output, &report, &solved_hbcn, &cycles, |ie| {
+//!     solved_hbcn[ie].weight() - solved_hbcn[ie].slack()
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, io};
+
+use anyhow::Result;
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+
+use crate::hbcn::{
+    CircuitNode, MarkablePlace, Named, SlackablePlace, SolvedHBCN, TimedEvent, Transition,
+    place_kind,
+};
+
+/// Number of bins of the slack histograms.
+const HISTOGRAM_BINS: usize = 20;
+
+const STYLE: &str = r#"
+body { font-family: Helvetica, Arial, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+h2 { font-size: 1.2em; margin-top: 2em; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.2em 0.8em; text-align: right; border-bottom: 1px solid #ddd; }
+th { background: #f0f0f0; }
+th[data-sort] { cursor: pointer; user-select: none; }
+th[data-sort]:hover { background: #e0e0e0; }
+td.name, th.name { text-align: left; }
+tbody.cycle > tr.summary { cursor: pointer; }
+tbody.cycle > tr.summary:hover { background: #f7f7ff; }
+tr.critical td { color: #b00; }
+tr.places > td { padding: 0 0 1em 2em; background: #fafafa; }
+.bar { background: #4a7bd0; height: 0.9em; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th[data-sort]").forEach(function (th) {
+    th.addEventListener("click", function () {
+      var col = th.cellIndex;
+      var descending = th.dataset.order !== "desc";
+      th.dataset.order = descending ? "desc" : "asc";
+      var items = Array.from(table.querySelectorAll(table.dataset.items));
+      var key = function (item) {
+        var row = item.tagName === "TR" ? item : item.rows[0];
+        var value = row.cells[col].dataset.v;
+        var number = parseFloat(value);
+        return isNaN(number) ? value : number;
+      };
+      items.sort(function (a, b) {
+        var ka = key(a), kb = key(b);
+        var order = ka < kb ? -1 : ka > kb ? 1 : 0;
+        return descending ? -order : order;
+      });
+      items.forEach(function (item) { item.parentNode.appendChild(item); });
+    });
+  });
+});
+document.querySelectorAll("tbody.cycle > tr.summary").forEach(function (row) {
+  row.addEventListener("click", function () {
+    var places = row.nextElementSibling;
+    places.hidden = !places.hidden;
+  });
+});
+"#;
+
+/// The run-specific content of an HTML report.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlReport {
+    /// Page title, usually the input file.
+    pub title: String,
+    /// Label and value pairs listed in the summary section.
+    pub summary: Vec<(String, String)>,
+}
+
+/// Write a solved HBCN and its critical `cycles` as a self-contained HTML page.
+///
+/// Cycles are listed in the given order, each as its list of `(source, target)` transition
+/// pairs as returned by [`find_critical_cycles`](super::hbcn::find_critical_cycles). `cost`
+/// gives the cost each place is reported with, which differs between the analysis (the
+/// delay less the slack) and constraint generation (the unconstrained weight).
+///
+/// # Arguments
+///
+/// * `w` - Writer to output the HTML page to
+/// * `report` - Title and summary of the run
+/// * `hbcn` - The solved HBCN with arrival times, delays and slacks
+/// * `cycles` - The critical cycles to tabulate
+/// * `cost` - The cost of each place
+pub fn write_html(
+    w: &mut dyn io::Write,
+    report: &HtmlReport,
+    hbcn: &SolvedHBCN,
+    cycles: &[Vec<(NodeIndex, NodeIndex)>],
+    cost: impl Fn(EdgeIndex) -> f64,
+) -> Result<()> {
+    let title = escape(&report.title);
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html lang=\"en\">")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>HBCN report: {}</title>", title)?;
+    writeln!(w, "<style>{}</style>", STYLE)?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(w, "<h1>HBCN report: {}</h1>", title)?;
+    writeln!(w, "<p>Generated by hbcn {}</p>", env!("CARGO_PKG_VERSION"))?;
+
+    writeln!(w, "<h2>Summary</h2>")?;
+    writeln!(w, "<table>")?;
+    for (label, value) in &report.summary {
+        writeln!(
+            w,
+            "<tr><th class=\"name\">{}</th><td>{}</td></tr>",
+            escape(label),
+            escape(value)
+        )?;
+    }
+    writeln!(
+        w,
+        "<tr><th class=\"name\">Cycles</th><td>{}</td></tr>",
+        cycles.len()
+    )?;
+    writeln!(w, "</table>")?;
+
+    let cycle_slacks = write_cycles(w, hbcn, cycles, &cost)?;
+    write_arrivals(w, hbcn)?;
+
+    writeln!(w, "<h2>Place slack</h2>")?;
+    let place_slacks: Vec<f64> = hbcn.edge_indices().map(|ie| hbcn[ie].slack()).collect();
+    write_histogram(w, &place_slacks, "places")?;
+    writeln!(w, "<h2>Cycle slack</h2>")?;
+    write_histogram(w, &cycle_slacks, "cycles")?;
+
+    writeln!(w, "<script>{}</script>", SCRIPT)?;
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+    Ok(())
+}
+
+/// Write the cycle table, returning the total slack of each cycle.
+fn write_cycles(
+    w: &mut dyn io::Write,
+    hbcn: &SolvedHBCN,
+    cycles: &[Vec<(NodeIndex, NodeIndex)>],
+    cost: &impl Fn(EdgeIndex) -> f64,
+) -> Result<Vec<f64>> {
+    writeln!(w, "<h2>Critical cycles</h2>")?;
+    writeln!(w, "<p>Click a cycle to list its places.</p>")?;
+    writeln!(w, "<table class=\"sortable\" data-items=\"tbody.cycle\">")?;
+    writeln!(
+        w,
+        "<thead><tr><th data-sort>Cycle</th><th data-sort>Transitions</th>\
+         <th data-sort>Tokens</th><th data-sort>Cost</th><th data-sort>Slack</th></tr></thead>"
+    )?;
+
+    let mut cycle_slacks = Vec::with_capacity(cycles.len());
+    for (i, cycle) in cycles.iter().enumerate() {
+        let places: Vec<(NodeIndex, NodeIndex, EdgeIndex)> = cycle
+            .iter()
+            .filter_map(|&(is, it)| Some((is, it, hbcn.find_edge(is, it)?)))
+            .collect();
+        let tokens = places
+            .iter()
+            .filter(|&&(_, _, ie)| hbcn[ie].is_marked())
+            .count();
+        let total_cost: f64 = places.iter().map(|&(_, _, ie)| cost(ie)).sum();
+        let total_slack: f64 = places.iter().map(|&(_, _, ie)| hbcn[ie].slack()).sum();
+        cycle_slacks.push(total_slack);
+
+        writeln!(w, "<tbody class=\"cycle\">")?;
+        writeln!(
+            w,
+            "<tr class=\"summary{}\">{}{}{}{}{}</tr>",
+            if total_slack.abs() < 1e-6 {
+                " critical"
+            } else {
+                ""
+            },
+            number_cell(i as f64),
+            number_cell(places.len() as f64),
+            number_cell(tokens as f64),
+            number_cell(total_cost),
+            number_cell(total_slack),
+        )?;
+        writeln!(w, "<tr class=\"places\" hidden><td colspan=\"5\"><table>")?;
+        writeln!(
+            w,
+            "<tr><th>T</th><th class=\"name\">Node</th><th class=\"name\">Transition</th>\
+             <th>Cost</th><th>Min Delay</th><th>Max Delay</th><th>Slack</th><th>Time</th></tr>"
+        )?;
+        for &(is, it, ie) in &places {
+            let s = &hbcn[is];
+            let e = &hbcn[ie];
+            writeln!(
+                w,
+                "<tr><td>{}</td><td class=\"name\">{}</td><td class=\"name\">{}</td>\
+                 {}{}{}{}{}</tr>",
+                if e.is_marked() { "●" } else { "" },
+                escape(s.name()),
+                place_kind(&s.transition, &hbcn[it].transition),
+                number_cell(cost(ie)),
+                number_cell(e.delay.min.unwrap_or(0.0)),
+                number_cell(e.delay.max),
+                number_cell(e.slack()),
+                number_cell(s.time()),
+            )?;
+        }
+        writeln!(w, "</table></td></tr>")?;
+        writeln!(w, "</tbody>")?;
+    }
+    writeln!(w, "</table>")?;
+
+    Ok(cycle_slacks)
+}
+
+/// Write the table of data and spacer arrival times of every circuit node.
+fn write_arrivals(w: &mut dyn io::Write, hbcn: &SolvedHBCN) -> Result<()> {
+    let mut arrivals: BTreeMap<&CircuitNode, (Option<f64>, Option<f64>)> = BTreeMap::new();
+    for ix in hbcn.node_indices() {
+        let event = &hbcn[ix];
+        let entry = arrivals.entry(event.transition.as_ref()).or_default();
+        match event.transition {
+            Transition::Data(_) => entry.0 = Some(event.time),
+            Transition::Spacer(_) => entry.1 = Some(event.time),
+        }
+    }
+
+    writeln!(w, "<h2>Arrival times</h2>")?;
+    writeln!(w, "<table class=\"sortable\" data-items=\"tbody > tr\">")?;
+    writeln!(
+        w,
+        "<thead><tr><th class=\"name\" data-sort>Node</th><th class=\"name\" data-sort>Type</th>\
+         <th data-sort>Data</th><th data-sort>Spacer</th></tr></thead>"
+    )?;
+    writeln!(w, "<tbody>")?;
+    for (node, (data, spacer)) in arrivals {
+        let kind = match node {
+            CircuitNode::Port(_) => "Port",
+            CircuitNode::Register(_) => "Register",
+        };
+        let name = escape(node.name());
+        writeln!(
+            w,
+            "<tr><td class=\"name\" data-v=\"{}\">{}</td><td class=\"name\" data-v=\"{}\">{}</td>\
+             {}{}</tr>",
+            name,
+            name,
+            kind,
+            kind,
+            data.map_or_else(|| "<td></td>".into(), number_cell),
+            spacer.map_or_else(|| "<td></td>".into(), number_cell),
+        )?;
+    }
+    writeln!(w, "</tbody>")?;
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+/// Write a histogram of `values` as a table of bars.
+fn write_histogram(w: &mut dyn io::Write, values: &[f64], unit: &str) -> Result<()> {
+    let bins = super::histogram(values, HISTOGRAM_BINS);
+    let highest = bins.iter().map(|&(_, _, count)| count).max().unwrap_or(0);
+    if highest == 0 {
+        writeln!(w, "<p>No {}.</p>", unit)?;
+        return Ok(());
+    }
+
+    writeln!(w, "<table class=\"histogram\">")?;
+    writeln!(
+        w,
+        "<tr><th>Slack</th><th>{}</th><th></th></tr>",
+        capitalise(unit)
+    )?;
+    for (low, high, count) in bins {
+        writeln!(
+            w,
+            "<tr><td>{:.3} – {:.3}</td><td>{}</td>\
+             <td class=\"name\" style=\"width: 20em\"><div class=\"bar\" style=\"width: {:.1}%\"></div></td></tr>",
+            low,
+            high,
+            count,
+            100.0 * count as f64 / highest as f64
+        )?;
+    }
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+/// A numeric table cell, sorted by its exact value and shown to three decimals.
+fn number_cell(value: f64) -> String {
+    let shown = format!("{:.3}", value);
+    let shown = shown.trim_end_matches('0').trim_end_matches('.');
+    format!("<td data-v=\"{}\">{}</td>", value, shown)
+}
+
+fn capitalise(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect())
+}
+
+/// Escape text for HTML content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{DelayPair, DelayedPlace, HasWeight, Place, TransitionEvent};
+    use string_cache::DefaultAtom;
+
+    fn place(token: bool, max: f64, slack: f64) -> DelayedPlace {
+        DelayedPlace {
+            place: Place {
+                token,
+                is_internal: false,
+            },
+            delay: DelayPair::new(None, max),
+            slack: Some(slack),
+        }
+    }
+
+    #[test]
+    fn renders_cycles_arrivals_and_histograms() {
+        let a = CircuitNode::Port(DefaultAtom::from("a<0>"));
+        let b = CircuitNode::Register(DefaultAtom::from("b"));
+        let event = |transition, time| TransitionEvent { time, transition };
+
+        let mut hbcn = SolvedHBCN::new();
+        let a_data = hbcn.add_node(event(Transition::Data(a.clone()), 0.0));
+        let a_spacer = hbcn.add_node(event(Transition::Spacer(a), 12.5));
+        let b_data = hbcn.add_node(event(Transition::Data(b.clone()), 5.0));
+        let b_spacer = hbcn.add_node(event(Transition::Spacer(b), 17.5));
+        hbcn.add_edge(a_data, b_data, place(false, 5.0, 0.0));
+        hbcn.add_edge(b_data, a_spacer, place(false, 7.5, 2.5));
+        hbcn.add_edge(a_spacer, b_spacer, place(false, 5.0, 0.0));
+        hbcn.add_edge(b_spacer, a_data, place(true, 7.5, 2.5));
+
+        let cycles = vec![vec![
+            (a_data, b_data),
+            (b_data, a_spacer),
+            (a_spacer, b_spacer),
+            (b_spacer, a_data),
+        ]];
+        let report = HtmlReport {
+            title: "ring".into(),
+            summary: vec![("Worst cycle-time".into(), "25".into())],
+        };
+        let mut out = Vec::new();
+        write_html(&mut out, &report, &hbcn, &cycles, |ie| {
+            hbcn[ie].weight() - hbcn[ie].slack()
+        })
+        .unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("http"));
+        assert!(html.contains("<th class=\"name\">Worst cycle-time</th><td>25</td>"));
+        // One cycle of four places, one token, cost 20 and slack 5
+        assert!(html.contains(
+            "<tr class=\"summary\"><td data-v=\"0\">0</td><td data-v=\"4\">4</td>\
+             <td data-v=\"1\">1</td><td data-v=\"20\">20</td><td data-v=\"5\">5</td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr><td>●</td><td class=\"name\">b</td><td class=\"name\">Null Ack</td>\
+             <td data-v=\"5\">5</td><td data-v=\"0\">0</td><td data-v=\"7.5\">7.5</td>\
+             <td data-v=\"2.5\">2.5</td><td data-v=\"17.5\">17.5</td></tr>"
+        ));
+        // Names are escaped, and each node has its data and spacer arrival
+        assert!(html.contains("<td class=\"name\" data-v=\"a&lt;0&gt;\">a&lt;0&gt;</td>"));
+        assert!(html.contains("<td data-v=\"0\">0</td><td data-v=\"12.5\">12.5</td></tr>"));
+        assert_eq!(html.matches("class=\"histogram\"").count(), 2);
+    }
+}
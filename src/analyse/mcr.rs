@@ -0,0 +1,576 @@
+//! Exact cycle time of an HBCN as its maximum cycle ratio, without an LP solver.
+//!
+//! The cycle time of a marked graph is its maximum cycle ratio: the largest total delay
+//! over token count of any of its cycles. [`compute_cycle_time`] finds it with Howard's
+//! policy iteration, as described by Cochet-Terrasson et al. and Dasdan:
+//!
+//! 1. A **policy** picks one outgoing place for every transition, so that following the
+//!    policy from any transition ends in a cycle.
+//! 2. **Value determination** gives every transition the ratio of the cycle its policy
+//!    ends in, and a potential relative to that cycle.
+//! 3. **Policy improvement** switches a transition to a place leading to a larger ratio,
+//!    or else to one increasing its potential, until no switch improves the policy.
+//!
+//! The ratio of the final policy's worst cycle is the cycle time. Arrival times are then
+//! the longest paths with each place costing its delay less the cycle time per token,
+//! and each place's slack the time its target waits beyond its delay, so the solved HBCN
+//! matches the one of [`super::hbcn::compute_cycle_time`] up to the choice of arrival
+//! times, which the LP leaves free.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::analyse::mcr::compute_cycle_time;
+//! use hbcn::hbcn::parser::parse_hbcn;
+//!
+//! let hbcn = parse_hbcn("
+//!     * +{a} => +{b} : 10
+//!     +{b} => -{a} : 5
+//!     -{a} => -{b} : 10
+//!     -{b} => +{a} : 5
+//! ").unwrap();
+//!
+//! let (cycle_time, solved) = compute_cycle_time(&hbcn, true).unwrap();
+//! assert_eq!(cycle_time, 30.0);
+//! assert!(solved.edge_indices().all(|ie| solved[ie].slack == Some(0.0)));
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Result, bail};
+use petgraph::{algo::is_cyclic_directed, stable_graph::StableGraph};
+
+use crate::{
+    AppError, Transition,
+    hbcn::{
+        DelayPair, DelayedPlace, HasWeight, MarkablePlace, SolvedHBCN, TransitionEvent,
+        round_to_sig_digits,
+    },
+};
+
+/// A place as seen by the policy iteration, between dense transition indices.
+#[derive(Debug, Clone, Copy)]
+struct Arc {
+    source: usize,
+    target: usize,
+    weight: f64,
+    tokens: f64,
+}
+
+/// Compute the cycle time of an HBCN as its maximum cycle ratio.
+///
+/// Takes the same arguments and returns the same cycle time and solved HBCN as
+/// [`super::hbcn::compute_cycle_time`]. A cycle without tokens deadlocks the circuit and is
+/// reported as [`AppError::Infeasible`], as the LP would be; an HBCN without any cycle has
+/// a cycle time of 0.
+///
+/// # Arguments
+///
+/// * `hbcn` - The HBCN to analyse
+/// * `weighted` - If `true`, places cost their weight; if `false`, they cost 1 each
+pub fn compute_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    solve_cycle_time(hbcn, weighted, false)
+}
+
+/// Compute the cycle time of an HBCN as its maximum cycle ratio rounded up to an integer.
+///
+/// Matches [`super::hbcn::compute_integer_cycle_time`]: arrival times and slacks follow the
+/// rounded cycle time, so the slacks absorb the rounding.
+pub fn compute_integer_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    solve_cycle_time(hbcn, weighted, true)
+}
+
+fn solve_cycle_time<P: HasWeight + MarkablePlace + Into<DelayedPlace> + Clone>(
+    hbcn: &StableGraph<Transition, P>,
+    weighted: bool,
+    integer: bool,
+) -> Result<(f64, SolvedHBCN)> {
+    // A cycle of unmarked places never fires
+    let unmarked = hbcn.filter_map(|_, _| Some(()), |_, p| (!p.is_marked()).then_some(()));
+    if is_cyclic_directed(&unmarked) {
+        return Err(AppError::Infeasible.into());
+    }
+
+    let index: HashMap<_, usize> = hbcn
+        .node_indices()
+        .enumerate()
+        .map(|(i, ix)| (ix, i))
+        .collect();
+    let places: HashMap<_, usize> = hbcn
+        .edge_indices()
+        .enumerate()
+        .map(|(i, ie)| (ie, i))
+        .collect();
+    let arcs: Vec<Arc> = hbcn
+        .edge_indices()
+        .map(|ie| {
+            let (source, target) = hbcn.edge_endpoints(ie).unwrap();
+            let place = &hbcn[ie];
+            Arc {
+                source: index[&source],
+                target: index[&target],
+                weight: if weighted { place.weight() } else { 1.0 },
+                tokens: if place.is_marked() { 1.0 } else { 0.0 },
+            }
+        })
+        .collect();
+
+    let eps = tolerance(&arcs);
+    // Arrival times follow the exact ratio, which rounding could bring below a cycle's
+    let mut cycle_time = maximum_cycle_ratio(index.len(), &arcs)?.unwrap_or(0.0);
+    if integer {
+        // Rounded first so that a ratio a hair above an integer stays on it
+        cycle_time = round_to_sig_digits(cycle_time, 8).ceil();
+    }
+    let arrival = arrival_times(index.len(), &arcs, cycle_time)?;
+
+    Ok((
+        round_to_sig_digits(cycle_time, 8),
+        hbcn.filter_map(
+            |ix, transition| {
+                Some(TransitionEvent {
+                    transition: transition.clone(),
+                    time: round_to_sig_digits(arrival[index[&ix]], 8),
+                })
+            },
+            |ie, place| {
+                let arc = &arcs[places[&ie]];
+                let slack = arrival[arc.target] - arrival[arc.source] + cycle_time * arc.tokens
+                    - arc.weight;
+                // Tight places come out of the arrival times with rounding noise
+                let slack = if slack < eps {
+                    0.0
+                } else {
+                    round_to_sig_digits(slack, 8)
+                };
+                let place: DelayedPlace = place.clone().into();
+                Some(DelayedPlace {
+                    place: place.place,
+                    delay: DelayPair {
+                        min: place.delay.min,
+                        max: round_to_sig_digits(arc.weight + slack, 8),
+                    },
+                    slack: Some(slack),
+                })
+            },
+        ),
+    ))
+}
+
+/// Tolerance below which ratios and potentials are taken to be equal, scaled to the
+/// weights of the graph.
+fn tolerance(arcs: &[Arc]) -> f64 {
+    let scale = arcs.iter().map(|arc| arc.weight.abs()).fold(1.0, f64::max);
+    scale * 1e-9
+}
+
+/// The largest ratio of weight to tokens of any cycle of the graph, by Howard's policy
+/// iteration, or `None` if the graph has no cycle. Every cycle must hold a token.
+fn maximum_cycle_ratio(nodes: usize, arcs: &[Arc]) -> Result<Option<f64>> {
+    let eps = tolerance(arcs);
+
+    // Transitions that reach no cycle are pruned, so that following the policy from any
+    // other transition ends in one
+    let mut out_degree = vec![0usize; nodes];
+    let mut incoming: Vec<Vec<usize>> = vec![Vec::new(); nodes];
+    for (i, arc) in arcs.iter().enumerate() {
+        out_degree[arc.source] += 1;
+        incoming[arc.target].push(i);
+    }
+    let mut dead: Vec<usize> = (0..nodes).filter(|&u| out_degree[u] == 0).collect();
+    let mut alive = vec![true; nodes];
+    while let Some(v) = dead.pop() {
+        alive[v] = false;
+        for &a in &incoming[v] {
+            let u = arcs[a].source;
+            out_degree[u] -= 1;
+            if out_degree[u] == 0 {
+                dead.push(u);
+            }
+        }
+    }
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); nodes];
+    for (i, arc) in arcs.iter().enumerate() {
+        if alive[arc.source] && alive[arc.target] {
+            outgoing[arc.source].push(i);
+        }
+    }
+
+    // Start from the heaviest place out of each transition
+    let mut policy: Vec<Option<usize>> = outgoing
+        .iter()
+        .map(|out| {
+            out.iter()
+                .copied()
+                .max_by(|&a, &b| arcs[a].weight.total_cmp(&arcs[b].weight))
+        })
+        .collect();
+
+    // Howard's iteration converges in far fewer steps in practice; the bound only guards
+    // against cycling on floating-point ties
+    for _ in 0..(nodes + 1) * 100 {
+        let (ratio, potential) = evaluate_policy(&policy, arcs);
+
+        // Switch to places leading to a larger ratio
+        let mut improved = false;
+        for (u, out) in outgoing.iter().enumerate() {
+            let best = out
+                .iter()
+                .copied()
+                .filter(|&a| ratio[arcs[a].target] > ratio[u] + eps)
+                .max_by(|&a, &b| ratio[arcs[a].target].total_cmp(&ratio[arcs[b].target]));
+            if let Some(a) = best {
+                policy[u] = Some(a);
+                improved = true;
+            }
+        }
+        if improved {
+            continue;
+        }
+
+        // Otherwise to places increasing the potential within the same ratio
+        for (u, out) in outgoing.iter().enumerate() {
+            if !ratio[u].is_finite() {
+                continue;
+            }
+            let value = |a: usize| {
+                let arc = &arcs[a];
+                arc.weight - ratio[u] * arc.tokens + potential[arc.target]
+            };
+            let best = out
+                .iter()
+                .copied()
+                .filter(|&a| (ratio[arcs[a].target] - ratio[u]).abs() <= eps)
+                .filter(|&a| value(a) > potential[u] + eps)
+                .max_by(|&a, &b| value(a).total_cmp(&value(b)));
+            if let Some(a) = best {
+                policy[u] = Some(a);
+                improved = true;
+            }
+        }
+        if !improved {
+            let ratio = ratio.into_iter().filter(|r| r.is_finite()).reduce(f64::max);
+            return Ok(ratio);
+        }
+    }
+    bail!("Maximum cycle ratio did not converge")
+}
+
+/// The ratio of the cycle each transition's policy ends in, and its potential relative to
+/// that cycle. Transitions whose policy ends without a cycle have a ratio of `-inf`.
+fn evaluate_policy(policy: &[Option<usize>], arcs: &[Arc]) -> (Vec<f64>, Vec<f64>) {
+    let nodes = policy.len();
+    let mut ratio = vec![f64::NAN; nodes];
+    let mut potential = vec![0.0; nodes];
+    // The walk each transition was reached in, to spot the cycle closing it
+    let mut walk_of = vec![usize::MAX; nodes];
+
+    for start in 0..nodes {
+        if !ratio[start].is_nan() {
+            continue;
+        }
+        let mut path = Vec::new();
+        let mut u = start;
+        let end = loop {
+            if !ratio[u].is_nan() {
+                break None;
+            }
+            if walk_of[u] == start {
+                break Some(path.iter().position(|&v| v == u).unwrap());
+            }
+            walk_of[u] = start;
+            path.push(u);
+            match policy[u] {
+                Some(a) => u = arcs[a].target,
+                None => {
+                    // A transition without places out of it ends the walk
+                    ratio[u] = f64::NEG_INFINITY;
+                    path.pop();
+                    break None;
+                }
+            }
+        };
+
+        if let Some(cycle_start) = end {
+            let cycle = &path[cycle_start..];
+            let (weight, tokens) = cycle.iter().fold((0.0, 0.0), |(w, t), &v| {
+                let arc = &arcs[policy[v].unwrap()];
+                (w + arc.weight, t + arc.tokens)
+            });
+            let cycle_ratio = weight / tokens;
+            let root = cycle[0];
+            ratio[root] = cycle_ratio;
+            potential[root] = 0.0;
+            // The rest of the cycle is settled from the root backwards
+            for &v in cycle[1..].iter().rev() {
+                let arc = &arcs[policy[v].unwrap()];
+                ratio[v] = cycle_ratio;
+                potential[v] = arc.weight - cycle_ratio * arc.tokens + potential[arc.target];
+            }
+            path.truncate(cycle_start);
+        }
+
+        // The walk's tail takes the ratio of where it ended
+        for &v in path.iter().rev() {
+            let arc = &arcs[policy[v].unwrap()];
+            ratio[v] = ratio[arc.target];
+            potential[v] = if ratio[v].is_finite() {
+                arc.weight - ratio[v] * arc.tokens + potential[arc.target]
+            } else {
+                0.0
+            };
+        }
+    }
+    (ratio, potential)
+}
+
+/// Earliest arrival times, from 0, at which every place's target follows its source by the
+/// place's weight less `cycle_time` per token.
+fn arrival_times(nodes: usize, arcs: &[Arc], cycle_time: f64) -> Result<Vec<f64>> {
+    let eps = tolerance(arcs);
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); nodes];
+    for (i, arc) in arcs.iter().enumerate() {
+        outgoing[arc.source].push(i);
+    }
+
+    // Longest paths by queue-based Bellman-Ford; the critical cycles weigh 0 in total, up
+    // to the tolerance
+    let mut arrival = vec![0.0; nodes];
+    let mut queued = vec![true; nodes];
+    let mut updates = vec![0usize; nodes];
+    let mut queue: VecDeque<usize> = (0..nodes).collect();
+    while let Some(u) = queue.pop_front() {
+        queued[u] = false;
+        for &a in &outgoing[u] {
+            let arc = &arcs[a];
+            let reached = arrival[u] + arc.weight - cycle_time * arc.tokens;
+            if reached > arrival[arc.target] + eps {
+                arrival[arc.target] = reached;
+                updates[arc.target] += 1;
+                if updates[arc.target] > nodes {
+                    bail!("Cycle time {} is below a cycle's ratio", cycle_time);
+                }
+                if !queued[arc.target] {
+                    queued[arc.target] = true;
+                    queue.push_back(arc.target);
+                }
+            }
+        }
+    }
+    Ok(arrival)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{Place, from_structural_graph, parser::parse_hbcn};
+    use crate::structural_graph::parse;
+
+    fn arc(source: usize, target: usize, weight: f64, tokens: f64) -> Arc {
+        Arc {
+            source,
+            target,
+            weight,
+            tokens,
+        }
+    }
+
+    #[test]
+    fn finds_the_worst_of_several_cycles() {
+        // 0 -> 1 -> 0 has ratio 12 / 1, 1 -> 2 -> 1 has 30 / 2, 2 -> 3 has no way back
+        let arcs = [
+            arc(0, 1, 5.0, 1.0),
+            arc(1, 0, 7.0, 0.0),
+            arc(1, 2, 10.0, 1.0),
+            arc(2, 1, 20.0, 1.0),
+            arc(2, 3, 100.0, 0.0),
+        ];
+        assert_eq!(maximum_cycle_ratio(4, &arcs).unwrap(), Some(15.0));
+        assert_eq!(
+            maximum_cycle_ratio(2, &[arc(0, 1, 1.0, 0.0)]).unwrap(),
+            None
+        );
+    }
+
+    /// An HBCN of the given places between transitions `+t0`, `-t0`, `+t1`, ..., bypassing
+    /// the channel validation of the parser.
+    fn graph(
+        transitions: usize,
+        places: &[(usize, usize, f64, bool)],
+    ) -> StableGraph<Transition, DelayedPlace> {
+        let mut hbcn = StableGraph::new();
+        let nodes: Vec<_> = (0..transitions)
+            .map(|i| {
+                let node = crate::hbcn::CircuitNode::Register(format!("t{}", i / 2).into());
+                hbcn.add_node(if i % 2 == 0 {
+                    Transition::Data(node)
+                } else {
+                    Transition::Spacer(node)
+                })
+            })
+            .collect();
+        for &(source, target, weight, token) in places {
+            hbcn.add_edge(
+                nodes[source],
+                nodes[target],
+                DelayedPlace {
+                    place: Place {
+                        token,
+                        is_internal: false,
+                    },
+                    delay: DelayPair::new(None, weight),
+                    slack: None,
+                },
+            );
+        }
+        hbcn
+    }
+
+    #[test]
+    fn solved_places_wait_for_their_delay() {
+        // 0 -> 1 -> 2 -> 0 holds 22 over 3 tokens, 0 -> 1 -> 0 holds 11 over 2
+        let hbcn = graph(
+            3,
+            &[
+                (0, 1, 10.0, true),
+                (1, 2, 1.0, true),
+                (2, 0, 11.0, true),
+                (1, 0, 1.0, true),
+            ],
+        );
+        let (cycle_time, solved) = compute_cycle_time(&hbcn, true).unwrap();
+        assert_eq!(cycle_time, 7.3333333);
+
+        // Every place's delay is its arrival difference plus the cycle time per token,
+        // and the critical cycle has no slack
+        for ie in solved.edge_indices() {
+            let (s, t) = solved.edge_endpoints(ie).unwrap();
+            let place = &solved[ie];
+            let token = if place.place.token { cycle_time } else { 0.0 };
+            assert!(place.slack.unwrap() >= 0.0);
+            assert!(
+                (solved[t].time - solved[s].time + token - place.delay.max).abs() < 1e-6,
+                "place {:?} is not tight",
+                ie
+            );
+        }
+        let slack: Vec<f64> = solved
+            .edge_indices()
+            .map(|ie| solved[ie].slack.unwrap())
+            .collect();
+        assert_eq!(&slack[..3], [0.0, 0.0, 0.0]);
+        assert!(slack[3] > 0.0);
+    }
+
+    #[test]
+    fn integer_cycle_time_rounds_up_into_the_slack() {
+        let hbcn = graph(
+            3,
+            &[
+                (0, 1, 10.0, true),
+                (1, 2, 1.0, true),
+                (2, 0, 11.0, true),
+                (1, 0, 1.0, true),
+            ],
+        );
+        let (cycle_time, solved) = compute_integer_cycle_time(&hbcn, true).unwrap();
+        assert_eq!(cycle_time, 8.0);
+
+        // The critical cycle holds 3 tokens, 24 against its 22 of delay
+        let slack: f64 = solved
+            .edge_indices()
+            .take(3)
+            .map(|ie| solved[ie].slack.unwrap())
+            .sum();
+        assert!((slack - 2.0).abs() < 1e-6, "slack {}", slack);
+
+        // Integer ratios are not pushed to the next integer
+        let ring = graph(2, &[(0, 1, 10.0, true), (1, 0, 20.0, false)]);
+        assert_eq!(compute_integer_cycle_time(&ring, true).unwrap().0, 30.0);
+    }
+
+    #[test]
+    fn deadlocks_are_infeasible() {
+        let hbcn = graph(2, &[(0, 1, 1.0, false), (1, 0, 1.0, false)]);
+        let err = compute_cycle_time(&hbcn, true).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::Infeasible)
+        ));
+    }
+
+    #[test]
+    fn parsed_channels_take_their_token_cycle() {
+        let hbcn = parse_hbcn(
+            "
+            * +{a} => +{b} : 10
+            +{b} => -{a} : 1
+            -{a} => -{b} : 10
+            -{b} => +{a} : 1.5
+            ",
+        )
+        .unwrap();
+        assert_eq!(compute_cycle_time(&hbcn, true).unwrap().0, 22.5);
+        assert_eq!(compute_cycle_time(&hbcn, false).unwrap().0, 4.0);
+    }
+
+    #[test]
+    fn solved_places_keep_their_minimum_delay() {
+        let hbcn = parse_hbcn(
+            "
+            * +{a} => +{b} : (2, 10)
+            +{b} => -{a} : 1
+            -{a} => -{b} : (0.5, 10)
+            -{b} => +{a} : 1
+            ",
+        )
+        .unwrap();
+        let (_, solved) = compute_cycle_time(&hbcn, true).unwrap();
+        let min: Vec<_> = solved
+            .edge_indices()
+            .map(|ie| solved[ie].delay.min)
+            .collect();
+        assert_eq!(min, [Some(2.0), None, Some(0.5), None]);
+    }
+
+    /// The maximum cycle ratio agrees with the LP on circuits of every register kind,
+    /// weighted and unweighted, exact or rounded up to an integer.
+    #[test]
+    fn agrees_with_the_lp() {
+        let inputs = [
+            r#"Port "a" [("b", 20)]
+               Port "b" []"#,
+            r#"Port "input" [("reg", 30)]
+               DataReg "reg" [("output", 25), ("reg", 20)]
+               Port "output" []"#,
+            r#"Port "a" [("r", 13)]
+               NullReg "r" [("s", 7)]
+               DataReg "s" [("t", 11), ("r", 3)]
+               Port "t" [("a", 5)]"#,
+        ];
+        for input in inputs {
+            let hbcn = from_structural_graph(&parse(input).unwrap(), false).unwrap();
+            for weighted in [true, false] {
+                let (mcr, _) = compute_cycle_time(&hbcn, weighted).unwrap();
+                let (lp, _) = super::super::hbcn::compute_cycle_time(&hbcn, weighted).unwrap();
+                assert!(
+                    (lp - mcr).abs() < 1e-6,
+                    "{}: LP {} and MCR {} disagree",
+                    input,
+                    lp,
+                    mcr
+                );
+                let (mcr, _) = compute_integer_cycle_time(&hbcn, weighted).unwrap();
+                let (lp, _) =
+                    super::super::hbcn::compute_integer_cycle_time(&hbcn, weighted).unwrap();
+                assert_eq!(lp, mcr, "{}: integer cycle times disagree", input);
+            }
+        }
+    }
+}
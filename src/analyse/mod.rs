@@ -6,16 +6,24 @@
 //! # Main Operations
 //!
 //! - **[`analyse_main`]**: Performs comprehensive cycle time analysis, finds critical cycles,
-//!   and can generate VCD waveform files and DOT graph visualisations.
+//!   and can generate VCD waveform files and DOT graph visualisations highlighting the
+//!   critical cycles.
 //!   Use the `--depth` flag to analyse cycle depth instead of weighted cycle time.
+//!   Use the `--corners` flag to also report the min, mixed and max delay corners (see
+//!   [`corners`]).
+//!   Use `--monte-carlo <N>` for the cycle-time distribution over `N` samples of the place
+//!   delays (see [`montecarlo`]).
+//!   Use `--top-k <N>`, optionally with `--slack-window <SLACK>`, to report the least-slack
+//!   distinct cycles instead (see [`hbcn::find_top_critical_cycles`]).
 //!
 //! # Workflow
 //!
 //! 1. Parse input (HBCN by default, or structural graph if --structural is passed)
 //! 2. If structural graph, convert to HBCN representation
-//! 3. Compute cycle time using linear programming
+//! 3. Compute cycle time using linear programming, or as the maximum cycle ratio with
+//!    `--engine mcr` (see [`mcr`])
 //! 4. Identify critical cycles (paths with minimal slack)
-//! 5. Generate reports, VCD waveforms, or DOT visualisations
+//! 5. Generate reports, VCD waveforms, DOT visualisations or HTML reports
 //!
 //! # Example
 //!
@@ -27,9 +35,27 @@
 //!     input: "circuit.hbcn".into(),
 //!     structural: false,  // Read as HBCN (default)
 //!     depth: false,  // Weighted analysis (default)
+//!     engine: hbcn::analyse::hbcn::Engine::Lp,
+//!     integer_cycle_time: false,  // Exact cycle time (default)
+//!     corners: false,
+//!     monte_carlo: None,
+//!     sigma: None,
+//!     seed: 0,
+//!     top_k: None,
+//!     slack_window: None,
 //!     report: Some("analysis.rpt".into()),
 //!     vcd: Some("timing.vcd".into()),
+//!     vcd_periods: 1,
+//!     vcd_timescale: hbcn::analyse::vcd::TimescaleUnit::PS,
+//!     vcd_channels: false,
 //!     dot: Some("graph.dot".into()),
+//!     library: None,
+//!     liberty: None,
+//!     liberty_cells: Default::default(),
+//!     virtual_unit: None,
+//!     json: None,
+//!     cache: None,
+//!     html: None,
 //! };
 //!
 //! analyse_main(args)?;
@@ -42,15 +68,57 @@ use std::{cmp, fs, io::Write, path::PathBuf};
 use anyhow::*;
 use clap::Parser;
 use ordered_float::OrderedFloat;
-use petgraph::dot;
 use prettytable::*;
 use rayon::prelude::*;
 
-use crate::{hbcn::*, read_file};
+use crate::{
+    cache::load_or_build, hbcn::declarations::node_declarations,
+    hbcn::serialisation::write_transition, hbcn::*, liberty::LibertyCells, read_delay_model,
+    read_structural_input,
+};
 
+pub mod corners;
+pub mod dot;
 pub mod hbcn;
+pub mod html;
+pub mod mcr;
+pub mod montecarlo;
 pub mod vcd;
 
+/// Number of bins of the Monte Carlo cycle-time histogram.
+const MONTE_CARLO_HISTOGRAM_BINS: usize = 20;
+
+/// Split `values` into `bins` equal-width bins, as `(low, high, count)` triples.
+///
+/// Values all equal to each other make a single bin. Non-finite values are left out.
+pub(crate) fn histogram(values: &[f64], bins: usize) -> Vec<(f64, f64, usize)> {
+    let finite = values.iter().copied().filter(|v| v.is_finite());
+    let (low, high) = finite
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        });
+    if low > high {
+        return Vec::new();
+    }
+    if high - low < 1e-9 {
+        return vec![(low, high, finite.count())];
+    }
+
+    let bins = bins.max(1);
+    let width = (high - low) / bins as f64;
+    let mut counts = vec![0; bins];
+    for v in finite {
+        let bin = (((v - low) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (low + i as f64 * width, low + (i + 1) as f64 * width, count))
+        .collect()
+}
+
 /// Command-line arguments for the analysis command.
 #[derive(Parser, Debug)]
 pub struct AnalyseArgs {
@@ -65,6 +133,45 @@ pub struct AnalyseArgs {
     #[clap(long)]
     pub depth: bool,
 
+    /// Cycle-time engine: `lp` (linear program) or `mcr` (maximum cycle ratio, no LP solver)
+    #[clap(long, default_value = "lp")]
+    pub engine: hbcn::Engine,
+
+    /// Round the cycle time up to an integer (an integer program with the `lp` engine)
+    #[clap(long)]
+    pub integer_cycle_time: bool,
+
+    /// Also report the cycle time and critical cycle at the min, mixed and max delay corners
+    #[clap(long, conflicts_with = "depth")]
+    pub corners: bool,
+
+    /// Monte Carlo analysis: solve the cycle time of this many samples of the place delays
+    #[clap(
+        long,
+        value_name = "SAMPLES",
+        conflicts_with_all = ["depth", "integer_cycle_time"]
+    )]
+    pub monte_carlo: Option<usize>,
+
+    /// Draw Monte Carlo delays as normal around each place's max delay with this standard
+    /// deviation in percent, clamped at its min delay (or zero), instead of uniformly within
+    /// its min/max range
+    #[clap(long, value_name = "PERCENT", requires = "monte_carlo")]
+    pub sigma: Option<f64>,
+
+    /// Seed of the Monte Carlo samples
+    #[clap(long, default_value_t = 0, requires = "monte_carlo")]
+    pub seed: u64,
+
+    /// Report the N distinct simple cycles of least total slack instead of one critical
+    /// cycle per token
+    #[clap(long, value_name = "N")]
+    pub top_k: Option<usize>,
+
+    /// Report only the top-K cycles whose total slack is at most this
+    #[clap(long, value_name = "SLACK", requires = "top_k")]
+    pub slack_window: Option<f64>,
+
     /// Report file for analysis results (default: stdout)
     #[clap(long, short)]
     pub report: Option<PathBuf>,
@@ -73,9 +180,49 @@ pub struct AnalyseArgs {
     #[clap(long)]
     pub vcd: Option<PathBuf>,
 
-    /// DOT file displaying the StructuralHBCN marked graph
+    /// Number of steady-state periods unrolled in the VCD waveform
+    #[clap(long, default_value_t = 1, requires = "vcd")]
+    pub vcd_periods: usize,
+
+    /// Timescale unit of the VCD waveform (s, ms, us, ns, ps or fs)
+    #[clap(long, default_value = "ps", requires = "vcd")]
+    pub vcd_timescale: vcd::TimescaleUnit,
+
+    /// Add request and acknowledge signals for every channel to the VCD waveform
+    #[clap(long, requires = "vcd")]
+    pub vcd_channels: bool,
+
+    /// DOT file rendering the solved HBCN, with its critical cycles highlighted
     #[clap(long)]
     pub dot: Option<PathBuf>,
+
+    /// Component library declaring additional register kinds (structural input only)
+    #[clap(long, requires = "structural")]
+    pub library: Option<PathBuf>,
+
+    /// Liberty cell library pricing the expansion in its characterised delays (structural
+    /// input only)
+    #[clap(long, requires = "structural", requires = "virtual_unit")]
+    pub liberty: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub liberty_cells: LibertyCells,
+
+    /// Nanoseconds per unit of the structural graph's channel delays, required with --liberty
+    #[clap(long, value_name = "NS", requires = "liberty")]
+    pub virtual_unit: Option<f64>,
+
+    /// JSON output file with the solved HBCN (arrival times, delays and slacks)
+    #[clap(long)]
+    pub json: Option<PathBuf>,
+
+    /// Binary cache of the parsed HBCN, reused while the input files are unchanged
+    #[clap(long)]
+    pub cache: Option<PathBuf>,
+
+    /// Self-contained HTML report with sortable critical-cycle tables and slack histograms
+    #[clap(long)]
+    pub html: Option<PathBuf>,
 }
 
 /// Perform comprehensive cycle time analysis on an HBCN circuit.
@@ -83,7 +230,7 @@ pub struct AnalyseArgs {
 /// This function:
 /// 1. Reads and parses the input (HBCN by default, or structural graph if --structural is passed)
 /// 2. If structural graph, converts it to an HBCN representation
-/// 3. Computes cycle time using weighted linear programming
+/// 3. Computes cycle time using weighted linear programming, or the maximum cycle ratio
 /// 4. Identifies critical cycles (paths with minimal slack)
 /// 5. Generates formatted reports and optional visualisations
 ///
@@ -96,6 +243,8 @@ pub struct AnalyseArgs {
 /// - **Report** (stdout or file): Detailed cycle analysis with critical path information
 /// - **VCD** (optional): Waveform file with timing information for visualisation
 /// - **DOT** (optional): Graph visualisation file in Graphviz format
+/// - **JSON** (optional): Solved HBCN with arrival times, delays, slacks and the cycle time
+/// - **HTML** (optional): Self-contained report of the critical cycles, see [`html::write_html`]
 ///
 /// # Example
 ///
@@ -107,9 +256,27 @@ pub struct AnalyseArgs {
 ///     input: "circuit.hbcn".into(),
 ///     structural: false,  // Read as HBCN (default)
 ///     depth: false,  // Weighted analysis (default)
+///     engine: hbcn::analyse::hbcn::Engine::Lp,
+///     integer_cycle_time: false,
+///     corners: false,
+///     monte_carlo: None,
+///     sigma: None,
+///     seed: 0,
+///     top_k: None,
+///     slack_window: None,
 ///     report: None,  // Print to stdout
 ///     vcd: Some("waves.vcd".into()),
+///     vcd_periods: 1,
+///     vcd_timescale: hbcn::analyse::vcd::TimescaleUnit::PS,
+///     vcd_channels: false,
 ///     dot: Some("graph.dot".into()),
+///     library: None,
+///     liberty: None,
+///     liberty_cells: Default::default(),
+///     virtual_unit: None,
+///     json: None,
+///     cache: None,
+///     html: None,
 /// };
 ///
 /// analyse_main(args)?;
@@ -123,9 +290,27 @@ pub fn analyse_main(args: AnalyseArgs) -> Result<()> {
         input,
         structural,
         depth,
+        engine,
+        integer_cycle_time,
+        corners,
+        monte_carlo,
+        sigma,
+        seed,
+        top_k,
+        slack_window,
         report,
         vcd,
+        vcd_periods,
+        vcd_timescale,
+        vcd_channels,
         dot,
+        library,
+        liberty,
+        liberty_cells,
+        virtual_unit,
+        json,
+        cache,
+        html,
     } = args;
 
     if is_verbose() {
@@ -148,29 +333,71 @@ pub fn analyse_main(args: AnalyseArgs) -> Result<()> {
         }
     }
 
-    let (ct, solved_hbcn) = {
-        if structural {
-            // Parse as structural graph
-            let g = read_file(&input)?;
-            let hbcn = crate::hbcn::from_structural_graph(&g, false)
-                .ok_or_else(|| anyhow!("Failed to convert structural graph to StructuralHBCN"))?;
-
-            if is_verbose() {
-                eprintln!("Computing cycle time (weighted={})...", weighted);
-            }
-            hbcn::compute_cycle_time(&hbcn, weighted)
-        } else {
-            // Parse as HBCN
-            let file_contents = fs::read_to_string(&input)?;
-            let hbcn = crate::hbcn::parser::parse_hbcn(&file_contents)?;
-            // DelayedPlace implements HasWeight, so we can use it directly
+    let monte_carlo_options = monte_carlo.map(|samples| montecarlo::MonteCarloOptions {
+        samples,
+        seed,
+        variation: match sigma {
+            Some(sigma) => montecarlo::Variation::Sigma(sigma),
+            None => montecarlo::Variation::Range,
+        },
+    });
+
+    let (hbcn, declarations): (HBCN<Transition, DelayedPlace>, _) = if structural {
+        // Parse as structural graph
+        let mut sources = vec![("input", input.as_path())];
+        sources.extend(library.as_deref().map(|library| ("library", library)));
+        sources.extend(liberty.as_deref().map(|liberty| ("liberty", liberty)));
+        let settings =
+            crate::delay_model_settings(liberty.as_deref(), &liberty_cells, virtual_unit);
+        let (hbcn, declarations) =
+            load_or_build(cache.as_deref(), &sources, &settings, false, || {
+                let g = read_structural_input(&input, library.as_deref())?;
+                let delays = read_delay_model(liberty.as_deref(), &liberty_cells, virtual_unit)?;
+                let hbcn = crate::hbcn::from_structural_graph_with_delays(&g, false, &delays)
+                    .ok_or_else(|| {
+                        anyhow!("Failed to convert structural graph to StructuralHBCN")
+                    })?;
+                Ok((hbcn, node_declarations(&g)))
+            })?;
+        crate::warn_min_delay_excesses(&hbcn);
+        // The weight of a place is the max of its delay pair
+        let hbcn = hbcn.map(
+            |_, transition| transition.clone(),
+            |_, place| place.clone().into(),
+        );
+        (hbcn, declarations)
+    } else {
+        // Parse as HBCN
+        load_or_build(cache.as_deref(), &[("input", &input)], &[], false, || {
+            crate::read_hbcn_file_with_declarations(&input)
+        })?
+    };
 
+    if is_verbose() {
+        eprintln!(
+            "Computing cycle time (weighted={}, engine={}, integer={})...",
+            weighted, engine, integer_cycle_time
+        );
+    }
+    let (ct, solved_hbcn) =
+        hbcn::compute_cycle_time_with(&hbcn, weighted, engine, integer_cycle_time)?;
+    let corner_analyses = if corners {
+        if is_verbose() {
+            eprintln!("Computing corner cycle times...");
+        }
+        corners::analyse_corners(&hbcn, engine, integer_cycle_time)?
+    } else {
+        Vec::new()
+    };
+    let monte_carlo_analysis = match &monte_carlo_options {
+        Some(options) => {
             if is_verbose() {
-                eprintln!("Computing cycle time (weighted={})...", weighted);
+                eprintln!("Running {} Monte Carlo samples...", options.samples);
             }
-            hbcn::compute_cycle_time(&hbcn, weighted)
+            Some(montecarlo::monte_carlo(&hbcn, options, engine)?)
         }
-    }?;
+        None => None,
+    };
 
     if is_verbose() {
         eprintln!("Cycle time computed: {}", ct);
@@ -184,26 +411,56 @@ pub fn analyse_main(args: AnalyseArgs) -> Result<()> {
         writeln!(writer, "Worst cycle-time: {}", ct)?;
     }
 
-    if let Some(filename) = dot {
+    if let Some(filename) = json {
         if is_verbose() {
-            eprintln!("Writing DOT graph to: {:?}", filename);
+            eprintln!("Writing JSON results to: {:?}", filename);
         }
-        fs::write(filename, format!("{:?}", dot::Dot::new(&solved_hbcn)))?;
+        let summary = crate::json::SolvedSummary {
+            cycle_time: Some(ct),
+            pseudoclock_period: None,
+        };
+        fs::write(
+            filename,
+            crate::json::solved_hbcn_to_json_with_declarations(
+                &solved_hbcn,
+                summary,
+                &declarations,
+            ),
+        )?;
     }
 
     if let Some(filename) = vcd {
         if is_verbose() {
             eprintln!("Writing VCD waveform to: {:?}", filename);
         }
+        let options = vcd::VcdOptions {
+            cycle_time: Some(ct),
+            periods: vcd_periods,
+            timescale: vcd_timescale,
+            channels: vcd_channels,
+        };
         let mut file = std::io::BufWriter::new(fs::File::create(filename)?);
-        vcd::write_vcd(&solved_hbcn, &mut file)?;
+        vcd::write_vcd_with_options(&solved_hbcn, &options, &mut file)?;
     }
 
     if is_verbose() {
         eprintln!("Finding critical cycles...");
     }
 
-    let cycles = if depth {
+    // The slack window only narrows the top K: the cycles within it can be exponentially many
+    let top_cycles = top_k
+        .map(|k| {
+            hbcn::find_top_critical_cycles(&solved_hbcn, k, slack_window.unwrap_or(f64::INFINITY))
+        })
+        .transpose()?;
+
+    let cycles = if let Some(top_cycles) = &top_cycles {
+        // Already in order of increasing slack
+        top_cycles
+            .iter()
+            .map(|cycle| cycle.places.clone())
+            .collect()
+    } else if depth {
         // For depth analysis, sort by cycle length (depth)
         let mut cycles = hbcn::find_critical_cycles(&solved_hbcn);
         cycles.par_sort_unstable_by_key(|cycle| cmp::Reverse(cycle.len()));
@@ -234,131 +491,342 @@ pub fn analyse_main(args: AnalyseArgs) -> Result<()> {
             .collect()
     };
 
+    if let Some(filename) = dot {
+        if is_verbose() {
+            eprintln!("Writing DOT graph to: {:?}", filename);
+        }
+        // Highlight the cycles without slack, the ones setting the cycle time
+        let critical: Vec<_> = cycles
+            .iter()
+            .filter(|cycle| {
+                let slack: f64 = cycle
+                    .iter()
+                    .map(|(is, it)| solved_hbcn[solved_hbcn.find_edge(*is, *it).unwrap()].slack())
+                    .sum();
+                slack.abs() < 1e-6
+            })
+            .cloned()
+            .collect();
+        let mut file = std::io::BufWriter::new(fs::File::create(filename)?);
+        dot::write_dot(&solved_hbcn, &critical, &mut file)?;
+    }
+
+    if let Some(filename) = html {
+        if is_verbose() {
+            eprintln!("Writing HTML report to: {:?}", filename);
+        }
+        let mut report = html::HtmlReport {
+            title: input.display().to_string(),
+            summary: vec![
+                (
+                    if depth {
+                        "Critical Cycle (Depth/Tokens)"
+                    } else if structural {
+                        "Worst virtual cycle-time"
+                    } else {
+                        "Worst cycle-time"
+                    }
+                    .into(),
+                    format!("{}", ct),
+                ),
+                (
+                    "Analysis".into(),
+                    if depth { "depth" } else { "weighted" }.into(),
+                ),
+                ("Engine".into(), engine.to_string()),
+                (
+                    "Cycle time".into(),
+                    if integer_cycle_time {
+                        "integer"
+                    } else {
+                        "exact"
+                    }
+                    .into(),
+                ),
+            ],
+        };
+        report
+            .summary
+            .extend(corner_analyses.iter().map(|analysis| {
+                (
+                    format!("Cycle-time at the {} corner", analysis.corner),
+                    format!("{}", analysis.cycle_time),
+                )
+            }));
+        if let Some(analysis) = &monte_carlo_analysis {
+            report.summary.extend([
+                (
+                    "Monte Carlo mean cycle-time".into(),
+                    format!("{}", round_to_sig_digits(analysis.mean(), 8)),
+                ),
+                (
+                    "Monte Carlo cycle-time std dev".into(),
+                    format!("{}", round_to_sig_digits(analysis.std_dev(), 8)),
+                ),
+            ]);
+        }
+        let mut file = std::io::BufWriter::new(fs::File::create(filename)?);
+        html::write_html(&mut file, &report, &solved_hbcn, &cycles, |ie| {
+            solved_hbcn[ie].weight() - solved_hbcn[ie].slack()
+        })?;
+    }
+
+    if let Some(top_cycles) = &top_cycles {
+        let mut table = Table::new();
+        table.set_titles(row![
+            "Cycle",
+            if structural { "Cost" } else { "Delay" },
+            "Tokens",
+            "Ratio",
+            "Slack"
+        ]);
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        for (i, cycle) in top_cycles.iter().enumerate() {
+            table.add_row(row![
+                i,
+                round_to_sig_digits(cycle.delay, 8),
+                cycle.tokens,
+                round_to_sig_digits(cycle.ratio, 8),
+                round_to_sig_digits(cycle.slack, 8),
+            ]);
+        }
+        writeln!(writer, "\nLeast-slack cycles:")?;
+        table.print(&mut writer)?;
+    }
+
     for (i, cycle) in cycles.into_iter().enumerate() {
+        if !depth {
+            write_weighted_cycle(
+                &mut writer,
+                &solved_hbcn,
+                &cycle,
+                &format!("Cycle {}", i),
+                structural,
+            )?;
+            continue;
+        }
+
         let mut table = Table::new();
         let mut tokens = 0;
         let count = cycle.len();
 
-        if depth {
-            table.set_titles(row!["T", "Node", "Transition", "Slack", "Time"]);
-        } else {
-            let delay_sum: f64 = cycle
-                .iter()
-                .map(|(is, it)| {
-                    let ie = solved_hbcn.find_edge(*is, *it).unwrap();
-                    let e = &solved_hbcn[ie];
-                    e.weight() - e.slack()
-                })
-                .sum();
+        table.set_titles(row!["T", "Node", "Transition", "Slack", "Time"]);
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+        for (is, it) in cycle.iter() {
+            let ie = solved_hbcn.find_edge(*is, *it).unwrap();
+            let s = &solved_hbcn[*is];
+            let t = &solved_hbcn[*it];
+            let e = &solved_hbcn[ie];
 
-            table.set_titles(row![
-                "T",
-                "Node",
-                "Transition",
-                if structural { "Cost" } else { "Delay" },
-                "Slack",
-                "Time",
+            let ttype = place_kind(&s.transition, &t.transition);
+
+            table.add_row(row![
+                if e.is_marked() {
+                    tokens += 1;
+                    "*"
+                } else {
+                    " "
+                },
+                s.transition.name(),
+                ttype,
+                format!("{}", e.slack()),
+                format!("{}", s.time),
             ]);
+        }
 
-            for (is, it) in cycle.iter() {
-                let ie = solved_hbcn.find_edge(*is, *it).unwrap();
-                let s = &solved_hbcn[*is];
-                let t = &solved_hbcn[*it];
-                let e = &solved_hbcn[ie];
+        writeln!(
+            writer,
+            "\nCycle {}: total cost = {} ({} transitions / {} {}):",
+            i,
+            count,
+            count,
+            tokens,
+            if tokens == 1 { "token" } else { "tokens" }
+        )?;
+        table.print(&mut writer)?;
+    }
 
-                let ttype = match (&s.transition, &t.transition) {
-                    (Transition::Data(_), Transition::Data(_)) => "Data Prop",
-                    (Transition::Spacer(_), Transition::Spacer(_)) => "Null Prop",
-                    (Transition::Data(_), Transition::Spacer(_)) => "Data Ack",
-                    (Transition::Spacer(_), Transition::Data(_)) => "Null Ack",
-                };
-
-                table.add_row(row![
-                    if e.is_marked() {
-                        tokens += 1;
-                        "*"
-                    } else {
-                        " "
-                    },
-                    s.name(),
-                    ttype,
-                    format!("{}", e.weight() - e.slack()),
-                    format!("{}", e.slack()),
-                    format!("{}", s.time),
-                ]);
-            }
+    if !corner_analyses.is_empty() {
+        let mut table = Table::new();
+        table.set_titles(row!["Corner", "Cycle-time", "Transitions", "Tokens"]);
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        for analysis in &corner_analyses {
+            let cycle = analysis.critical_cycle.as_deref().unwrap_or_default();
+            let tokens = cycle
+                .iter()
+                .filter(|(is, it)| {
+                    analysis.solved[analysis.solved.find_edge(*is, *it).unwrap()].is_marked()
+                })
+                .count();
+            table.add_row(row![
+                analysis.corner,
+                analysis.cycle_time,
+                cycle.len(),
+                tokens
+            ]);
+        }
+        writeln!(writer, "\nCorner cycle-times:")?;
+        table.print(&mut writer)?;
 
-            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-            if structural {
-                writeln!(
-                    writer,
-                    "\nCycle {}: cost - slack = {} ({} transitions / {} {}):",
-                    i,
-                    delay_sum,
-                    count,
-                    tokens,
-                    if tokens == 1 { "token" } else { "tokens" }
-                )?;
-            } else {
-                writeln!(
-                    writer,
-                    "\nCycle {}: delay - slack = {} ({} transitions / {} {}):",
-                    i,
-                    delay_sum,
-                    count,
-                    tokens,
-                    if tokens == 1 { "token" } else { "tokens" }
+        for analysis in &corner_analyses {
+            if let Some(cycle) = &analysis.critical_cycle {
+                write_weighted_cycle(
+                    &mut writer,
+                    &analysis.solved,
+                    cycle,
+                    &format!("{} corner critical cycle", analysis.corner),
+                    structural,
                 )?;
             }
         }
+    }
 
-        if depth {
-            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    if let (Some(analysis), Some(options)) = (&monte_carlo_analysis, &monte_carlo_options) {
+        write_monte_carlo(&mut writer, &solved_hbcn, analysis, options)?;
+    }
 
-            for (is, it) in cycle.iter() {
-                let ie = solved_hbcn.find_edge(*is, *it).unwrap();
-                let s = &solved_hbcn[*is];
-                let t = &solved_hbcn[*it];
-                let e = &solved_hbcn[ie];
+    if is_verbose() {
+        eprintln!("Analysis complete");
+    }
 
-                let ttype = match (&s.transition, &t.transition) {
-                    (Transition::Data(_), Transition::Data(_)) => "Data Prop",
-                    (Transition::Spacer(_), Transition::Spacer(_)) => "Null Prop",
-                    (Transition::Data(_), Transition::Spacer(_)) => "Data Ack",
-                    (Transition::Spacer(_), Transition::Data(_)) => "Null Ack",
-                };
-
-                table.add_row(row![
-                    if e.is_marked() {
-                        tokens += 1;
-                        "*"
-                    } else {
-                        " "
-                    },
-                    s.transition.name(),
-                    ttype,
-                    format!("{}", e.slack()),
-                    format!("{}", s.time),
-                ]);
-            }
+    Ok(())
+}
 
-            writeln!(
-                writer,
-                "\nCycle {}: total cost = {} ({} transitions / {} {}):",
-                i,
-                count,
-                count,
-                tokens,
-                if tokens == 1 { "token" } else { "tokens" }
-            )?;
-        }
-        table.print(&mut writer)?;
+/// Write the cycle-time statistics, histogram and place criticality of a Monte Carlo run.
+fn write_monte_carlo(
+    writer: &mut dyn Write,
+    solved_hbcn: &SolvedHBCN,
+    analysis: &montecarlo::MonteCarloAnalysis,
+    options: &montecarlo::MonteCarloOptions,
+) -> Result<()> {
+    let mut table = Table::new();
+    table.set_titles(row!["Statistic", "Cycle-time"]);
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.add_row(row!["Mean", round_to_sig_digits(analysis.mean(), 8)]);
+    table.add_row(row!["Std dev", round_to_sig_digits(analysis.std_dev(), 8)]);
+    for (label, p) in [
+        ("Min", 0.0),
+        ("P5", 5.0),
+        ("P25", 25.0),
+        ("Median", 50.0),
+        ("P75", 75.0),
+        ("P95", 95.0),
+        ("P99", 99.0),
+        ("Max", 100.0),
+    ] {
+        table.add_row(row![label, round_to_sig_digits(analysis.percentile(p), 8)]);
+    }
+    writeln!(
+        writer,
+        "\nMonte Carlo cycle-time ({} samples, {}, seed {}):",
+        options.samples, options.variation, options.seed
+    )?;
+    table.print(writer)?;
+
+    writeln!(writer, "\nMonte Carlo cycle-time histogram:")?;
+    let histogram = analysis.histogram(MONTE_CARLO_HISTOGRAM_BINS);
+    let largest = histogram
+        .iter()
+        .map(|(_, _, count)| *count)
+        .max()
+        .unwrap_or(1);
+    for (start, end, count) in histogram {
+        writeln!(
+            writer,
+            "[{:>12}, {:>12}) {:>8} {}",
+            round_to_sig_digits(start, 8),
+            round_to_sig_digits(end, 8),
+            count,
+            "#".repeat(count * 40 / largest)
+        )?;
     }
 
-    if is_verbose() {
-        eprintln!("Analysis complete");
+    let mut critical: Vec<_> = analysis.criticality.iter().collect();
+    critical.sort_by(|(a, pa), (b, pb)| pb.total_cmp(pa).then(a.cmp(b)));
+    let mut table = Table::new();
+    table.set_titles(row!["Place", "Criticality"]);
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    for (ie, p) in critical {
+        let (source, target) = solved_hbcn.edge_endpoints(*ie).unwrap();
+        let mut place = String::new();
+        // Infallible for String
+        let _ = write_transition(&solved_hbcn[source], &mut place);
+        place.push_str(" => ");
+        let _ = write_transition(&solved_hbcn[target], &mut place);
+        table.add_row(row![place, format!("{:.1}%", p * 100.0)]);
+    }
+    writeln!(writer, "\nMonte Carlo place criticality:")?;
+    table.print(writer)?;
+    Ok(())
+}
+
+/// Write a cycle of a weighted analysis as a heading with its total delay and token count,
+/// followed by a table of its places.
+fn write_weighted_cycle(
+    writer: &mut dyn Write,
+    solved_hbcn: &SolvedHBCN,
+    cycle: &[(petgraph::graph::NodeIndex, petgraph::graph::NodeIndex)],
+    label: &str,
+    structural: bool,
+) -> Result<()> {
+    let mut table = Table::new();
+    let mut tokens = 0;
+    let count = cycle.len();
+
+    let delay_sum: f64 = cycle
+        .iter()
+        .map(|(is, it)| {
+            let ie = solved_hbcn.find_edge(*is, *it).unwrap();
+            let e = &solved_hbcn[ie];
+            e.weight() - e.slack()
+        })
+        .sum();
+
+    table.set_titles(row![
+        "T",
+        "Node",
+        "Transition",
+        if structural { "Cost" } else { "Delay" },
+        "Slack",
+        "Time",
+    ]);
+
+    for (is, it) in cycle.iter() {
+        let ie = solved_hbcn.find_edge(*is, *it).unwrap();
+        let s = &solved_hbcn[*is];
+        let t = &solved_hbcn[*it];
+        let e = &solved_hbcn[ie];
+
+        let ttype = place_kind(&s.transition, &t.transition);
+
+        table.add_row(row![
+            if e.is_marked() {
+                tokens += 1;
+                "*"
+            } else {
+                " "
+            },
+            s.name(),
+            ttype,
+            format!("{}", e.weight() - e.slack()),
+            format!("{}", e.slack()),
+            format!("{}", s.time),
+        ]);
     }
 
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    writeln!(
+        writer,
+        "\n{}: {} - slack = {} ({} transitions / {} {}):",
+        label,
+        if structural { "cost" } else { "delay" },
+        delay_sum,
+        count,
+        tokens,
+        if tokens == 1 { "token" } else { "tokens" }
+    )?;
+    table.print(writer)?;
     Ok(())
 }
 
@@ -504,10 +972,16 @@ mod tests {
 
         let (_, solved_hbcn) = run_analysis(input, true).expect("Should compute cycle time");
 
-        let dot_content = format!("{:?}", petgraph::dot::Dot::new(&solved_hbcn));
-
-        // DOT should contain basic graph structure
-        assert!(dot_content.contains("digraph") || dot_content.contains("graph"));
+        let cycles = hbcn::find_critical_cycles(&solved_hbcn);
+        let mut out = Vec::new();
+        dot::write_dot(&solved_hbcn, &cycles, &mut out).expect("Should write DOT");
+        let dot_content = String::from_utf8(out).unwrap();
+
+        // DOT should contain a cluster per circuit node and every transition
+        assert!(dot_content.starts_with("digraph hbcn {"));
+        assert_eq!(dot_content.matches("subgraph").count(), 2);
+        assert!(dot_content.contains("+a\\nt="));
+        assert!(dot_content.contains("-b\\nt="));
     }
 
     /// Test cycle cost calculation
@@ -550,12 +1024,7 @@ mod tests {
                 let s = &solved_hbcn[*is];
                 let t = &solved_hbcn[*it];
 
-                let ttype = match (&s.transition, &t.transition) {
-                    (Transition::Data(_), Transition::Data(_)) => "Data Prop",
-                    (Transition::Spacer(_), Transition::Spacer(_)) => "Null Prop",
-                    (Transition::Data(_), Transition::Spacer(_)) => "Data Ack",
-                    (Transition::Spacer(_), Transition::Data(_)) => "Null Ack",
-                };
+                let ttype = place_kind(&s.transition, &t.transition);
 
                 // Should classify transition types correctly
                 assert!(!ttype.is_empty(), "Transition type should not be empty");
@@ -625,4 +1094,14 @@ mod tests {
         let result = parse(invalid_input);
         assert!(result.is_err(), "Should fail to parse invalid input");
     }
+
+    #[test]
+    fn histogram_bins() {
+        assert_eq!(
+            histogram(&[0.0, 1.0, 1.5, 4.0], 4),
+            vec![(0.0, 1.0, 1), (1.0, 2.0, 2), (2.0, 3.0, 0), (3.0, 4.0, 1)]
+        );
+        assert_eq!(histogram(&[2.0, 2.0], 4), vec![(2.0, 2.0, 2)]);
+        assert!(histogram(&[], 4).is_empty());
+    }
 }
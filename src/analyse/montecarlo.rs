@@ -0,0 +1,420 @@
+//! Monte Carlo analysis of the cycle time of an HBCN under delay variation.
+//!
+//! [`monte_carlo`] draws the delay of every place from a distribution, solves the weighted
+//! cycle time of each draw and collects the resulting distribution of cycle times. Two
+//! delay models are supported, as selected by a [`Variation`]:
+//!
+//! - **Range**: each place uniformly within its `min` to `max` delay; places without a min
+//!   delay keep their max
+//! - **Sigma**: each place normally distributed around its max delay, with a standard
+//!   deviation of a percentage of it, and clamped from below at its min delay, or at zero
+//!   for a place without one, so that a wide distribution draws no negative delays
+//!
+//! Samples are solved in parallel. Each one draws from its own generator, seeded from the
+//! run's seed and the sample's index, so a run is reproducible from its seed regardless of
+//! the number of threads. Besides the cycle times, the analysis counts how often each place
+//! lies on a critical cycle of a sample, its criticality probability.
+//!
+//! The cycle times are exact: rounding them up to integers would only blur their
+//! distribution.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::analyse::hbcn::Engine;
+//! use hbcn::analyse::montecarlo::{MonteCarloOptions, Variation, monte_carlo};
+//! use hbcn::hbcn::parser::parse_hbcn;
+//!
+//! let hbcn = parse_hbcn("
+//!     * +{a} => +{b} : (6, 10)
+//!     +{b} => -{a} : 5
+//!     -{a} => -{b} : (8, 10)
+//!     -{b} => +{a} : 5
+//! ").unwrap();
+//!
+//! let options = MonteCarloOptions {
+//!     samples: 100,
+//!     seed: 42,
+//!     variation: Variation::Range,
+//! };
+//! let analysis = monte_carlo(&hbcn, &options, Engine::Mcr).unwrap();
+//! assert_eq!(analysis.cycle_times.len(), 100);
+//! assert!(analysis.percentile(0.0) >= 24.0 && analysis.percentile(100.0) <= 30.0);
+//!
+//! // The only cycle is critical in every sample
+//! assert!(analysis.criticality.values().all(|&p| p == 1.0));
+//! ```
+
+use std::{collections::HashMap, f64::consts::PI, fmt};
+
+use anyhow::{Result, bail};
+use petgraph::{algo::tarjan_scc, graph::EdgeIndex, stable_graph::StableGraph};
+use rayon::prelude::*;
+
+use super::hbcn::{Engine, compute_cycle_time_with};
+use crate::hbcn::{
+    DelayPair, DelayedPlace, SlackablePlace, SolvedHBCN, StructuralHBCN, Transition, WeightedPlace,
+};
+
+/// Distribution the delay of each place is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variation {
+    /// Uniform between the min and max delay of the place.
+    Range,
+    /// Normal around the max delay of the place, with a standard deviation of the given
+    /// percentage of it, clamped from below at the place's min delay, or at zero without
+    /// one. A place whose own delays are negative is clamped at its max instead.
+    Sigma(f64),
+}
+
+impl fmt::Display for Variation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variation::Range => write!(f, "min/max ranges"),
+            Variation::Sigma(sigma) => write!(f, "sigma {}%", sigma),
+        }
+    }
+}
+
+/// Parameters of a Monte Carlo run.
+#[derive(Debug, Clone)]
+pub struct MonteCarloOptions {
+    /// Number of samples drawn.
+    pub samples: usize,
+    /// Seed the sample generators derive from.
+    pub seed: u64,
+    /// Distribution of the place delays.
+    pub variation: Variation,
+}
+
+/// Cycle times of a Monte Carlo run and the criticality of its places.
+#[derive(Debug, Clone)]
+pub struct MonteCarloAnalysis {
+    /// Cycle time of every sample, in sample order.
+    pub cycle_times: Vec<f64>,
+    /// Fraction of the samples in which each place lies on a critical cycle. Places that
+    /// never do are left out.
+    pub criticality: HashMap<EdgeIndex, f64>,
+}
+
+impl MonteCarloAnalysis {
+    /// Mean of the cycle times.
+    pub fn mean(&self) -> f64 {
+        self.cycle_times.iter().sum::<f64>() / self.cycle_times.len() as f64
+    }
+
+    /// Sample standard deviation of the cycle times, 0 for a single sample.
+    pub fn std_dev(&self) -> f64 {
+        let n = self.cycle_times.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let square_sum: f64 = self.cycle_times.iter().map(|ct| (ct - mean).powi(2)).sum();
+        (square_sum / (n - 1) as f64).sqrt()
+    }
+
+    /// The `p`th percentile of the cycle times, for `p` from 0 to 100, interpolating
+    /// linearly between the closest samples.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let mut sorted = self.cycle_times.clone();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+        let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+        sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+    }
+
+    /// Histogram of the cycle times over `bins` equal bins spanning them, as
+    /// `(start, end, count)` triples.
+    pub fn histogram(&self, bins: usize) -> Vec<(f64, f64, usize)> {
+        super::histogram(&self.cycle_times, bins)
+    }
+}
+
+/// Run a Monte Carlo analysis of the weighted cycle time of an HBCN.
+///
+/// Every sample prices the places with delays drawn as selected by `options.variation` and
+/// is solved with the given engine. A place is critical in a sample if it lies on any of
+/// the sample's cycles setting the cycle time.
+pub fn monte_carlo<P: Clone + Into<DelayedPlace> + Sync>(
+    hbcn: &StableGraph<Transition, P>,
+    options: &MonteCarloOptions,
+    engine: Engine,
+) -> Result<MonteCarloAnalysis> {
+    if options.samples == 0 {
+        bail!("Monte Carlo analysis needs at least one sample");
+    }
+    if let Variation::Sigma(sigma) = options.variation
+        && !(sigma.is_finite() && sigma >= 0.0)
+    {
+        bail!("invalid delay variation of {}%", sigma);
+    }
+
+    let delays: Vec<(EdgeIndex, DelayPair)> = hbcn
+        .edge_indices()
+        .map(|ie| (ie, Into::<DelayedPlace>::into(hbcn[ie].clone()).delay))
+        .collect();
+
+    let samples: Vec<(f64, Vec<EdgeIndex>)> = (0..options.samples)
+        .into_par_iter()
+        .map(|sample| {
+            let mut rng = SplitMix64::new(options.seed, sample as u64);
+            let weights: HashMap<EdgeIndex, f64> = delays
+                .iter()
+                .map(|(ie, delay)| (*ie, draw(&mut rng, delay, options.variation)))
+                .collect();
+            let (cycle_time, solved) =
+                compute_cycle_time_with(&sampled_hbcn(hbcn, &weights), true, engine, false)?;
+            Ok((cycle_time, critical_places(&solved, cycle_time)))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut counts: HashMap<EdgeIndex, usize> = HashMap::new();
+    for (_, critical) in &samples {
+        for &ie in critical {
+            *counts.entry(ie).or_default() += 1;
+        }
+    }
+
+    Ok(MonteCarloAnalysis {
+        cycle_times: samples.iter().map(|(cycle_time, _)| *cycle_time).collect(),
+        criticality: counts
+            .into_iter()
+            .map(|(ie, count)| (ie, count as f64 / options.samples as f64))
+            .collect(),
+    })
+}
+
+/// The places of a solved HBCN lying on a cycle that sets its cycle time.
+///
+/// A cycle sets the cycle time exactly if none of its places has slack, so these are the
+/// places without slack whose transitions are strongly connected through such places.
+fn critical_places(solved: &SolvedHBCN, cycle_time: f64) -> Vec<EdgeIndex> {
+    let tolerance = 1e-6 * cycle_time.abs().max(1.0);
+    let tight = solved.filter_map(
+        |_, _| Some(()),
+        |_, place| (place.slack() <= tolerance).then_some(()),
+    );
+
+    let mut component = HashMap::new();
+    for (i, scc) in tarjan_scc(&tight).into_iter().enumerate() {
+        component.extend(scc.into_iter().map(|ix| (ix, i)));
+    }
+    tight
+        .edge_indices()
+        .filter(|&ie| {
+            let (src, dst) = tight.edge_endpoints(ie).unwrap();
+            component[&src] == component[&dst]
+        })
+        .collect()
+}
+
+/// Price the places of an HBCN at the drawn weights.
+fn sampled_hbcn<P>(
+    hbcn: &StableGraph<Transition, P>,
+    weights: &HashMap<EdgeIndex, f64>,
+) -> StructuralHBCN
+where
+    P: Clone + Into<DelayedPlace>,
+{
+    hbcn.filter_map(
+        |_, transition| Some(transition.clone()),
+        |ie, place| {
+            Some(WeightedPlace {
+                place: Into::<DelayedPlace>::into(place.clone()).place,
+                weight: weights[&ie],
+                min_delay: None,
+            })
+        },
+    )
+}
+
+/// Draw the delay of a place.
+fn draw(rng: &mut SplitMix64, delay: &DelayPair, variation: Variation) -> f64 {
+    match variation {
+        Variation::Range => match delay.min {
+            Some(min) => min + (delay.max - min) * rng.next_f64(),
+            None => delay.max,
+        },
+        Variation::Sigma(sigma) => {
+            let floor = delay.min.unwrap_or(0.0).min(delay.max);
+            (delay.max * (1.0 + sigma / 100.0 * rng.next_normal())).max(floor)
+        }
+    }
+}
+
+/// SplitMix64 generator, small and with a fixed output for a given seed, which keeps runs
+/// reproducible across platforms and releases.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    /// Generator of one sample, its state mixed from the seed and the sample index so
+    /// that neighbouring samples do not share their streams.
+    fn new(seed: u64, sample: u64) -> Self {
+        let mut stream = SplitMix64(sample);
+        SplitMix64(seed ^ stream.next_u64())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(Self::GAMMA);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, by the Box-Muller transform.
+    fn next_normal(&mut self) -> f64 {
+        let radius = (-2.0 * (1.0 - self.next_f64()).ln()).sqrt();
+        radius * (2.0 * PI * self.next_f64()).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::parser::parse_hbcn;
+
+    fn ring() -> StableGraph<Transition, DelayedPlace> {
+        parse_hbcn(
+            "
+            * +{a} => +{b} : (1, 10)
+              +{b} => -{a} : (1, 10)
+              -{a} => -{b} : (1, 10)
+              -{b} => +{a} : (1, 10)
+              +{b} => +{c} : 5
+              +{c} => -{b} : 5
+              -{b} => -{c} : 5
+            * -{c} => +{b} : 5
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_are_reproducible_from_the_seed() {
+        let hbcn = ring();
+        let run = |seed, variation| {
+            let options = MonteCarloOptions {
+                samples: 50,
+                seed,
+                variation,
+            };
+            monte_carlo(&hbcn, &options, Engine::Mcr)
+                .unwrap()
+                .cycle_times
+        };
+        assert_eq!(run(7, Variation::Range), run(7, Variation::Range));
+        assert_ne!(run(7, Variation::Range), run(8, Variation::Range));
+        assert_eq!(run(7, Variation::Sigma(5.0)), run(7, Variation::Sigma(5.0)));
+    }
+
+    /// The b-c channel is fixed while the a-b channel ranges widely, so the critical cycle
+    /// moves between them and neither channel is critical in every sample.
+    #[test]
+    fn criticality_splits_between_cycles() {
+        let hbcn = ring();
+        let options = MonteCarloOptions {
+            samples: 200,
+            seed: 0,
+            variation: Variation::Range,
+        };
+        let analysis = monte_carlo(&hbcn, &options, Engine::Mcr).unwrap();
+
+        assert!(
+            analysis
+                .cycle_times
+                .iter()
+                .all(|&ct| (20.0..=40.0).contains(&ct))
+        );
+        for (ie, place) in hbcn.edge_indices().map(|ie| (ie, &hbcn[ie])) {
+            let p = analysis.criticality.get(&ie).copied().unwrap_or(0.0);
+            assert!(
+                p > 0.0 && p < 1.0,
+                "place {:?} ({:?}): {}",
+                ie,
+                place.delay,
+                p
+            );
+        }
+    }
+
+    /// Without min delays every sample is the max corner.
+    #[test]
+    fn fixed_delays_do_not_vary() {
+        let hbcn = parse_hbcn(
+            "
+            * +{a} => +{b} : 10
+              +{b} => -{a} : 5
+              -{a} => -{b} : 10
+              -{b} => +{a} : 5
+            ",
+        )
+        .unwrap();
+        let options = MonteCarloOptions {
+            samples: 10,
+            seed: 3,
+            variation: Variation::Range,
+        };
+        let analysis = monte_carlo(&hbcn, &options, Engine::Mcr).unwrap();
+        assert_eq!(analysis.cycle_times, [30.0; 10]);
+        assert_eq!(analysis.std_dev(), 0.0);
+        assert_eq!(analysis.histogram(20), [(30.0, 30.0, 10)]);
+    }
+
+    #[test]
+    fn statistics_of_known_samples() {
+        let analysis = MonteCarloAnalysis {
+            cycle_times: vec![4.0, 1.0, 3.0, 2.0, 5.0],
+            criticality: HashMap::new(),
+        };
+        assert_eq!(analysis.mean(), 3.0);
+        assert!((analysis.std_dev() - 2.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(analysis.percentile(0.0), 1.0);
+        assert_eq!(analysis.percentile(50.0), 3.0);
+        assert_eq!(analysis.percentile(90.0), 4.6);
+        assert_eq!(analysis.histogram(2), [(1.0, 3.0, 2), (3.0, 5.0, 3)]);
+    }
+
+    #[test]
+    fn sigma_centres_on_the_max_delay() {
+        let mut rng = SplitMix64::new(1, 0);
+        let delay = DelayPair::new(Some(5.0), 10.0);
+        let draws: Vec<f64> = (0..10_000)
+            .map(|_| draw(&mut rng, &delay, Variation::Sigma(10.0)))
+            .collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let variance = draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / draws.len() as f64;
+        assert!((mean - 10.0).abs() < 0.05, "mean {}", mean);
+        assert!(
+            (variance.sqrt() - 1.0).abs() < 0.05,
+            "sigma {}",
+            variance.sqrt()
+        );
+    }
+
+    #[test]
+    fn sigma_draws_are_clamped() {
+        let mut rng = SplitMix64::new(5, 0);
+        let ranged = DelayPair::new(Some(6.0), 10.0);
+        let fixed = DelayPair::new(None, 10.0);
+        let draws = |delay: &DelayPair, rng: &mut SplitMix64| -> Vec<f64> {
+            (0..1000)
+                .map(|_| draw(rng, delay, Variation::Sigma(200.0)))
+                .collect()
+        };
+        let ranged = draws(&ranged, &mut rng);
+        assert!(ranged.iter().all(|&d| d >= 6.0));
+        assert!(ranged.contains(&6.0));
+        let fixed = draws(&fixed, &mut rng);
+        assert!(fixed.iter().all(|&d| d >= 0.0));
+        assert!(fixed.contains(&0.0));
+    }
+}
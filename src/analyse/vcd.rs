@@ -7,34 +7,85 @@
 //!
 //! The generated VCD file follows the standard VCD format and includes:
 //!
-//! - Timescale information (picoseconds)
+//! - Timescale information (picoseconds by default, see [`VcdOptions::timescale`])
 //! - Variable declarations for each circuit node
+//! - Optionally, a scope per channel with its request and acknowledge signals
 //! - Value changes showing data vs. spacer transitions over time
 //!
+//! Given the cycle time, [`write_vcd_with_options`] unrolls several periods of the steady
+//! state: each transition fires once per period, at its arrival time modulo the cycle time, so
+//! negative arrival times and those beyond the first period are placed where they recur.
+//!
 //! # Usage
 //!
 //! ```no_run
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
-//! use hbcn::analyse::vcd::write_vcd;
+//! use hbcn::analyse::vcd::{VcdOptions, write_vcd, write_vcd_with_options};
 //! use std::io::BufWriter;
 //! use std::fs::File;
 //! # let solved_hbcn = hbcn::hbcn::SolvedHBCN::default(); // Example only
 //!
 //! let mut output = BufWriter::new(File::create("timing.vcd")?);
 //! write_vcd(&solved_hbcn, &mut output)?;
+//!
+//! // Four periods of a 12ns cycle, with the handshake of every channel
+//! let options = VcdOptions {
+//!     cycle_time: Some(12.0),
+//!     periods: 4,
+//!     channels: true,
+//!     ..VcdOptions::default()
+//! };
+//! let mut output = BufWriter::new(File::create("unrolled.vcd")?);
+//! write_vcd_with_options(&solved_hbcn, &options, &mut output)?;
 //! # Ok(())
 //! # }
 //! ```
 
 use std::{collections::HashMap, io};
 
-use anyhow::Result;
-use itertools::Itertools;
-use petgraph::visit::IntoNodeReferences;
-use rayon::prelude::*;
+use anyhow::{Result, bail};
+use petgraph::{
+    stable_graph::{EdgeIndex, NodeIndex},
+    visit::IntoNodeReferences,
+};
 use regex::Regex;
 
-use crate::hbcn::{HBCN, Named, TimedEvent, Transition};
+use crate::hbcn::{CircuitNode, HBCN, HasDelay, Named, SlackablePlace, TimedEvent, Transition};
+
+pub use vcd::TimescaleUnit;
+
+/// Options of [`write_vcd_with_options`].
+#[derive(Debug, Clone)]
+pub struct VcdOptions {
+    /// Cycle time the arrival times repeat with.
+    ///
+    /// Without it a single period is written as solved, shifted to start at time 0 if any
+    /// arrival time is negative.
+    pub cycle_time: Option<f64>,
+    /// Number of periods to unroll (with a cycle time).
+    pub periods: usize,
+    /// Unit of the VCD timestamps; arrival times are taken to be in nanoseconds.
+    pub timescale: TimescaleUnit,
+    /// Add a scope per channel with its `req` and `ack` signals.
+    pub channels: bool,
+}
+
+impl Default for VcdOptions {
+    fn default() -> Self {
+        VcdOptions {
+            cycle_time: None,
+            periods: 1,
+            timescale: TimescaleUnit::PS,
+            channels: false,
+        }
+    }
+}
+
+/// A waveform signal and the times (in nanoseconds, within one period) it changes value at.
+struct Signal {
+    id: vcd::IdCode,
+    changes: Vec<(f64, bool)>,
+}
 
 /// Write VCD (Value Change Dump) format output for an HBCN.
 ///
@@ -45,6 +96,9 @@ use crate::hbcn::{HBCN, Named, TimedEvent, Transition};
 /// - **Data transitions** → wire value = 1
 /// - **Spacer transitions** → wire value = 0
 ///
+/// This writes a single period with the [default options](VcdOptions::default); see
+/// [`write_vcd_with_options`] to unroll several periods.
+///
 /// # Arguments
 ///
 /// * `hbcn` - The solved HBCN with timing information
@@ -64,54 +118,319 @@ use crate::hbcn::{HBCN, Named, TimedEvent, Transition};
 /// # Ok(())
 /// # }
 /// ```
-pub fn write_vcd<T: AsRef<Transition> + TimedEvent + Send + Sync, P>(
+pub fn write_vcd<T, P>(hbcn: &HBCN<T, P>, w: &mut dyn io::Write) -> Result<()>
+where
+    T: AsRef<Transition> + TimedEvent,
+    P: HasDelay + SlackablePlace,
+{
+    write_vcd_with_options(hbcn, &VcdOptions::default(), w)
+}
+
+/// Write VCD format output for an HBCN, unrolling `options.periods` periods.
+///
+/// Each transition fires once per period, at its arrival time modulo the cycle time. A wire
+/// starts at the value of its transition that fired last in the previous period.
+///
+/// With [`VcdOptions::channels`], every channel — the four places between the data and
+/// spacer transitions of two nodes — gets a scope named after its ends with two wires. `req`
+/// rises when the data token reaches the receiver and falls when the spacer does, and `ack`
+/// rises when the receiver's acknowledge of the data reaches the sender and falls with the
+/// acknowledge of the spacer. Tokens reach a place's end after its delay less its slack,
+/// so the gap up to the receiving transition is the time it waits on its other inputs.
+pub fn write_vcd_with_options<T, P>(
     hbcn: &HBCN<T, P>,
+    options: &VcdOptions,
     w: &mut dyn io::Write,
-) -> Result<()> {
+) -> Result<()>
+where
+    T: AsRef<Transition> + TimedEvent,
+    P: HasDelay + SlackablePlace,
+{
+    let period = match options.cycle_time {
+        Some(ct) if !(ct.is_finite() && ct > 0.0) => {
+            bail!("VCD cycle time must be positive, got {}", ct)
+        }
+        ct => ct,
+    };
+    if options.periods == 0 {
+        bail!("VCD must have at least one period");
+    }
+    let periods = if period.is_some() { options.periods } else { 1 };
+
     let mut writer = vcd::Writer::new(w);
     let re = Regex::new(r"[^a-zA-Z0-9_]").unwrap();
 
-    writer.timescale(1, vcd::TimescaleUnit::PS)?;
+    writer.timescale(1, options.timescale)?;
     writer.add_module("top")?;
 
-    let mut variables = HashMap::new();
-
-    let events = {
-        let mut events: Vec<&T> = hbcn
-            .node_references()
-            .map(|(_idx, e)| {
-                let cnode = e.as_ref().name();
-                if !variables.contains_key(cnode) {
-                    variables.insert(
-                        cnode.clone(),
-                        writer.add_wire(1, &re.replace_all(cnode, "_")).unwrap(),
-                    );
-                }
-
-                e
-            })
-            .collect();
-        // total_cmp gives a total order even if a degenerate solve yields a NaN time,
-        // where partial_cmp would return None and panic the unwrap.
-        events.par_sort_unstable_by(|a, b| a.time().total_cmp(&b.time()));
-        events
+    let mut signals: Vec<Signal> = Vec::new();
+    let mut node_signal: HashMap<&CircuitNode, usize> = HashMap::new();
+    for (_, event) in hbcn.node_references() {
+        let transition = event.as_ref();
+        let node: &CircuitNode = transition.as_ref();
+        let i = match node_signal.get(node) {
+            Some(&i) => i,
+            None => {
+                let id = writer.add_wire(1, &re.replace_all(node.name(), "_"))?;
+                signals.push(Signal {
+                    id,
+                    changes: Vec::new(),
+                });
+                node_signal.insert(node, signals.len() - 1);
+                signals.len() - 1
+            }
+        };
+        let value = matches!(transition, Transition::Data(_));
+        signals[i].changes.push((event.time(), value));
+    }
+
+    if options.channels {
+        for (sender, receiver, places) in channels(hbcn) {
+            writer.add_module(
+                &re.replace_all(&format!("{}__{}", sender.name(), receiver.name()), "_"),
+            )?;
+            // The data and spacer requests, then the data and spacer acknowledges
+            for (wire, places) in [("req", &places[..2]), ("ack", &places[2..])] {
+                let id = writer.add_wire(1, wire)?;
+                let changes = places
+                    .iter()
+                    .zip([true, false])
+                    .map(|(&(src, place), value)| {
+                        let place = &hbcn[place];
+                        let arrival = hbcn[src].time() + place.delay().max - place.slack();
+                        (arrival, value)
+                    })
+                    .collect();
+                signals.push(Signal { id, changes });
+            }
+            writer.upscope()?;
+        }
+    }
+
+    writer.upscope()?;
+    writer.enddefinitions()?;
+
+    // Place every change within its period
+    let shift = match period {
+        Some(_) => 0.0,
+        None => signals
+            .iter()
+            .flat_map(|signal| signal.changes.iter().map(|&(time, _)| time))
+            .filter(|time| time.is_finite())
+            .fold(0.0, f64::min),
     };
+    for signal in &mut signals {
+        for (time, _) in &mut signal.changes {
+            *time = match period {
+                Some(ct) => time.rem_euclid(ct),
+                None => *time - shift,
+            };
+        }
+        // total_cmp gives a total order even if a degenerate solve yields a NaN time
+        signal.changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
 
-    for (_, var) in variables.iter() {
-        writer.change_scalar(*var, vcd::Value::V0)?;
+    let scale = ticks_per_ns(options.timescale);
+    let mut changes: Vec<(u64, usize, bool)> = Vec::new();
+    for (i, signal) in signals.iter().enumerate() {
+        for k in 0..periods {
+            let offset = k as f64 * period.unwrap_or(0.0);
+            for &(time, value) in &signal.changes {
+                changes.push((((time + offset) * scale).round() as u64, i, value));
+            }
+        }
     }
+    changes.sort_by_key(|&(tick, _, _)| tick);
+
+    // In the steady state a signal starts where the previous period left it
+    let mut values: Vec<bool> = signals
+        .iter()
+        .map(|signal| period.is_some() && signal.changes.last().is_some_and(|&(_, value)| value))
+        .collect();
 
-    for (time, events) in events.into_iter().group_by(|x| x.time()).into_iter() {
-        writer.timestamp((time.abs() * 1000.0) as u64)?;
-        for event in events {
-            match event.as_ref() {
-                Transition::Data(id) => writer.change_scalar(variables[id.name()], vcd::Value::V1),
-                Transition::Spacer(id) => {
-                    writer.change_scalar(variables[id.name()], vcd::Value::V0)
-                }
-            }?;
+    // Changes at time 0 make the initial values
+    let start = changes.partition_point(|&(tick, _, _)| tick == 0);
+    for &(_, i, value) in &changes[..start] {
+        values[i] = value;
+    }
+    writer.timestamp(0)?;
+    for (signal, &value) in signals.iter().zip(&values) {
+        writer.change_scalar(signal.id, value)?;
+    }
+
+    let mut last_tick = 0;
+    for &(tick, i, value) in &changes[start..] {
+        if values[i] == value {
+            continue;
         }
+        if tick != last_tick {
+            writer.timestamp(tick)?;
+            last_tick = tick;
+        }
+        values[i] = value;
+        writer.change_scalar(signals[i].id, value)?;
     }
 
     Ok(())
 }
+
+/// The four places of a channel, each with its source transition.
+type ChannelPlaces = [(NodeIndex, EdgeIndex); 4];
+
+/// The channels of an HBCN, as their sender, receiver and four places.
+///
+/// The places are given with their source transition, in the order: data request, spacer
+/// request, data acknowledge, spacer acknowledge. Channels missing any of the four places
+/// are left out.
+fn channels<T, P>(hbcn: &HBCN<T, P>) -> Vec<(&CircuitNode, &CircuitNode, ChannelPlaces)>
+where
+    T: AsRef<Transition>,
+{
+    let mut transitions: HashMap<(&CircuitNode, bool), NodeIndex> = HashMap::new();
+    for (ix, event) in hbcn.node_references() {
+        let transition = event.as_ref();
+        let is_data = matches!(transition, Transition::Data(_));
+        transitions.insert((transition.as_ref(), is_data), ix);
+    }
+
+    hbcn.edge_indices()
+        .filter_map(|ie| {
+            let (src, dst) = hbcn.edge_endpoints(ie)?;
+            let (Transition::Data(sender), Transition::Data(receiver)) =
+                (hbcn[src].as_ref(), hbcn[dst].as_ref())
+            else {
+                return None;
+            };
+            let sender_spacer = *transitions.get(&(sender, false))?;
+            let receiver_spacer = *transitions.get(&(receiver, false))?;
+            Some((
+                sender,
+                receiver,
+                [
+                    (src, ie),
+                    (
+                        sender_spacer,
+                        hbcn.find_edge(sender_spacer, receiver_spacer)?,
+                    ),
+                    (dst, hbcn.find_edge(dst, sender_spacer)?),
+                    (receiver_spacer, hbcn.find_edge(receiver_spacer, src)?),
+                ],
+            ))
+        })
+        .collect()
+}
+
+/// VCD timestamp ticks per nanosecond of arrival time.
+fn ticks_per_ns(unit: TimescaleUnit) -> f64 {
+    match unit {
+        TimescaleUnit::S => 1e-9,
+        TimescaleUnit::MS => 1e-6,
+        TimescaleUnit::US => 1e-3,
+        TimescaleUnit::NS => 1.0,
+        TimescaleUnit::PS => 1e3,
+        TimescaleUnit::FS => 1e6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{DelayPair, DelayedPlace, Place, TransitionEvent};
+    use string_cache::DefaultAtom;
+
+    fn place(token: bool, max: f64, slack: f64) -> DelayedPlace {
+        DelayedPlace {
+            place: Place {
+                token,
+                is_internal: false,
+            },
+            delay: DelayPair::new(None, max),
+            slack: Some(slack),
+        }
+    }
+
+    /// A two-node ring with a 20ns cycle, whose spacer of `a` arrives at -5ns.
+    fn ring() -> HBCN<TransitionEvent, DelayedPlace> {
+        let a = CircuitNode::Port(DefaultAtom::from("a"));
+        let b = CircuitNode::Register(DefaultAtom::from("b"));
+        let event = |transition, time| TransitionEvent { time, transition };
+
+        let mut hbcn = HBCN::new();
+        let a_data = hbcn.add_node(event(Transition::Data(a.clone()), 0.0));
+        let a_spacer = hbcn.add_node(event(Transition::Spacer(a), -5.0));
+        let b_data = hbcn.add_node(event(Transition::Data(b.clone()), 5.0));
+        let b_spacer = hbcn.add_node(event(Transition::Spacer(b), 10.0));
+        hbcn.add_edge(a_data, b_data, place(false, 5.0, 1.0));
+        hbcn.add_edge(a_spacer, b_spacer, place(false, 15.0, 0.0));
+        hbcn.add_edge(b_data, a_spacer, place(true, 10.0, 0.0));
+        hbcn.add_edge(b_spacer, a_data, place(true, 10.0, 0.0));
+        hbcn
+    }
+
+    fn write(options: &VcdOptions) -> String {
+        let mut out = Vec::new();
+        write_vcd_with_options(&ring(), options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The value-change section, as lines.
+    fn changes(vcd: &str) -> Vec<&str> {
+        vcd.split("$enddefinitions $end\n")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .collect()
+    }
+
+    #[test]
+    fn single_period_is_shifted_to_start_at_zero() {
+        let vcd = write(&VcdOptions::default());
+        assert!(vcd.contains("$timescale 1 ps $end"));
+        assert_eq!(
+            changes(&vcd),
+            vec![
+                "#0", "0!", "0\"", "#5000", "1!", "#10000", "1\"", "#15000", "0\""
+            ]
+        );
+    }
+
+    #[test]
+    fn periods_wrap_arrival_times_by_the_cycle_time() {
+        let vcd = write(&VcdOptions {
+            cycle_time: Some(20.0),
+            periods: 2,
+            timescale: TimescaleUnit::NS,
+            channels: false,
+        });
+        assert!(vcd.contains("$timescale 1 ns $end"));
+        // `a` falls at -5 + 20, so it starts high after the previous period's data
+        assert_eq!(
+            changes(&vcd),
+            vec![
+                "#0", "1!", "0\"", "#5", "1\"", "#10", "0\"", "#15", "0!", "#20", "1!", "#25",
+                "1\"", "#30", "0\"", "#35", "0!",
+            ]
+        );
+    }
+
+    #[test]
+    fn channels_have_request_and_acknowledge_scopes() {
+        let vcd = write(&VcdOptions {
+            cycle_time: Some(20.0),
+            channels: true,
+            timescale: TimescaleUnit::NS,
+            ..VcdOptions::default()
+        });
+        assert!(vcd.contains("$scope module a__b $end"));
+        assert!(vcd.contains("$var wire 1 # req $end"));
+        assert!(vcd.contains("$var wire 1 $ ack $end"));
+        assert_eq!(vcd.matches("$upscope $end").count(), 2);
+        // The data request arrives at 4 (delay 5, slack 1), the spacer one at 10; the
+        // acknowledges at 15 and 20 (wrapping to 0)
+        let changes = changes(&vcd);
+        assert_eq!(&changes[..5], &["#0", "1!", "0\"", "0#", "0$"]);
+        assert!(changes.windows(2).any(|w| w == ["#4", "1#"]));
+        assert!(changes.windows(3).any(|w| w == ["#10", "0\"", "0#"]));
+        assert!(changes.windows(3).any(|w| w == ["#15", "0!", "1$"]));
+    }
+}
@@ -0,0 +1,887 @@
+//! Back-annotation of post-synthesis delays onto an HBCN.
+//!
+//! After synthesis the netlist's delays are known from its SDF file. Back-annotation
+//! replaces the delay of every place of an HBCN with the smallest and largest delay of the
+//! netlist paths between the place's two circuit nodes, so that `analyse` can check the
+//! cycle time of the synthesised circuit.
+//!
+//! # Rails
+//!
+//! Paths start and end at the same netlist objects the SDC constraints of
+//! [`crate::constrain::sdc`] name:
+//!
+//! - A **port** starts paths at the top-level input ports matching its rails (`name_*`, and
+//!   for a bus bit `name_*[i]` and `name_ack`), and ends them at the matching output ports
+//!   and at the inputs of the port's instance (`inst:name`).
+//! - A **register** starts paths at the outputs of its cells (`name/*`) and ends them at
+//!   their inputs. Cells of a register nested inside it, such as the stage `name/s0`, are
+//!   that register's rather than its own.
+//!
+//! The SDF does not say which cells are sequential, so a register's outputs and inputs are
+//! taken to be the pins of its cells connected to logic outside the register. A path ends
+//! at the first rail input it reaches, and combinational loops are broken where a path
+//! would revisit a pin.
+//!
+//! # Timing reports
+//!
+//! Alternatively, the delays can come from the synthesis tool's own timing reports. The
+//! Tcl script written by `constrain --report-tcl` reports the late and early path of
+//! every place in Genus, and [`apply_timing_report`] gives each place the delays of its
+//! paths (see [`report`]). Given the constrained HBCN written by `constrain --json`, whose
+//! place delays are the budgets of the SDC constraints, this also finds the places that
+//! violated their budget.
+//!
+//! # Simulation
+//!
+//! The delays can also be measured in a gate-level simulation of the netlist. Given its
+//! VCD, [`Firings`] finds when each transition fired by the same rail naming (see
+//! [`vcd`]), and [`apply_firings`] gives each place the time its target took to follow its
+//! source. Checked against the budgets of the constrained HBCN, this also finds the places
+//! that fell behind the constrained schedule, and the waveform gives the circuit's
+//! steady-state cycle time without any timing analysis.
+//!
+//! # Workflow
+//!
+//! 1. Parse the HBCN and the SDF file (see [`sdf`])
+//! 2. Time the paths leaving each circuit node of the HBCN, once per node
+//! 3. Give each place the `(min, max)` delay of the paths between its nodes, rounded to
+//!    the femtosecond
+//! 4. Write the characterised HBCN, keeping the delay of places without any netlist path
+//!
+//! # Example
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hbcn::backannotate::{BackannotateArgs, backannotate_main};
+//!
+//! let args = BackannotateArgs {
+//!     input: "circuit.hbcn".into(),
+//!     sdf: Some("circuit.sdf".into()),
+//!     timing_report: None,
+//!     vcd: None,
+//!     vcd_scope: None,
+//!     output: "characterised.hbcn".into(),
+//! };
+//!
+//! backannotate_main(args)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use petgraph::stable_graph::EdgeIndex;
+use prettytable::*;
+
+use crate::{
+    analyse::hbcn::{Engine, compute_cycle_time_with},
+    constrain::sdc::{port_instance, port_wildcard},
+    diagnostic::Diagnostic,
+    hbcn::{
+        CircuitNode, DelayPair, DelayedPlace, HBCN, Transition,
+        parser::parse_hbcn_with_declarations,
+        petri,
+        serialisation::{serialise_hbcn_with_declarations, write_transition},
+    },
+    json::{self, GraphKind},
+};
+
+pub mod report;
+pub mod sdf;
+pub mod vcd;
+
+use report::TimingReport;
+use sdf::{ArcDelay, TimingGraph};
+use vcd::Firings;
+
+/// Command-line arguments for the backannotate command.
+#[derive(Parser, Debug)]
+pub struct BackannotateArgs {
+    /// HBCN input file, or the JSON output of constrain to check the places' budgets
+    pub input: PathBuf,
+
+    /// SDF file of the synthesised netlist
+    #[clap(
+        long,
+        required_unless_present_any = ["timing_report", "vcd"],
+        conflicts_with_all = ["timing_report", "vcd"]
+    )]
+    pub sdf: Option<PathBuf>,
+
+    /// Timing report of the script written by `constrain --report-tcl`
+    #[clap(long, conflicts_with = "vcd")]
+    pub timing_report: Option<PathBuf>,
+
+    /// VCD file of a gate-level simulation of the synthesised netlist
+    #[clap(long)]
+    pub vcd: Option<PathBuf>,
+
+    /// Scope of the design in the VCD, as dot-separated instance names (default: the top
+    /// scope)
+    #[clap(long, requires = "vcd")]
+    pub vcd_scope: Option<String>,
+
+    /// Characterised HBCN output file
+    #[clap(short, long)]
+    pub output: PathBuf,
+}
+
+/// Outcome of [`backannotate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    /// Number of places given the delay of their netlist paths.
+    pub annotated: usize,
+    /// Places without any netlist path between their nodes, whose delay was kept.
+    pub unmatched: Vec<EdgeIndex>,
+}
+
+/// Replace the delay of every place of `hbcn` with the `(min, max)` delay of the netlist
+/// paths between its nodes.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::{backannotate, sdf::parse_sdf};
+/// use hbcn::hbcn::parser::parse_hbcn;
+///
+/// let mut hbcn = parse_hbcn(r#"
+///     port {a} input
+///     port {b} output
+///     * +{a} => +{r} : 10
+///     +{r} => -{a} : 10
+///     -{a} => -{r} : 10
+///     -{r} => +{a} : 10
+///     +{r} => +{b} : 10
+///     +{b} => -{r} : 10
+///     -{r} => -{b} : 10
+///     * -{b} => +{r} : 10
+/// "#).unwrap();
+///
+/// // a_t -> r/u1 -> b_t, with r/u1 acknowledging a through a_ack
+/// let sdf = parse_sdf(r#"
+///     (DELAYFILE (TIMESCALE 1ns)
+///       (CELL (CELLTYPE "top") (INSTANCE)
+///         (DELAY (ABSOLUTE
+///           (INTERCONNECT a_t r/u1/D (0.1:0.1:0.2))
+///           (INTERCONNECT r/u1/Q b_t (0.3))
+///           (INTERCONNECT r/u1/Q a_ack (0.4)))))
+///       (CELL (CELLTYPE "LATCH") (INSTANCE r/u1)
+///         (DELAY (ABSOLUTE (IOPATH D Q (1.0:1.5:2.0))))))
+/// "#).unwrap();
+///
+/// let annotation = backannotate(&mut hbcn, &sdf);
+/// assert_eq!(annotation.annotated, 6);
+/// // The paths from r to a (the acknowledge) and from a to r
+/// let delays: Vec<_> = hbcn.edge_indices().map(|ie| hbcn[ie].delay.max).collect();
+/// assert_eq!(&delays[..4], &[0.2, 0.4, 0.2, 0.4]);
+/// // b drives no netlist path back to r
+/// assert_eq!(annotation.unmatched.len(), 2);
+/// ```
+pub fn backannotate(hbcn: &mut HBCN<Transition, DelayedPlace>, sdf: &TimingGraph) -> Annotation {
+    let groups = hbcn
+        .node_indices()
+        .map(|ni| match AsRef::<CircuitNode>::as_ref(&hbcn[ni]) {
+            CircuitNode::Port(name) => port_instance(name),
+            CircuitNode::Register(name) => name.to_string(),
+        })
+        .collect();
+    let rails = Rails::new(sdf, &groups);
+
+    // The nodes each place's paths end at, grouped by where they start
+    let mut places: HashMap<&CircuitNode, Vec<(EdgeIndex, &CircuitNode)>> = HashMap::new();
+    for ie in hbcn.edge_indices() {
+        let (source, target) = hbcn.edge_endpoints(ie).unwrap();
+        let source: &CircuitNode = hbcn[source].as_ref();
+        let target: &CircuitNode = hbcn[target].as_ref();
+        places.entry(source).or_default().push((ie, target));
+    }
+
+    let mut ends: HashMap<&CircuitNode, Vec<usize>> = HashMap::new();
+    for (_, target) in places.values().flatten() {
+        ends.entry(target).or_insert_with(|| rails.ends(target));
+    }
+    let endpoints: HashSet<usize> = ends.values().flatten().copied().collect();
+
+    let mut delays: Vec<(EdgeIndex, Option<ArcDelay>)> = Vec::new();
+    for (source, places) in &places {
+        let arrival = arrival_times(sdf, &rails.starts(source), &endpoints);
+        for (ie, target) in places {
+            let delay = ends[target]
+                .iter()
+                .filter_map(|pin| arrival.get(pin))
+                .copied()
+                .reduce(ArcDelay::merge);
+            delays.push((*ie, delay));
+        }
+    }
+
+    let mut annotation = Annotation::default();
+    delays.sort_by_key(|(ie, _)| *ie);
+    for (ie, delay) in delays {
+        match delay {
+            Some(delay) => {
+                // Sums of scaled SDF values pick up floating-point noise far below any
+                // SDF's resolution
+                let round = |value: f64| (value * 1e6).round() / 1e6;
+                hbcn[ie].delay = DelayPair::new(Some(round(delay.min)), round(delay.max));
+                annotation.annotated += 1;
+            }
+            None => annotation.unmatched.push(ie),
+        }
+    }
+    annotation
+}
+
+/// A place whose achieved delay falls outside its budget, see [`apply_timing_report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The place.
+    pub place: EdgeIndex,
+    /// The place's delay before annotation.
+    pub budget: DelayPair,
+}
+
+/// Achieved delays within this much of their budget meet it: the SDC gives budgets to
+/// the picosecond.
+const BUDGET_TOLERANCE: f64 = 5e-4;
+
+/// Replace the delay of every place of `hbcn` with the delays a timing report gives its
+/// paths, and list the places whose achieved delay is outside their previous delay.
+///
+/// A place keeps its `min` or `max` delay where the report has no early or late path for
+/// it; a place with neither is unmatched.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::{apply_timing_report, report::parse_timing_report};
+/// use hbcn::hbcn::parser::parse_hbcn;
+///
+/// let mut hbcn = parse_hbcn("
+///     * +{a} => +{b} : (0.5,1.0)
+///     +{b} => -{a} : 1.0
+///     -{a} => -{b} : (0.5,1.0)
+///     -{b} => +{a} : 1.0
+/// ").unwrap();
+/// let report = parse_timing_report("
+///     hbcn_place max +{a} => +{b}
+///               Data Path:-    1120
+///     hbcn_place min +{a} => +{b}
+///               Data Path:-     640
+///     hbcn_place max -{a} => -{b}
+///               Data Path:-     980
+/// ").unwrap();
+///
+/// let (annotation, violations) = apply_timing_report(&mut hbcn, &report);
+/// assert_eq!(annotation.annotated, 2);
+/// assert_eq!(annotation.unmatched.len(), 2);
+/// // +{a} => +{b} took 1.12 of its 1.0 budget
+/// assert_eq!(violations.len(), 1);
+/// assert_eq!(hbcn[violations[0].place].delay.max, 1.12);
+/// assert_eq!(violations[0].budget.max, 1.0);
+/// ```
+pub fn apply_timing_report(
+    hbcn: &mut HBCN<Transition, DelayedPlace>,
+    report: &TimingReport,
+) -> (Annotation, Vec<Violation>) {
+    let mut annotation = Annotation::default();
+    let mut violations = Vec::new();
+    let places: Vec<EdgeIndex> = hbcn.edge_indices().collect();
+    for ie in places {
+        let (source, target) = hbcn.edge_endpoints(ie).unwrap();
+        let reported = report
+            .delay(&hbcn[source], &hbcn[target])
+            .filter(|delay| delay.min.is_some() || delay.max.is_some());
+        let Some(reported) = reported else {
+            annotation.unmatched.push(ie);
+            continue;
+        };
+
+        let budget = hbcn[ie].delay.clone();
+        let achieved = DelayPair::new(
+            reported.min.or(budget.min),
+            reported.max.unwrap_or(budget.max),
+        );
+        if violates(&budget, &achieved) {
+            violations.push(Violation { place: ie, budget });
+        }
+        hbcn[ie].delay = achieved;
+        annotation.annotated += 1;
+    }
+    (annotation, violations)
+}
+
+/// Replace the delay of every place of `hbcn` with the separations its transitions fired
+/// at in a simulation, and list the places whose measured delay is outside their previous
+/// delay.
+///
+/// A place's measured delay is the `(min, max)` time from each firing of its source to the
+/// next firing of its target (see [`Firings::separation`]). Like the delay of a place of a
+/// solved HBCN, it includes any wait for the target's other places, so it is compared with
+/// the budgets of the constrained HBCN written by `constrain --json`. Places whose target
+/// never followed their source are unmatched and keep their delay.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::{apply_firings, vcd::{Firings, parse_vcd}};
+/// use hbcn::hbcn::parser::parse_hbcn;
+///
+/// let mut hbcn = parse_hbcn("
+///     * +{a} => +{b} : (0.5,1.0)
+///     +{b} => -{a} : 1.0
+///     -{a} => -{b} : (0.5,1.0)
+///     -{b} => +{a} : 1.0
+/// ").unwrap();
+/// let waveform = parse_vcd(b"
+/// $timescale 100ps $end
+/// $scope module top $end
+/// $var wire 1 ! a/q $end
+/// $var wire 1 \" b/q $end
+/// $upscope $end
+/// $enddefinitions $end
+/// #0 0! 0\"
+/// #10 1!
+/// #22 1\"
+/// #30 0!
+/// #36 0\"
+/// ", None).unwrap();
+///
+/// let firings = Firings::new(&hbcn, &waveform);
+/// let (annotation, violations) = apply_firings(&mut hbcn, &firings);
+/// // b never fired again after -{b}, so -{b} => +{a} is unmatched
+/// assert_eq!(annotation.annotated, 3);
+/// // +{a} => +{b} took 1.2 of its 1.0 budget
+/// assert_eq!(violations.len(), 1);
+/// assert_eq!(hbcn[violations[0].place].delay.max, 1.2);
+/// ```
+pub fn apply_firings(
+    hbcn: &mut HBCN<Transition, DelayedPlace>,
+    firings: &Firings,
+) -> (Annotation, Vec<Violation>) {
+    let mut annotation = Annotation::default();
+    let mut violations = Vec::new();
+    let places: Vec<EdgeIndex> = hbcn.edge_indices().collect();
+    for ie in places {
+        let Some(achieved) = firings.separation(hbcn, ie) else {
+            annotation.unmatched.push(ie);
+            continue;
+        };
+        let budget = hbcn[ie].delay.clone();
+        if violates(&budget, &achieved) {
+            violations.push(Violation { place: ie, budget });
+        }
+        hbcn[ie].delay = achieved;
+        annotation.annotated += 1;
+    }
+    (annotation, violations)
+}
+
+/// Whether an achieved delay is outside its budget.
+fn violates(budget: &DelayPair, achieved: &DelayPair) -> bool {
+    let too_fast = match (budget.min, achieved.min) {
+        (Some(budget), Some(achieved)) => achieved < budget - BUDGET_TOLERANCE,
+        _ => false,
+    };
+    too_fast || achieved.max > budget.max + BUDGET_TOLERANCE
+}
+
+/// Where the paths of each circuit node start and end in a timing graph.
+struct Rails<'a> {
+    sdf: &'a TimingGraph,
+    /// Top-level ports.
+    ports: Vec<usize>,
+    /// The cell pins of each group of cells, by group.
+    groups: HashMap<&'a str, Vec<usize>>,
+    /// The group each cell pin belongs to, if any.
+    owner: Vec<Option<&'a str>>,
+}
+
+impl<'a> Rails<'a> {
+    /// Rails of a timing graph, where the cells of each of `groups` are those below it in
+    /// the hierarchy that are not below a nested group.
+    fn new(sdf: &'a TimingGraph, groups: &HashSet<String>) -> Self {
+        let mut ports = Vec::new();
+        let mut owner = Vec::with_capacity(sdf.pin_count());
+        let mut members: HashMap<&str, Vec<usize>> = HashMap::new();
+        for pin in 0..sdf.pin_count() {
+            let Some(instance) = sdf.instance(pin) else {
+                ports.push(pin);
+                owner.push(None);
+                continue;
+            };
+            // The innermost group wins, so `r/s0/u1` belongs to `r/s0` rather than `r`
+            let group = std::iter::once(instance)
+                .chain(instance.rmatch_indices('/').map(|(i, _)| &instance[..i]))
+                .find(|ancestor| groups.contains(*ancestor));
+            if let Some(group) = group {
+                members.entry(group).or_default().push(pin);
+            }
+            owner.push(group);
+        }
+        Self {
+            sdf,
+            ports,
+            groups: members,
+            owner,
+        }
+    }
+
+    /// Pins of the cells of `group` that drive pins outside it.
+    fn group_outputs(&self, group: &str) -> Vec<usize> {
+        let pins = self.groups.get(group).map_or(&[][..], Vec::as_slice);
+        pins.iter()
+            .copied()
+            .filter(|&pin| {
+                self.sdf
+                    .arcs(pin)
+                    .iter()
+                    .any(|&(to, _)| self.owner[to] != Some(group))
+            })
+            .collect()
+    }
+
+    /// Pins of the cells of `group` driven from outside it.
+    fn group_inputs(&self, group: &str) -> Vec<usize> {
+        let pins = self.groups.get(group).map_or(&[][..], Vec::as_slice);
+        pins.iter()
+            .copied()
+            .filter(|&pin| {
+                self.sdf
+                    .drivers(pin)
+                    .iter()
+                    .any(|&from| self.owner[from] != Some(group))
+            })
+            .collect()
+    }
+
+    /// Top-level ports matching the rails of an HBCN port, keeping those that drive arcs
+    /// (`drives`) or receive them.
+    fn ports(&self, name: &str, drives: bool) -> Vec<usize> {
+        let patterns = port_wildcard(name);
+        self.ports
+            .iter()
+            .copied()
+            .filter(|&pin| {
+                let connected = if drives {
+                    !self.sdf.arcs(pin).is_empty()
+                } else {
+                    !self.sdf.drivers(pin).is_empty()
+                };
+                connected
+                    && patterns
+                        .split_whitespace()
+                        .any(|pattern| glob_match(pattern, self.sdf.name(pin)))
+            })
+            .collect()
+    }
+
+    /// Pins at which the paths of `node` start.
+    fn starts(&self, node: &CircuitNode) -> Vec<usize> {
+        match node {
+            CircuitNode::Port(name) => self.ports(name, true),
+            CircuitNode::Register(name) => self.group_outputs(name),
+        }
+    }
+
+    /// Pins at which the paths into `node` end.
+    fn ends(&self, node: &CircuitNode) -> Vec<usize> {
+        match node {
+            CircuitNode::Port(name) => {
+                let mut pins = self.ports(name, false);
+                pins.extend(self.group_inputs(&port_instance(name)));
+                pins
+            }
+            CircuitNode::Register(name) => self.group_inputs(name),
+        }
+    }
+}
+
+/// Earliest and latest arrival at each pin reachable from `starts`, not continuing past
+/// `endpoints` and ignoring arcs that close a loop.
+fn arrival_times(
+    sdf: &TimingGraph,
+    starts: &[usize],
+    endpoints: &HashSet<usize>,
+) -> HashMap<usize, ArcDelay> {
+    // Paths end at the first endpoint they reach, unless it is also where they start
+    let starts_at: HashSet<usize> = starts.iter().copied().collect();
+    let ends_at = |pin: &usize| endpoints.contains(pin) && !starts_at.contains(pin);
+
+    // Depth-first search for the pins reachable from the starts, in post-order; an arc to
+    // a pin still on the stack closes a loop and is not followed
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut on_stack = HashSet::new();
+    let mut loops: HashSet<(usize, usize)> = HashSet::new();
+    for &start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        on_stack.insert(start);
+        let mut stack = vec![(start, 0)];
+        while let Some((pin, next)) = stack.last_mut() {
+            let pin = *pin;
+            let arcs = if ends_at(&pin) {
+                &[][..]
+            } else {
+                sdf.arcs(pin)
+            };
+            match arcs.get(*next) {
+                Some(&(to, _)) => {
+                    *next += 1;
+                    if on_stack.contains(&to) {
+                        loops.insert((pin, to));
+                    } else if visited.insert(to) {
+                        on_stack.insert(to);
+                        stack.push((to, 0));
+                    }
+                }
+                None => {
+                    on_stack.remove(&pin);
+                    order.push(pin);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    let mut arrival: HashMap<usize, ArcDelay> = starts
+        .iter()
+        .map(|&pin| (pin, ArcDelay { min: 0.0, max: 0.0 }))
+        .collect();
+    for &pin in order.iter().rev() {
+        if ends_at(&pin) {
+            continue;
+        }
+        let Some(&at) = arrival.get(&pin) else {
+            continue;
+        };
+        for &(to, delay) in sdf.arcs(pin) {
+            if loops.contains(&(pin, to)) {
+                continue;
+            }
+            let reached = ArcDelay {
+                min: at.min + delay.min,
+                max: at.max + delay.max,
+            };
+            arrival
+                .entry(to)
+                .and_modify(|a| *a = a.merge(reached))
+                .or_insert(reached);
+        }
+    }
+    arrival
+}
+
+/// Match `text` against a pattern where `*` stands for any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Back-annotate an HBCN file with the delays of an SDF file, a timing report or a
+/// simulation's VCD, and write the characterised HBCN.
+///
+/// The output keeps the input's node declarations, behind a `#` comment header naming the
+/// generator version and both input files. Places without any netlist path keep their
+/// delay and are reported on stderr.
+///
+/// With a timing report, the places whose achieved delay violates their budget are listed
+/// on stdout, followed by the cycle time of the achieved delays. With a VCD, the places
+/// whose measured delay violates their budget are listed likewise, followed by the measured
+/// steady-state cycle time and, for the JSON output of `constrain`, its cycle-time budget.
+pub fn backannotate_main(args: BackannotateArgs) -> Result<()> {
+    use crate::verbose::is_verbose;
+
+    let BackannotateArgs {
+        input,
+        sdf,
+        timing_report,
+        vcd,
+        vcd_scope,
+        output,
+    } = args;
+
+    if is_verbose() {
+        eprintln!("Reading HBCN file: {:?}", input);
+    }
+    let text = fs::read_to_string(&input)?;
+    let with_file = |e: anyhow::Error| match e.downcast::<Diagnostic>() {
+        Ok(diag) => diag.with_file(&input).into(),
+        Err(e) => e,
+    };
+    let (mut hbcn, declarations) = if json::is_json(&text) {
+        json::hbcn_from_json_with_declarations(&text)?
+    } else if petri::is_stg(&text) {
+        (petri::parse_stg(&text).map_err(with_file)?, Vec::new())
+    } else {
+        parse_hbcn_with_declarations(&text).map_err(with_file)?
+    };
+    // The cycle time the places' budgets were constrained for
+    let budget_cycle_time =
+        if json::is_json(&text) && json::graph_kind(&text)? == GraphKind::SolvedHbcn {
+            json::solved_hbcn_from_json(&text)?.1.cycle_time
+        } else {
+            None
+        };
+
+    let (source, annotation, violations, measured) = match (sdf, timing_report, vcd) {
+        (Some(sdf), _, _) => {
+            if is_verbose() {
+                eprintln!("Reading SDF file: {:?}", sdf);
+            }
+            let sdf_text =
+                fs::read_to_string(&sdf).with_context(|| format!("Failed to read {:?}", sdf))?;
+            let timing =
+                sdf::parse_sdf(&sdf_text).map_err(|e| match e.downcast::<Diagnostic>() {
+                    Ok(diag) => diag.with_file(&sdf).into(),
+                    Err(e) => e,
+                })?;
+            let unapplied = timing.unapplied_wildcards();
+            if !unapplied.is_empty() {
+                eprintln!(
+                    "warning: {} wildcard cells of {} match no instance named in it and are skipped",
+                    unapplied.len(),
+                    sdf.display()
+                );
+                if is_verbose() {
+                    for cell_type in unapplied {
+                        eprintln!("  (INSTANCE *) of CELLTYPE {:?}", cell_type);
+                    }
+                }
+            }
+            let annotation = backannotate(&mut hbcn, &timing);
+            (sdf, annotation, None, None)
+        }
+        (None, Some(path), _) => {
+            if is_verbose() {
+                eprintln!("Reading timing report: {:?}", path);
+            }
+            let report_text =
+                fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            let report = report::parse_timing_report(&report_text)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            let (annotation, violations) = apply_timing_report(&mut hbcn, &report);
+            (path, annotation, Some(violations), None)
+        }
+        (None, None, Some(path)) => {
+            if is_verbose() {
+                eprintln!("Reading VCD file: {:?}", path);
+            }
+            let vcd_bytes =
+                fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            let waveform = vcd::parse_vcd(&vcd_bytes, vcd_scope.as_deref())
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            let firings = Firings::new(&hbcn, &waveform);
+            let (annotation, violations) = apply_firings(&mut hbcn, &firings);
+            (
+                path,
+                annotation,
+                Some(violations),
+                Some(firings.cycle_time()),
+            )
+        }
+        (None, None, None) => bail!("one of --sdf, --timing-report or --vcd is required"),
+    };
+
+    if is_verbose() {
+        eprintln!(
+            "Annotated {} of {} places",
+            annotation.annotated,
+            hbcn.edge_count()
+        );
+    }
+    if !annotation.unmatched.is_empty() {
+        eprintln!(
+            "warning: {} places have no path in {} and keep their delay",
+            annotation.unmatched.len(),
+            source.display()
+        );
+        if is_verbose() {
+            for &ie in &annotation.unmatched {
+                let (source, target) = hbcn.edge_endpoints(ie).unwrap();
+                eprintln!("  {} => {}", hbcn[source], hbcn[target]);
+            }
+        }
+    }
+
+    // Solved before writing anything, by the maximum cycle ratio so that no LP solver is
+    // needed
+    let achieved = match (&violations, measured) {
+        (Some(_), None) => {
+            if is_verbose() {
+                eprintln!("Computing achieved cycle time...");
+            }
+            Some(compute_cycle_time_with(&hbcn, true, Engine::Mcr, false)?.0)
+        }
+        _ => None,
+    };
+
+    let header = [
+        format!("Generated by hbcn {}", env!("CARGO_PKG_VERSION")),
+        format!("Source: {}", input.display()),
+        format!("Back-annotated from: {}", source.display()),
+    ];
+    fs::write(
+        &output,
+        serialise_hbcn_with_declarations(&hbcn, &header, &declarations),
+    )?;
+
+    if let Some(violations) = violations {
+        let mut table = Table::new();
+        table.set_titles(row!["Place", "Budget", "Achieved"]);
+        let range = |delay: &DelayPair| match delay.min {
+            Some(min) => format!("{:.3} .. {:.3}", min, delay.max),
+            None => format!("{:.3}", delay.max),
+        };
+        for violation in &violations {
+            let (source, target) = hbcn.edge_endpoints(violation.place).unwrap();
+            let mut place = String::new();
+            // Infallible for String
+            let _ = write_transition(&hbcn[source], &mut place);
+            place.push_str(" => ");
+            let _ = write_transition(&hbcn[target], &mut place);
+            table.add_row(row![
+                place,
+                range(&violation.budget),
+                range(&hbcn[violation.place].delay),
+            ]);
+        }
+        println!(
+            "{} of {} places violate their budget",
+            violations.len(),
+            hbcn.edge_count()
+        );
+        if !violations.is_empty() {
+            table.printstd();
+        }
+
+        match (measured, achieved) {
+            (Some(measured), _) => {
+                match measured {
+                    Some(cycle_time) => println!("Measured cycle-time: {}", cycle_time),
+                    None => println!("Measured cycle-time: unknown (too few waves)"),
+                }
+                if let Some(budget) = budget_cycle_time {
+                    println!("Cycle-time budget: {}", budget);
+                }
+            }
+            (None, Some(cycle_time)) => println!("Achieved cycle-time: {}", cycle_time),
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::parser::parse_hbcn;
+
+    /// Two registers in a ring: `r1` drives `r2` through a gate, and `r2` acknowledges
+    /// `r1` directly. `r1` also has an internal loop through its C-element.
+    const SDF: &str = r#"
+        (DELAYFILE (TIMESCALE 1ns)
+          (CELL (CELLTYPE "top") (INSTANCE)
+            (DELAY (ABSOLUTE
+              (INTERCONNECT r1/c/Q g1/A (0.1))
+              (INTERCONNECT r1/c/Q r1/c/B (0.1))
+              (INTERCONNECT g1/Y r2/c/A (0.2:0.2:0.3))
+              (INTERCONNECT r2/c/Q r1/c/A (0.5)))))
+          (CELL (CELLTYPE "C2") (INSTANCE r1/c)
+            (DELAY (ABSOLUTE (IOPATH A Q (1:1:2)) (IOPATH B Q (1:1:2)))))
+          (CELL (CELLTYPE "C2") (INSTANCE r2/c)
+            (DELAY (ABSOLUTE (IOPATH A Q (1:1:2)))))
+          (CELL (CELLTYPE "BUF") (INSTANCE g1)
+            (DELAY (ABSOLUTE (IOPATH A Y (2:3:4))))))
+    "#;
+
+    const HBCN: &str = "
+        * +{r1} => +{r2} : 1
+        +{r2} => -{r1} : 1
+        -{r1} => -{r2} : 1
+        -{r2} => +{r1} : 1
+    ";
+
+    #[test]
+    fn annotates_forward_and_acknowledge_places() {
+        let mut hbcn = parse_hbcn(HBCN).unwrap();
+        let annotation = backannotate(&mut hbcn, &sdf::parse_sdf(SDF).unwrap());
+
+        assert_eq!(annotation.annotated, 4);
+        assert!(annotation.unmatched.is_empty());
+        let delays: Vec<_> = hbcn
+            .edge_indices()
+            .map(|ie| {
+                let delay = &hbcn[ie].delay;
+                (delay.min.unwrap(), delay.max)
+            })
+            .collect();
+        // r1/c/Q -> g1 -> r2/c/A, stopping at r2's input; and r2/c/Q -> r1/c/A
+        assert_eq!(delays, [(2.3, 4.4), (0.5, 0.5), (2.3, 4.4), (0.5, 0.5)]);
+    }
+
+    #[test]
+    fn rails_follow_the_sdc_naming() {
+        let sdf = sdf::parse_sdf(
+            r#"(DELAYFILE (CELL (CELLTYPE "top") (INSTANCE)
+                (DELAY (ABSOLUTE
+                  (INTERCONNECT x_t\[0\] r/u/A (1))
+                  (INTERCONNECT x_f\[0\] r/u/B (1))
+                  (INTERCONNECT r/u/Y x_ack (1))
+                  (INTERCONNECT r/u/Y inst\:m/io/u/A (1))
+                  (INTERCONNECT xy_t r/u/C (1))))))"#,
+        )
+        .unwrap();
+        let groups = ["r", "inst:m/io"].map(String::from).into();
+        let rails = Rails::new(&sdf, &groups);
+        let names = |pins: Vec<usize>| -> Vec<&str> {
+            let mut names: Vec<&str> = pins.iter().map(|&pin| sdf.name(pin)).collect();
+            names.sort();
+            names
+        };
+
+        let bit = CircuitNode::Port("x[0]".into());
+        assert_eq!(names(rails.starts(&bit)), ["x_f[0]", "x_t[0]"]);
+        assert_eq!(names(rails.ends(&bit)), ["x_ack"]);
+        let sub_port = CircuitNode::Port("port:m/o".into());
+        assert_eq!(names(rails.ends(&sub_port)), ["inst:m/io/u/A"]);
+        let register = CircuitNode::Register("r".into());
+        assert_eq!(names(rails.starts(&register)), ["r/u/Y"]);
+        assert_eq!(names(rails.ends(&register)), ["r/u/A", "r/u/B", "r/u/C"]);
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("a_*", "a_t"));
+        assert!(glob_match("a_*[0]", "a_t[0]"));
+        assert!(!glob_match("a_*[0]", "a_t[1]"));
+        assert!(glob_match("a_ack", "a_ack"));
+        assert!(!glob_match("a_ack", "a_ack2"));
+        assert!(!glob_match("a_*", "ab_t"));
+        assert!(glob_match("*_t*", "a_t[0]"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Reader for the timing reports of the script written by
+//! [`crate::constrain::sdc::write_timing_report_script`].
+//!
+//! The script precedes each `report_timing` with a marker line naming the place it times
+//! and whether it is the late (`max`) or early (`min`) path:
+//!
+//! ```text
+//! hbcn_place max +{a} => +{b/s0}
+//! ```
+//!
+//! The achieved delay of a path is read from the first path of the report that follows
+//! its marker, in either of two layouts:
+//!
+//! - **Genus**: the `Data Path:-` line of the path summary, in picoseconds.
+//! - **OpenSTA**: the `data arrival time` line, in the library's time unit, taken to be
+//!   nanoseconds.
+//!
+//! Delays are kept in nanoseconds, the unit of the constraints. A marker followed by no
+//! path (such as Genus's `No paths found`) leaves that delay unknown; anything outside the
+//! markers, such as the tool's report banners, is ignored.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    Symbol,
+    hbcn::{Named, Transition, parser::unescape},
+};
+
+/// Smallest and largest achieved delay of a place, in nanoseconds, as far as they were
+/// reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReportedDelay {
+    /// Delay of the early path.
+    pub min: Option<f64>,
+    /// Delay of the late path.
+    pub max: Option<f64>,
+}
+
+/// A transition as named by a marker: its sign (`true` for data) and its node's name.
+type TransitionKey = (bool, Symbol);
+
+/// The achieved delays of the places of a timing report, by their transitions.
+#[derive(Debug, Clone, Default)]
+pub struct TimingReport {
+    places: HashMap<(TransitionKey, TransitionKey), ReportedDelay>,
+}
+
+impl TimingReport {
+    /// Number of places with a marker in the report.
+    pub fn len(&self) -> usize {
+        self.places.len()
+    }
+
+    /// Whether the report has no markers.
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+
+    /// The reported delay of the place from `source` to `target`, if it has a marker.
+    pub fn delay(&self, source: &Transition, target: &Transition) -> Option<ReportedDelay> {
+        self.places.get(&(key(source), key(target))).copied()
+    }
+}
+
+fn key(transition: &Transition) -> TransitionKey {
+    let data = matches!(transition, Transition::Data(_));
+    (data, transition.name().clone())
+}
+
+/// Parse a timing report written by the script of
+/// [`crate::constrain::sdc::write_timing_report_script`].
+///
+/// Fails if the report has no `hbcn_place` markers, as a report of any other script would
+/// not say which place each path belongs to.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::report::parse_timing_report;
+/// use hbcn::hbcn::{CircuitNode, Transition};
+///
+/// let report = parse_timing_report("
+/// hbcn_place max +{a} => +{b}
+/// Path 1: VIOLATED (-120 ps) Path Delay Check
+///           Data Path:-    1120
+/// hbcn_place min +{a} => +{b}
+/// No paths found.
+/// ").unwrap();
+///
+/// let a = Transition::Data(CircuitNode::Port("a".into()));
+/// let b = Transition::Data(CircuitNode::Register("b".into()));
+/// let delay = report.delay(&a, &b).unwrap();
+/// assert_eq!((delay.min, delay.max), (None, Some(1.12)));
+/// ```
+pub fn parse_timing_report(input: &str) -> Result<TimingReport> {
+    lazy_static! {
+        static ref MARKER_RE: Regex = Regex::new(
+            r"^\s*hbcn_place (max|min) ([+-])\{((?:[^{}\\]|\\.)*)\} => ([+-])\{((?:[^{}\\]|\\.)*)\}\s*$"
+        )
+        .unwrap();
+        static ref GENUS_RE: Regex =
+            Regex::new(r"^\s*Data Path:-\s*(-?[0-9]+(?:\.[0-9]*)?)\s*$").unwrap();
+        static ref OPENSTA_RE: Regex =
+            Regex::new(r"^\s*(-?[0-9]+(?:\.[0-9]*)?)\s+data arrival time\s*$").unwrap();
+    }
+
+    let mut report = TimingReport::default();
+    // The marker whose path is still to be read, with its check
+    let mut pending: Option<(bool, (TransitionKey, TransitionKey))> = None;
+
+    for line in input.lines() {
+        if let Some(c) = MARKER_RE.captures(line) {
+            let transition =
+                |sign: &str, name: &str| (sign == "+", Symbol::from(unescape(name).as_ref()));
+            let place = (transition(&c[2], &c[3]), transition(&c[4], &c[5]));
+            report.places.entry(place.clone()).or_default();
+            pending = Some((&c[1] == "max", place));
+            continue;
+        }
+
+        let delay = if let Some(c) = GENUS_RE.captures(line) {
+            c[1].parse::<f64>()? / 1000.0
+        } else if let Some(c) = OPENSTA_RE.captures(line) {
+            c[1].parse::<f64>()?
+        } else {
+            continue;
+        };
+        if let Some((max, place)) = pending.take() {
+            let reported = report.places.get_mut(&place).unwrap();
+            if max {
+                reported.max = Some(delay);
+            } else {
+                reported.min = Some(delay);
+            }
+        }
+    }
+
+    if report.is_empty() {
+        bail!(
+            "timing report has no hbcn_place markers; report the paths with the script \
+             written by `hbcn constrain --report-tcl`"
+        );
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::CircuitNode;
+
+    fn data(name: &str) -> Transition {
+        Transition::Data(CircuitNode::Register(name.into()))
+    }
+
+    fn spacer(name: &str) -> Transition {
+        Transition::Spacer(CircuitNode::Register(name.into()))
+    }
+
+    #[test]
+    fn reads_genus_and_opensta_paths() {
+        let report = parse_timing_report(
+            r"
+============================================================
+  Generated by:           Genus(TM) Synthesis Solution
+============================================================
+hbcn_place max +{r1} => +{r2}
+Path 1: MET (880 ps) Path Delay Check
+     Path Delay:+    2000
+          Data Path:-    1120
+              Slack:=     880
+hbcn_place min +{r1} => +{r2}
+          Data Path:-     740
+hbcn_place max -{r\{0\}} => +{r1}
+Startpoint: r{0}/c (rising edge-triggered flip-flop)
+   0.42   0.42 ^ r{0}/c/Q (C2)
+          1.25   data arrival time
+hbcn_place min -{r\{0\}} => +{r1}
+No paths found.
+",
+        )
+        .unwrap();
+
+        assert_eq!(report.len(), 2);
+        assert_eq!(
+            report.delay(&data("r1"), &data("r2")),
+            Some(ReportedDelay {
+                min: Some(0.74),
+                max: Some(1.12)
+            })
+        );
+        assert_eq!(
+            report.delay(&spacer("r{0}"), &data("r1")),
+            Some(ReportedDelay {
+                min: None,
+                max: Some(1.25)
+            })
+        );
+        assert_eq!(report.delay(&data("r2"), &data("r1")), None);
+    }
+
+    #[test]
+    fn rejects_reports_without_markers() {
+        let err = parse_timing_report("Path 1: MET\n  Data Path:- 10\n").unwrap_err();
+        assert!(err.to_string().contains("no hbcn_place markers"));
+    }
+}
@@ -0,0 +1,634 @@
+//! Reader for Standard Delay Format (SDF) files.
+//!
+//! Only the parts of SDF needed to time paths through a netlist are read: the `DIVIDER` and
+//! `TIMESCALE` of the header, and the `IOPATH` and `INTERCONNECT` delays of every `CELL`,
+//! whether or not under a `COND`. Everything else (timing checks, `PORT` and `DEVICE`
+//! delays, path pulse limits) is skipped.
+//!
+//! The delays become the arcs of a [`TimingGraph`] over the pins of the netlist, each with
+//! the smallest and largest delay the SDF gives it: a triple `min:typ:max` contributes its
+//! `min` and `max`, and the rise, fall and other transition values of an arc are merged,
+//! since the unateness of the cells is not known from the SDF alone. `ABSOLUTE` delays
+//! merge into those already read for the same arc, while `INCREMENT` delays are added to
+//! them, in the order of the file.
+//!
+//! A wildcard `(INSTANCE *)`, or `(INSTANCE r.*)` below an instance `r`, applies to every
+//! instance of its `CELLTYPE` that a `CELL` of the file names. A wildcard matching none
+//! applies to no pin, and its cell type is listed by
+//! [`TimingGraph::unapplied_wildcards`].
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::diagnostic::{Diagnostic, Span};
+
+/// Smallest and largest delay of a timing arc or path, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArcDelay {
+    /// The smallest delay.
+    pub min: f64,
+    /// The largest delay.
+    pub max: f64,
+}
+
+impl ArcDelay {
+    /// The range covering both `self` and `other`.
+    pub fn merge(self, other: ArcDelay) -> ArcDelay {
+        ArcDelay {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// The pins of a netlist and the timing arcs between them.
+///
+/// Pins are named by their full hierarchical path with `/` as the divider and any SDF
+/// escapes removed, e.g. `r/u1/Q`. Top-level ports have no divider in their names.
+#[derive(Debug, Default)]
+pub struct TimingGraph {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    arcs: Vec<Vec<(usize, ArcDelay)>>,
+    drivers: Vec<Vec<usize>>,
+    unapplied: Vec<String>,
+}
+
+impl TimingGraph {
+    /// Number of pins.
+    pub fn pin_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Full name of a pin.
+    pub fn name(&self, pin: usize) -> &str {
+        &self.names[pin]
+    }
+
+    /// The pin named `name`, if any arc starts or ends there.
+    pub fn pin(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Arcs leaving `pin`, with their target pins.
+    pub fn arcs(&self, pin: usize) -> &[(usize, ArcDelay)] {
+        &self.arcs[pin]
+    }
+
+    /// Pins with an arc ending at `pin`.
+    pub fn drivers(&self, pin: usize) -> &[usize] {
+        &self.drivers[pin]
+    }
+
+    /// Whether `pin` is a top-level port rather than a pin of a cell instance.
+    pub fn is_port(&self, pin: usize) -> bool {
+        !self.names[pin].contains('/')
+    }
+
+    /// The instance a cell pin belongs to, or `None` for a top-level port.
+    pub fn instance(&self, pin: usize) -> Option<&str> {
+        self.names[pin]
+            .rsplit_once('/')
+            .map(|(instance, _)| instance)
+    }
+
+    /// Cell types of the wildcard `CELL`s that match no instance named in the file, whose
+    /// delays are therefore not read.
+    pub fn unapplied_wildcards(&self) -> &[String] {
+        &self.unapplied
+    }
+
+    fn intern(&mut self, name: String) -> usize {
+        if let Some(&pin) = self.index.get(&name) {
+            return pin;
+        }
+        let pin = self.names.len();
+        self.index.insert(name.clone(), pin);
+        self.names.push(name);
+        self.arcs.push(Vec::new());
+        self.drivers.push(Vec::new());
+        pin
+    }
+
+    /// Add an arc, merging its delays into any arc already between the same pins, or with
+    /// `increment` adding them to it.
+    fn add_arc(&mut self, from: String, to: String, delay: ArcDelay, increment: bool) {
+        let from = self.intern(from);
+        let to = self.intern(to);
+        match self.arcs[from].iter_mut().find(|(target, _)| *target == to) {
+            Some((_, existing)) if increment => {
+                existing.min += delay.min;
+                existing.max += delay.max;
+            }
+            Some((_, existing)) => *existing = existing.merge(delay),
+            None => {
+                self.arcs[from].push((to, delay));
+                self.drivers[to].push(from);
+            }
+        }
+    }
+}
+
+/// An SDF s-expression, with the span of its text.
+#[derive(Debug)]
+enum Expr<'a> {
+    Atom(&'a str, Span),
+    List(Vec<Expr<'a>>, Span),
+}
+
+impl<'a> Expr<'a> {
+    fn span(&self) -> Span {
+        match self {
+            Expr::Atom(_, span) | Expr::List(_, span) => *span,
+        }
+    }
+
+    fn atom(&self) -> Option<&'a str> {
+        match self {
+            Expr::Atom(text, _) => Some(text),
+            Expr::List(..) => None,
+        }
+    }
+
+    /// The keyword and arguments of a list such as `(IOPATH A Y ...)`.
+    fn keyword(&self) -> Option<(&'a str, &[Expr<'a>])> {
+        match self {
+            Expr::List(items, _) => {
+                let (first, rest) = items.split_first()?;
+                Some((first.atom()?, rest))
+            }
+            Expr::Atom(..) => None,
+        }
+    }
+}
+
+/// Split an SDF file into s-expressions: parentheses, quoted strings and atoms. Backslash
+/// escapes a character inside an atom, so `a\(0\)` is one atom.
+fn read_exprs(input: &str) -> Result<Vec<Expr<'_>>, (String, Span)> {
+    let bytes = input.as_bytes();
+    let mut stack: Vec<(Vec<Expr>, usize)> = vec![(Vec::new(), 0)];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += input[i..].find('\n').unwrap_or(input.len() - i);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match input[i + 2..].find("*/") {
+                Some(end) => i += end + 4,
+                None => return Err(("unterminated comment".into(), Span::new(i, i + 2))),
+            },
+            b'(' => {
+                stack.push((Vec::new(), i));
+                i += 1;
+            }
+            b')' => {
+                if stack.len() == 1 {
+                    return Err(("unbalanced `)`".into(), Span::new(i, i + 1)));
+                }
+                let (items, open) = stack.pop().unwrap();
+                i += 1;
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .push(Expr::List(items, Span::new(open, i)));
+            }
+            b'"' => {
+                let end = input[i + 1..]
+                    .find('"')
+                    .ok_or_else(|| ("unterminated string".to_string(), Span::new(i, i + 1)))?;
+                i += end + 2;
+                let text = &input[start + 1..i - 1];
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .push(Expr::Atom(text, Span::new(start, i)));
+            }
+            _ => {
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 1 + input[i + 1..].chars().next().map_or(0, char::len_utf8),
+                        b'(' | b')' | b'"' => break,
+                        b if b.is_ascii_whitespace() => break,
+                        _ => i += 1,
+                    }
+                }
+                i = i.min(bytes.len());
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .push(Expr::Atom(&input[start..i], Span::new(start, i)));
+            }
+        }
+    }
+    match stack.pop() {
+        Some((items, _)) if stack.is_empty() => Ok(items),
+        Some((_, open)) => Err(("unclosed `(`".into(), Span::new(open, open + 1))),
+        None => unreachable!(),
+    }
+}
+
+/// Hierarchical naming of an SDF file.
+struct Names {
+    divider: char,
+}
+
+impl Names {
+    /// Remove escapes from an SDF identifier and use `/` as its hierarchy divider.
+    fn normalise(&self, name: &str) -> String {
+        let mut out = String::with_capacity(name.len());
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.extend(chars.next()),
+                c if c == self.divider => out.push('/'),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// Full name of `name` inside `instance`.
+    fn join(&self, instance: &str, name: &str) -> String {
+        let name = self.normalise(name);
+        if instance.is_empty() {
+            name
+        } else {
+            format!("{}/{}", instance, name)
+        }
+    }
+}
+
+/// Parse an SDF file into the timing graph of its netlist, with delays in nanoseconds.
+///
+/// Errors are reported as a [`Diagnostic`] pointing into `input`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::sdf::parse_sdf;
+///
+/// let graph = parse_sdf(r#"
+///     (DELAYFILE
+///       (SDFVERSION "3.0") (DIVIDER /) (TIMESCALE 1ps)
+///       (CELL (CELLTYPE "top") (INSTANCE)
+///         (DELAY (ABSOLUTE (INTERCONNECT a u1/A (10:12:15)))))
+///       (CELL (CELLTYPE "INV") (INSTANCE u1)
+///         (DELAY (ABSOLUTE (IOPATH A Y (20:25:30) (18:22:28))))))
+/// "#).unwrap();
+///
+/// let a = graph.pin("a").unwrap();
+/// let (input, delay) = graph.arcs(a)[0];
+/// assert_eq!(graph.name(input), "u1/A");
+/// assert_eq!((delay.min, delay.max), (0.01, 0.015));
+/// ```
+pub fn parse_sdf(input: &str) -> Result<TimingGraph> {
+    read_sdf(input).map_err(|(message, span)| {
+        Diagnostic::new(
+            input,
+            span,
+            format!("Failed to parse SDF input: {}", message),
+        )
+        .into()
+    })
+}
+
+fn read_sdf(input: &str) -> Result<TimingGraph, (String, Span)> {
+    let exprs = read_exprs(input)?;
+    let body = match exprs.as_slice() {
+        [file] => match file.keyword() {
+            Some(("DELAYFILE", body)) => body,
+            _ => return Err(("expected `(DELAYFILE ...)`".into(), file.span())),
+        },
+        [] => return Err(("empty SDF file".into(), Span::new(0, 0))),
+        [_, extra, ..] => return Err(("extra input after DELAYFILE".into(), extra.span())),
+    };
+
+    let mut names = Names { divider: '/' };
+    let mut scale = 1.0;
+    for entry in body {
+        match entry.keyword() {
+            Some(("DIVIDER", [divider])) => {
+                let mut chars = divider.atom().unwrap_or_default().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c @ ('/' | '.')), None) => names.divider = c,
+                    _ => return Err(("DIVIDER must be `/` or `.`".into(), divider.span())),
+                }
+            }
+            Some(("TIMESCALE", value)) => scale = timescale(value, entry.span())?,
+            _ => {}
+        }
+    }
+
+    let cells: Vec<(&str, Instance, &[Expr])> = body
+        .iter()
+        .filter_map(|cell| match cell.keyword() {
+            Some(("CELL", items)) => Some((cell_type(items), instance(items, &names)?, items)),
+            _ => None,
+        })
+        .collect();
+    // The instances of each cell type, in the order of the file
+    let mut instances: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (cell_type, instance, _) in &cells {
+        if let Instance::Named(name) = instance {
+            let list = instances.entry(cell_type).or_default();
+            if !list.contains(&name.as_str()) {
+                list.push(name);
+            }
+        }
+    }
+
+    let mut graph = TimingGraph::default();
+    for (cell_type, instance, items) in &cells {
+        let targets: Vec<&str> = match instance {
+            Instance::Named(name) => vec![name],
+            Instance::Wildcard(scope) => instances
+                .get(cell_type)
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|name| name.starts_with(scope.as_str()))
+                .collect(),
+        };
+        if targets.is_empty() {
+            graph.unapplied.push(cell_type.to_string());
+        }
+        for target in targets {
+            for item in *items {
+                if let Some(("DELAY", delays)) = item.keyword() {
+                    read_delays(delays, target, &names, scale, false, &mut graph)?;
+                }
+            }
+        }
+    }
+    Ok(graph)
+}
+
+/// The instance of a `CELL`.
+enum Instance {
+    /// A named instance, or the top level with an empty name.
+    Named(String),
+    /// Every instance of the cell type below a scope: `r/` for `r.*`, empty for `*`.
+    Wildcard(String),
+}
+
+/// The `CELLTYPE` of a `CELL`, empty if none is given.
+fn cell_type<'a>(items: &[Expr<'a>]) -> &'a str {
+    items
+        .iter()
+        .find_map(|item| match item.keyword() {
+            Some(("CELLTYPE", [name])) => name.atom(),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// The `INSTANCE` of a `CELL`, or `None` if it has none the reader understands.
+fn instance(items: &[Expr], names: &Names) -> Option<Instance> {
+    items.iter().find_map(|item| match item.keyword() {
+        Some(("INSTANCE", [])) => Some(Instance::Named(String::new())),
+        Some(("INSTANCE", [name])) => {
+            let name = name.atom()?;
+            let scope = if name == "*" {
+                Some("")
+            } else {
+                name.strip_suffix('*')
+                    .filter(|scope| scope.ends_with(names.divider))
+            };
+            Some(match scope {
+                Some(scope) => Instance::Wildcard(names.normalise(scope)),
+                None => Instance::Named(names.normalise(name)),
+            })
+        }
+        _ => None,
+    })
+}
+
+/// Nanoseconds per unit of a `TIMESCALE`, such as `1ns`, `100 ps` or `1.0 us`.
+fn timescale(value: &[Expr], span: Span) -> Result<f64, (String, Span)> {
+    let text: String = value.iter().filter_map(Expr::atom).collect();
+    let split = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let unit = match unit {
+        "s" => 1e9,
+        "ms" => 1e6,
+        "us" => 1e3,
+        "ns" => 1.0,
+        "ps" => 1e-3,
+        "fs" => 1e-6,
+        _ => return Err((format!("unknown TIMESCALE unit `{}`", unit), span)),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 => Ok(number * unit),
+        _ => Err((format!("invalid TIMESCALE `{}`", text), span)),
+    }
+}
+
+/// Read the arcs of a cell's `DELAY` entries, adding them to the arcs read before if
+/// `increment`.
+fn read_delays(
+    delays: &[Expr],
+    instance: &str,
+    names: &Names,
+    scale: f64,
+    increment: bool,
+    graph: &mut TimingGraph,
+) -> Result<(), (String, Span)> {
+    for expr in delays {
+        match expr.keyword() {
+            Some(("ABSOLUTE", entries)) => {
+                read_delays(entries, instance, names, scale, false, graph)?
+            }
+            Some(("INCREMENT", entries)) => {
+                read_delays(entries, instance, names, scale, true, graph)?
+            }
+            // (COND expr (IOPATH ...)), with an optional condition name before the expression
+            Some(("COND" | "CONDELSE", args)) => {
+                read_delays(args, instance, names, scale, increment, graph)?
+            }
+            Some(("IOPATH" | "INTERCONNECT", [from, to, values @ ..])) => {
+                let delay = delay_range(values, scale)?;
+                graph.add_arc(
+                    names.join(instance, port_name(from)?),
+                    names.join(instance, port_name(to)?),
+                    delay,
+                    increment,
+                );
+            }
+            Some((keyword @ ("IOPATH" | "INTERCONNECT"), _)) => {
+                return Err((format!("{} needs two ports", keyword), expr.span()));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The port of a port specification, which may carry an edge: `A` or `(posedge CK)`.
+fn port_name<'a>(spec: &Expr<'a>) -> Result<&'a str, (String, Span)> {
+    match spec {
+        Expr::Atom(name, _) => Ok(name),
+        Expr::List(items, span) => items
+            .last()
+            .and_then(Expr::atom)
+            .ok_or_else(|| ("expected a port name".into(), *span)),
+    }
+}
+
+/// The smallest and largest delay among the values of an arc. An arc without any value
+/// (`()`) has no delay.
+fn delay_range(values: &[Expr], scale: f64) -> Result<ArcDelay, (String, Span)> {
+    let mut range: Option<ArcDelay> = None;
+    let mut visit = |atom: &str, span: Span| -> Result<(), (String, Span)> {
+        let parts = atom
+            .split(':')
+            .map(|part| {
+                (!part.is_empty())
+                    .then(|| part.parse::<f64>())
+                    .transpose()
+                    .map_err(|_| (format!("invalid delay value `{}`", atom), span))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let present: Vec<f64> = parts.iter().flatten().copied().collect();
+        if parts.len() > 3 || present.is_empty() {
+            return Err((format!("invalid delay value `{}`", atom), span));
+        }
+        // The first and last values of a triple are its min and max; a missing one falls
+        // back to the values that are given
+        let min = parts[0].unwrap_or_else(|| present.iter().copied().fold(f64::MAX, f64::min));
+        let max = parts[parts.len() - 1]
+            .unwrap_or_else(|| present.iter().copied().fold(f64::MIN, f64::max));
+        let delay = ArcDelay {
+            min: min * scale,
+            max: max * scale,
+        };
+        range = Some(range.map_or(delay, |range| range.merge(delay)));
+        Ok(())
+    };
+
+    fn walk(
+        expr: &Expr,
+        visit: &mut impl FnMut(&str, Span) -> Result<(), (String, Span)>,
+    ) -> Result<(), (String, Span)> {
+        match expr {
+            Expr::Atom(atom, span) => visit(atom, *span),
+            Expr::List(items, _) => items.iter().try_for_each(|item| walk(item, visit)),
+        }
+    }
+    for value in values {
+        walk(value, &mut visit)?;
+    }
+
+    Ok(range.unwrap_or(ArcDelay { min: 0.0, max: 0.0 }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDF: &str = r#"
+        (DELAYFILE
+          (SDFVERSION "OVI 3.0")
+          (DESIGN "top")
+          (DIVIDER .)
+          (TIMESCALE 100 ps)
+          (CELL (CELLTYPE "top") (INSTANCE)
+            (DELAY (ABSOLUTE
+              (INTERCONNECT a\[0\] r.u1.A (1:2:3))
+              (INTERCONNECT r.u1.Y b (0.5)))))
+          // Conditional arcs merge into one
+          (CELL (CELLTYPE "AO22") (INSTANCE r.u1)
+            (DELAY (ABSOLUTE
+              (COND A==1 (IOPATH A Y (2:3:4) (1:2:5)))
+              (IOPATH (posedge A) Y (::6) ()))))
+          (CELL (CELLTYPE "DFF") (INSTANCE *)
+            (DELAY (ABSOLUTE (IOPATH CK Q (1)))))
+          (CELL (CELLTYPE "DFF") (INSTANCE r.u2)
+            (TIMINGCHECK (SETUP D (posedge CK) (1)))))
+    "#;
+
+    #[test]
+    fn reads_arcs_with_merged_delays() {
+        let graph = parse_sdf(SDF).unwrap();
+        let arc = |from: &str| {
+            let (to, delay) = graph.arcs(graph.pin(from).unwrap())[0];
+            let round = |value: f64| (value * 1e6).round() / 1e6;
+            (
+                graph.name(to).to_string(),
+                round(delay.min),
+                round(delay.max),
+            )
+        };
+
+        assert_eq!(arc("a[0]"), ("r/u1/A".to_string(), 0.1, 0.3));
+        assert_eq!(arc("r/u1/A"), ("r/u1/Y".to_string(), 0.1, 0.6));
+        assert_eq!(arc("r/u1/Y"), ("b".to_string(), 0.05, 0.05));
+        assert_eq!(graph.arcs(graph.pin("r/u1/A").unwrap()).len(), 1);
+        // The DFF wildcard times the DFF instance named by the file
+        assert_eq!(arc("r/u2/CK"), ("r/u2/Q".to_string(), 0.1, 0.1));
+        assert_eq!(graph.pin_count(), 6);
+        assert!(graph.unapplied_wildcards().is_empty());
+
+        let port = graph.pin("a[0]").unwrap();
+        assert!(graph.is_port(port));
+        assert_eq!(graph.instance(graph.pin("r/u1/Y").unwrap()), Some("r/u1"));
+        assert_eq!(
+            graph.drivers(graph.pin("r/u1/Y").unwrap()),
+            [graph.pin("r/u1/A").unwrap()]
+        );
+    }
+
+    #[test]
+    fn adds_increments_and_scopes_wildcards() {
+        let graph = parse_sdf(
+            r#"
+            (DELAYFILE (DIVIDER .) (TIMESCALE 1ns)
+              (CELL (CELLTYPE "INV") (INSTANCE r.*) (DELAY (ABSOLUTE (IOPATH A Y (1:2:3)))))
+              (CELL (CELLTYPE "INV") (INSTANCE r.u1) (DELAY (INCREMENT (IOPATH A Y (0.5::1.5)))))
+              (CELL (CELLTYPE "INV") (INSTANCE r.u2))
+              (CELL (CELLTYPE "INV") (INSTANCE s.u1))
+              (CELL (CELLTYPE "NAND2") (INSTANCE *) (DELAY (ABSOLUTE (IOPATH A Y (1))))))
+        "#,
+        )
+        .unwrap();
+        let delay = |pin: &str| graph.arcs(graph.pin(pin).unwrap())[0].1;
+
+        // The increment adds to the delay the wildcard gave r.u1 first
+        assert_eq!(delay("r/u1/A"), ArcDelay { min: 1.5, max: 4.5 });
+        assert_eq!(delay("r/u2/A"), ArcDelay { min: 1.0, max: 3.0 });
+        // s.u1 is outside the wildcard's scope, and no NAND2 is named
+        assert_eq!(graph.pin("s/u1/A"), None);
+        assert_eq!(graph.unapplied_wildcards(), ["NAND2"]);
+    }
+
+    #[test]
+    fn reports_malformed_files() {
+        for (input, message) in [
+            ("(DELAYFILE (CELL (INSTANCE u) (DELAY", "unclosed `(`"),
+            ("(DELAYFILE))", "unbalanced `)`"),
+            ("(CELL)", "expected `(DELAYFILE ...)`"),
+            (
+                "(DELAYFILE (TIMESCALE 1 min))",
+                "unknown TIMESCALE unit `min`",
+            ),
+            (
+                "(DELAYFILE (CELL (INSTANCE u) (DELAY (ABSOLUTE (IOPATH A Y (1:x:2))))))",
+                "invalid delay value `1:x:2`",
+            ),
+            (
+                "(DELAYFILE (CELL (INSTANCE u) (DELAY (ABSOLUTE (IOPATH A)))))",
+                "IOPATH needs two ports",
+            ),
+        ] {
+            let error = parse_sdf(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", input, error);
+        }
+    }
+}
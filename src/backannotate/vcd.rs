@@ -0,0 +1,502 @@
+//! Reader for the waveforms of a gate-level simulation, in VCD.
+//!
+//! A simulation of the synthesised netlist shows when each circuit node of the HBCN
+//! actually fired. [`parse_vcd`] reads the one-bit signals of a VCD file below the design's
+//! scope, and [`Firings`] maps them onto the transitions of an HBCN by the rail naming of
+//! the SDC constraints of [`crate::constrain::sdc`]:
+//!
+//! - A **port**'s rails are the signals matching `name_*` (for a bus bit `name_*[i]`),
+//!   other than its acknowledge `name_ack`.
+//! - A **register**'s rails are the signals of its cells (`name/*`). Signals of a register
+//!   nested inside it, such as the stage `name/s0`, are that register's rather than its own.
+//!
+//! Signal names are the scopes below the design's scope and the signal's reference, joined
+//! by `/`, so a netlist dumped hierarchically (`a` › `c` › `Q`) and one flattened into
+//! escaped names (`\a/c/Q`) both name the signal `a/c/Q`.
+//!
+//! As in the SDC, data rises the rails and the spacer lowers them. A node's data transition
+//! fires at the last rise of each wave, once no more rails rise before they start to fall,
+//! and its spacer transition at the fall that leaves all its rails low. Values at time 0 are
+//! the reset state and fire nothing; `x` and `z` read as low. Times are converted to
+//! nanoseconds using the VCD `$timescale`.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result, bail};
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use vcd::{Command, IdCode, ReferenceIndex, ScopeItem, Value};
+
+use super::glob_match;
+use crate::{
+    constrain::sdc::port_wildcard,
+    hbcn::{CircuitNode, DelayPair, HBCN, Transition},
+};
+
+/// The one-bit signals of a simulation and their value changes.
+#[derive(Debug, Clone, Default)]
+pub struct Waveform {
+    signals: Vec<Signal>,
+}
+
+#[derive(Debug, Clone)]
+struct Signal {
+    name: String,
+    /// Value in the reset state.
+    initial: bool,
+    /// Times in nanoseconds at which the value changes, and the value it changes to.
+    changes: Vec<(f64, bool)>,
+}
+
+impl Waveform {
+    /// Number of one-bit signals, counting each bit of a vector.
+    pub fn len(&self) -> usize {
+        self.signals.len()
+    }
+
+    /// Whether the waveform has no signals.
+    pub fn is_empty(&self) -> bool {
+        self.signals.is_empty()
+    }
+
+    /// Names of the signals, in the order they are declared.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.signals.iter().map(|signal| signal.name.as_str())
+    }
+}
+
+/// Parse a VCD file, naming its signals relative to `scope`.
+///
+/// `scope` is the dot-separated path of the design's instance, such as `tb.dut`; without
+/// it the signals are named relative to the top scope. Vectors are split into one signal
+/// per bit, named `reference[i]`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::backannotate::vcd::parse_vcd;
+///
+/// let waveform = parse_vcd(b"
+/// $timescale 1ps $end
+/// $scope module tb $end
+/// $var wire 1 ! go $end
+/// $scope module dut $end
+/// $var wire 1 \" a_t $end
+/// $scope module r $end
+/// $var wire 2 # q [1:0] $end
+/// $upscope $end
+/// $upscope $end
+/// $upscope $end
+/// $enddefinitions $end
+/// ", Some("tb.dut")).unwrap();
+///
+/// assert_eq!(waveform.names().collect::<Vec<_>>(), ["a_t", "r/q[1]", "r/q[0]"]);
+/// ```
+pub fn parse_vcd(input: &[u8], scope: Option<&str>) -> Result<Waveform> {
+    let mut parser = vcd::Parser::new(input);
+    let header = parser.parse_header().context("Invalid VCD header")?;
+    let ns_per_tick = match header.timescale {
+        Some((n, unit)) => n as f64 * unit.fraction() * 1e9,
+        None => 1.0,
+    };
+
+    let items = match scope {
+        Some(path) => {
+            let path: Vec<&str> = path.split('.').collect();
+            match header.find_scope(&path) {
+                Some(scope) => &scope.children,
+                None => bail!("VCD has no scope {}", path.join(".")),
+            }
+        }
+        None => match &header.items[..] {
+            [ScopeItem::Scope(top)] => &top.children,
+            items => items,
+        },
+    };
+
+    let mut waveform = Waveform::default();
+    // The signals of each identifier code, most significant bit first
+    let mut bits: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    let mut pending = vec![(items, String::new())];
+    while let Some((items, prefix)) = pending.pop() {
+        // Scopes are visited after the signals declared beside them
+        for item in items.iter().rev() {
+            if let ScopeItem::Scope(scope) = item {
+                pending.push((&scope.children, format!("{}{}/", prefix, scope.identifier)));
+            }
+        }
+        for item in items {
+            let ScopeItem::Var(var) = item else {
+                continue;
+            };
+            let reference = var.reference.trim_start_matches('\\').trim_end();
+            let name = format!("{}{}", prefix, reference);
+            let names: Vec<String> = match var.index {
+                Some(ReferenceIndex::BitSelect(i)) => vec![format!("{}[{}]", name, i)],
+                _ if var.size == 1 => vec![name],
+                Some(ReferenceIndex::Range(msb, lsb)) if msb >= lsb => (lsb..=msb)
+                    .rev()
+                    .map(|i| format!("{}[{}]", name, i))
+                    .collect(),
+                Some(ReferenceIndex::Range(msb, lsb)) => {
+                    (msb..=lsb).map(|i| format!("{}[{}]", name, i)).collect()
+                }
+                None => (0..var.size)
+                    .rev()
+                    .map(|i| format!("{}[{}]", name, i))
+                    .collect(),
+            };
+            let signals = bits.entry(var.code).or_default();
+            for name in names {
+                signals.push(waveform.signals.len());
+                waveform.signals.push(Signal {
+                    name,
+                    initial: false,
+                    changes: Vec::new(),
+                });
+            }
+        }
+    }
+
+    let mut tick = 0;
+    let mut values: Vec<bool> = vec![false; waveform.signals.len()];
+    for command in parser {
+        let command = command.context("Invalid VCD value change")?;
+        let (code, new): (IdCode, Vec<Value>) = match command {
+            Command::Timestamp(t) => {
+                tick = t;
+                continue;
+            }
+            Command::ChangeScalar(code, value) => (code, vec![value]),
+            Command::ChangeVector(code, value) => (code, value),
+            _ => continue,
+        };
+        let Some(signals) = bits.get(&code) else {
+            continue;
+        };
+        // Vectors are left-extended with zeros
+        let padding = signals.len().saturating_sub(new.len());
+        for (k, &i) in signals.iter().enumerate() {
+            let value = k >= padding && new.get(k - padding) == Some(&Value::V1);
+            if value == values[i] {
+                continue;
+            }
+            values[i] = value;
+            let signal = &mut waveform.signals[i];
+            if tick == 0 {
+                signal.initial = value;
+            } else {
+                // Scaled timestamps pick up floating-point noise far below any timescale
+                let time = (tick as f64 * ns_per_tick * 1e6).round() / 1e6;
+                signal.changes.push((time, value));
+            }
+        }
+    }
+    Ok(waveform)
+}
+
+/// The times at which the transitions of an HBCN fired in a waveform.
+#[derive(Debug, Clone, Default)]
+pub struct Firings {
+    times: HashMap<NodeIndex, Vec<f64>>,
+}
+
+impl Firings {
+    /// Find the firing times of every transition of `hbcn` whose node has rails in
+    /// `waveform`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hbcn::backannotate::vcd::{Firings, parse_vcd};
+    /// use hbcn::hbcn::parser::parse_hbcn;
+    ///
+    /// let hbcn = parse_hbcn("
+    ///     port {a} input
+    ///     * +{a} => +{r} : 1
+    ///     +{r} => -{a} : 1
+    ///     -{a} => -{r} : 1
+    ///     -{r} => +{a} : 1
+    /// ").unwrap();
+    /// // The rails of a, and a register r that takes 0.4ns to latch data and spacer
+    /// let waveform = parse_vcd(b"
+    /// $timescale 100ps $end
+    /// $scope module top $end
+    /// $var wire 1 ! a_t $end
+    /// $var wire 1 \" a_f $end
+    /// $var wire 1 # r/q $end
+    /// $upscope $end
+    /// $enddefinitions $end
+    /// #0 0! 0\" 0#
+    /// #10 1!
+    /// #14 1#
+    /// #20 0!
+    /// #24 0#
+    /// #30 1\"
+    /// #34 1#
+    /// #40 0\"
+    /// #44 0#
+    /// ", None).unwrap();
+    ///
+    /// let firings = Firings::new(&hbcn, &waveform);
+    /// let data_r = hbcn.edge_endpoints(hbcn.edge_indices().next().unwrap()).unwrap().1;
+    /// assert_eq!(firings.times(data_r), Some(&[1.4, 3.4][..]));
+    /// let delay = firings.separation(&hbcn, hbcn.edge_indices().next().unwrap()).unwrap();
+    /// assert_eq!((delay.min, delay.max), (Some(0.4), 0.4));
+    /// ```
+    pub fn new<T: AsRef<Transition>, P>(hbcn: &HBCN<T, P>, waveform: &Waveform) -> Self {
+        let registers: HashSet<&str> = hbcn
+            .node_indices()
+            .filter_map(|ix| match AsRef::<CircuitNode>::as_ref(hbcn[ix].as_ref()) {
+                CircuitNode::Register(name) => Some(name.as_ref()),
+                CircuitNode::Port(_) => None,
+            })
+            .collect();
+        // The innermost register whose cells each signal belongs to, so `r/s0/q` is the
+        // rail of `r/s0` rather than `r`
+        let owner: Vec<Option<&str>> = waveform
+            .signals
+            .iter()
+            .map(|signal| {
+                signal
+                    .name
+                    .rmatch_indices('/')
+                    .map(|(i, _)| &signal.name[..i])
+                    .find(|prefix| registers.contains(prefix))
+            })
+            .collect();
+
+        let mut nodes: HashMap<&CircuitNode, (Vec<f64>, Vec<f64>)> = HashMap::new();
+        let mut times = HashMap::new();
+        for ix in hbcn.node_indices() {
+            let transition = hbcn[ix].as_ref();
+            let node: &CircuitNode = transition.as_ref();
+            let firings = nodes.entry(node).or_insert_with(|| {
+                let rails: Vec<&Signal> = waveform
+                    .signals
+                    .iter()
+                    .zip(&owner)
+                    .filter(|(signal, owner)| match node {
+                        CircuitNode::Port(name) => {
+                            owner.is_none()
+                                && !signal.name.ends_with("_ack")
+                                && port_wildcard(name)
+                                    .split_whitespace()
+                                    .any(|pattern| glob_match(pattern, &signal.name))
+                        }
+                        CircuitNode::Register(name) => **owner == Some(name.as_ref()),
+                    })
+                    .map(|(signal, _)| signal)
+                    .collect();
+                if rails.is_empty() {
+                    (Vec::new(), Vec::new())
+                } else {
+                    node_firings(&rails)
+                }
+            });
+            if firings.0.is_empty() && firings.1.is_empty() {
+                continue;
+            }
+            let fired = match transition {
+                Transition::Data(_) => &firings.0,
+                Transition::Spacer(_) => &firings.1,
+            };
+            times.insert(ix, fired.clone());
+        }
+        Self { times }
+    }
+
+    /// The times at which `transition` fired, if its node has rails in the waveform.
+    pub fn times(&self, transition: NodeIndex) -> Option<&[f64]> {
+        self.times.get(&transition).map(Vec::as_slice)
+    }
+
+    /// Smallest and largest time from each firing of a place's source to the next firing
+    /// of its target, or `None` if the target never followed the source.
+    ///
+    /// The separation covers the place's path and any wait for the target's other places,
+    /// as its delay in a solved HBCN covers the path and the place's slack.
+    pub fn separation<T, P>(&self, hbcn: &HBCN<T, P>, place: EdgeIndex) -> Option<DelayPair> {
+        let (source, target) = hbcn.edge_endpoints(place)?;
+        let (sources, targets) = (self.times(source)?, self.times(target)?);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for &fired in sources {
+            let next = targets.partition_point(|&t| t < fired);
+            if let Some(&followed) = targets.get(next) {
+                // The difference of two rounded times is rounded again
+                let separation = ((followed - fired) * 1e6).round() / 1e6;
+                min = min.min(separation);
+                max = max.max(separation);
+            }
+        }
+        (min <= max).then(|| DelayPair::new(Some(min), max))
+    }
+
+    /// The steady-state cycle time: the longest average period between successive firings
+    /// of a transition, skipping its first firing, which leaves the reset state.
+    ///
+    /// `None` unless some transition fired at least three times.
+    pub fn cycle_time(&self) -> Option<f64> {
+        self.times
+            .values()
+            .filter(|times| times.len() >= 3)
+            .map(|times| (times[times.len() - 1] - times[1]) / (times.len() - 2) as f64)
+            .map(|period| (period * 1e6).round() / 1e6)
+            .reduce(f64::max)
+    }
+}
+
+/// Data and spacer firing times of a node with the given rails.
+fn node_firings(rails: &[&Signal]) -> (Vec<f64>, Vec<f64>) {
+    enum Phase {
+        /// All rails are low.
+        Spacer,
+        /// Rails are rising, the last at the given time.
+        Rising(f64),
+        /// Rails are falling back to the spacer.
+        Falling,
+    }
+
+    let mut changes: Vec<(f64, bool)> = rails
+        .iter()
+        .flat_map(|rail| rail.changes.iter().copied())
+        .collect();
+    changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut high = rails.iter().filter(|rail| rail.initial).count();
+    // A node reset with data on its rails fires its spacer first
+    let mut phase = if high > 0 {
+        Phase::Falling
+    } else {
+        Phase::Spacer
+    };
+    let (mut data, mut spacer) = (Vec::new(), Vec::new());
+    for (time, rise) in changes {
+        if rise {
+            high += 1;
+            if !matches!(phase, Phase::Falling) {
+                phase = Phase::Rising(time);
+            }
+            continue;
+        }
+        high = high.saturating_sub(1);
+        if let Phase::Rising(last) = phase {
+            data.push(last);
+            phase = Phase::Falling;
+        }
+        if high == 0 && matches!(phase, Phase::Falling) {
+            spacer.push(time);
+            phase = Phase::Spacer;
+        }
+    }
+    (data, spacer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{Named, parser::parse_hbcn};
+
+    fn rail(initial: bool, changes: &[(f64, bool)]) -> Signal {
+        Signal {
+            name: String::new(),
+            initial,
+            changes: changes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn dual_rail_waves_fire_on_completion() {
+        // Two bits: the data wave completes when the second bit arrives, the spacer when
+        // the last rail falls; a node reset with data fires its spacer first
+        let bit0 = rail(
+            false,
+            &[(1.0, true), (4.0, false), (7.0, true), (9.0, false)],
+        );
+        let bit1 = rail(
+            false,
+            &[(2.0, true), (5.0, false), (6.0, true), (10.0, false)],
+        );
+        assert_eq!(
+            node_firings(&[&bit0, &bit1]),
+            (vec![2.0, 7.0], vec![5.0, 10.0])
+        );
+
+        let reset = rail(true, &[(1.0, false), (3.0, true), (4.0, false)]);
+        assert_eq!(node_firings(&[&reset]), (vec![3.0], vec![1.0, 4.0]));
+    }
+
+    #[test]
+    fn signals_map_onto_rails_by_the_sdc_naming() {
+        let hbcn = parse_hbcn(
+            "
+            port {x[0]} input
+            * +{x[0]} => +{r} : 1
+            +{r} => -{x[0]} : 1
+            -{x[0]} => -{r} : 1
+            -{r} => +{x[0]} : 1
+            * +{r} => +{r/s0} : 1
+            +{r/s0} => -{r} : 1
+            -{r} => -{r/s0} : 1
+            -{r/s0} => +{r} : 1
+            ",
+        )
+        .unwrap();
+        let waveform = parse_vcd(
+            b"
+            $timescale 1ns $end
+            $scope module top $end
+            $var wire 2 ! x_t [1:0] $end
+            $var wire 2 \" x_f [1:0] $end
+            $var wire 1 # x_ack $end
+            $scope module r $end
+            $var wire 1 & q $end
+            $scope module s0 $end
+            $var wire 1 % q $end
+            $upscope $end
+            $upscope $end
+            $upscope $end
+            $enddefinitions $end
+            #0 b0 ! b0 \" 0# 0& 0%
+            #1 b10 ! 1#
+            #2 b11 \" 1&
+            #3 b0 \"
+            #4 0& 1%
+            #5 0%
+            ",
+            None,
+        )
+        .unwrap();
+        let firings = Firings::new(&hbcn, &waveform);
+        let at = |name: &str, data: bool| {
+            let ix = hbcn
+                .node_indices()
+                .find(|&ix| {
+                    let transition = &hbcn[ix];
+                    transition.name().as_ref() == name
+                        && matches!(transition, Transition::Data(_)) == data
+                })
+                .unwrap();
+            firings.times(ix).map(<[f64]>::to_vec)
+        };
+
+        // x[0] is x_t[0] and x_f[0], but neither x_t[1] nor the acknowledge
+        assert_eq!(at("x[0]", true), Some(vec![2.0]));
+        assert_eq!(at("x[0]", false), Some(vec![3.0]));
+        // r/q belongs to r, and r/s0/q to the nested r/s0
+        assert_eq!(at("r", true), Some(vec![2.0]));
+        assert_eq!(at("r", false), Some(vec![4.0]));
+        assert_eq!(at("r/s0", true), Some(vec![4.0]));
+        assert_eq!(at("r/s0", false), Some(vec![5.0]));
+    }
+
+    #[test]
+    fn cycle_time_skips_the_reset_wave() {
+        let mut firings = Firings::default();
+        firings
+            .times
+            .insert(NodeIndex::new(0), vec![0.5, 3.0, 5.0, 7.0]);
+        firings.times.insert(NodeIndex::new(1), vec![1.0, 2.0]);
+        assert_eq!(firings.cycle_time(), Some(2.0));
+        assert_eq!(Firings::default().cycle_time(), None);
+    }
+}
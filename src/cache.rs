@@ -0,0 +1,841 @@
+//! Compact binary cache of expanded and solved HBCNs.
+//!
+//! Parsing, expanding and validating a large `.graph` dominates short `analyse` and
+//! `constrain` runs, and repeats the same work every time. A cache file keeps the resulting
+//! graph in a compact binary form, together with a [`CacheKey`]: a content hash of the
+//! source files and the expansion options the graph was built with. A cache is only read
+//! back under the same key, so an edited source file or a different option simply misses
+//! and the graph is rebuilt.
+//!
+//! # Layout
+//!
+//! All integers are unsigned LEB128 varints unless noted, and floats are little-endian
+//! IEEE 754 doubles:
+//!
+//! | Field        | Contents                                                            |
+//! |--------------|---------------------------------------------------------------------|
+//! | magic        | the 8 bytes `HBCNBIN\0`                                             |
+//! | version      | [`CACHE_VERSION`]                                                   |
+//! | graph        | one byte each for the node and edge types                           |
+//! | key          | the source hash (8 bytes, little-endian), then an options byte      |
+//! | names        | count, then each interned node name as its length and UTF-8 bytes   |
+//! | nodes        | count, then per node its kind byte, name index and arrival time     |
+//! | edges        | count, then per edge its endpoints, a flags byte and its delays     |
+//! | declarations | count, then per node declaration its kind byte, name index and cost |
+//!
+//! Each node name is stored once however many transitions refer to it. Optional values
+//! (minimum delays, slacks, declared costs) only take space when present, as flagged in the
+//! edge's flags byte or the declaration's kind byte.
+//!
+//! The node declarations (see [`declarations`](crate::hbcn::declarations)) are cached with
+//! the graph, so the JSON outputs of a run served from the cache still carry them.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::cache::{CacheKey, structural_hbcn_from_cache, structural_hbcn_to_cache};
+//! use hbcn::{from_structural_graph, structural_graph::parse};
+//!
+//! let source = r#"
+//!     Port "a" [("r", 10)]
+//!     DataReg "r" [("a", 20)]
+//! "#;
+//! let hbcn = from_structural_graph(&parse(source).unwrap(), false).unwrap();
+//!
+//! let key = CacheKey::new(&[source.as_bytes()], false);
+//! let bytes = structural_hbcn_to_cache(&hbcn, &[], key);
+//! let (read_back, _) = structural_hbcn_from_cache(&bytes, key).unwrap();
+//! assert_eq!(read_back.edge_count(), hbcn.edge_count());
+//!
+//! // The same bytes are stale for any other source or options
+//! assert!(structural_hbcn_from_cache(&bytes, CacheKey::new(&[source.as_bytes()], true)).is_err());
+//! ```
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use petgraph::stable_graph::NodeIndex;
+
+use crate::{
+    Symbol,
+    hbcn::{
+        CircuitNode, DelayPair, DelayedPlace, HBCN, Place, SolvedHBCN, StructuralHBCN, Transition,
+        TransitionEvent, WeightedPlace,
+        declarations::{NodeDeclaration, NodeKind, PortDirection, RegisterClass},
+    },
+};
+
+/// Version of the binary layout written by this library.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Leading bytes of every cache file.
+const MAGIC: &[u8; 8] = b"HBCNBIN\0";
+
+/// What a cached graph was built from: its sources and expansion options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// [`content_hash`] of the source files, in order.
+    pub source_hash: u64,
+    /// Whether forward completion delays were used when expanding the structural graph.
+    pub forward_completion: bool,
+}
+
+impl CacheKey {
+    /// The key of a graph built from `sources` with the given expansion options.
+    pub fn new(sources: &[&[u8]], forward_completion: bool) -> Self {
+        Self {
+            source_hash: content_hash(sources),
+            forward_completion,
+        }
+    }
+
+    fn options(&self) -> u8 {
+        self.forward_completion as u8
+    }
+}
+
+/// 64-bit FNV-1a hash of the contents of `parts` and of this library's version.
+///
+/// Each part is hashed with its length, so moving bytes from one source file to the next
+/// changes the hash. The library version is included because a new release may expand the
+/// same source differently.
+pub fn content_hash(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    std::iter::once(version)
+        .chain(parts.iter().copied())
+        .flat_map(|part| {
+            (part.len() as u64)
+                .to_le_bytes()
+                .into_iter()
+                .chain(part.iter().copied())
+        })
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+/// Serialise an expanded HBCN and its node declarations for the cache.
+pub fn structural_hbcn_to_cache(
+    hbcn: &StructuralHBCN,
+    declarations: &[NodeDeclaration],
+    key: CacheKey,
+) -> Vec<u8> {
+    to_cache(hbcn, declarations, key)
+}
+
+/// Read an expanded HBCN and its node declarations back from the cache, failing unless it
+/// was written under `key`.
+pub fn structural_hbcn_from_cache(
+    bytes: &[u8],
+    key: CacheKey,
+) -> Result<(StructuralHBCN, Vec<NodeDeclaration>)> {
+    from_cache(bytes, key)
+}
+
+/// Serialise a solved HBCN, with its arrival times and slacks, and its node declarations for
+/// the cache.
+pub fn solved_hbcn_to_cache(
+    hbcn: &SolvedHBCN,
+    declarations: &[NodeDeclaration],
+    key: CacheKey,
+) -> Vec<u8> {
+    to_cache(hbcn, declarations, key)
+}
+
+/// Read a solved HBCN and its node declarations back from the cache, failing unless it was
+/// written under `key`.
+pub fn solved_hbcn_from_cache(
+    bytes: &[u8],
+    key: CacheKey,
+) -> Result<(SolvedHBCN, Vec<NodeDeclaration>)> {
+    from_cache(bytes, key)
+}
+
+/// Serialise an HBCN read from an `.hbcn` file and its node declarations for the cache.
+pub fn hbcn_to_cache(
+    hbcn: &HBCN<Transition, DelayedPlace>,
+    declarations: &[NodeDeclaration],
+    key: CacheKey,
+) -> Vec<u8> {
+    to_cache(hbcn, declarations, key)
+}
+
+/// Read an HBCN of an `.hbcn` file and its node declarations back from the cache, failing
+/// unless it was written under `key`.
+pub fn hbcn_from_cache(
+    bytes: &[u8],
+    key: CacheKey,
+) -> Result<(HBCN<Transition, DelayedPlace>, Vec<NodeDeclaration>)> {
+    from_cache(bytes, key)
+}
+
+/// Load the graph and node declarations cached at `cache` if they are up to date with
+/// `sources`, `settings` and `forward_completion`, otherwise build them and refresh the
+/// cache.
+///
+/// Each source is given with its role (`input`, `library`, `liberty`), which is hashed
+/// ahead of its contents: the same file passed as `--library` in one run and as
+/// `--liberty` in the next builds a different graph, so it must not hit the same cache.
+/// The `(name, value)` settings, such as the Liberty cell names, are hashed after them.
+///
+/// Without a cache path the graph is always built. A cache that cannot be read, is stale
+/// or is corrupt is rebuilt rather than reported, since it only ever holds derived data.
+pub(crate) fn load_or_build<T: Codec, P: Codec>(
+    cache: Option<&Path>,
+    sources: &[(&str, &Path)],
+    settings: &[(&str, String)],
+    forward_completion: bool,
+    build: impl FnOnce() -> Result<(HBCN<T, P>, Vec<NodeDeclaration>)>,
+) -> Result<(HBCN<T, P>, Vec<NodeDeclaration>)> {
+    use crate::verbose::is_verbose;
+
+    let Some(cache) = cache else {
+        return build();
+    };
+
+    let contents = sources
+        .iter()
+        .map(|(_, source)| fs::read(source).with_context(|| format!("Failed to read {:?}", source)))
+        .collect::<Result<Vec<_>>>()?;
+    let contents: Vec<&[u8]> = sources
+        .iter()
+        .zip(&contents)
+        .flat_map(|((role, _), contents)| [role.as_bytes(), contents.as_slice()])
+        .chain(
+            settings
+                .iter()
+                .flat_map(|(name, value)| [name.as_bytes(), value.as_bytes()]),
+        )
+        .collect();
+    let key = CacheKey::new(&contents, forward_completion);
+
+    match fs::read(cache)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| from_cache(&bytes, key))
+    {
+        Ok(cached) => {
+            if is_verbose() {
+                eprintln!("Using cached HBCN from: {:?}", cache);
+            }
+            Ok(cached)
+        }
+        Err(e) => {
+            if is_verbose() {
+                eprintln!("Not using cache {:?}: {}", cache, e);
+            }
+            let (hbcn, declarations) = build()?;
+            fs::write(cache, to_cache(&hbcn, &declarations, key))
+                .with_context(|| format!("Failed to write cache {:?}", cache))?;
+            if is_verbose() {
+                eprintln!("Wrote HBCN cache to: {:?}", cache);
+            }
+            Ok((hbcn, declarations))
+        }
+    }
+}
+
+fn to_cache<T: Codec, P: Codec>(
+    graph: &HBCN<T, P>,
+    declarations: &[NodeDeclaration],
+    key: CacheKey,
+) -> Vec<u8> {
+    // Nodes and edges go to the body first, interning names as they are met, since the
+    // name table has to precede them
+    let mut body = Writer::default();
+    let index: HashMap<NodeIndex, usize> = graph
+        .node_indices()
+        .enumerate()
+        .map(|(i, ni)| (ni, i))
+        .collect();
+
+    body.varint(graph.node_count() as u64);
+    for ni in graph.node_indices() {
+        graph[ni].encode(&mut body);
+    }
+    body.varint(graph.edge_count() as u64);
+    for ie in graph.edge_indices() {
+        let (source, target) = graph.edge_endpoints(ie).unwrap();
+        body.varint(index[&source] as u64);
+        body.varint(index[&target] as u64);
+        graph[ie].encode(&mut body);
+    }
+    body.varint(declarations.len() as u64);
+    for declaration in declarations {
+        declaration.encode(&mut body);
+    }
+
+    let mut out = Writer::default();
+    out.bytes.extend_from_slice(MAGIC);
+    out.varint(CACHE_VERSION as u64);
+    out.bytes.extend([T::TAG, P::TAG]);
+    out.bytes.extend(key.source_hash.to_le_bytes());
+    out.bytes.push(key.options());
+    out.varint(body.table.len() as u64);
+    for name in &body.table {
+        out.varint(name.len() as u64);
+        out.bytes.extend_from_slice(name.as_bytes());
+    }
+    out.bytes.extend(body.bytes);
+    out.bytes
+}
+
+fn from_cache<T: Codec, P: Codec>(
+    bytes: &[u8],
+    key: CacheKey,
+) -> Result<(HBCN<T, P>, Vec<NodeDeclaration>)> {
+    let mut input = Reader {
+        bytes,
+        pos: 0,
+        names: Vec::new(),
+    };
+
+    if input.take(MAGIC.len())? != MAGIC {
+        bail!("Not an HBCN cache file");
+    }
+    let version = input.varint()?;
+    if version != CACHE_VERSION as u64 {
+        bail!(
+            "Unsupported cache version {} (expected {})",
+            version,
+            CACHE_VERSION
+        );
+    }
+    if input.take(2)? != [T::TAG, P::TAG] {
+        bail!("Cache holds a different graph type");
+    }
+    let source_hash = u64::from_le_bytes(input.take(8)?.try_into().unwrap());
+    let options = input.byte()?;
+    if source_hash != key.source_hash || options != key.options() {
+        bail!("Cache is out of date");
+    }
+
+    let names = input.count()?;
+    input.names.reserve(names);
+    for _ in 0..names {
+        let len = input.count()?;
+        let name = std::str::from_utf8(input.take(len)?).context("Corrupt cache file")?;
+        input.names.push(Symbol::from(name));
+    }
+
+    let node_count = input.count()?;
+    let mut graph = HBCN::with_capacity(node_count, node_count);
+    let nodes = (0..node_count)
+        .map(|_| Ok(graph.add_node(T::decode(&mut input)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let edge_count = input.count()?;
+    for _ in 0..edge_count {
+        let mut endpoint = || {
+            let i = input.count()?;
+            nodes
+                .get(i)
+                .copied()
+                .ok_or_else(|| anyhow!("Corrupt cache file: edge to missing node {}", i))
+        };
+        let (source, target) = (endpoint()?, endpoint()?);
+        graph.add_edge(source, target, P::decode(&mut input)?);
+    }
+    let declaration_count = input.count()?;
+    let declarations = (0..declaration_count)
+        .map(|_| NodeDeclaration::decode(&mut input))
+        .collect::<Result<Vec<_>>>()?;
+
+    if input.pos != bytes.len() {
+        bail!("Corrupt cache file: trailing data");
+    }
+    Ok((graph, declarations))
+}
+
+/// Output buffer, interning node names into a table.
+#[derive(Default)]
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+    names: HashMap<Symbol, u64>,
+    table: Vec<Symbol>,
+}
+
+impl Writer {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn float(&mut self, value: f64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn name(&mut self, name: &Symbol) {
+        let next = self.table.len() as u64;
+        let index = *self.names.entry(name.clone()).or_insert_with(|| {
+            self.table.push(name.clone());
+            next
+        });
+        self.varint(index);
+    }
+
+    /// Flags byte of a place, with `extra` or'ed in above the place's own two bits.
+    fn place(&mut self, place: &Place, extra: u8) {
+        self.bytes
+            .push(place.token as u8 | (place.is_internal as u8) << 1 | extra << 2);
+    }
+}
+
+/// Input cursor, resolving node names through the table read from the file.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    names: Vec<Symbol>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Corrupt cache file: unexpected end of data"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        bail!("Corrupt cache file: overlong integer")
+    }
+
+    /// A count or index, bounded by the remaining data so corrupt counts cannot make the
+    /// reader reserve huge amounts of memory.
+    fn count(&mut self) -> Result<usize> {
+        let value = self.varint()?;
+        usize::try_from(value)
+            .ok()
+            .filter(|&value| value <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Corrupt cache file: count {} out of range", value))
+    }
+
+    fn float(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<Symbol> {
+        let index = self.count()?;
+        self.names
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow!("Corrupt cache file: missing name {}", index))
+    }
+
+    /// The place of a flags byte, and the flags above the place's own two bits.
+    fn place(&mut self) -> Result<(Place, u8)> {
+        let flags = self.byte()?;
+        let place = Place {
+            token: flags & 1 != 0,
+            is_internal: flags & 2 != 0,
+        };
+        Ok((place, flags >> 2))
+    }
+}
+
+/// Node and edge weights that can be cached.
+pub(crate) trait Codec: Sized {
+    /// Identifies the type in the file header.
+    const TAG: u8;
+
+    fn encode(&self, out: &mut Writer);
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self>;
+}
+
+const SPACER: u8 = 1;
+const REGISTER: u8 = 2;
+
+impl Codec for Transition {
+    const TAG: u8 = 1;
+
+    fn encode(&self, out: &mut Writer) {
+        let (spacer, node) = match self {
+            Transition::Data(node) => (0, node),
+            Transition::Spacer(node) => (SPACER, node),
+        };
+        let (register, name) = match node {
+            CircuitNode::Port(name) => (0, name),
+            CircuitNode::Register(name) => (REGISTER, name),
+        };
+        out.bytes.push(spacer | register);
+        out.name(name);
+    }
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self> {
+        let kind = input.byte()?;
+        if kind > SPACER | REGISTER {
+            bail!("Corrupt cache file: unknown transition kind {}", kind);
+        }
+        let name = input.name()?;
+        let node = if kind & REGISTER != 0 {
+            CircuitNode::Register(name)
+        } else {
+            CircuitNode::Port(name)
+        };
+        Ok(if kind & SPACER != 0 {
+            Transition::Spacer(node)
+        } else {
+            Transition::Data(node)
+        })
+    }
+}
+
+impl Codec for TransitionEvent {
+    const TAG: u8 = 2;
+
+    fn encode(&self, out: &mut Writer) {
+        self.transition.encode(out);
+        out.float(self.time);
+    }
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self> {
+        let transition = Transition::decode(input)?;
+        let time = input.float()?;
+        Ok(TransitionEvent { time, transition })
+    }
+}
+
+const HAS_MIN: u8 = 1;
+const HAS_SLACK: u8 = 2;
+
+impl Codec for WeightedPlace {
+    const TAG: u8 = 1;
+
+    fn encode(&self, out: &mut Writer) {
+        out.place(
+            &self.place,
+            if self.min_delay.is_some() { HAS_MIN } else { 0 },
+        );
+        out.float(self.weight);
+        if let Some(min) = self.min_delay {
+            out.float(min);
+        }
+    }
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self> {
+        let (place, flags) = input.place()?;
+        let weight = input.float()?;
+        let min_delay = if flags & HAS_MIN != 0 {
+            Some(input.float()?)
+        } else {
+            None
+        };
+        Ok(WeightedPlace {
+            place,
+            weight,
+            min_delay,
+        })
+    }
+}
+
+impl Codec for DelayedPlace {
+    const TAG: u8 = 2;
+
+    fn encode(&self, out: &mut Writer) {
+        let mut flags = 0;
+        if self.delay.min.is_some() {
+            flags |= HAS_MIN;
+        }
+        if self.slack.is_some() {
+            flags |= HAS_SLACK;
+        }
+        out.place(&self.place, flags);
+        out.float(self.delay.max);
+        for value in [self.delay.min, self.slack].into_iter().flatten() {
+            out.float(value);
+        }
+    }
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self> {
+        let (place, flags) = input.place()?;
+        let max = input.float()?;
+        let mut optional = |flag: u8| -> Result<Option<f64>> {
+            if flags & flag != 0 {
+                input.float().map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let min = optional(HAS_MIN)?;
+        let slack = optional(HAS_SLACK)?;
+        Ok(DelayedPlace {
+            place,
+            delay: DelayPair::new(min, max),
+            slack,
+        })
+    }
+}
+
+const DECLARED_REGISTER: u8 = 1;
+const HAS_COST: u8 = 2;
+
+impl NodeDeclaration {
+    /// Kind byte: the node kind, its attribute (0 when absent) above it, and whether a cost
+    /// follows.
+    fn encode(&self, out: &mut Writer) {
+        let (register, attribute) = match self.kind {
+            NodeKind::Port(direction) => (
+                0,
+                match direction {
+                    None => 0,
+                    Some(PortDirection::Input) => 1,
+                    Some(PortDirection::Output) => 2,
+                    Some(PortDirection::InOut) => 3,
+                },
+            ),
+            NodeKind::Register(class) => (
+                DECLARED_REGISTER,
+                match class {
+                    None => 0,
+                    Some(RegisterClass::Data) => 1,
+                    Some(RegisterClass::Control) => 2,
+                },
+            ),
+        };
+        let cost = if self.cost.is_some() { HAS_COST } else { 0 };
+        out.bytes.push(register | cost | attribute << 2);
+        out.name(&self.name);
+        if let Some(cost) = self.cost {
+            out.varint(cost as u64);
+        }
+    }
+
+    fn decode(input: &mut Reader<'_>) -> Result<Self> {
+        let flags = input.byte()?;
+        let kind = match (flags & DECLARED_REGISTER != 0, flags >> 2) {
+            (false, 0) => NodeKind::Port(None),
+            (false, 1) => NodeKind::Port(Some(PortDirection::Input)),
+            (false, 2) => NodeKind::Port(Some(PortDirection::Output)),
+            (false, 3) => NodeKind::Port(Some(PortDirection::InOut)),
+            (true, 0) => NodeKind::Register(None),
+            (true, 1) => NodeKind::Register(Some(RegisterClass::Data)),
+            (true, 2) => NodeKind::Register(Some(RegisterClass::Control)),
+            _ => bail!("Corrupt cache file: unknown declaration kind {}", flags),
+        };
+        let name = input.name()?;
+        let cost = if flags & HAS_COST != 0 {
+            let cost = input.varint()?;
+            Some(
+                usize::try_from(cost)
+                    .map_err(|_| anyhow!("Corrupt cache file: cost {} out of range", cost))?,
+            )
+        } else {
+            None
+        };
+        Ok(NodeDeclaration { name, kind, cost })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::{
+        declarations::node_declarations, from_structural_graph,
+        test_helpers::create_valid_two_channel_hbcn,
+    };
+    use crate::structural_graph::parse;
+    use std::cell::Cell;
+
+    const SOURCE: &str = r#"
+        Port "input" [("r", 10, min=2)]
+        DataReg "r" [("b", 20)]
+        Port "b" []
+    "#;
+
+    fn key() -> CacheKey {
+        CacheKey::new(&[SOURCE.as_bytes()], false)
+    }
+
+    fn edges<T, P, E: PartialEq + std::fmt::Debug>(
+        graph: &HBCN<T, P>,
+        edge: impl Fn(&P) -> E,
+    ) -> Vec<(usize, usize, E)> {
+        graph
+            .edge_indices()
+            .map(|ie| {
+                let (s, t) = graph.edge_endpoints(ie).unwrap();
+                (s.index(), t.index(), edge(&graph[ie]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn structural_hbcn_round_trips() {
+        let graph = parse(SOURCE).unwrap();
+        let hbcn = from_structural_graph(&graph, false).unwrap();
+        let mut declarations = node_declarations(&graph);
+        declarations[1].kind = NodeKind::Register(Some(RegisterClass::Control));
+        let (read_back, read_declarations) = structural_hbcn_from_cache(
+            &structural_hbcn_to_cache(&hbcn, &declarations, key()),
+            key(),
+        )
+        .unwrap();
+        assert_eq!(read_declarations, declarations);
+
+        let nodes = |g: &StructuralHBCN| g.node_indices().map(|i| g[i].clone()).collect::<Vec<_>>();
+        assert_eq!(nodes(&read_back), nodes(&hbcn));
+        let place = |p: &WeightedPlace| (p.place.token, p.place.is_internal, p.weight, p.min_delay);
+        assert_eq!(edges(&read_back, place), edges(&hbcn, place));
+    }
+
+    #[test]
+    fn solved_hbcn_round_trips() {
+        let mut solved: SolvedHBCN =
+            create_valid_two_channel_hbcn("a", "b", "c", 4.0, 2.0, 3.0, 1.0, 0, 1).map(
+                |i, t| TransitionEvent {
+                    time: i.index() as f64 * 1.5,
+                    transition: t.clone(),
+                },
+                |_, p| p.clone(),
+            );
+        let first = solved.edge_indices().next().unwrap();
+        solved[first].slack = Some(0.25);
+        solved[first].delay = DelayPair::new(Some(1.0), 3.0);
+
+        let (read_back, declarations) =
+            solved_hbcn_from_cache(&solved_hbcn_to_cache(&solved, &[], key()), key()).unwrap();
+        assert!(declarations.is_empty());
+
+        let nodes = |g: &SolvedHBCN| g.node_indices().map(|i| g[i].clone()).collect::<Vec<_>>();
+        assert_eq!(nodes(&read_back), nodes(&solved));
+        let place = |p: &DelayedPlace| (p.place.token, p.delay.min, p.delay.max, p.slack);
+        assert_eq!(edges(&read_back, place), edges(&solved, place));
+    }
+
+    #[test]
+    fn names_are_stored_once() {
+        let hbcn = from_structural_graph(&parse(SOURCE).unwrap(), false).unwrap();
+        let bytes = structural_hbcn_to_cache(&hbcn, &[], key());
+        // Both transitions of the port refer to the same table entry
+        assert_eq!(bytes.windows(5).filter(|w| w == b"input").count(), 1);
+    }
+
+    #[test]
+    fn rejects_stale_and_corrupt_caches() {
+        let hbcn = from_structural_graph(&parse(SOURCE).unwrap(), false).unwrap();
+        let bytes = structural_hbcn_to_cache(&hbcn, &[], key());
+
+        let other_source = CacheKey::new(&[b"Port \"a\" []"], false);
+        let other_options = CacheKey::new(&[SOURCE.as_bytes()], true);
+        for (bytes, key, message) in [
+            (&bytes[..], other_source, "out of date"),
+            (&bytes[..], other_options, "out of date"),
+            (&bytes[..bytes.len() - 3], key(), "unexpected end"),
+            (&b"HBCN\0\0\0\0"[..], key(), "Not an HBCN cache"),
+        ] {
+            let error = structural_hbcn_from_cache(bytes, key).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+
+        let error = solved_hbcn_from_cache(&bytes, key()).unwrap_err();
+        assert!(
+            error.to_string().contains("different graph type"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn content_hash_separates_sources() {
+        assert_ne!(content_hash(&[b"ab", b"c"]), content_hash(&[b"a", b"bc"]));
+        assert_eq!(content_hash(&[b"ab"]), content_hash(&[b"ab"]));
+    }
+
+    #[test]
+    fn load_or_build_reuses_an_up_to_date_cache() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("circuit.graph");
+        let cache = dir.path().join("circuit.cache");
+        fs::write(&source, SOURCE).unwrap();
+
+        let builds = Cell::new(0);
+        let load = |forward_completion| {
+            load_or_build(
+                Some(&cache),
+                &[("input", &source)],
+                &[],
+                forward_completion,
+                || {
+                    builds.set(builds.get() + 1);
+                    let graph = crate::read_file(&source)?;
+                    let hbcn = from_structural_graph(&graph, forward_completion).unwrap();
+                    Ok((hbcn, node_declarations(&graph)))
+                },
+            )
+            .unwrap()
+        };
+
+        let built = load(false);
+        let cached = load(false);
+        assert_eq!(builds.get(), 1);
+        assert_eq!(cached.0.edge_count(), built.0.edge_count());
+        assert_eq!(cached.1, built.1);
+
+        // Changing the options or the source rebuilds the cache
+        load(true);
+        assert_eq!(builds.get(), 2);
+        fs::write(&source, SOURCE.replace("20", "30")).unwrap();
+        load(true);
+        assert_eq!(builds.get(), 3);
+        load(true);
+        assert_eq!(builds.get(), 3);
+    }
+
+    #[test]
+    fn load_or_build_tells_source_roles_and_settings_apart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("circuit.graph");
+        let extra = dir.path().join("cells");
+        let cache = dir.path().join("circuit.cache");
+        fs::write(&source, SOURCE).unwrap();
+        fs::write(&extra, "library (cells) { }").unwrap();
+
+        let builds = Cell::new(0);
+        let load = |role, register: &str| {
+            load_or_build(
+                Some(&cache),
+                &[("input", &source), (role, &extra)],
+                &[("liberty-register", register.to_string())],
+                false,
+                || {
+                    builds.set(builds.get() + 1);
+                    let graph = crate::read_file(&source)?;
+                    Ok((from_structural_graph(&graph, false).unwrap(), Vec::new()))
+                },
+            )
+            .unwrap()
+        };
+
+        // The same bytes as a library and as a Liberty file make different graphs
+        load("library", "C2R");
+        load("liberty", "C2R");
+        assert_eq!(builds.get(), 2);
+        load("liberty", "C2R");
+        assert_eq!(builds.get(), 2);
+
+        // So do other cells of the same Liberty file
+        load("liberty", "DFF");
+        assert_eq!(builds.get(), 3);
+    }
+}
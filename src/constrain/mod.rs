@@ -20,6 +20,12 @@
 //! - **CSV**: Tabular format for analysis and debugging
 //! - **VCD**: Waveform format showing arrival times
 //! - **Report**: Human-readable text reports with cycle analysis
+//! - **HTML**: A self-contained, navigable version of the report (see
+//!   [`crate::analyse::html`])
+//! - **JSON**: The constrained HBCN with per-place delays, slacks and arrival times (see
+//!   [`crate::json`])
+//! - **Report Tcl**: A Genus script reporting the achieved delay of every place, for
+//!   [`crate::backannotate`]
 //!
 //! # Usage Example
 //!
@@ -36,10 +42,21 @@
 //!     csv: Some("constraints.csv".into()),
 //!     rpt: Some("report.rpt".into()),
 //!     vcd: None,
+//!     vcd_periods: 1,
+//!     vcd_timescale: hbcn::analyse::vcd::TimescaleUnit::PS,
+//!     vcd_channels: false,
 //!     no_proportional: false,
 //!     no_forward_completion: false,
 //!     forward_margin: None,
 //!     backward_margin: None,
+//!     library: None,
+//!     liberty: None,
+//!     liberty_cells: Default::default(),
+//!     virtual_unit: None,
+//!     json: None,
+//!     cache: None,
+//!     html: None,
+//!     report_tcl: None,
 //! };
 //!
 //! constrain_main(args)?;
@@ -60,7 +77,10 @@ use ordered_float::OrderedFloat;
 use prettytable::*;
 use rayon::prelude::*;
 
-use crate::{hbcn::*, read_file};
+use crate::{
+    cache::load_or_build, hbcn::declarations::node_declarations, hbcn::*, liberty::LibertyCells,
+    read_delay_model, read_structural_input,
+};
 
 pub mod hbcn;
 /// SDC (Synopsys Design Constraints) file generation for Cadence Genus.
@@ -117,6 +137,18 @@ pub struct ConstrainArgs {
     #[clap(long)]
     pub vcd: Option<PathBuf>,
 
+    /// Number of steady-state periods unrolled in the VCD waveform
+    #[clap(long, default_value_t = 1, requires = "vcd")]
+    pub vcd_periods: usize,
+
+    /// Timescale unit of the VCD waveform (s, ms, us, ns, ps or fs)
+    #[clap(long, default_value = "ps", requires = "vcd")]
+    pub vcd_timescale: crate::analyse::vcd::TimescaleUnit,
+
+    /// Add request and acknowledge signals for every channel to the VCD waveform
+    #[clap(long, requires = "vcd")]
+    pub vcd_channels: bool,
+
     /// Use pseudo-clock to constrain paths
     #[clap(long)]
     pub no_proportional: bool,
@@ -132,6 +164,39 @@ pub struct ConstrainArgs {
     /// Minimal percentual margin between maximum and minimum delay in the backward path
     #[clap(long, short('b'), value_parser = clap::value_parser!(u8).range(0 .. 100))]
     pub backward_margin: Option<u8>,
+
+    /// Component library declaring additional register kinds (structural input only)
+    #[clap(long, requires = "structural")]
+    pub library: Option<PathBuf>,
+
+    /// Liberty cell library pricing the expansion in its characterised delays (structural
+    /// input only)
+    #[clap(long, requires = "structural", requires = "virtual_unit")]
+    pub liberty: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub liberty_cells: LibertyCells,
+
+    /// Nanoseconds per unit of the structural graph's channel delays, required with --liberty
+    #[clap(long, value_name = "NS", requires = "liberty")]
+    pub virtual_unit: Option<f64>,
+
+    /// Output JSON file with the constrained HBCN and pseudo-clock period
+    #[clap(long)]
+    pub json: Option<PathBuf>,
+
+    /// Binary cache of the parsed HBCN, reused while the input files and expansion options
+    /// are unchanged
+    #[clap(long)]
+    pub cache: Option<PathBuf>,
+
+    /// Self-contained HTML report with sortable critical-cycle tables and slack histograms
+    #[clap(long)]
+    pub html: Option<PathBuf>,
+
+    /// Output Genus Tcl script reporting the achieved delay of every place
+    #[clap(long)]
+    pub report_tcl: Option<PathBuf>,
 }
 
 /// Generate timing constraints for an HBCN circuit.
@@ -153,6 +218,10 @@ pub struct ConstrainArgs {
 /// - **CSV** (optional): Tabular constraint data
 /// - **VCD** (optional): Waveform with arrival times
 /// - **Report** (optional): Human-readable cycle analysis
+/// - **JSON** (optional): Constrained HBCN and pseudo-clock period
+/// - **HTML** (optional): Self-contained report of the critical cycles
+/// - **Report Tcl** (optional): Genus script timing every place, see
+///   [`sdc::write_timing_report_script`]
 ///
 /// # Example
 ///
@@ -169,10 +238,21 @@ pub struct ConstrainArgs {
 ///     csv: None,
 ///     rpt: None,
 ///     vcd: None,
+///     vcd_periods: 1,
+///     vcd_timescale: hbcn::analyse::vcd::TimescaleUnit::PS,
+///     vcd_channels: false,
 ///     no_proportional: false,
 ///     no_forward_completion: false,
 ///     forward_margin: None,
 ///     backward_margin: None,
+///     library: None,
+///     liberty: None,
+///     liberty_cells: Default::default(),
+///     virtual_unit: None,
+///     json: None,
+///     cache: None,
+///     html: None,
+///     report_tcl: None,
 /// };
 ///
 /// constrain_main(args)?;
@@ -191,10 +271,21 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
         ref csv,
         ref rpt,
         ref vcd,
+        vcd_periods,
+        vcd_timescale,
+        vcd_channels,
         no_proportional,
         no_forward_completion,
         forward_margin,
         backward_margin,
+        ref library,
+        ref liberty,
+        ref liberty_cells,
+        virtual_unit,
+        ref json,
+        ref cache,
+        ref html,
+        ref report_tcl,
     } = args;
     let forward_completion = !no_forward_completion;
     let forward_margin = forward_margin.map(|x| 1.0 - (x as f64 / 100.0));
@@ -218,15 +309,33 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
     // HBCN is built with `StableGraph::map`, which preserves edge indices, so these costs line
     // up with the solved edges by index — letting the CSV report the input weight alongside the
     // computed max/min (the solved edge's own `weight()` is the computed max, not the cost).
-    let (constraints, original_cost) = {
+    let (constraints, original_cost, declarations) = {
         if structural {
             // Parse as structural graph
             if is_verbose() {
                 eprintln!("Parsing structural graph...");
             }
-            let g = read_file(&input)?;
-            let hbcn = from_structural_graph(&g, forward_completion)
-                .ok_or_else(|| anyhow!("Failed to convert structural graph to StructuralHBCN"))?;
+            let mut sources = vec![("input", input.as_path())];
+            sources.extend(library.as_deref().map(|library| ("library", library)));
+            sources.extend(liberty.as_deref().map(|liberty| ("liberty", liberty)));
+            let settings =
+                crate::delay_model_settings(liberty.as_deref(), liberty_cells, virtual_unit);
+            let (hbcn, declarations) = load_or_build(
+                cache.as_deref(),
+                &sources,
+                &settings,
+                forward_completion,
+                || {
+                    let g = read_structural_input(&input, library.as_deref())?;
+                    let delays = read_delay_model(liberty.as_deref(), liberty_cells, virtual_unit)?;
+                    let hbcn = from_structural_graph_with_delays(&g, forward_completion, &delays)
+                        .ok_or_else(|| {
+                        anyhow!("Failed to convert structural graph to StructuralHBCN")
+                    })?;
+                    Ok((hbcn, node_declarations(&g)))
+                },
+            )?;
+            crate::warn_min_delay_excesses(&hbcn);
             let original_cost: HashMap<_, f64> = hbcn
                 .edge_indices()
                 .map(|ie| (ie, hbcn[ie].weight()))
@@ -246,14 +355,16 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
                     forward_margin,
                 )?
             };
-            (constraints, original_cost)
+            (constraints, original_cost, declarations)
         } else {
             // Parse as HBCN
             if is_verbose() {
                 eprintln!("Parsing HBCN file...");
             }
-            let file_contents = fs::read_to_string(&input)?;
-            let hbcn = crate::hbcn::parser::parse_hbcn(&file_contents)?;
+            let (hbcn, declarations) =
+                load_or_build(cache.as_deref(), &[("input", &input)], &[], false, || {
+                    crate::read_hbcn_file_with_declarations(&input)
+                })?;
             let original_cost: HashMap<_, f64> = hbcn
                 .edge_indices()
                 .map(|ie| (ie, hbcn[ie].weight()))
@@ -273,7 +384,7 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
                     forward_margin,
                 )?
             };
-            (constraints, original_cost)
+            (constraints, original_cost, declarations)
         }
     };
 
@@ -344,21 +455,49 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
         constraints.pseudoclock_period,
     )?;
 
-    if let Some(output) = vcd {
+    if let Some(output) = report_tcl {
         if is_verbose() {
-            eprintln!("Writing VCD waveform to: {:?}", output);
+            eprintln!("Writing timing report script to: {:?}", output);
         }
         let mut out_file = BufWriter::new(fs::File::create(output)?);
+        sdc::write_timing_report_script(&mut out_file, &constraints.hbcn)?;
+    }
 
-        crate::analyse::vcd::write_vcd(&constraints.hbcn, &mut out_file)?;
+    if let Some(output) = json {
+        if is_verbose() {
+            eprintln!("Writing JSON results to: {:?}", output);
+        }
+        let summary = crate::json::SolvedSummary {
+            cycle_time: Some(cycle_time),
+            pseudoclock_period: Some(constraints.pseudoclock_period),
+        };
+        fs::write(
+            output,
+            crate::json::solved_hbcn_to_json_with_declarations(
+                &constraints.hbcn,
+                summary,
+                &declarations,
+            ),
+        )?;
     }
 
-    if let Some(output) = rpt {
+    if let Some(output) = vcd {
         if is_verbose() {
-            eprintln!("Writing report to: {:?}", output);
+            eprintln!("Writing VCD waveform to: {:?}", output);
         }
         let mut out_file = BufWriter::new(fs::File::create(output)?);
 
+        let options = crate::analyse::vcd::VcdOptions {
+            cycle_time: Some(cycle_time),
+            periods: vcd_periods,
+            timescale: vcd_timescale,
+            channels: vcd_channels,
+        };
+        crate::analyse::vcd::write_vcd_with_options(&constraints.hbcn, &options, &mut out_file)?;
+    }
+
+    // Both reports list the critical cycles, least slack first
+    let cycles = if rpt.is_some() || html.is_some() {
         let mut cycles = crate::analyse::hbcn::find_critical_cycles(&constraints.hbcn)
             .into_par_iter()
             .map(|cycle| {
@@ -375,9 +514,57 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
                 (slack, cycle)
             })
             .collect::<Vec<_>>();
+        cycles.par_sort_unstable_by_key(|(slack, _)| OrderedFloat(*slack));
+        cycles
+    } else {
+        Vec::new()
+    };
+
+    if let Some(output) = html {
+        if is_verbose() {
+            eprintln!("Writing HTML report to: {:?}", output);
+        }
+        let report = crate::analyse::html::HtmlReport {
+            title: input.display().to_string(),
+            summary: vec![
+                (
+                    "Cycle time constraint".into(),
+                    format!("{:.3} ns", cycle_time),
+                ),
+                (
+                    "Pseudo-clock period".into(),
+                    format!("{:.3} ns", constraints.pseudoclock_period),
+                ),
+                ("Minimal delay".into(), format!("{:.3} ns", minimal_delay)),
+                (
+                    "Algorithm".into(),
+                    if no_proportional {
+                        "pseudoclock"
+                    } else {
+                        "proportional"
+                    }
+                    .into(),
+                ),
+            ],
+        };
+        let cycles: Vec<_> = cycles.iter().map(|(_, cycle)| cycle.clone()).collect();
+        let mut out_file = BufWriter::new(fs::File::create(output)?);
+        crate::analyse::html::write_html(&mut out_file, &report, hbcn, &cycles, |ie| {
+            original_cost
+                .get(&ie)
+                .copied()
+                .unwrap_or_else(|| hbcn[ie].weight())
+        })?;
+    }
+
+    if let Some(output) = rpt {
+        if is_verbose() {
+            eprintln!("Writing report to: {:?}", output);
+        }
+        let mut out_file = BufWriter::new(fs::File::create(output)?);
+
         writeln!(out_file, "Cycle time constraint: {:.3} ns", cycle_time,)?;
         writeln!(out_file, "Cycles: {}", cycles.len())?;
-        cycles.par_sort_unstable_by_key(|(slack, _)| OrderedFloat(*slack));
 
         for (i, (slack, cycle)) in cycles.into_iter().enumerate() {
             let mut table = Table::new();
@@ -410,12 +597,7 @@ pub fn constrain_main(args: ConstrainArgs) -> Result<()> {
                     .copied()
                     .unwrap_or_else(|| e.weight());
 
-                let ttype = match (&s.transition, &t.transition) {
-                    (Transition::Data(_), Transition::Data(_)) => "Data Prop",
-                    (Transition::Spacer(_), Transition::Spacer(_)) => "Null Prop",
-                    (Transition::Data(_), Transition::Spacer(_)) => "Data Ack",
-                    (Transition::Spacer(_), Transition::Data(_)) => "Null Ack",
-                };
+                let ttype = place_kind(&s.transition, &t.transition);
 
                 let min_delay = e.delay.min.unwrap_or(0.0);
                 let max_delay = e.delay.max;
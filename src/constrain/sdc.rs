@@ -3,9 +3,12 @@ use regex::Regex;
 use std::io::{self, Write};
 
 use crate::constrain::hbcn::is_rise;
+use crate::hbcn::serialisation::write_transition;
 use crate::hbcn::{CircuitNode, SolvedHBCN, Transition};
 
-fn port_wildcard(s: &str) -> String {
+/// Netlist port patterns of an HBCN port: its rails (`name_*`), or for a bus bit its rails
+/// and the bus acknowledge. Patterns are separated by spaces.
+pub(crate) fn port_wildcard(s: &str) -> String {
     lazy_static! {
         static ref INDEX_RE: Regex = Regex::new(r"^(.+)(\[[0-9]+\])").unwrap();
     }
@@ -17,7 +20,8 @@ fn port_wildcard(s: &str) -> String {
     }
 }
 
-fn port_instance(s: &str) -> String {
+/// Instance holding the logic of a port of a sub-module (`port:m/p` is `inst:m/ip`).
+pub(crate) fn port_instance(s: &str) -> String {
     lazy_static! {
         static ref REPLACE_RE: Regex = Regex::new(r"^port:([^/]+)/(.+)").unwrap();
         static ref INDEX_RE: Regex = Regex::new(r"^(.+)\[([0-9]+)\]").unwrap();
@@ -160,6 +164,79 @@ pub fn write_path_constraints(
     Ok(())
 }
 
+/// Write a Genus Tcl script reporting the achieved delay of every place.
+///
+/// For each place the script runs a late and an early `report_timing` through the same
+/// rails and `-rise_through`/`-fall_through` qualifiers as [`write_path_constraints`], so
+/// the reported paths are exactly the constrained ones. Every report is preceded by a
+/// marker line naming its place as in the HBCN format (`hbcn_place max +{a} => +{b}`);
+/// [`crate::backannotate::report::parse_timing_report`] reads the resulting file back.
+///
+/// The report is appended to the file named by the Tcl variable `hbcn_timing_report`
+/// (`hbcn_timing.rpt` unless set before sourcing the script), which is emptied first.
+/// Places are reported whatever their budget, so `-unconstrained` paths are included.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hbcn::constrain::sdc::write_timing_report_script;
+/// use hbcn::hbcn::SolvedHBCN;
+/// use std::fs::File;
+/// use std::io::BufWriter;
+/// # let hbcn = SolvedHBCN::default();
+///
+/// let mut writer = BufWriter::new(File::create("report_timing.tcl")?);
+/// write_timing_report_script(&mut writer, &hbcn)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_timing_report_script(writer: &mut dyn Write, hbcn: &SolvedHBCN) -> io::Result<()> {
+    writeln!(writer, "# Generated by hbcn {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(
+        writer,
+        "# Reports the achieved delay of every place for `hbcn backannotate --timing-report`"
+    )?;
+    writeln!(
+        writer,
+        "if {{![info exists hbcn_timing_report]}} {{\n\tset hbcn_timing_report hbcn_timing.rpt\n}}"
+    )?;
+    writeln!(writer, "close [open $hbcn_timing_report w]")?;
+    writeln!(
+        writer,
+        "proc hbcn_place {{check place}} {{\n\tglobal hbcn_timing_report\n\tset f [open $hbcn_timing_report a]\n\tputs $f \"hbcn_place $check $place\"\n\tclose $f\n}}"
+    )?;
+
+    for ie in hbcn.edge_indices() {
+        let Some((is, id)) = hbcn.edge_endpoints(ie) else {
+            continue;
+        };
+        let src_t: &Transition = &hbcn[is].transition;
+        let dst_t: &Transition = &hbcn[id].transition;
+        let mut place = String::new();
+        // Infallible for String
+        let _ = write_transition(src_t, &mut place);
+        place.push_str(" => ");
+        let _ = write_transition(dst_t, &mut place);
+
+        for (check, early) in [("max", ""), ("min", " -early")] {
+            writeln!(
+                writer,
+                "\nhbcn_place {} {{{}}}\nreport_timing{} -unconstrained \\\n\t-{} {} \\\n\t-{} {} \\\n\t>> $hbcn_timing_report",
+                check,
+                place,
+                early,
+                through_keyword(src_t),
+                src_rails(src_t.as_ref()),
+                through_keyword(dst_t),
+                dst_rails(dst_t.as_ref()),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -528,4 +605,28 @@ mod tests {
             "spacer-ack fall->rise"
         );
     }
+
+    /// The report script marks each late and early report with its place and reuses the
+    /// constraint qualifiers.
+    #[test]
+    fn test_timing_report_script_marks_places() {
+        let hbcn = solved_with(vec![(
+            data(register("r{0}")),
+            spacer(port("a[1]")),
+            DelayPair::new(None, 3.0),
+        )]);
+
+        let mut output = Cursor::new(Vec::new());
+        write_timing_report_script(&mut output, &hbcn).expect("Should write script");
+        let script = String::from_utf8(output.into_inner()).expect("Should be valid UTF-8");
+
+        assert!(
+            script
+                .contains("hbcn_place max {+{r\\{0\\}} => -{a[1]}}\nreport_timing -unconstrained")
+        );
+        assert!(script.contains("hbcn_place min {+{r\\{0\\}} => -{a[1]}}\nreport_timing -early"));
+        assert_eq!(script.matches("\t-rise_through").count(), 2);
+        assert_eq!(script.matches("\t-fall_through").count(), 2);
+        assert_eq!(script.matches(">> $hbcn_timing_report").count(), 2);
+    }
 }
@@ -0,0 +1,230 @@
+//! Source-located diagnostics for the `.graph` and `.hbcn` input formats.
+//!
+//! Both grammars record the byte range ([`Span`]) of every entry they parse, and the
+//! parse/validation errors built from them carry that range. A [`Diagnostic`] resolves a span
+//! against the source text into a 1-based line/column and renders a caret-annotated snippet in
+//! the familiar compiler style:
+//!
+//! ```text
+//! error: Undefined Element: output
+//!  --> circuit.graph:4:36
+//!   |
+//! 4 |     NullReg "result" [("acc", 30), ("output", 40)]
+//!   |                                    ^^^^^^^^^^^^^^
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::diagnostic::{Diagnostic, Span};
+//!
+//! let source = "Port \"a\" [(\"b\", 10)]\nPort \"c\" []";
+//! let diag = Diagnostic::new(source, Span::new(10, 19), "Undefined Element: b")
+//!     .with_file("circuit.graph");
+//!
+//! assert_eq!((diag.line, diag.column), (1, 11));
+//! assert!(diag.to_string().contains("circuit.graph:1:11"));
+//! ```
+
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A half-open byte range `start..end` into a parsed source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct Span {
+    /// Byte offset of the first character covered by the span.
+    pub start: usize,
+    /// Byte offset one past the last character covered by the span.
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span covering `start..end`.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Resolve the start of this span to a 1-based `(line, column)` pair in `source`.
+    ///
+    /// Columns count characters, not bytes, so multi-byte names line up with the caret.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let start = self.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        (line, column)
+    }
+}
+
+/// Error payload for semantic checks raised from inside a grammar action.
+///
+/// LALRPOP's `User` error carries no location of its own, so the grammars attach the span of
+/// the offending token (for instance an overlong numeric literal) here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserError {
+    /// Human-readable description of the problem.
+    pub message: &'static str,
+    /// Location of the offending token.
+    pub span: Span,
+}
+
+/// Convert a LALRPOP parse error into a message and the span it refers to.
+pub(crate) fn describe_lalrpop_error<T: fmt::Display>(
+    err: &lalrpop_util::ParseError<usize, T, UserError>,
+) -> (String, Span) {
+    use lalrpop_util::ParseError::*;
+
+    fn expected_list(expected: &[String]) -> String {
+        match expected {
+            [] => String::new(),
+            [one] => format!(", expected {}", one),
+            many => format!(", expected one of {}", many.join(", ")),
+        }
+    }
+
+    match err {
+        InvalidToken { location } => (
+            "invalid token".to_string(),
+            Span::new(*location, location + 1),
+        ),
+        UnrecognizedEOF { location, expected } => (
+            format!("unexpected end of input{}", expected_list(expected)),
+            Span::new(*location, *location),
+        ),
+        UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => (
+            format!("unexpected token `{}`{}", token, expected_list(expected)),
+            Span::new(*start, *end),
+        ),
+        ExtraToken {
+            token: (start, token, end),
+        } => (format!("extra token `{}`", token), Span::new(*start, *end)),
+        User { error } => (error.message.to_string(), error.span),
+    }
+}
+
+/// A message anchored to a location in an input file, with a rendered source snippet.
+///
+/// Produced from the parse and validation errors of both input formats (see
+/// [`crate::structural_graph::ParseError::to_diagnostic`] and
+/// [`crate::hbcn::parser::parse_hbcn`]). [`crate::read_file`] and [`crate::read_hbcn_file`]
+/// fill in the file name with [`Diagnostic::with_file`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Description of the problem.
+    pub message: String,
+    /// The input file, when known.
+    pub file: Option<PathBuf>,
+    /// Byte range of the offending input.
+    pub span: Span,
+    /// 1-based line of the start of `span`.
+    pub line: usize,
+    /// 1-based character column of the start of `span`.
+    pub column: usize,
+    /// The source line containing the start of `span`.
+    pub source_line: String,
+    /// Number of characters of `source_line` underlined by the caret (at least one).
+    pub caret_len: usize,
+}
+
+impl Diagnostic {
+    /// Resolve `span` against `source` and build a diagnostic carrying `message`.
+    pub fn new(source: &str, span: Span, message: impl Into<String>) -> Self {
+        let (line, column) = span.line_col(source);
+        let source_line = source.lines().nth(line - 1).unwrap_or("").to_string();
+        // Underline the span, clipped to the end of its first line.
+        let remaining = source_line.chars().count().saturating_sub(column - 1);
+        let span_chars = source
+            .get(span.start.min(source.len())..span.end.min(source.len()))
+            .map_or(0, |s| s.chars().take_while(|&c| c != '\n').count());
+        let caret_len = span_chars.min(remaining).max(1);
+
+        Self {
+            message: message.into(),
+            file: None,
+            span,
+            line,
+            column,
+            source_line,
+            caret_len,
+        }
+    }
+
+    /// Attach the name of the file the diagnostic refers to.
+    pub fn with_file(mut self, file: impl AsRef<Path>) -> Self {
+        self.file = Some(file.as_ref().to_path_buf());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = self
+            .file
+            .as_ref()
+            .map_or_else(|| "<input>".to_string(), |p| p.display().to_string());
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{}--> {}:{}:{}", gutter, file, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column - 1),
+            "^".repeat(self.caret_len)
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_is_one_based() {
+        let src = "abc\ndef\nghi";
+        assert_eq!(Span::new(0, 1).line_col(src), (1, 1));
+        assert_eq!(Span::new(5, 6).line_col(src), (2, 2));
+        assert_eq!(Span::new(8, 11).line_col(src), (3, 1));
+    }
+
+    #[test]
+    fn line_col_counts_characters_not_bytes() {
+        let src = "\"µ\" x";
+        // `x` starts at byte 5 but is the fifth character.
+        assert_eq!(Span::new(5, 6).line_col(src), (1, 5));
+    }
+
+    #[test]
+    fn renders_caret_under_span() {
+        let src = "Port \"a\" []\nPort \"b\" [(\"zz\", 1)]";
+        let start = src.find("(\"zz\"").unwrap();
+        let diag = Diagnostic::new(src, Span::new(start, start + 9), "Undefined Element: zz")
+            .with_file("x.graph");
+        let text = diag.to_string();
+
+        assert_eq!((diag.line, diag.column), (2, 11));
+        assert!(text.contains("error: Undefined Element: zz"));
+        assert!(text.contains("--> x.graph:2:11"));
+        assert!(text.contains("2 | Port \"b\" [(\"zz\", 1)]"));
+        assert!(text.ends_with(&format!("  | {}{}", " ".repeat(10), "^".repeat(9))));
+    }
+
+    #[test]
+    fn eof_span_still_gets_a_caret() {
+        let src = "Port \"a\" [";
+        let diag = Diagnostic::new(src, Span::new(src.len(), src.len()), "unexpected end");
+        assert_eq!(diag.caret_len, 1);
+        assert_eq!(diag.column, src.len() + 1);
+    }
+}
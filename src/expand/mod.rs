@@ -11,8 +11,15 @@
 //! # Workflow
 //!
 //! 1. Parse input as a structural graph
-//! 2. Convert to HBCN representation using `from_structural_graph`
-//! 3. Serialize the HBCN to the output format
+//! 2. Convert to HBCN representation using `from_structural_graph`, or with the delay model
+//!    of a Liberty library when `--liberty` is given (see [`crate::liberty`])
+//! 3. Serialize the HBCN to the output format, behind a `#` comment header naming the
+//!    generator version, source file and options, and a declaration section giving each
+//!    port its direction and each register its cost
+//! 4. Optionally write the HBCN as a `structural_hbcn` JSON document (see [`crate::json`]),
+//!    or as a Petri net in PNML or the `.g` STG format (see [`crate::hbcn::petri`])
+//! 5. Optionally render the structural graph in Graphviz DOT (see
+//!    [`crate::structural_graph::dot`])
 //!
 //! # Example
 //!
@@ -24,6 +31,14 @@
 //!     input: "circuit.graph".into(),
 //!     output: "circuit.hbcn".into(),
 //!     forward_completion: false,
+//!     library: None,
+//!     liberty: None,
+//!     liberty_cells: Default::default(),
+//!     virtual_unit: None,
+//!     json: None,
+//!     pnml: None,
+//!     stg: None,
+//!     dot: None,
 //! };
 //!
 //! expand_main(args)?;
@@ -38,8 +53,10 @@ use clap::Parser;
 use petgraph::graph::NodeIndex;
 
 use crate::{
-    hbcn::{serialisation, *},
-    read_file,
+    hbcn::{declarations::node_declarations, petri, serialisation, *},
+    liberty::LibertyCells,
+    read_delay_model, read_file_with_library, read_library_file,
+    structural_graph::{ComponentLibrary, structural_graph_to_dot},
 };
 
 /// Command-line arguments for the expand command.
@@ -55,6 +72,37 @@ pub struct ExpandArgs {
     /// Enable forward completion delay calculation
     #[clap(long)]
     pub forward_completion: bool,
+
+    /// Component library declaring additional register kinds
+    #[clap(long)]
+    pub library: Option<PathBuf>,
+
+    /// Liberty cell library pricing the expansion in its characterised delays
+    #[clap(long, requires = "virtual_unit")]
+    pub liberty: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub liberty_cells: LibertyCells,
+
+    /// Nanoseconds per unit of the structural graph's channel delays, required with --liberty
+    #[clap(long, value_name = "NS", requires = "liberty")]
+    pub virtual_unit: Option<f64>,
+
+    /// JSON output file with the expanded StructuralHBCN
+    #[clap(long)]
+    pub json: Option<PathBuf>,
+
+    /// PNML output file with the HBCN as a timed Petri net
+    #[clap(long)]
+    pub pnml: Option<PathBuf>,
+
+    /// STG output file with the HBCN in the petrify/Workcraft `.g` format
+    #[clap(long)]
+    pub stg: Option<PathBuf>,
+
+    /// DOT file rendering the structural graph with its register kinds and channel delays
+    #[clap(long)]
+    pub dot: Option<PathBuf>,
 }
 
 /// Convert a structural graph to HBCN representation and write to output file.
@@ -63,7 +111,8 @@ pub struct ExpandArgs {
 ///
 /// 1. Reads and parses the structural graph
 /// 2. Converts to HBCN representation using `from_structural_graph`
-/// 3. Serialises the HBCN to the output file in the parser format
+/// 3. Serialises the HBCN to the output file in the parser format, preceded by a comment
+///    header recording the generator version, source file and options
 ///
 /// # Arguments
 ///
@@ -79,6 +128,14 @@ pub struct ExpandArgs {
 ///     input: "circuit.graph".into(),
 ///     output: "circuit.hbcn".into(),
 ///     forward_completion: false,
+///     library: None,
+///     liberty: None,
+///     liberty_cells: Default::default(),
+///     virtual_unit: None,
+///     json: None,
+///     pnml: None,
+///     stg: None,
+///     dot: None,
 /// };
 ///
 /// expand_main(args)?;
@@ -90,14 +147,28 @@ pub fn expand_main(args: ExpandArgs) -> Result<()> {
         input,
         output,
         forward_completion,
+        library,
+        liberty,
+        liberty_cells,
+        virtual_unit,
+        json,
+        pnml,
+        stg,
+        dot,
     } = args;
 
     // Read and parse the structural graph
-    let graph = read_file(&input)?;
+    let component_library = match &library {
+        Some(library) => read_library_file(library)?,
+        None => ComponentLibrary::default(),
+    };
+    let graph = read_file_with_library(&input, &component_library)?;
+    let delays = read_delay_model(liberty.as_deref(), &liberty_cells, virtual_unit)?;
 
     // Convert to HBCN representation
-    let hbcn = from_structural_graph(&graph, forward_completion)
+    let hbcn = from_structural_graph_with_delays(&graph, forward_completion, &delays)
         .ok_or_else(|| anyhow!("Failed to convert structural graph to HBCN"))?;
+    crate::warn_min_delay_excesses(&hbcn);
 
     // Convert StructuralHBCN (WeightedPlace) to SolvedHBCN (DelayedPlace) for serialisation
     let mut converted_hbcn = SolvedHBCN::new();
@@ -124,10 +195,54 @@ pub fn expand_main(args: ExpandArgs) -> Result<()> {
         converted_hbcn.add_edge(node_map[&src], node_map[&dst], delayed_place);
     }
 
-    // Serialise the HBCN to the output file
-    let serialised = serialisation::serialise_hbcn(&converted_hbcn);
+    // Serialise the HBCN to the output file, recording how it was generated
+    let header = [
+        format!("Generated by hbcn {}", env!("CARGO_PKG_VERSION")),
+        format!("Source: {}", input.display()),
+        {
+            let mut options = format!("Options: forward_completion={}", forward_completion);
+            if let Some(library) = &library {
+                options += &format!(", library={}", library.display());
+            }
+            if let Some(liberty) = &liberty {
+                options += &format!(", liberty={}", liberty.display());
+            }
+            if let Some(virtual_unit) = virtual_unit {
+                options += &format!(", virtual_unit={}", virtual_unit);
+            }
+            options
+        },
+    ];
+    let declarations = node_declarations(&graph);
+    let serialised =
+        serialisation::serialise_hbcn_with_declarations(&converted_hbcn, &header, &declarations);
     fs::write(&output, serialised)?;
 
+    if let Some(json) = json {
+        fs::write(
+            json,
+            crate::json::structural_hbcn_to_json_with_declarations(&hbcn, &declarations),
+        )?;
+    }
+
+    // Petri-net exports are named after the input file
+    let name = input
+        .file_stem()
+        .map_or_else(|| "hbcn".into(), |stem| stem.to_string_lossy());
+    if let Some(pnml) = pnml {
+        fs::write(
+            pnml,
+            petri::serialise_timed_pnml(&converted_hbcn, &name, &header),
+        )?;
+    }
+    if let Some(stg) = stg {
+        fs::write(stg, petri::serialise_stg(&converted_hbcn, &name, &header))?;
+    }
+
+    if let Some(dot) = dot {
+        fs::write(dot, structural_graph_to_dot(&graph, &component_library))?;
+    }
+
     Ok(())
 }
 
@@ -189,4 +304,82 @@ mod tests {
         // Should have tokens (one place marked per channel)
         assert!(output.contains("* "));
     }
+
+    #[test]
+    fn test_expand_writes_comment_header() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("pair.graph");
+        let output = dir.path().join("pair.hbcn");
+        fs::write(&input, "Port \"a\" [(\"b\", 10)]\nPort \"b\" []\n").unwrap();
+
+        expand_main(ExpandArgs {
+            input: input.clone(),
+            output: output.clone(),
+            forward_completion: true,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .unwrap();
+
+        let text = fs::read_to_string(&output).unwrap();
+        let header: Vec<&str> = text.lines().take_while(|l| l.starts_with('#')).collect();
+        assert_eq!(
+            header,
+            [
+                format!("# Generated by hbcn {}", env!("CARGO_PKG_VERSION")).as_str(),
+                format!("# Source: {}", input.display()).as_str(),
+                "# Options: forward_completion=true",
+            ]
+        );
+
+        // The header is a comment, so the output still parses, with the ports declared
+        let body: Vec<&str> = text.lines().skip(header.len()).take(3).collect();
+        assert_eq!(body, ["port {a} input", "port {b} output", ""]);
+        let hbcn = crate::hbcn::parser::parse_hbcn(&text).unwrap();
+        assert_eq!(hbcn.edge_count(), 4);
+        assert!(hbcn.node_indices().all(|idx| matches!(
+            AsRef::<CircuitNode>::as_ref(&hbcn[idx]),
+            CircuitNode::Port(_)
+        )));
+    }
+
+    #[test]
+    fn test_expand_declares_prefixed_register() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("prefixed.graph");
+        let output = dir.path().join("prefixed.hbcn");
+        fs::write(
+            &input,
+            "Port \"a\" [(\"port:r\", 10)]\nNullReg \"port:r\" [(\"b\", 10)]\nPort \"b\" []\n",
+        )
+        .unwrap();
+
+        expand_main(ExpandArgs {
+            input,
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .unwrap();
+
+        // The declaration overrides the prefix, so the register reads back as one
+        let hbcn = crate::hbcn::parser::parse_hbcn(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert!(hbcn.node_indices().any(|idx| matches!(
+            AsRef::<CircuitNode>::as_ref(&hbcn[idx]),
+            CircuitNode::Register(name) if name.as_ref() == "port:r"
+        )));
+    }
 }
@@ -0,0 +1,293 @@
+//! Node declarations of the `.hbcn` text format.
+//!
+//! An `.hbcn` file may open with a declaration section naming each circuit node with its
+//! kind and optional attributes, ahead of the places:
+//!
+//! ```text
+//! port {a} input
+//! port {b} output
+//! register {acc} data cost 10
+//! register {ctl} control cost 50
+//! ```
+//!
+//! A declared node is a port or register regardless of its name. A node without a
+//! declaration falls back to the naming convention: names starting with `port:` are ports,
+//! all others are registers (see [`undeclared_node`]).
+//!
+//! The parser checks a port's direction against its places (see
+//! [`parse_hbcn_with_declarations`](super::parser::parse_hbcn_with_declarations)). The
+//! register class and cost only describe the circuit and are written back unchanged.
+
+use super::{CircuitNode, HBCN, Named, Transition, is_backward_place};
+use crate::Symbol;
+use crate::structural_graph::{CircuitNode as StructuralCircuitNode, StructuralGraph};
+use petgraph::Direction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Direction of a declared port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortDirection {
+    /// The port only drives channels into the circuit (`input`).
+    Input,
+    /// The port only receives channels from the circuit (`output`).
+    Output,
+    /// The port both drives and receives channels (`inout`).
+    InOut,
+}
+
+/// Class of a declared register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterClass {
+    /// A register holding data (`data`).
+    Data,
+    /// A register in the control path (`control`).
+    Control,
+}
+
+/// Kind of a declared node, with its kind-specific attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// A port, optionally with its direction.
+    Port(Option<PortDirection>),
+    /// A register, optionally with its class.
+    Register(Option<RegisterClass>),
+}
+
+/// A node declaration of an `.hbcn` file.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::CircuitNode;
+/// use hbcn::hbcn::declarations::{NodeDeclaration, NodeKind, PortDirection};
+///
+/// let declaration = NodeDeclaration {
+///     name: "a".into(),
+///     kind: NodeKind::Port(Some(PortDirection::Input)),
+///     cost: None,
+/// };
+/// assert_eq!(declaration.to_string(), "port {a} input");
+/// assert_eq!(declaration.circuit_node(), CircuitNode::Port("a".into()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeDeclaration {
+    /// Name of the node, as used by the places.
+    pub name: Symbol,
+    /// Kind of the node.
+    pub kind: NodeKind,
+    /// Cost of the node, when declared.
+    pub cost: Option<usize>,
+}
+
+impl NodeDeclaration {
+    /// The circuit node this declaration gives the places naming it.
+    pub fn circuit_node(&self) -> CircuitNode {
+        match self.kind {
+            NodeKind::Port(_) => CircuitNode::Port(self.name.clone()),
+            NodeKind::Register(_) => CircuitNode::Register(self.name.clone()),
+        }
+    }
+}
+
+impl fmt::Display for PortDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PortDirection::Input => "input",
+            PortDirection::Output => "output",
+            PortDirection::InOut => "inout",
+        })
+    }
+}
+
+impl fmt::Display for RegisterClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegisterClass::Data => "data",
+            RegisterClass::Control => "control",
+        })
+    }
+}
+
+/// Renders the declaration in the `.hbcn` syntax, without a trailing newline.
+impl fmt::Display for NodeDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Escape braces in TCL-style, as for the names in places
+        let name = self.name.replace('{', "\\{").replace('}', "\\}");
+        match self.kind {
+            NodeKind::Port(direction) => {
+                write!(f, "port {{{}}}", name)?;
+                if let Some(direction) = direction {
+                    write!(f, " {}", direction)?;
+                }
+            }
+            NodeKind::Register(class) => {
+                write!(f, "register {{{}}}", name)?;
+                if let Some(class) = class {
+                    write!(f, " {}", class)?;
+                }
+            }
+        }
+        if let Some(cost) = self.cost {
+            write!(f, " cost {}", cost)?;
+        }
+        Ok(())
+    }
+}
+
+/// The circuit node of an undeclared name: a port if it starts with `port:`, otherwise a
+/// register.
+pub fn undeclared_node(name: &Symbol) -> CircuitNode {
+    if name.as_ref().starts_with("port:") {
+        CircuitNode::Port(name.clone())
+    } else {
+        CircuitNode::Register(name.clone())
+    }
+}
+
+/// The first of `declarations` naming a port whose places contradict its direction: a port
+/// declared `output` that drives a channel, or `input` that receives one. Returns the
+/// position of the declaration and a message describing the offending channel.
+pub(crate) fn misdirected_port<'a, P>(
+    hbcn: &HBCN<Transition, P>,
+    declarations: impl IntoIterator<Item = &'a NodeDeclaration>,
+) -> Option<(usize, String)> {
+    let declarations: Vec<&NodeDeclaration> = declarations.into_iter().collect();
+    let directions: HashMap<&str, PortDirection> = declarations
+        .iter()
+        .filter_map(|declaration| match declaration.kind {
+            NodeKind::Port(Some(direction)) => Some((declaration.name.as_ref(), direction)),
+            _ => None,
+        })
+        .collect();
+    if directions.is_empty() {
+        return None;
+    }
+
+    // The forward places of a channel run from the node driving it to the one receiving it
+    let mut misdirected: HashMap<&str, String> = HashMap::new();
+    for ie in hbcn.edge_indices() {
+        let (src, dst) = hbcn.edge_endpoints(ie).unwrap();
+        let (src, dst) = (&hbcn[src], &hbcn[dst]);
+        let (driver, receiver) = (src.name().as_ref(), dst.name().as_ref());
+        if is_backward_place(src, dst) || driver == receiver {
+            continue;
+        }
+        let violation = match (directions.get(driver), directions.get(receiver)) {
+            (Some(PortDirection::Output), _) => Some((
+                driver,
+                format!(
+                    "output port '{}' drives a channel to '{}'",
+                    driver, receiver
+                ),
+            )),
+            (_, Some(PortDirection::Input)) => Some((
+                receiver,
+                format!(
+                    "input port '{}' receives a channel from '{}'",
+                    receiver, driver
+                ),
+            )),
+            _ => None,
+        };
+        if let Some((name, message)) = violation {
+            misdirected.entry(name).or_insert(message);
+        }
+    }
+    declarations
+        .iter()
+        .enumerate()
+        .find_map(|(i, declaration)| {
+            misdirected
+                .get(declaration.name.as_ref())
+                .map(|message| (i, message.clone()))
+        })
+}
+
+/// Declarations of the nodes of a structural graph, in node order.
+///
+/// Ports are declared with the direction of their channels and registers with their cost;
+/// the register class is not recorded by a structural graph, so it is left out. Nodes
+/// without any channel have no transitions in the HBCN and are not declared.
+pub fn node_declarations(graph: &StructuralGraph) -> Vec<NodeDeclaration> {
+    graph
+        .node_indices()
+        .filter_map(|idx| {
+            let drives = graph.edges_directed(idx, Direction::Outgoing).count() > 0;
+            let receives = graph.edges_directed(idx, Direction::Incoming).count() > 0;
+            match &graph[idx] {
+                StructuralCircuitNode::Port(name) => {
+                    let direction = match (drives, receives) {
+                        (true, false) => PortDirection::Input,
+                        (false, true) => PortDirection::Output,
+                        (true, true) => PortDirection::InOut,
+                        (false, false) => return None,
+                    };
+                    Some(NodeDeclaration {
+                        name: name.clone(),
+                        kind: NodeKind::Port(Some(direction)),
+                        cost: None,
+                    })
+                }
+                StructuralCircuitNode::Register { name, cost } => {
+                    (drives || receives).then(|| NodeDeclaration {
+                        name: name.clone(),
+                        kind: NodeKind::Register(None),
+                        cost: Some(*cost),
+                    })
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structural_graph::parse;
+
+    #[test]
+    fn declarations_of_structural_graph() {
+        let graph = parse(
+            r#"
+            Port "a" [("r", 20)]
+            DataReg "r" [("b", 20), ("io", 5)]
+            Port "io" [("r", 5)]
+            Port "b" []
+            Port "unused" []
+            "#,
+        )
+        .unwrap();
+
+        let text: Vec<String> = node_declarations(&graph)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            text,
+            [
+                "port {a} input",
+                "register {r} cost 10",
+                "register {r/s0} cost 10",
+                "register {r/s1} cost 10",
+                "port {io} inout",
+                "port {b} output",
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_nodes_follow_the_prefix() {
+        assert_eq!(
+            undeclared_node(&"port:a".into()),
+            CircuitNode::Port("port:a".into())
+        );
+        assert_eq!(
+            undeclared_node(&"a".into()),
+            CircuitNode::Register("a".into())
+        );
+    }
+}
@@ -85,19 +85,23 @@
 //! - **[`CircuitNode`]**: Simplified circuit node representation (without cost field)
 //! - **[`DelayPair`]**: Min/max delay constraint representation used in timing analysis
 
+pub mod declarations;
 pub mod parser;
+pub mod petri;
 pub mod serialisation;
 pub mod structural_graph;
 #[cfg(test)]
 pub mod test_helpers;
-pub use structural_graph::from_structural_graph;
+pub use structural_graph::{
+    DelayModel, from_structural_graph, from_structural_graph_with_delays, min_delay_excesses,
+};
 
 use crate::Symbol;
 use crate::structural_graph::CircuitNode as StructuralCircuitNode;
-use anyhow::{Result, bail};
+use anyhow::Result;
 use petgraph::graph::{EdgeIndex, NodeIndex};
 use petgraph::stable_graph::StableGraph;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
 
 /// Trait for types that have a name.
@@ -335,6 +339,17 @@ pub fn is_backward_place(src: &Transition, dst: &Transition) -> bool {
     )
 }
 
+/// Kind of the place between two transitions, as named in the reports: `Data Prop` and
+/// `Null Prop` for forward places, `Data Ack` and `Null Ack` for backward ones.
+pub(crate) fn place_kind(src: &Transition, dst: &Transition) -> &'static str {
+    match (src, dst) {
+        (Transition::Data(_), Transition::Data(_)) => "Data Prop",
+        (Transition::Spacer(_), Transition::Spacer(_)) => "Null Prop",
+        (Transition::Data(_), Transition::Spacer(_)) => "Data Ack",
+        (Transition::Spacer(_), Transition::Data(_)) => "Null Ack",
+    }
+}
+
 /// Represents a place (edge) in the HBCN graph.
 ///
 /// Places model timing dependencies between transitions in the HBCN. They represent
@@ -546,7 +561,9 @@ impl TimedEvent for TransitionEvent {
 /// A place with an associated weight/delay value.
 ///
 /// This represents a place that has a weight (delay/cost) associated with it.
-/// The `weight` field contains the delay or cost value for this place.
+/// The `weight` field contains the delay or cost value for this place; `min_delay`
+/// optionally records a lower bound, which becomes the `min` of the place's
+/// [`DelayPair`] when converted to a [`DelayedPlace`].
 ///
 /// `WeightedPlace` is used in [`StructuralHBCN`] to represent places with weights.
 ///
@@ -563,6 +580,7 @@ impl TimedEvent for TransitionEvent {
 /// let weighted_place = WeightedPlace {
 ///     place,
 ///     weight: 10.0,
+///     min_delay: None,
 /// };
 /// ```
 #[derive(Debug, Clone, Default)]
@@ -571,6 +589,8 @@ pub struct WeightedPlace {
     pub place: Place,
     /// The delay/cost weight of this place.
     pub weight: f64,
+    /// Optional minimum delay of this place.
+    pub min_delay: Option<f64>,
 }
 
 impl HasWeight for WeightedPlace {
@@ -601,7 +621,7 @@ impl From<WeightedPlace> for DelayedPlace {
     fn from(w: WeightedPlace) -> Self {
         DelayedPlace {
             place: w.place,
-            delay: DelayPair::new(None, w.weight),
+            delay: DelayPair::new(w.min_delay, w.weight),
             slack: None,
         }
     }
@@ -653,6 +673,41 @@ impl SlackablePlace for DelayedPlace {
     }
 }
 
+/// Error reported by [`validate_hbcn`].
+///
+/// Alongside the message it records the place (and the transition, where one is singled
+/// out) at which the violation was found. A caller that knows where each element was
+/// declared — such as [`parser::parse_hbcn`] — uses these to point at the offending source
+/// line. The error travels inside [`anyhow::Error`]; recover it with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Description of the violated rule.
+    pub message: String,
+    /// The offending place, or a place of the offending channel.
+    pub place: Option<EdgeIndex>,
+    /// The offending transition, when the rule concerns a single transition.
+    pub transition: Option<NodeIndex>,
+}
+
+impl ValidationError {
+    fn at_place(place: EdgeIndex, message: String) -> anyhow::Error {
+        ValidationError {
+            message,
+            place: Some(place),
+            transition: None,
+        }
+        .into()
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
 /// Validate an HBCN according to the pairing and marking rules.
 ///
 /// The validation rules are:
@@ -668,7 +723,8 @@ impl SlackablePlace for DelayedPlace {
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` if validation passes, or an `Error` if validation fails.
+/// Returns `Ok(())` if validation passes, or an `Error` wrapping a [`ValidationError`] if
+/// validation fails.
 ///
 /// # Example
 ///
@@ -692,18 +748,25 @@ pub fn validate_hbcn<T: AsRef<Transition>, P: MarkablePlace>(hbcn: &HBCN<T, P>)
         };
         if src == dst {
             let transition = hbcn[src].as_ref();
-            return Err(anyhow::anyhow!(
-                "Invalid HBCN: found self-loop - transition {} has an edge connecting to itself",
-                transition
-            ));
+            return Err(ValidationError {
+                message: format!(
+                    "Invalid HBCN: found self-loop - transition {} has an edge connecting to itself",
+                    transition
+                ),
+                place: Some(edge_idx),
+                transition: Some(src),
+            }
+            .into());
         }
         if edge_map.insert((src, dst), edge_idx).is_some() {
             let src_transition = hbcn[src].as_ref();
             let dst_transition = hbcn[dst].as_ref();
-            return Err(anyhow::anyhow!(
-                "Invalid HBCN: found duplicate place between transitions {} and {}",
-                src_transition,
-                dst_transition
+            return Err(ValidationError::at_place(
+                edge_idx,
+                format!(
+                    "Invalid HBCN: found duplicate place between transitions {} and {}",
+                    src_transition, dst_transition
+                ),
             ));
         }
     }
@@ -734,33 +797,35 @@ pub fn validate_hbcn<T: AsRef<Transition>, P: MarkablePlace>(hbcn: &HBCN<T, P>)
     // - Spacer(a) -> Spacer(b) reveals channel (a, b)
     // - Data(b) -> Spacer(a) reveals channel (a, b) (backward pairing)
     // - Spacer(b) -> Data(a) reveals channel (a, b) (backward pairing)
-    let channel_pairs: HashSet<(CircuitNode, CircuitNode)> = hbcn
-        .edge_indices()
-        .filter_map(|edge_idx| hbcn.edge_endpoints(edge_idx))
-        .map(|(src_idx, dst_idx)| {
-            let src_transition = hbcn[src_idx].as_ref();
-            let dst_transition = hbcn[dst_idx].as_ref();
-
-            match (src_transition, dst_transition) {
-                // Data(a) -> Data(b) reveals channel (a, b)
-                (Transition::Data(node_a), Transition::Data(node_b)) => {
-                    (node_a.clone(), node_b.clone())
-                }
-                // Spacer(a) -> Spacer(b) reveals channel (a, b)
-                (Transition::Spacer(node_a), Transition::Spacer(node_b)) => {
-                    (node_a.clone(), node_b.clone())
-                }
-                // Data(b) -> Spacer(a) reveals channel (a, b) (backward pairing)
-                (Transition::Data(node_b), Transition::Spacer(node_a)) => {
-                    (node_a.clone(), node_b.clone())
-                }
-                // Spacer(b) -> Data(a) reveals channel (a, b) (backward pairing)
-                (Transition::Spacer(node_b), Transition::Data(node_a)) => {
-                    (node_a.clone(), node_b.clone())
-                }
+    // The first place seen for each channel is kept so errors can point at the channel.
+    let mut channel_pairs: HashMap<(CircuitNode, CircuitNode), EdgeIndex> = HashMap::new();
+    for edge_idx in hbcn.edge_indices() {
+        let Some((src_idx, dst_idx)) = hbcn.edge_endpoints(edge_idx) else {
+            continue;
+        };
+        let src_transition = hbcn[src_idx].as_ref();
+        let dst_transition = hbcn[dst_idx].as_ref();
+
+        let pair = match (src_transition, dst_transition) {
+            // Data(a) -> Data(b) reveals channel (a, b)
+            (Transition::Data(node_a), Transition::Data(node_b)) => {
+                (node_a.clone(), node_b.clone())
+            }
+            // Spacer(a) -> Spacer(b) reveals channel (a, b)
+            (Transition::Spacer(node_a), Transition::Spacer(node_b)) => {
+                (node_a.clone(), node_b.clone())
+            }
+            // Data(b) -> Spacer(a) reveals channel (a, b) (backward pairing)
+            (Transition::Data(node_b), Transition::Spacer(node_a)) => {
+                (node_a.clone(), node_b.clone())
+            }
+            // Spacer(b) -> Data(a) reveals channel (a, b) (backward pairing)
+            (Transition::Spacer(node_b), Transition::Data(node_a)) => {
+                (node_a.clone(), node_b.clone())
             }
-        })
-        .collect();
+        };
+        channel_pairs.entry(pair).or_insert(edge_idx);
+    }
 
     // Validate each channel pair once
     // For a channel between node_a and node_b, we need exactly 4 places:
@@ -769,16 +834,18 @@ pub fn validate_hbcn<T: AsRef<Transition>, P: MarkablePlace>(hbcn: &HBCN<T, P>)
     // 3. Spacer(node_a) -> Spacer(node_b): forward spacer flow
     // 4. Spacer(node_b) -> Data(node_a): backward acknowledgment for spacer
     // Exactly one of these 4 places must be marked (have a token)
-    channel_pairs.into_iter().try_fold((), |_, (node_a, node_b)| {
+    channel_pairs.into_iter().try_fold((), |_, ((node_a, node_b), channel_place)| {
+        let fail = |message: String| ValidationError::at_place(channel_place, message);
+
         // Get transition node indices for both circuit nodes
         let data_a = node_to_data.get(&node_a)
-            .ok_or_else(|| anyhow::anyhow!("Channel validation failed: missing Data transition for node {}", node_a))?;
+            .ok_or_else(|| fail(format!("Channel validation failed: missing Data transition for node {}", node_a)))?;
         let data_b = node_to_data.get(&node_b)
-            .ok_or_else(|| anyhow::anyhow!("Channel validation failed: missing Data transition for node {}", node_b))?;
+            .ok_or_else(|| fail(format!("Channel validation failed: missing Data transition for node {}", node_b)))?;
         let spacer_a = node_to_spacer.get(&node_a)
-            .ok_or_else(|| anyhow::anyhow!("Channel validation failed: missing Spacer transition for node {}", node_a))?;
+            .ok_or_else(|| fail(format!("Channel validation failed: missing Spacer transition for node {}", node_a)))?;
         let spacer_b = node_to_spacer.get(&node_b)
-            .ok_or_else(|| anyhow::anyhow!("Channel validation failed: missing Spacer transition for node {}", node_b))?;
+            .ok_or_else(|| fail(format!("Channel validation failed: missing Spacer transition for node {}", node_b)))?;
 
         // Find all four required places and check their markings
         let forward_data_place = edge_map.get(&(*data_a, *data_b));
@@ -802,43 +869,43 @@ pub fn validate_hbcn<T: AsRef<Transition>, P: MarkablePlace>(hbcn: &HBCN<T, P>)
 
         // Validate that all required places exist
         if forward_data_place.is_none() {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} is missing the forward data place: Data({}) -> Data({})",
                 node_a,
                 node_b,
                 node_a,
                 node_b
-            );
+            )));
         }
 
         if backward_data_ack_place.is_none() {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} is missing the backward acknowledgment place: Data({}) -> Spacer({})",
                 node_a,
                 node_b,
                 node_b,
                 node_a
-            );
+            )));
         }
 
         if forward_spacer_place.is_none() {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} is missing the forward spacer place: Spacer({}) -> Spacer({})",
                 node_a,
                 node_b,
                 node_a,
                 node_b
-            );
+            )));
         }
 
         if backward_spacer_ack_place.is_none() {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} is missing the backward spacer acknowledgment place: Spacer({}) -> Data({})",
                 node_a,
                 node_b,
                 node_b,
                 node_a
-            );
+            )));
         }
 
         // Count how many places are marked
@@ -853,20 +920,20 @@ pub fn validate_hbcn<T: AsRef<Transition>, P: MarkablePlace>(hbcn: &HBCN<T, P>)
         .count();
 
         if marked_count == 0 {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} has no marked places. Exactly one of the four places must be marked (have a token).",
                 node_a,
                 node_b
-            );
+            )));
         }
 
         if marked_count > 1 {
-            bail!(
+            return Err(fail(format!(
                 "Channel from {} to {} has {} marked places. Exactly one of the four places must be marked (have a token).",
                 node_a,
                 node_b,
                 marked_count
-            );
+            )));
         }
 
         Ok(())
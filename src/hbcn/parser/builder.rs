@@ -0,0 +1,175 @@
+//! Incremental construction of an HBCN while its text is being parsed.
+//!
+//! The grammar hands every declaration and place to an [`HbcnBuilder`] as soon as it is
+//! reduced, so the places never exist as a list of their own: each one becomes an edge of
+//! the graph straight away. Node names are interned once, on first mention; later mentions
+//! are looked up by their text without allocating.
+
+use crate::Symbol;
+use crate::diagnostic::Span;
+use crate::hbcn::declarations::{NodeDeclaration, misdirected_port, undeclared_node};
+use crate::hbcn::{CircuitNode, DelayPair, DelayedPlace, HBCN, Place, Transition};
+use petgraph::graph::NodeIndex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A transition as written in a place: its sign (`true` for data) and its escaped name.
+pub type RawTransition<'input> = (bool, &'input str);
+
+/// Builds an HBCN from the declarations and places of a file, in file order.
+pub struct HbcnBuilder<'input> {
+    hbcn: HBCN<Transition, DelayedPlace>,
+    declarations: Vec<(NodeDeclaration, Span)>,
+    declared: HashMap<Symbol, CircuitNode>,
+    transitions: HashMap<(Cow<'input, str>, bool), NodeIndex>,
+    /// Span of the place that first mentions each transition, by node index.
+    transition_spans: Vec<Span>,
+    /// Span of each place, by edge index.
+    place_spans: Vec<Span>,
+    /// The first semantic error met while building, reported once parsing ends.
+    error: Option<(String, Span)>,
+}
+
+/// The result of [`HbcnBuilder::finish`].
+pub struct BuiltHbcn {
+    pub hbcn: HBCN<Transition, DelayedPlace>,
+    pub declarations: Vec<(NodeDeclaration, Span)>,
+    pub transition_spans: Vec<Span>,
+    pub place_spans: Vec<Span>,
+}
+
+impl<'input> HbcnBuilder<'input> {
+    /// A builder with room for `places` places; each node of an HBCN has two transitions
+    /// and takes part in at least one channel of four places, so half as many transitions
+    /// are reserved.
+    pub fn with_capacity(places: usize) -> Self {
+        Self {
+            hbcn: HBCN::with_capacity(places / 2, places),
+            declarations: Vec::new(),
+            declared: HashMap::new(),
+            transitions: HashMap::with_capacity(places / 2),
+            transition_spans: Vec::with_capacity(places / 2),
+            place_spans: Vec::with_capacity(places),
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, message: String, span: Span) {
+        self.error.get_or_insert((message, span));
+    }
+
+    /// Record a node declaration; the grammar puts all of them before the first place.
+    pub fn declare(&mut self, declaration: NodeDeclaration, span: Span) {
+        if self
+            .declared
+            .insert(declaration.name.clone(), declaration.circuit_node())
+            .is_some()
+        {
+            self.fail(
+                format!("node '{}' declared more than once", declaration.name),
+                span,
+            );
+        }
+        self.declarations.push((declaration, span));
+    }
+
+    /// Add a place, creating its transitions on first mention.
+    pub fn place(
+        &mut self,
+        source: RawTransition<'input>,
+        target: RawTransition<'input>,
+        delay: DelayPair,
+        token: bool,
+        span: Span,
+    ) {
+        let source = self.transition(source, span);
+        let target = self.transition(target, span);
+        self.hbcn.add_edge(
+            source,
+            target,
+            DelayedPlace {
+                place: Place {
+                    token,
+                    is_internal: false,
+                },
+                delay,
+                slack: None,
+            },
+        );
+        self.place_spans.push(span);
+    }
+
+    fn transition(&mut self, (data, raw): RawTransition<'input>, span: Span) -> NodeIndex {
+        let name = unescape(raw);
+        if let Some(&idx) = self.transitions.get(&(name.clone(), data)) {
+            return idx;
+        }
+
+        let symbol = Symbol::from(name.as_ref());
+        let circuit_node = self
+            .declared
+            .get(&symbol)
+            .cloned()
+            .unwrap_or_else(|| undeclared_node(&symbol));
+        let idx = self.hbcn.add_node(if data {
+            Transition::Data(circuit_node)
+        } else {
+            Transition::Spacer(circuit_node)
+        });
+        self.transitions.insert((name, data), idx);
+        self.transition_spans.push(span);
+        idx
+    }
+
+    /// Finish building, failing with the first error met: a repeated declaration, a
+    /// declaration naming a node no place mentions, or a port declared `input` that
+    /// receives a channel or `output` that drives one.
+    pub fn finish(self) -> Result<BuiltHbcn, (String, Span)> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        // A declaration nothing refers to is most likely a misspelt name
+        let mentioned = |name: &str| {
+            [true, false]
+                .into_iter()
+                .any(|data| self.transitions.contains_key(&(Cow::Borrowed(name), data)))
+        };
+        if let Some((declaration, span)) = self
+            .declarations
+            .iter()
+            .find(|(declaration, _)| !mentioned(declaration.name.as_ref()))
+        {
+            return Err((
+                format!(
+                    "declared node '{}' does not appear in any place",
+                    declaration.name
+                ),
+                *span,
+            ));
+        }
+
+        if let Some((i, message)) = misdirected_port(
+            &self.hbcn,
+            self.declarations.iter().map(|(declaration, _)| declaration),
+        ) {
+            return Err((message, self.declarations[i].1));
+        }
+
+        Ok(BuiltHbcn {
+            hbcn: self.hbcn,
+            declarations: self.declarations,
+            transition_spans: self.transition_spans,
+            place_spans: self.place_spans,
+        })
+    }
+}
+
+/// Undo the TCL-style brace escapes of a node name, borrowing when there are none.
+pub fn unescape(raw: &str) -> Cow<'_, str> {
+    if raw.contains('\\') {
+        Cow::Owned(raw.replace("\\{", "{").replace("\\}", "}"))
+    } else {
+        Cow::Borrowed(raw)
+    }
+}
@@ -1,4 +1,4 @@
-mod ast;
+mod builder;
 
 // Include the generated parser with clippy warnings suppressed
 #[allow(clippy::all)]
@@ -13,17 +13,23 @@ mod parser {
     include!(concat!(env!("OUT_DIR"), "/hbcn/parser/parser.rs"));
 }
 
+use crate::diagnostic::{Diagnostic, Span, describe_lalrpop_error};
+use crate::hbcn::declarations::NodeDeclaration;
 use crate::hbcn::{
-    CircuitNode, DelayedPlace, HBCN, Place, Transition, insert_default_tokens, validate_hbcn,
+    DelayedPlace, HBCN, Transition, ValidationError, insert_default_tokens, validate_hbcn,
 };
+use crate::lexer::Lexer;
 use anyhow::Result;
-use std::collections::{HashMap, HashSet};
+pub(crate) use builder::unescape;
+use builder::{BuiltHbcn, HbcnBuilder};
 
 /// Parse an HBCN from the grammar format.
 ///
 /// This function parses the HBCN format defined in the grammar and generates a
-/// `HBCN<Transition, DelayedPlace>`. Node names are used to determine if a circuit node
-/// is a register or a port: all names starting with "port:" are ports, all others are registers.
+/// `HBCN<Transition, DelayedPlace>`. Nodes named in the optional declaration section are
+/// ports or registers as declared; for any other node the name decides: all names starting
+/// with "port:" are ports, all others are registers. Use [`parse_hbcn_with_declarations`]
+/// to also get the declared attributes.
 ///
 /// Any channel that marks no place has a default token inserted at its spacer-acknowledge
 /// place (`Spacer(b) => Data(a)`) by `insert_default_tokens`; the HBCN is then validated
@@ -36,7 +42,9 @@ use std::collections::{HashMap, HashSet};
 /// # Returns
 ///
 /// Returns `Ok(HBCN<Transition, DelayedPlace>)` if parsing and validation succeed,
-/// or an `Error` if parsing fails or validation fails.
+/// or an `Error` if parsing fails or validation fails. Both kinds of failure are reported
+/// as a [`Diagnostic`] pointing at the offending line: the unexpected token for syntax
+/// errors, and the place (or channel) that breaks the rules for validation errors.
 ///
 /// # Example
 ///
@@ -54,78 +62,125 @@ use std::collections::{HashMap, HashSet};
 /// assert!(hbcn.node_count() > 0);
 /// ```
 pub fn parse_hbcn(input: &str) -> Result<HBCN<Transition, DelayedPlace>> {
-    let adjacency_list = parser::AdjacencyListParser::new()
-        .parse(input)
-        .map_err(|e| anyhow::anyhow!("Failed to parse HBCN input: {}", e))?;
-
-    // Collect all unique transitions
-    let unique_transitions: HashSet<_> = adjacency_list
-        .iter()
-        .flat_map(|entry| [&entry.source, &entry.target])
-        .collect();
-
-    // Build graph nodes and create transition map
-    let (hbcn, transition_map) = unique_transitions.into_iter().fold(
-        (HBCN::new(), HashMap::new()),
-        |(mut graph, mut map), ast_trans| {
-            let circuit_node = match ast_trans {
-                ast::Transition::Data(sym) | ast::Transition::Spacer(sym) => {
-                    if sym.as_ref().starts_with("port:") {
-                        CircuitNode::Port(sym.clone())
-                    } else {
-                        CircuitNode::Register(sym.clone())
-                    }
-                }
-            };
-
-            let hbcn_transition = match ast_trans {
-                ast::Transition::Data(_) => Transition::Data(circuit_node),
-                ast::Transition::Spacer(_) => Transition::Spacer(circuit_node),
-            };
-
-            let node_idx = graph.add_node(hbcn_transition);
-            map.insert(ast_trans.clone(), node_idx);
-            (graph, map)
-        },
-    );
-
-    // Add edges
-    let mut hbcn = adjacency_list.iter().fold(hbcn, |mut graph, entry| {
-        let source_idx = transition_map[&entry.source];
-        let target_idx = transition_map[&entry.target];
-
-        graph.add_edge(
-            source_idx,
-            target_idx,
-            DelayedPlace {
-                place: Place {
-                    token: entry.token,
-                    is_internal: false,
-                },
-                delay: entry.delay.clone(),
-                slack: None,
-            },
-        );
-        graph
-    });
+    parse_hbcn_with_declarations(input).map(|(hbcn, _)| hbcn)
+}
+
+/// Parse an HBCN like [`parse_hbcn`], also returning its node declarations in file order.
+///
+/// A node may be declared only once, and every declared node must appear in a place. A
+/// port declared `input` may not receive a channel, nor one declared `output` drive one.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::parser::parse_hbcn_with_declarations;
+/// use hbcn::hbcn::declarations::{NodeKind, PortDirection};
+/// use hbcn::hbcn::CircuitNode;
+///
+/// let input = r#"
+///     port {in} input
+///     register {reg1} cost 10
+///     * +{in} => +{reg1} : (1.0, 2.0)
+///     +{reg1} => -{in} : (0.5, 1.5)
+///     -{in} => -{reg1} : (0.5, 1.0)
+///     -{reg1} => +{in} : (0.0, 1.0)
+/// "#;
+///
+/// let (hbcn, declarations) = parse_hbcn_with_declarations(input).unwrap();
+/// assert_eq!(declarations[0].kind, NodeKind::Port(Some(PortDirection::Input)));
+/// assert_eq!(declarations[1].cost, Some(10));
+/// let port = CircuitNode::Port("in".into());
+/// assert!(hbcn.node_indices().any(|idx| AsRef::<CircuitNode>::as_ref(&hbcn[idx]) == &port));
+/// ```
+pub fn parse_hbcn_with_declarations(
+    input: &str,
+) -> Result<(HBCN<Transition, DelayedPlace>, Vec<NodeDeclaration>)> {
+    // Places are added to the graph as they are parsed, with room reserved for about one
+    // place per line, which is how `expand` writes them
+    let lines = input.bytes().filter(|&b| b == b'\n').count() + 1;
+    let mut builder = HbcnBuilder::with_capacity(lines);
+    parser::HbcnFileParser::new()
+        .parse(&mut builder, Lexer::hbcn(input))
+        .map_err(|e| {
+            let (message, span) = describe_lalrpop_error(&e);
+            Diagnostic::new(
+                input,
+                span,
+                format!("Failed to parse HBCN input: {}", message),
+            )
+        })?;
+    let BuiltHbcn {
+        mut hbcn,
+        declarations,
+        transition_spans,
+        place_spans,
+    } = builder
+        .finish()
+        .map_err(|(message, span)| Diagnostic::new(input, span, message))?;
 
     // Default the marking of any token-less channel before validating, so a hand-written
     // HBCN may omit the `*` on a channel and have a token inserted at its spacer-acknowledge
     // place (the canonical reset position).
     insert_default_tokens(&mut hbcn);
 
-    // Validate the HBCN
-    validate_hbcn(&hbcn).map_err(|e| anyhow::anyhow!("HBCN validation failed: {}", e))?;
+    // Validate the HBCN, pointing at the offending line when the error names an element
+    validate_hbcn(&hbcn).map_err(|e| {
+        let span: Option<Span> = e.downcast_ref::<ValidationError>().and_then(|v| {
+            v.place
+                .and_then(|p| place_spans.get(p.index()).copied())
+                .or_else(|| {
+                    v.transition
+                        .and_then(|t| transition_spans.get(t.index()).copied())
+                })
+        });
+        let message = format!("HBCN validation failed: {}", e);
+        match span {
+            Some(span) => Diagnostic::new(input, span, message).into(),
+            None => anyhow::anyhow!(message),
+        }
+    })?;
 
-    Ok(hbcn)
+    let declarations = declarations.into_iter().map(|(d, _)| d).collect();
+    Ok((hbcn, declarations))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hbcn::CircuitNode;
+    use crate::hbcn::declarations::{NodeKind, PortDirection, RegisterClass};
     use crate::hbcn::serialisation::serialise_hbcn_transition;
     use crate::hbcn::test_helpers::create_valid_two_channel_hbcn;
     use crate::hbcn::{DelayPair, MarkablePlace, Named, Transition, validate_hbcn};
+    use std::collections::HashSet;
+
+    /// A place as `(source, target, delay, marked)`, with transitions written `+name`/`-name`.
+    type PlaceEntry = (String, String, DelayPair, bool);
+
+    /// List the places of `hbcn` in the order they were added.
+    fn places(hbcn: &HBCN<Transition, DelayedPlace>) -> Vec<PlaceEntry> {
+        let signed = |t: &Transition| match t {
+            Transition::Data(n) => format!("+{}", n.name()),
+            Transition::Spacer(n) => format!("-{}", n.name()),
+        };
+        hbcn.edge_indices()
+            .map(|ie| {
+                let (s, t) = hbcn.edge_endpoints(ie).unwrap();
+                let place = &hbcn[ie];
+                (
+                    signed(&hbcn[s]),
+                    signed(&hbcn[t]),
+                    place.delay.clone(),
+                    place.is_marked(),
+                )
+            })
+            .collect()
+    }
+
+    /// Parse `input` as an HBCN and list its places in file order.
+    fn parse_places(input: &str) -> Result<Vec<PlaceEntry>> {
+        parse_hbcn_with_declarations(input).map(|(hbcn, _)| places(&hbcn))
+    }
 
     #[test]
     fn test_parse_hbcn_basic() {
@@ -166,6 +221,148 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_hbcn_validation_error_points_at_place() {
+        let input = "* +{port:a} => +{reg1} : (1.0, 2.0)\n\
+                     +{reg1} => -{port:a} : (0.5, 1.5)\n\
+                     -{port:a} => -{reg1} : (0.5, 1.0)\n\
+                     -{port:a} => -{reg1} : (0.5, 1.0)\n\
+                     -{reg1} => +{port:a} : (0.0, 1.0)\n";
+        let err = parse_hbcn(input).expect_err("duplicate edge should fail validation");
+        let diag = err
+            .downcast_ref::<Diagnostic>()
+            .expect("validation error should carry a source location");
+
+        assert_eq!((diag.line, diag.column), (4, 1));
+        assert!(diag.message.starts_with("HBCN validation failed:"));
+        assert!(diag.message.contains("duplicate place"));
+    }
+
+    #[test]
+    fn test_parse_hbcn_syntax_error_location() {
+        let input = "+{a} => +{b} : (1.0, 2.0)\n+{b} => -{a} (0.5, 1.5)\n";
+        let err = parse_hbcn(input).expect_err("missing colon should fail to parse");
+        let diag = err
+            .downcast_ref::<Diagnostic>()
+            .expect("syntax error should carry a source location");
+
+        assert_eq!((diag.line, diag.column), (2, 14));
+        assert!(diag.message.contains("unexpected token `(`"));
+        assert!(diag.to_string().contains("2 | +{b} => -{a} (0.5, 1.5)"));
+    }
+
+    #[test]
+    fn parse_declared_nodes() {
+        // Declarations override the prefix convention; `r` is left to the fallback
+        let input = r#"
+            port {a} input cost 0
+            register {port:b} control cost 50
+            * +{a} => +{port:b} : 2
+              +{port:b} => -{a} : 1
+              -{a} => -{port:b} : 2
+              -{port:b} => +{a} : 1
+            * +{port:b} => +{r} : 2
+              +{r} => -{port:b} : 1
+              -{port:b} => -{r} : 2
+              -{r} => +{port:b} : 1
+        "#;
+        let (hbcn, declarations) = parse_hbcn_with_declarations(input).unwrap();
+
+        let nodes: HashSet<CircuitNode> = hbcn
+            .node_indices()
+            .map(|idx| AsRef::<CircuitNode>::as_ref(&hbcn[idx]).clone())
+            .collect();
+        assert_eq!(
+            nodes,
+            HashSet::from([
+                CircuitNode::Port("a".into()),
+                CircuitNode::Register("port:b".into()),
+                CircuitNode::Register("r".into()),
+            ])
+        );
+        assert_eq!(
+            declarations,
+            [
+                NodeDeclaration {
+                    name: "a".into(),
+                    kind: NodeKind::Port(Some(PortDirection::Input)),
+                    cost: Some(0),
+                },
+                NodeDeclaration {
+                    name: "port:b".into(),
+                    kind: NodeKind::Register(Some(RegisterClass::Control)),
+                    cost: Some(50),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_declaration_errors() {
+        let places = "* +{a} => +{b} : 2\n+{b} => -{a} : 1\n-{a} => -{b} : 2\n-{b} => +{a} : 1\n";
+        let cases = [
+            (
+                "port {a}\nregister {a}\n",
+                (2, 1),
+                "node 'a' declared more than once",
+            ),
+            (
+                "port {a}\nport {c}\n",
+                (2, 1),
+                "declared node 'c' does not appear",
+            ),
+            (
+                "register {b} cost 1.5\n",
+                (1, 19),
+                "non-negative whole number",
+            ),
+            ("register {b} input\n", (1, 14), "unexpected token"),
+            (
+                "port {a} output\n",
+                (1, 1),
+                "output port 'a' drives a channel to 'b'",
+            ),
+            (
+                "port {a} input\nport {b} input\n",
+                (2, 1),
+                "input port 'b' receives a channel from 'a'",
+            ),
+        ];
+        for (declarations, position, message) in cases {
+            let input = format!("{}{}", declarations, places);
+            let err = parse_hbcn(&input).expect_err(declarations);
+            let diag = err.downcast_ref::<Diagnostic>().expect(declarations);
+            assert_eq!((diag.line, diag.column), position, "{}", declarations);
+            assert!(diag.message.contains(message), "{}", diag.message);
+        }
+    }
+
+    #[test]
+    fn serialise_declares_unprefixed_ports() {
+        // `a` and `b` are ports without the `port:` prefix, so they must be declared to
+        // round-trip; `c` is a register and needs no declaration
+        let mut hbcn = create_valid_two_channel_hbcn("a", "b", "c", 2.0, 1.0, 2.0, 1.0, 0, 0);
+        for idx in hbcn.node_indices().collect::<Vec<_>>() {
+            if hbcn[idx].name().as_ref() == "c" {
+                hbcn[idx] = match &hbcn[idx] {
+                    Transition::Data(_) => Transition::Data(CircuitNode::Register("c".into())),
+                    Transition::Spacer(_) => Transition::Spacer(CircuitNode::Register("c".into())),
+                };
+            }
+        }
+
+        let text = serialise_hbcn_transition(&hbcn);
+        assert!(text.starts_with("port {a}\nport {b}\n\n"), "{}", text);
+        let (parsed, declarations) = parse_hbcn_with_declarations(&text).unwrap();
+        assert_eq!(declarations.len(), 2);
+        let nodes: HashSet<CircuitNode> = parsed
+            .node_indices()
+            .map(|idx| AsRef::<CircuitNode>::as_ref(&parsed[idx]).clone())
+            .collect();
+        assert!(nodes.contains(&CircuitNode::Port("a".into())));
+        assert!(nodes.contains(&CircuitNode::Register("c".into())));
+    }
+
     #[test]
     fn test_parse_hbcn_rejects_overlong_literal() {
         // A digit run too long for f64 parses to infinity; the grammar must reject it
@@ -241,141 +438,121 @@ mod tests {
     }
 
     #[test]
-    fn parse_empty_adjacency_list() {
-        let input = "";
-        let result = super::parser::AdjacencyListParser::new().parse(input);
-        assert!(result.is_ok());
-        let list = result.unwrap();
+    fn parse_empty_input() {
+        let list = parse_places("").expect("an empty HBCN should parse");
         assert!(list.is_empty());
     }
 
     #[test]
     fn parse_single_edge_without_token() {
-        let input = r#"+{a} => -{b} : (1,2)"#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse single edge");
-        assert_eq!(list.len(), 1);
-        let e = &list[0];
-
-        match &e.source {
-            ast::Transition::Data(sym) => assert_eq!(sym.as_ref(), "a"),
-            _ => panic!("expected Data transition for source"),
-        }
-        match &e.target {
-            ast::Transition::Spacer(sym) => assert_eq!(sym.as_ref(), "b"),
-            _ => panic!("expected Spacer transition for target"),
-        }
+        let input = r#"
+            +{a} => -{b} : (1,2)
+            * +{b} => +{a} : 1
+            -{b} => -{a} : 1
+            -{a} => +{b} : 1
+        "#;
+        let list = parse_places(input).expect("should parse unmarked edge");
         assert_eq!(
-            e.delay,
-            DelayPair {
-                min: Some(1.0),
-                max: 2.0
-            }
+            list[0],
+            (
+                "+a".to_string(),
+                "-b".to_string(),
+                DelayPair {
+                    min: Some(1.0),
+                    max: 2.0
+                },
+                false
+            )
         );
-        assert!(!e.token);
     }
 
     #[test]
     fn parse_single_edge_with_token() {
-        let input = r#"* -{x} => +{y} : 3.5"#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse token edge");
-        assert_eq!(list.len(), 1);
-        let e = &list[0];
-
-        match &e.source {
-            ast::Transition::Spacer(sym) => assert_eq!(sym.as_ref(), "x"),
-            _ => panic!("expected Spacer source"),
-        }
-        match &e.target {
-            ast::Transition::Data(sym) => assert_eq!(sym.as_ref(), "y"),
-            _ => panic!("expected Data target"),
-        }
+        let input = r#"
+            * -{x} => +{y} : 3.5
+            +{y} => +{x} : 1
+            +{x} => -{y} : 1
+            -{y} => -{x} : 1
+        "#;
+        let list = parse_places(input).expect("should parse token edge");
         assert_eq!(
-            e.delay,
-            DelayPair {
-                min: None,
-                max: 3.5
-            }
+            list[0],
+            (
+                "-x".to_string(),
+                "+y".to_string(),
+                DelayPair {
+                    min: None,
+                    max: 3.5
+                },
+                true
+            )
         );
-        assert!(e.token);
     }
 
     #[test]
     fn parse_multiple_edges_and_delay_variants() {
         let input = r#"
             +{n1} => +{n2} : (0.5,0.0)
-            -{n2} => -{n3} : 0.0 
-            * +{n3} => -{n1} : (2,4.25)
+            -{n1} => -{n2} : 0.0 
+            * +{n2} => -{n1} : (2,4.25)
+            -{n2} => +{n1} : 1
         "#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse multiple edges");
-        assert_eq!(list.len(), 3);
+        let list = parse_places(input).expect("should parse multiple edges");
+        assert_eq!(list.len(), 4);
 
+        let delays: Vec<_> = list.iter().map(|(_, _, delay, _)| delay.clone()).collect();
         assert_eq!(
-            list[0].delay,
-            DelayPair {
-                min: Some(0.5),
-                max: 0.0
-            }
-        );
-        assert_eq!(
-            list[1].delay,
-            DelayPair {
-                min: None,
-                max: 0.0
-            }
-        );
-        assert_eq!(
-            list[2].delay,
-            DelayPair {
-                min: Some(2.0),
-                max: 4.25
-            }
+            delays[..3],
+            [
+                DelayPair {
+                    min: Some(0.5),
+                    max: 0.0
+                },
+                DelayPair {
+                    min: None,
+                    max: 0.0
+                },
+                DelayPair {
+                    min: Some(2.0),
+                    max: 4.25
+                },
+            ]
         );
-        assert!(list[2].token);
+        assert!(list[2].3);
 
-        let get_name = |t: &ast::Transition| match t {
-            ast::Transition::Data(s) | ast::Transition::Spacer(s) => s.as_ref().to_string(),
-        };
-        assert_eq!(get_name(&list[0].source), "n1");
-        assert_eq!(get_name(&list[0].target), "n2");
-        assert_eq!(get_name(&list[1].source), "n2");
-        assert_eq!(get_name(&list[1].target), "n3");
-        assert_eq!(get_name(&list[2].source), "n3");
-        assert_eq!(get_name(&list[2].target), "n1");
+        let ends: Vec<_> = list
+            .iter()
+            .map(|(source, target, _, _)| (source.as_str(), target.as_str()))
+            .collect();
+        assert_eq!(ends[..3], [("+n1", "+n2"), ("-n1", "-n2"), ("+n2", "-n1")]);
     }
 
     #[test]
     fn parse_floating_and_integer_numbers() {
         let input = r#"
             +{a} => +{b} : (10,20.75)
-            -{b} => +{c} : (1.25,2)
+            +{b} => -{a} : (1.25,2)
+            * -{a} => -{b} : 1
+            -{b} => +{a} : 1
         "#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse mixed numbers");
-        assert_eq!(list.len(), 2);
+        let list = parse_places(input).expect("should parse mixed numbers");
         assert_eq!(
-            list[0].delay,
+            list[0].2,
             DelayPair {
                 min: Some(10.0),
                 max: 20.75
             }
         );
         assert_eq!(
-            list[1].delay,
+            list[1].2,
             DelayPair {
                 min: Some(1.25),
                 max: 2.0
             }
         );
 
-        assert!(!list[0].token);
-        assert!(!list[1].token);
+        assert!(!list[0].3);
+        assert!(!list[1].3);
     }
 
     #[test]
@@ -385,21 +562,20 @@ mod tests {
         // must accept them, and a signed delay must not collide with the Spacer `-`.
         let input = r#"
             +{a} => +{b} : -5
-            -{b} => -{a} : (-10,-2.5)
+            -{a} => -{b} : (-10,-2.5)
+            +{b} => -{a} : 1
+            * -{b} => +{a} : 1
         "#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse negative delays");
-        assert_eq!(list.len(), 2);
+        let list = parse_places(input).expect("should parse negative delays");
         assert_eq!(
-            list[0].delay,
+            list[0].2,
             DelayPair {
                 min: None,
                 max: -5.0
             }
         );
         assert_eq!(
-            list[1].delay,
+            list[1].2,
             DelayPair {
                 min: Some(-10.0),
                 max: -2.5
@@ -407,46 +583,28 @@ mod tests {
         );
     }
 
-    fn edge_tuple_from_ast(
-        e: &super::ast::AdjacencyEntry,
-    ) -> (char, String, char, String, Option<f64>, f64, bool) {
-        let (sk, sn) = match &e.source {
-            super::ast::Transition::Data(s) => ('+', s.as_ref().to_string()),
-            super::ast::Transition::Spacer(s) => ('-', s.as_ref().to_string()),
-        };
-        let (tk, tn) = match &e.target {
-            super::ast::Transition::Data(s) => ('+', s.as_ref().to_string()),
-            super::ast::Transition::Spacer(s) => ('-', s.as_ref().to_string()),
-        };
-        (sk, sn, tk, tn, e.delay.min, e.delay.max, e.token)
-    }
-
     #[test]
     fn parse_node_with_escaped_braces() {
         // Test parsing nodes with TCL-style escaped braces
-        let input = r#"+{node\{with\}} => -{other\{name\}} : (1,2)"#;
-        let list = super::parser::AdjacencyListParser::new()
-            .parse(input)
-            .expect("should parse node with escaped braces");
-        assert_eq!(list.len(), 1);
-        let e = &list[0];
-
-        match &e.source {
-            ast::Transition::Data(sym) => assert_eq!(sym.as_ref(), "node{with}"),
-            _ => panic!("expected Data transition for source"),
-        }
-        match &e.target {
-            ast::Transition::Spacer(sym) => assert_eq!(sym.as_ref(), "other{name}"),
-            _ => panic!("expected Spacer transition for target"),
-        }
+        let input = r#"
+            +{node\{with\}} => -{other\{name\}} : (1,2)
+            * +{other\{name\}} => +{node\{with\}} : 1
+            -{other\{name\}} => -{node\{with\}} : 1
+            -{node\{with\}} => +{other\{name\}} : 1
+        "#;
+        let list = parse_places(input).expect("should parse node with escaped braces");
         assert_eq!(
-            e.delay,
-            DelayPair {
-                min: Some(1.0),
-                max: 2.0
-            }
+            list[0],
+            (
+                "+node{with}".to_string(),
+                "-other{name}".to_string(),
+                DelayPair {
+                    min: Some(1.0),
+                    max: 2.0
+                },
+                false
+            )
         );
-        assert!(!e.token);
     }
 
     #[test]
@@ -463,31 +621,8 @@ mod tests {
         validate_hbcn(&g).expect("Created HBCN should be valid");
 
         let text = serialise_hbcn_transition(&g);
-        let parsed = super::parser::AdjacencyListParser::new()
-            .parse(&text)
-            .expect("parser should accept serialised output");
-
-        assert_eq!(g.edge_count(), parsed.len());
-        for (i, e_ast) in parsed.iter().enumerate() {
-            let ie = g.edge_indices().nth(i).unwrap();
-            let (s, t) = g.edge_endpoints(ie).unwrap();
-            let e = &g[ie];
-            let (sk, sn, tk, tn, min, max, token) = edge_tuple_from_ast(e_ast);
-
-            let s_tr = &g[s];
-            let t_tr = &g[t];
-            let (gsk, gsn) = match s_tr {
-                Transition::Data(n) => ('+', n.name().as_ref().to_string()),
-                Transition::Spacer(n) => ('-', n.name().as_ref().to_string()),
-            };
-            let (gtk, gtn) = match t_tr {
-                Transition::Data(n) => ('+', n.name().as_ref().to_string()),
-                Transition::Spacer(n) => ('-', n.name().as_ref().to_string()),
-            };
-
-            assert_eq!((sk, sn, tk, tn), (gsk, gsn, gtk, gtn));
-            assert_eq!((min, max, token), (e.delay.min, e.delay.max, e.is_marked()));
-        }
+        let parsed = parse_places(&text).expect("parser should accept serialised output");
+        assert_eq!(parsed, places(&g));
     }
 
     #[test]
@@ -504,82 +639,83 @@ mod tests {
         validate_hbcn(&g).expect("Created HBCN should be valid");
 
         let text = serialise_hbcn_transition(&g);
-        let parsed = super::parser::AdjacencyListParser::new()
-            .parse(&text)
-            .expect("parser should accept serialised output");
-
-        assert_eq!(g.edge_count(), parsed.len());
-        for (i, e_ast) in parsed.iter().enumerate() {
-            let ie = g.edge_indices().nth(i).unwrap();
-            let (s, t) = g.edge_endpoints(ie).unwrap();
-            let e = &g[ie];
-            let (sk, sn, tk, tn, min, max, token) = edge_tuple_from_ast(e_ast);
-
-            let s_tr = &g[s];
-            let t_tr = &g[t];
-            let (gsk, gsn) = match s_tr {
-                Transition::Data(n) => ('+', n.name().as_ref().to_string()),
-                Transition::Spacer(n) => ('-', n.name().as_ref().to_string()),
-            };
-            let (gtk, gtn) = match t_tr {
-                Transition::Data(n) => ('+', n.name().as_ref().to_string()),
-                Transition::Spacer(n) => ('-', n.name().as_ref().to_string()),
-            };
-
-            assert_eq!((sk, sn, tk, tn), (gsk, gsn, gtk, gtn));
-            assert_eq!((min, max, token), (e.delay.min, e.delay.max, e.is_marked()));
-        }
+        let parsed = parse_places(&text).expect("parser should accept serialised output");
+        assert_eq!(parsed, places(&g));
     }
 
     #[test]
-    fn serialise_and_parse_round_trip_with_braces_in_name() {
-        // Test that nodes with braces in their names are properly escaped/unescaped
-        use crate::hbcn::{CircuitNode, DelayPair, DelayedPlace, Place, Transition};
-        use petgraph::stable_graph::StableGraph;
-        use string_cache::DefaultAtom;
+    fn test_parse_hbcn_comments() {
+        let input = r#"
+            # Channel port:a -> reg1, delays from the 2026-10 signoff run
+            * +{port:a} => +{reg1} : (1.0, 2.0)  // forward data
+            +{reg1} => -{port:a} : (0.5, 1.5)    # acknowledge
+            /* spacer half,
+               hand-tuned */
+            -{port:a} => -{reg1} : (0.5, 1.0)
+            -{reg1} => /* inline */ +{port:a} : (0.0, 1.0)
+            // -{reg1} => +{port:a} : (9.0, 9.0)
+        "#;
+        let hbcn = parse_hbcn(input).expect("comments should be skipped");
+        assert_eq!(hbcn.edge_count(), 4);
+    }
 
-        let mut hbcn: StableGraph<Transition, DelayedPlace> = StableGraph::new();
+    #[test]
+    fn test_parse_hbcn_comment_markers_inside_names() {
+        // `#`, `//` and `/*` inside a braced name are part of the name, not a comment
+        let input = r#"
+            * +{port:a#1} => +{r//b/*c} : 1
+            +{r//b/*c} => -{port:a#1} : 1
+            -{port:a#1} => -{r//b/*c} : 1
+            -{r//b/*c} => +{port:a#1} : 1
+        "#;
+        let hbcn = parse_hbcn(input).expect("names with comment markers should parse");
+        let names: HashSet<String> = hbcn
+            .node_indices()
+            .map(|n| hbcn[n].name().to_string())
+            .collect();
+        assert!(names.contains("port:a#1"));
+        assert!(names.contains("r//b/*c"));
+    }
 
-        // Create nodes with braces in names
-        let n1 = hbcn.add_node(Transition::Data(CircuitNode::Port(DefaultAtom::from(
-            "node{with}",
-        ))));
-        let n2 = hbcn.add_node(Transition::Spacer(CircuitNode::Register(
-            DefaultAtom::from("other{name}"),
-        )));
-
-        hbcn.add_edge(
-            n1,
-            n2,
-            DelayedPlace {
-                place: Place {
-                    token: false,
-                    is_internal: false,
-                },
-                delay: DelayPair {
-                    min: Some(1.0),
-                    max: 2.0,
-                },
-                slack: None,
-            },
-        );
+    #[test]
+    fn test_parse_hbcn_unterminated_block_comment() {
+        let input = "* +{a} => +{b} : 1\n/* never closed\n+{b} => -{a} : 1\n";
+        let err = parse_hbcn(input).expect_err("unterminated block comment should fail");
+        let diag = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!((diag.line, diag.column), (2, 1));
+    }
 
-        // Serialise and parse back
-        let text = serialise_hbcn_transition(&hbcn);
-        let parsed = super::parser::AdjacencyListParser::new()
-            .parse(&text)
-            .expect("parser should accept serialised output");
+    #[test]
+    fn serialise_with_header_round_trip() {
+        use crate::hbcn::serialisation::serialise_hbcn_with_header;
 
-        assert_eq!(parsed.len(), 1);
-        let e = &parsed[0];
+        let g = create_valid_two_channel_hbcn("n1", "n2", "n3", 1.0, 2.0, 3.0, 4.0, 0, 3);
+        let header = ["Generated by hbcn", "Source: two.graph\nOptions: none", ""];
+        let text = serialise_hbcn_with_header(&g, &header);
 
-        match &e.source {
-            ast::Transition::Data(sym) => assert_eq!(sym.as_ref(), "node{with}"),
-            _ => panic!("expected Data transition"),
-        }
-        match &e.target {
-            ast::Transition::Spacer(sym) => assert_eq!(sym.as_ref(), "other{name}"),
-            _ => panic!("expected Spacer transition"),
-        }
+        assert!(text.starts_with("# Generated by hbcn\n# Source: two.graph\n# Options: none\n#\n"));
+        let parsed = parse_hbcn(&text).expect("header comments should be skipped");
+        assert_eq!(parsed.edge_count(), g.edge_count());
+    }
+
+    #[test]
+    fn serialise_and_parse_round_trip_with_braces_in_name() {
+        // Test that nodes with braces in their names are properly escaped/unescaped
+        let g = create_valid_two_channel_hbcn(
+            "node{with}",
+            "other{name}",
+            "c",
+            1.0,
+            2.0,
+            1.0,
+            2.0,
+            0,
+            3,
+        );
+
+        let text = serialise_hbcn_transition(&g);
+        assert!(text.contains(r"{node\{with\}}"));
+        let parsed = parse_places(&text).expect("parser should accept serialised output");
+        assert_eq!(parsed, places(&g));
     }
 }
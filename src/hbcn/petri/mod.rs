@@ -0,0 +1,425 @@
+//! Export of HBCNs to Petri-net interchange formats.
+//!
+//! An HBCN is a marked graph, so it can be inspected and verified independently in Petri-net
+//! tools such as Workcraft. This module writes it in two formats:
+//!
+//! - **PNML** ([`serialise_pnml`], [`serialise_timed_pnml`]): the ISO/IEC 15909-2 place/transition
+//!   net format. Every HBCN transition is a PNML transition named `name+` (data) or `name-`
+//!   (spacer), and every place a PNML place with one input and one output arc, holding one
+//!   token in the initial marking when marked. PNML has no standard timing, so the timed
+//!   variant records each place's delay in a `toolspecific` element of tool `hbcn`.
+//! - **STG** ([`serialise_stg`]): the `.g` format of petrify and Workcraft. Every circuit node is
+//!   a signal, rising on its data transition and falling on its spacer transition: ports with
+//!   incoming forward places are outputs, other ports inputs, and registers internal signals.
+//!   Places are implicit (`<a+,b+>`) unless several join the same transitions, and the marked
+//!   ones form the `.marking`. The format has no timing, so delays are not exported.
+//!
+//! STGs specified in Workcraft can also be read back as HBCNs with [`parse_stg`], as long as
+//! they are marked graphs; see the [`reader`] module.
+//!
+//! Signal names in the `.g` format must be identifiers, so any other character of a node name
+//! (such as the `/` of hierarchical names or the `:` of `port:` prefixes) becomes `_`, and a
+//! name that clashes gets a numeric suffix. The renamed signals are listed in a comment.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::hbcn::parser::parse_hbcn;
+//! use hbcn::hbcn::petri::{serialise_stg, serialise_timed_pnml};
+//!
+//! let hbcn = parse_hbcn("port {a} input\nport {b} output\n\
+//!                        * +{a} => +{b} : 2\n+{b} => -{a} : 1\n\
+//!                        -{a} => -{b} : 2\n-{b} => +{a} : 1\n").unwrap();
+//!
+//! let stg = serialise_stg(&hbcn, "pair", &["Example"]);
+//! assert!(stg.contains(".inputs a\n.outputs b\n"));
+//! assert!(stg.contains(".marking { <a+,b+> }\n"));
+//!
+//! let pnml = serialise_timed_pnml(&hbcn, "pair", &["Example"]);
+//! assert!(pnml.contains(r#"<delay max="2"/>"#));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use petgraph::stable_graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+use super::{CircuitNode, DelayPair, HBCN, HasDelay, MarkablePlace, Named, Transition};
+
+pub mod reader;
+pub use reader::{is_stg, parse_stg};
+
+/// Serialise an HBCN as an untimed PNML place/transition net named `name`.
+///
+/// Each line of `header` becomes an XML comment before the net.
+pub fn serialise_pnml<T, P, S>(hbcn: &HBCN<T, P>, name: &str, header: &[S]) -> String
+where
+    T: AsRef<Transition>,
+    P: MarkablePlace,
+    S: AsRef<str>,
+{
+    let mut out = String::new();
+    // Infallible for String
+    let _ = write_pnml(hbcn, name, header, |_| None, &mut out);
+    out
+}
+
+/// Serialise an HBCN as a PNML place/transition net named `name`, recording each place's
+/// delay in a `toolspecific` element.
+///
+/// The delay is written as `<delay min=".." max=".."/>`, leaving `min` out when the place
+/// has none.
+pub fn serialise_timed_pnml<T, P, S>(hbcn: &HBCN<T, P>, name: &str, header: &[S]) -> String
+where
+    T: AsRef<Transition>,
+    P: MarkablePlace + HasDelay,
+    S: AsRef<str>,
+{
+    let mut out = String::new();
+    // Infallible for String
+    let _ = write_pnml(hbcn, name, header, |place| Some(place.delay()), &mut out);
+    out
+}
+
+/// Serialise an HBCN as a signal transition graph in the `.g` format, as model `name`.
+///
+/// Each line of `header` becomes a `#` comment before the model.
+pub fn serialise_stg<T, P, S>(hbcn: &HBCN<T, P>, name: &str, header: &[S]) -> String
+where
+    T: AsRef<Transition>,
+    P: MarkablePlace,
+    S: AsRef<str>,
+{
+    let mut out = String::new();
+    // Infallible for String
+    let _ = write_stg(hbcn, name, header, &mut out);
+    out
+}
+
+/// The `+`/`-` label of a transition, with the node's own name.
+fn transition_label(transition: &Transition) -> String {
+    match transition {
+        Transition::Data(node) => format!("{}+", node.name()),
+        Transition::Spacer(node) => format!("{}-", node.name()),
+    }
+}
+
+fn write_pnml<T, P, S, W>(
+    hbcn: &HBCN<T, P>,
+    name: &str,
+    header: &[S],
+    delay: impl Fn(&P) -> Option<&DelayPair>,
+    w: &mut W,
+) -> fmt::Result
+where
+    T: AsRef<Transition>,
+    P: MarkablePlace,
+    S: AsRef<str>,
+    W: Write,
+{
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    for line in header.iter().flat_map(|entry| entry.as_ref().lines()) {
+        // `--` may not appear inside an XML comment
+        writeln!(w, "<!-- {} -->", line.replace("--", "- -"))?;
+    }
+    writeln!(
+        w,
+        r#"<pnml xmlns="http://www.pnml.org/version-2009/grammar/pnml">"#
+    )?;
+    writeln!(
+        w,
+        r#"  <net id="net" type="http://www.pnml.org/version-2009/grammar/ptnet">"#
+    )?;
+    writeln!(w, "    <name><text>{}</text></name>", xml_escape(name))?;
+    writeln!(w, r#"    <page id="page">"#)?;
+
+    for ix in hbcn.node_indices() {
+        writeln!(
+            w,
+            r#"      <transition id="t{}"><name><text>{}</text></name></transition>"#,
+            ix.index(),
+            xml_escape(&transition_label(hbcn[ix].as_ref()))
+        )?;
+    }
+
+    for ie in hbcn.edge_indices() {
+        let (src, dst) = hbcn
+            .edge_endpoints(ie)
+            .expect("Edge should have valid endpoints");
+        let place = &hbcn[ie];
+        let label = format!(
+            "<{},{}>",
+            transition_label(hbcn[src].as_ref()),
+            transition_label(hbcn[dst].as_ref())
+        );
+        writeln!(w, r#"      <place id="p{}">"#, ie.index())?;
+        writeln!(
+            w,
+            "        <name><text>{}</text></name>",
+            xml_escape(&label)
+        )?;
+        if place.is_marked() {
+            writeln!(w, "        <initialMarking><text>1</text></initialMarking>")?;
+        }
+        if let Some(delay) = delay(place) {
+            writeln!(
+                w,
+                r#"        <toolspecific tool="hbcn" version="{}">"#,
+                env!("CARGO_PKG_VERSION")
+            )?;
+            match delay.min {
+                Some(min) => {
+                    writeln!(w, r#"          <delay min="{}" max="{}"/>"#, min, delay.max)?
+                }
+                None => writeln!(w, r#"          <delay max="{}"/>"#, delay.max)?,
+            }
+            writeln!(w, "        </toolspecific>")?;
+        }
+        writeln!(w, "      </place>")?;
+        writeln!(
+            w,
+            r#"      <arc id="a{0}i" source="t{1}" target="p{0}"/>"#,
+            ie.index(),
+            src.index()
+        )?;
+        writeln!(
+            w,
+            r#"      <arc id="a{0}o" source="p{0}" target="t{1}"/>"#,
+            ie.index(),
+            dst.index()
+        )?;
+    }
+
+    writeln!(w, "    </page>")?;
+    writeln!(w, "  </net>")?;
+    writeln!(w, "</pnml>")
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The signal of each circuit node: its name made an identifier, unique among the signals.
+fn signal_names<T, P>(hbcn: &HBCN<T, P>) -> HashMap<CircuitNode, String>
+where
+    T: AsRef<Transition>,
+{
+    let mut nodes: Vec<&CircuitNode> = hbcn
+        .node_indices()
+        .map(|ix| AsRef::<CircuitNode>::as_ref(hbcn[ix].as_ref()))
+        .collect();
+    nodes.sort();
+    nodes.dedup();
+
+    let mut taken = HashSet::new();
+    let mut signals = HashMap::new();
+    for node in nodes {
+        let mut base: String = node
+            .name()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            base.insert(0, '_');
+        }
+        let mut signal = base.clone();
+        let mut suffix = 1;
+        while !taken.insert(signal.clone()) {
+            signal = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        signals.insert(node.clone(), signal);
+    }
+    signals
+}
+
+fn write_stg<T, P, S, W>(hbcn: &HBCN<T, P>, name: &str, header: &[S], w: &mut W) -> fmt::Result
+where
+    T: AsRef<Transition>,
+    P: MarkablePlace,
+    S: AsRef<str>,
+    W: Write,
+{
+    let signals = signal_names(hbcn);
+    let transition = |ix: NodeIndex| hbcn[ix].as_ref();
+    let node = |ix: NodeIndex| AsRef::<CircuitNode>::as_ref(transition(ix));
+
+    // Name each transition by its signal and edge, numbering repeated ones as `a+/1`
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut labels: HashMap<NodeIndex, String> = HashMap::new();
+    for ix in hbcn.node_indices() {
+        let edge = match transition(ix) {
+            Transition::Data(_) => '+',
+            Transition::Spacer(_) => '-',
+        };
+        let label = format!("{}{}", signals[node(ix)], edge);
+        let count = seen.entry(label.clone()).or_default();
+        labels.insert(
+            ix,
+            match *count {
+                0 => label,
+                n => format!("{}/{}", label, n),
+            },
+        );
+        *count += 1;
+    }
+
+    // Ports that receive data are outputs of the circuit, the others inputs
+    let outputs: HashSet<&CircuitNode> = hbcn
+        .edge_indices()
+        .filter_map(|ie| {
+            let (src, dst) = hbcn.edge_endpoints(ie)?;
+            let forward = !super::is_backward_place(transition(src), transition(dst));
+            (forward && node(src) != node(dst)).then(|| node(dst))
+        })
+        .collect();
+    let mut inputs = Vec::new();
+    let mut output_signals = Vec::new();
+    let mut internal = Vec::new();
+    let mut nodes: Vec<_> = signals.iter().collect();
+    nodes.sort_by(|a, b| a.1.cmp(b.1));
+    for (node, signal) in &nodes {
+        match node {
+            CircuitNode::Port(_) if outputs.contains(node) => output_signals.push(signal.as_str()),
+            CircuitNode::Port(_) => inputs.push(signal.as_str()),
+            CircuitNode::Register(_) => internal.push(signal.as_str()),
+        }
+    }
+
+    for line in header.iter().flat_map(|entry| entry.as_ref().lines()) {
+        writeln!(w, "# {}", line)?;
+    }
+    for (node, signal) in &nodes {
+        if node.name().as_ref() != signal.as_str() {
+            writeln!(w, "# Signal {} is node {{{}}}", signal, node.name())?;
+        }
+    }
+    let model: String = name
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    writeln!(w, ".model {}", model)?;
+    for (keyword, list) in [
+        (".inputs", &inputs),
+        (".outputs", &output_signals),
+        (".internal", &internal),
+    ] {
+        if !list.is_empty() {
+            writeln!(w, "{} {}", keyword, list.join(" "))?;
+        }
+    }
+
+    // Places joining the same pair of transitions cannot be implicit
+    let mut pairs: HashMap<(NodeIndex, NodeIndex), usize> = HashMap::new();
+    for ie in hbcn.edge_indices() {
+        if let Some(pair) = hbcn.edge_endpoints(ie) {
+            *pairs.entry(pair).or_default() += 1;
+        }
+    }
+    let explicit = |ie: EdgeIndex| hbcn.edge_endpoints(ie).is_some_and(|pair| pairs[&pair] > 1);
+
+    writeln!(w, ".graph")?;
+    let mut marking = Vec::new();
+    for src in hbcn.node_indices() {
+        let mut edges: Vec<_> = hbcn.edges(src).map(|e| (e.id(), e.target())).collect();
+        edges.sort();
+        let mut successors = Vec::new();
+        for (ie, dst) in edges {
+            let place_name = if explicit(ie) {
+                let place = format!("p{}", ie.index());
+                writeln!(w, "{} {}", place, labels[&dst])?;
+                successors.push(place.clone());
+                place
+            } else {
+                successors.push(labels[&dst].clone());
+                format!("<{},{}>", labels[&src], labels[&dst])
+            };
+            if hbcn[ie].is_marked() {
+                marking.push((ie.index(), place_name));
+            }
+        }
+        if !successors.is_empty() {
+            writeln!(w, "{} {}", labels[&src], successors.join(" "))?;
+        }
+    }
+    marking.sort();
+    let marking: Vec<String> = marking.into_iter().map(|(_, place)| place).collect();
+    writeln!(w, ".marking {{ {} }}", marking.join(" "))?;
+    writeln!(w, ".end")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::test_helpers::create_valid_channel;
+
+    #[test]
+    fn stg_renames_signals_and_lists_marking() {
+        let hbcn = create_valid_channel("port:m/p", "r/s0", 2.0, 1.0, 1);
+        let stg = serialise_stg(&hbcn, "channel model", &["line one\nline two"]);
+        assert_eq!(
+            stg,
+            "# line one\n\
+             # line two\n\
+             # Signal port_m_p is node {port:m/p}\n\
+             # Signal r_s0 is node {r/s0}\n\
+             .model channel_model\n\
+             .inputs port_m_p\n\
+             .outputs r_s0\n\
+             .graph\n\
+             port_m_p+ r_s0+\n\
+             port_m_p- r_s0-\n\
+             r_s0+ port_m_p-\n\
+             r_s0- port_m_p+\n\
+             .marking { <r_s0+,port_m_p-> }\n\
+             .end\n"
+        );
+    }
+
+    #[test]
+    fn stg_makes_parallel_places_explicit() {
+        let mut hbcn = create_valid_channel("a", "b", 2.0, 1.0, 0);
+        let (src, dst) = hbcn
+            .edge_indices()
+            .filter_map(|ie| hbcn.edge_endpoints(ie))
+            .next()
+            .unwrap();
+        let place = hbcn[hbcn.find_edge(src, dst).unwrap()].clone();
+        let ie = hbcn.add_edge(src, dst, place);
+
+        let stg = serialise_stg(&hbcn, "m", &[] as &[&str]);
+        assert!(stg.contains(&format!("p{} b+\n", ie.index())));
+        assert!(stg.contains(&format!("a+ p0 p{}\n", ie.index())));
+        assert!(stg.contains(&format!(".marking {{ p0 p{} }}", ie.index())));
+    }
+
+    #[test]
+    fn pnml_escapes_names_and_records_delays() {
+        let mut hbcn = create_valid_channel("a&b", "c", 2.0, 1.0, 0);
+        let first = hbcn.edge_indices().next().unwrap();
+        hbcn[first].delay.min = Some(0.5);
+
+        let pnml = serialise_timed_pnml(&hbcn, "n", &["a -- b"]);
+        assert!(pnml.contains("<!-- a - - b -->"));
+        assert!(pnml.contains("<transition id=\"t0\"><name><text>a&amp;b+</text></name>"));
+        assert!(pnml.contains("<name><text>&lt;a&amp;b+,c+&gt;</text></name>"));
+        assert!(pnml.contains("<initialMarking><text>1</text></initialMarking>"));
+        assert!(pnml.contains("<delay min=\"0.5\" max=\"2\"/>"));
+        assert_eq!(pnml.matches("<arc ").count(), 2 * hbcn.edge_count());
+
+        let untimed = serialise_pnml(&hbcn, "n", &[] as &[&str]);
+        assert!(!untimed.contains("toolspecific"));
+    }
+}
@@ -0,0 +1,625 @@
+//! Reader for signal transition graphs in the `.g` format, restricted to marked graphs.
+//!
+//! Every signal becomes a circuit node: `.inputs` and `.outputs` signals are ports, and
+//! `.internal` signals registers. Its rising transition `sig+` becomes the node's data
+//! transition and its falling transition `sig-` the spacer transition. Every place becomes an
+//! HBCN place, marked when the `.marking` holds a token on it. The format has no timing, so
+//! every place takes a unit delay.
+//!
+//! A marked graph gives each place exactly one input and one output transition, so explicit
+//! places with several of either (choice and merge places) are rejected, as are dummy and
+//! toggle transitions and a signal with several rising or falling transitions.
+//!
+//! The `# Signal x is node {name}` comments written by [`super::serialise_stg`] give the
+//! signals back their original names.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use petgraph::graph::NodeIndex;
+
+use crate::Symbol;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::hbcn::{
+    CircuitNode, DelayPair, DelayedPlace, HBCN, Place, Transition, ValidationError,
+    insert_default_tokens, validate_hbcn,
+};
+
+/// Whether `input` looks like a `.g` file: its first line that is neither blank nor a `#`
+/// comment is a `.` directive, which no `.hbcn` line is.
+pub fn is_stg(input: &str) -> bool {
+    input
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .is_some_and(|line| line.starts_with('.'))
+}
+
+/// Parse a signal transition graph in the `.g` format into an HBCN.
+///
+/// Channels the marking leaves without a token get one from [`insert_default_tokens`], and
+/// the HBCN is then checked by [`validate_hbcn`]. Syntax errors, places a marked graph cannot
+/// represent and validation errors are reported as a [`Diagnostic`] pointing into `input`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::petri::parse_stg;
+///
+/// let hbcn = parse_stg("
+///     .inputs a
+///     .outputs b
+///     .graph
+///     a+ b+
+///     b+ a-
+///     a- b-
+///     b- a+
+///     .marking { <b-,a+> }
+///     .end
+/// ").unwrap();
+/// assert_eq!((hbcn.node_count(), hbcn.edge_count()), (4, 4));
+///
+/// let choice = parse_stg("
+///     .inputs a
+///     .outputs b c
+///     .graph
+///     a+ p0
+///     p0 b+ c+
+///     .end
+/// ");
+/// assert!(choice.unwrap_err().to_string().contains("choice"));
+/// ```
+pub fn parse_stg(input: &str) -> Result<HBCN<Transition, DelayedPlace>> {
+    let StgNet {
+        mut hbcn,
+        transition_spans,
+        place_spans,
+    } = read_stg(input).map_err(|(message, span)| {
+        Diagnostic::new(
+            input,
+            span,
+            format!("Failed to parse STG input: {}", message),
+        )
+    })?;
+
+    insert_default_tokens(&mut hbcn);
+
+    validate_hbcn(&hbcn).map_err(|e| {
+        let span = e.downcast_ref::<ValidationError>().and_then(|v| {
+            v.place
+                .and_then(|p| place_spans.get(p.index()).copied())
+                .or_else(|| {
+                    v.transition
+                        .and_then(|t| transition_spans.get(t.index()).copied())
+                })
+        });
+        let message = format!("HBCN validation failed: {}", e);
+        match span {
+            Some(span) => Diagnostic::new(input, span, message).into(),
+            None => anyhow!(message),
+        }
+    })?;
+
+    Ok(hbcn)
+}
+
+/// The HBCN of an STG, with the span of each transition's first mention and of each place,
+/// by index.
+struct StgNet {
+    hbcn: HBCN<Transition, DelayedPlace>,
+    transition_spans: Vec<Span>,
+    place_spans: Vec<Span>,
+}
+
+/// A whitespace-separated word of a line, `{`, `}` or an implicit place `<a+,b+>`.
+#[derive(Debug, Clone, Copy)]
+struct Word<'a> {
+    text: &'a str,
+    span: Span,
+    line: usize,
+}
+
+/// Split `input` into words, dropping `#` comments, and collect the signal renames of the
+/// `# Signal x is node {name}` comments.
+fn words(input: &str) -> (Vec<Word<'_>>, HashMap<&str, &str>) {
+    let mut words = Vec::new();
+    let mut renames = HashMap::new();
+    let mut offset = 0;
+    for (line, text) in input.split('\n').enumerate() {
+        let start = offset;
+        offset += text.len() + 1;
+
+        let code = match text.find('#') {
+            Some(comment) => {
+                let rename = text[comment + 1..]
+                    .trim()
+                    .strip_prefix("Signal ")
+                    .and_then(|rest| rest.split_once(" is node {"))
+                    .and_then(|(signal, name)| Some((signal, name.strip_suffix('}')?)));
+                if let Some((signal, name)) = rename {
+                    renames.insert(signal.trim(), name);
+                }
+                &text[..comment]
+            }
+            None => text,
+        };
+
+        let bytes = code.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let from = i;
+            match bytes[i] {
+                b if b.is_ascii_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                b'{' | b'}' => i += 1,
+                b'<' => i = code[i..].find('>').map_or(bytes.len(), |end| i + end + 1),
+                _ => {
+                    while i < bytes.len()
+                        && !bytes[i].is_ascii_whitespace()
+                        && !b"{}<".contains(&bytes[i])
+                    {
+                        i += 1;
+                    }
+                }
+            }
+            words.push(Word {
+                text: &code[from..i],
+                span: Span::new(start + from, start + i),
+                line,
+            });
+        }
+    }
+    (words, renames)
+}
+
+/// A place named in the `.graph` section, with the transitions of its arcs.
+#[derive(Default)]
+struct ExplicitPlace<'a> {
+    span: Span,
+    inputs: Vec<(NodeIndex, &'a str)>,
+    outputs: Vec<(NodeIndex, &'a str)>,
+}
+
+/// Reads the words of an STG into an HBCN.
+struct Reader<'a> {
+    /// Circuit node of each declared signal.
+    signals: HashMap<&'a str, CircuitNode>,
+    renames: HashMap<&'a str, &'a str>,
+    hbcn: HBCN<Transition, DelayedPlace>,
+    /// Transition of each signal edge, with the label that first named it.
+    transitions: HashMap<(&'a str, bool), (&'a str, NodeIndex)>,
+    transition_spans: Vec<Span>,
+    /// Places between two transitions, in order, with the span of their arc.
+    implicit: Vec<(NodeIndex, NodeIndex, Span)>,
+    explicit: HashMap<&'a str, ExplicitPlace<'a>>,
+    /// Explicit places in order of first mention.
+    explicit_order: Vec<&'a str>,
+}
+
+/// The signal name and edge (`+`, `-` or `~`) of a transition label such as `a+` or `a-/1`,
+/// or `None` if the word is not a transition label.
+fn split_label(text: &str) -> Option<(&str, char)> {
+    let base = match text.rsplit_once('/') {
+        Some((base, instance)) if instance.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => text,
+    };
+    let edge = base.chars().last()?;
+    matches!(edge, '+' | '-' | '~').then(|| (&base[..base.len() - 1], edge))
+}
+
+impl<'a> Reader<'a> {
+    /// The transition a word names, creating it on first mention, or `None` for a place.
+    fn transition(&mut self, word: Word<'a>) -> Result<Option<NodeIndex>, (String, Span)> {
+        let Some((signal, edge)) = split_label(word.text) else {
+            return Ok(None);
+        };
+        let Some(node) = self.signals.get(signal) else {
+            return Err((format!("undeclared signal `{}`", signal), word.span));
+        };
+        if edge == '~' {
+            return Err((
+                format!(
+                    "toggle transition `{}` cannot be represented in an HBCN",
+                    word.text
+                ),
+                word.span,
+            ));
+        }
+        let data = edge == '+';
+        if let Some(&(label, idx)) = self.transitions.get(&(signal, data)) {
+            if label != word.text {
+                return Err((
+                    format!(
+                        "signal `{}` has more than one {} transition (`{}` and `{}`), but an \
+                         HBCN node has one",
+                        signal,
+                        if data { "rising" } else { "falling" },
+                        label,
+                        word.text
+                    ),
+                    word.span,
+                ));
+            }
+            return Ok(Some(idx));
+        }
+        let idx = self.hbcn.add_node(if data {
+            Transition::Data(node.clone())
+        } else {
+            Transition::Spacer(node.clone())
+        });
+        self.transitions.insert((signal, data), (word.text, idx));
+        self.transition_spans.push(word.span);
+        Ok(Some(idx))
+    }
+
+    fn declare(&mut self, word: Word<'a>, port: bool) -> Result<(), (String, Span)> {
+        let name = Symbol::from(*self.renames.get(word.text).unwrap_or(&word.text));
+        let node = if port {
+            CircuitNode::Port(name)
+        } else {
+            CircuitNode::Register(name)
+        };
+        if self.signals.insert(word.text, node).is_some() {
+            return Err((
+                format!("signal `{}` declared more than once", word.text),
+                word.span,
+            ));
+        }
+        Ok(())
+    }
+
+    fn explicit_place(&mut self, word: Word<'a>) -> &mut ExplicitPlace<'a> {
+        if !self.explicit.contains_key(word.text) {
+            self.explicit_order.push(word.text);
+        }
+        self.explicit
+            .entry(word.text)
+            .or_insert_with(|| ExplicitPlace {
+                span: word.span,
+                ..ExplicitPlace::default()
+            })
+    }
+
+    /// An arc line of the `.graph` section: a node and its successors.
+    fn arcs(&mut self, line: &[Word<'a>]) -> Result<(), (String, Span)> {
+        let (&from, successors) = line.split_first().expect("lines are not empty");
+        let source = self.transition(from)?;
+        if successors.is_empty() {
+            return Err(("expected the successors of the node".into(), from.span));
+        }
+        for &to in successors {
+            match (source, self.transition(to)?) {
+                (Some(src), Some(dst)) => self.implicit.push((src, dst, to.span)),
+                (Some(src), None) => self.explicit_place(to).inputs.push((src, from.text)),
+                (None, Some(dst)) => self.explicit_place(from).outputs.push((dst, to.text)),
+                (None, None) => {
+                    return Err((
+                        format!("arc between places `{}` and `{}`", from.text, to.text),
+                        to.span,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The marked places of a `.marking` list: place names, and the `a+,b+` of implicit places.
+    fn marking(&mut self, words: &[Word<'a>]) -> Result<Vec<(&'a str, Span)>, (String, Span)> {
+        let mut marked = Vec::new();
+        for word in words {
+            if let Some(pair) = word.text.strip_prefix('<') {
+                let pair = pair
+                    .strip_suffix('>')
+                    .ok_or_else(|| ("unclosed implicit place".to_string(), word.span))?;
+                marked.push((pair, word.span));
+                continue;
+            }
+            let (place, tokens) = match word.text.split_once('=') {
+                Some((place, tokens)) => (
+                    place,
+                    tokens
+                        .parse::<u32>()
+                        .map_err(|_| (format!("invalid token count `{}`", tokens), word.span))?,
+                ),
+                None => (word.text, 1),
+            };
+            match tokens {
+                0 => {}
+                1 => marked.push((place, word.span)),
+                n => {
+                    return Err((
+                        format!(
+                            "place `{}` holds {} tokens, but an HBCN place holds at most one",
+                            place, n
+                        ),
+                        word.span,
+                    ));
+                }
+            }
+        }
+        Ok(marked)
+    }
+
+    /// Add the places to the graph, marking those of `marking`.
+    fn finish(mut self, marking: Vec<(&'a str, Span)>) -> Result<StgNet, (String, Span)> {
+        let mut places: Vec<(NodeIndex, NodeIndex, Span)> = std::mem::take(&mut self.implicit);
+        let mut explicit_index = HashMap::new();
+        for name in &self.explicit_order {
+            let place = &self.explicit[name];
+            let labels = |arcs: &[(NodeIndex, &str)]| {
+                arcs.iter()
+                    .map(|(_, label)| format!("`{}`", label))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let (src, dst) = match (place.inputs.as_slice(), place.outputs.as_slice()) {
+                ([(src, _)], [(dst, _)]) => (*src, *dst),
+                (_, outputs) if outputs.len() > 1 => {
+                    return Err((
+                        format!(
+                            "place `{}` is a choice between transitions {}, which a marked \
+                             graph cannot represent",
+                            name,
+                            labels(outputs)
+                        ),
+                        place.span,
+                    ));
+                }
+                (inputs, _) if inputs.len() > 1 => {
+                    return Err((
+                        format!(
+                            "place `{}` merges transitions {}, which a marked graph cannot \
+                             represent",
+                            name,
+                            labels(inputs)
+                        ),
+                        place.span,
+                    ));
+                }
+                ([], _) => {
+                    return Err((
+                        format!("place `{}` has no input transition", name),
+                        place.span,
+                    ));
+                }
+                _ => {
+                    return Err((
+                        format!("place `{}` has no output transition", name),
+                        place.span,
+                    ));
+                }
+            };
+            explicit_index.insert(*name, places.len());
+            places.push((src, dst, place.span));
+        }
+
+        let mut tokens = vec![false; places.len()];
+        for (place, span) in marking {
+            let index = match place.split_once(',') {
+                Some((src, dst)) => {
+                    let endpoint = |label: &str| {
+                        split_label(label.trim())
+                            .and_then(|(signal, edge)| self.transitions.get(&(signal, edge == '+')))
+                            .map(|&(_, idx)| idx)
+                    };
+                    let (src, dst) = (endpoint(src), endpoint(dst));
+                    places
+                        .iter()
+                        .position(|&(s, d, _)| Some(s) == src && Some(d) == dst)
+                }
+                None => explicit_index.get(place).copied(),
+            };
+            match index {
+                Some(index) => tokens[index] = true,
+                None => return Err(("marking names an unknown place".into(), span)),
+            }
+        }
+
+        let mut place_spans = Vec::with_capacity(places.len());
+        for ((src, dst, span), token) in places.into_iter().zip(tokens) {
+            self.hbcn.add_edge(
+                src,
+                dst,
+                DelayedPlace {
+                    place: Place {
+                        token,
+                        is_internal: false,
+                    },
+                    delay: DelayPair::new(None, 1.0),
+                    slack: None,
+                },
+            );
+            place_spans.push(span);
+        }
+
+        Ok(StgNet {
+            hbcn: self.hbcn,
+            transition_spans: self.transition_spans,
+            place_spans,
+        })
+    }
+}
+
+fn read_stg(input: &str) -> Result<StgNet, (String, Span)> {
+    let (words, renames) = words(input);
+    let mut reader = Reader {
+        signals: HashMap::new(),
+        renames,
+        hbcn: HBCN::default(),
+        transitions: HashMap::new(),
+        transition_spans: Vec::new(),
+        implicit: Vec::new(),
+        explicit: HashMap::new(),
+        explicit_order: Vec::new(),
+    };
+    let end = Span::new(input.len(), input.len());
+
+    let mut in_graph = false;
+    let mut marking = Vec::new();
+    let mut rest = words.as_slice();
+    while let Some(first) = rest.first() {
+        let line_len = rest.iter().take_while(|w| w.line == first.line).count();
+        let (line, next) = rest.split_at(line_len);
+        rest = next;
+
+        match first.text {
+            ".model" | ".name" | ".initial" | ".capacity" => {}
+            ".inputs" | ".outputs" | ".internal" => {
+                for &word in &line[1..] {
+                    reader.declare(word, first.text != ".internal")?;
+                }
+            }
+            ".dummy" => {
+                let word = line.get(1).unwrap_or(first);
+                return Err((
+                    "dummy transitions cannot be represented in an HBCN".into(),
+                    word.span,
+                ));
+            }
+            ".graph" => in_graph = true,
+            ".marking" => {
+                // The list may run over several lines, up to its `}`
+                let mut list = line[1..].to_vec();
+                let mut more = rest.iter();
+                while !list.iter().any(|w| w.text == "}") {
+                    match more.next() {
+                        Some(&word) => list.push(word),
+                        None => return Err(("unclosed `.marking` list".into(), first.span)),
+                    }
+                }
+                rest = more.as_slice();
+                let close = list.iter().position(|w| w.text == "}").unwrap();
+                if list.first().map(|w| w.text) != Some("{") {
+                    return Err(("expected `{` after `.marking`".into(), first.span));
+                }
+                if let Some(extra) = list.get(close + 1) {
+                    return Err(("unexpected input after `.marking` list".into(), extra.span));
+                }
+                marking = reader.marking(&list[1..close])?;
+            }
+            ".end" => {
+                if let Some(extra) = line.get(1).or(rest.first()) {
+                    return Err(("unexpected input after `.end`".into(), extra.span));
+                }
+                break;
+            }
+            directive if directive.starts_with('.') => {
+                return Err((format!("unsupported directive `{}`", directive), first.span));
+            }
+            _ if in_graph => reader.arcs(line)?,
+            _ => return Err(("expected `.graph` before the arcs".into(), first.span)),
+        }
+    }
+
+    if !in_graph {
+        return Err(("expected a `.graph` section".into(), end));
+    }
+    reader.finish(marking)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbcn::petri::serialise_stg;
+    use crate::hbcn::test_helpers::create_valid_two_channel_hbcn;
+    use crate::hbcn::{MarkablePlace, Named};
+
+    /// Exporting an HBCN and reading it back gives the same nodes and marking.
+    #[test]
+    fn round_trips_exported_stg() {
+        let hbcn =
+            create_valid_two_channel_hbcn("port:a", "r/s0", "port:b", 2.0, 1.0, 2.0, 1.0, 1, 3);
+        let stg = serialise_stg(&hbcn, "two", &["header"]);
+        let read = parse_stg(&stg).unwrap();
+
+        let marked = |g: &HBCN<Transition, DelayedPlace>| {
+            let mut places: Vec<String> = g
+                .edge_indices()
+                .filter(|&ie| g[ie].is_marked())
+                .map(|ie| {
+                    let (src, dst) = g.edge_endpoints(ie).unwrap();
+                    format!("{} -> {}", g[src], g[dst])
+                })
+                .collect();
+            places.sort();
+            places
+        };
+        assert_eq!(read.edge_count(), hbcn.edge_count());
+        assert_eq!(marked(&read), marked(&hbcn));
+        let names: Vec<&str> = read
+            .node_indices()
+            .map(|ix| read[ix].name().as_ref())
+            .collect();
+        assert!(names.contains(&"r/s0"));
+    }
+
+    #[test]
+    fn explicit_places_and_multiline_marking() {
+        let hbcn = parse_stg(
+            ".model m\n.inputs a\n.internal r\n.graph\n\
+             a+ p0\np0 r+\nr+ a-\na- r-\nr- a+\n\
+             .marking {\n  p0=1\n}\n.end\n",
+        )
+        .unwrap();
+        let marked: Vec<_> = hbcn
+            .edge_indices()
+            .filter(|&ie| hbcn[ie].is_marked())
+            .collect();
+        assert_eq!(marked.len(), 1);
+        let (src, dst) = hbcn.edge_endpoints(marked[0]).unwrap();
+        assert!(matches!(&hbcn[src], Transition::Data(CircuitNode::Port(_))));
+        assert!(matches!(
+            &hbcn[dst],
+            Transition::Data(CircuitNode::Register(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_what_a_marked_graph_cannot_represent() {
+        for (input, message) in [
+            (
+                ".inputs a b\n.graph\np0 a+\np1 a+\na+ p0\nb+ p0\n.end",
+                "place `p0` merges transitions `a+`, `b+`",
+            ),
+            (".inputs a\n.dummy t\n.graph\n", "dummy transitions"),
+            (".inputs a\n.graph\na~ a+\n", "toggle transition `a~`"),
+            (".inputs a\n.graph\na+ b+\n", "undeclared signal `b`"),
+            (
+                ".inputs a b\n.graph\na+ b+\na+/1 b-\n",
+                "signal `a` has more than one rising transition (`a+` and `a+/1`)",
+            ),
+            (
+                ".inputs a\n.graph\na+ p0\n.end",
+                "place `p0` has no output transition",
+            ),
+            (
+                ".inputs a b\n.graph\na+ p0\np0 b+\n.marking { p0=2 }",
+                "place `p0` holds 2 tokens",
+            ),
+            (
+                ".inputs a b\n.graph\na+ b+\n.marking { <b+,a+> }",
+                "unknown place",
+            ),
+            (
+                ".inputs a\n.graph\n.inputs b c\n.foo\n",
+                "unsupported directive `.foo`",
+            ),
+            (
+                ".inputs a b\n.graph\na+ b+\nb+ a-\na- b-\nb- a+\n.marking { <a+,b+> <b-,a+> }",
+                "HBCN validation failed",
+            ),
+        ] {
+            let error = parse_stg(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", input, error);
+        }
+    }
+
+    #[test]
+    fn detects_stg_input() {
+        assert!(is_stg("# comment\n\n.model m\n"));
+        assert!(!is_stg("# comment\n* +{a} => +{b} : 1\n"));
+        assert!(!is_stg("{\"format\": \"hbcn\"}"));
+    }
+}
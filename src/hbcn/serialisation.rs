@@ -1,10 +1,14 @@
 //! Serialisation functions for HBCN graphs.
 //!
 //! This module provides functions to serialise HBCN graphs to the format
-//! defined by the parser grammar.
+//! defined by the parser grammar, optionally preceded by a `#` comment header
+//! (see [`write_comment_header`]) and a node declaration section (see
+//! [`serialise_hbcn_with_declarations`]).
 
-use crate::hbcn::{DelayPair, HasDelay, MarkablePlace, Named, Transition};
+use crate::hbcn::declarations::{NodeDeclaration, NodeKind, undeclared_node};
+use crate::hbcn::{CircuitNode, DelayPair, HasDelay, MarkablePlace, Named, Transition};
 use petgraph::stable_graph::StableGraph;
+use std::collections::HashSet;
 use std::fmt::{self};
 
 /// Serialise an HBCN to the format defined by the parser grammar into the provided writer.
@@ -16,7 +20,7 @@ where
     P: MarkablePlace + HasDelay,
     W: fmt::Write,
 {
-    serialise_hbcn_internal(hbcn, |node| node.as_ref(), writer)
+    serialise_hbcn_internal(hbcn, |node| node.as_ref(), &[], writer)
 }
 
 /// Serialise an HBCN to the format defined by the parser grammar.
@@ -26,6 +30,8 @@ where
 /// - Edges implement [`MarkablePlace`] and [`HasDelay`]
 ///
 /// The output format matches the parser grammar:
+/// - Declarations: `port {name}` for each port whose name lacks the `port:` prefix and
+///   `register {name}` for each register whose name has it, so the node kinds round-trip
 /// - Edges: `source => target : delay` or `* source => target : delay` (if token is marked)
 /// - Transitions: `+{name}` for Data, `-{name}` for Spacer (TCL-escaped strings)
 /// - DelayPair: `(min, max)` when min is present, or `max` when min is absent
@@ -74,16 +80,144 @@ where
     out
 }
 
+/// Write `header` as a block of `#` line comments, one per line.
+///
+/// Used to record provenance (generator version, source file, options) at the top of a
+/// serialised graph; the parsers skip comments, so the output still round-trips. Embedded
+/// newlines start a new comment line, and an empty line is written as a bare `#`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::serialisation::write_comment_header;
+///
+/// let mut out = String::new();
+/// write_comment_header(&["Generated by hbcn", "Source: a.graph"], &mut out).unwrap();
+/// assert_eq!(out, "# Generated by hbcn\n# Source: a.graph\n");
+/// ```
+pub fn write_comment_header<S, W>(header: &[S], writer: &mut W) -> fmt::Result
+where
+    S: AsRef<str>,
+    W: fmt::Write,
+{
+    for line in header.iter().flat_map(|entry| entry.as_ref().split('\n')) {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            writer.write_str("#\n")?;
+        } else {
+            writeln!(writer, "# {}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Serialise an HBCN like [`serialise_hbcn`], preceded by a `#` comment header.
+///
+/// See [`write_comment_header`] for how `header` is rendered.
+pub fn serialise_hbcn_with_header<N, P, S>(hbcn: &StableGraph<N, P>, header: &[S]) -> String
+where
+    N: AsRef<Transition>,
+    P: MarkablePlace + HasDelay,
+    S: AsRef<str>,
+{
+    serialise_hbcn_with_declarations(hbcn, header, &[])
+}
+
+/// Serialise an HBCN like [`serialise_hbcn_with_header`], with a declaration section.
+///
+/// `declarations` are written in order after the header; any node they leave out whose kind
+/// its name would not imply gets a bare declaration, as in [`serialise_hbcn`]. A blank line
+/// separates the declarations from the places.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::declarations::{NodeDeclaration, NodeKind};
+/// use hbcn::hbcn::parser::parse_hbcn_with_declarations;
+/// use hbcn::hbcn::serialisation::serialise_hbcn_with_declarations;
+///
+/// let input = "* +{port:a} => +{r} : 2\n+{r} => -{port:a} : 1\n\
+///              -{port:a} => -{r} : 2\n-{r} => +{port:a} : 1\n";
+/// let (hbcn, _) = parse_hbcn_with_declarations(input).unwrap();
+/// let declarations = [NodeDeclaration {
+///     name: "r".into(),
+///     kind: NodeKind::Register(None),
+///     cost: Some(10),
+/// }];
+///
+/// let text = serialise_hbcn_with_declarations(&hbcn, &["Example"], &declarations);
+/// assert!(text.starts_with("# Example\nregister {r} cost 10\n\n* +{port:a} => +{r} : 2"));
+/// assert_eq!(parse_hbcn_with_declarations(&text).unwrap().1, declarations);
+/// ```
+pub fn serialise_hbcn_with_declarations<N, P, S>(
+    hbcn: &StableGraph<N, P>,
+    header: &[S],
+    declarations: &[NodeDeclaration],
+) -> String
+where
+    N: AsRef<Transition>,
+    P: MarkablePlace + HasDelay,
+    S: AsRef<str>,
+{
+    let mut out = String::new();
+    // Infallible for String
+    let _ = write_comment_header(header, &mut out);
+    let _ = serialise_hbcn_internal(hbcn, |node| node.as_ref(), declarations, &mut out);
+    out
+}
+
+/// Write `declarations`, then a bare declaration for each other node of `hbcn` whose kind
+/// differs from what its name implies, one per line. Returns whether anything was written.
+fn write_declarations<N, P, W>(
+    hbcn: &StableGraph<N, P>,
+    get_transition: &impl Fn(&N) -> &Transition,
+    declarations: &[NodeDeclaration],
+    writer: &mut W,
+) -> Result<bool, fmt::Error>
+where
+    W: fmt::Write,
+{
+    let mut declared: HashSet<_> = declarations.iter().map(|d| d.name.clone()).collect();
+    for declaration in declarations {
+        writeln!(writer, "{}", declaration)?;
+    }
+
+    let mut written = !declarations.is_empty();
+    for idx in hbcn.node_indices() {
+        let node: &CircuitNode = get_transition(&hbcn[idx]).as_ref();
+        if *node == undeclared_node(node.name()) || !declared.insert(node.name().clone()) {
+            continue;
+        }
+        let kind = match node {
+            CircuitNode::Port(_) => NodeKind::Port(None),
+            CircuitNode::Register(_) => NodeKind::Register(None),
+        };
+        let declaration = NodeDeclaration {
+            name: node.name().clone(),
+            kind,
+            cost: None,
+        };
+        writeln!(writer, "{}", declaration)?;
+        written = true;
+    }
+    Ok(written)
+}
+
 /// Internal helper that works with a transition extractor function and a writer.
 fn serialise_hbcn_internal<N, P, W>(
     hbcn: &StableGraph<N, P>,
     get_transition: impl Fn(&N) -> &Transition,
+    declarations: &[NodeDeclaration],
     writer: &mut W,
 ) -> std::fmt::Result
 where
     P: MarkablePlace + HasDelay,
     W: fmt::Write,
 {
+    if write_declarations(hbcn, &get_transition, declarations, writer)? && hbcn.edge_count() > 0 {
+        writer.write_char('\n')?;
+    }
+
     let mut first = true;
 
     for edge_idx in hbcn.edge_indices() {
@@ -129,11 +263,11 @@ where
     P: MarkablePlace + HasDelay,
 {
     let mut out = String::new();
-    let _ = serialise_hbcn_internal(hbcn, |t| t, &mut out);
+    let _ = serialise_hbcn_internal(hbcn, |t| t, &[], &mut out);
     out
 }
 
-fn write_transition<T: AsRef<Transition>, W: fmt::Write>(
+pub(crate) fn write_transition<T: AsRef<Transition>, W: fmt::Write>(
     transition: T,
     writer: &mut W,
 ) -> fmt::Result {
@@ -31,6 +31,8 @@
 //!      completion costs when `forward_completion` is enabled
 //!    - Backward places: Include register delays based on fan-in/fan-out (computed via log₂ of
 //!      the degree) plus base costs of circuit nodes
+//!    - A channel's `forward_delay`/`backward_delay`, when set, replace these computed weights,
+//!      and its `min_delay` becomes each place's minimum delay (capped at the place's weight)
 //!
 //! # Cost Model
 //!
@@ -46,6 +48,10 @@
 //! - **Forward completion**: When enabled, forward place weights consider both the virtual delay
 //!   and the completion detection logic required for the destination node.
 //!
+//! These costs come from a [`DelayModel`]. The default model prices them in virtual units, as
+//! above; [`from_structural_graph_with_delays`] takes a model built from the characterised
+//! cells of a Liberty file instead (see [`crate::liberty`]), so that they are in nanoseconds.
+//!
 //! # Example
 //!
 //! ```
@@ -68,7 +74,9 @@
 
 #[allow(unused_imports)] // Used in tests
 use super::{Place, StructuralHBCN, Transition, WeightedPlace, is_backward_place};
-use crate::structural_graph::{Channel, ChannelPhase, StructuralGraph};
+use crate::structural_graph::{
+    Channel, ChannelPhase, StructuralGraph, library::DEFAULT_REGISTER_COST,
+};
 #[cfg(debug_assertions)]
 use crate::validate_hbcn;
 
@@ -117,6 +125,47 @@ fn clog2(x: usize) -> u32 {
 /// `DEFAULT_REGISTER_DELAY * log₂(4) = 10.0 * 2 = 20.0` time units to backward costs.
 const DEFAULT_REGISTER_DELAY: f64 = 10.0;
 
+/// Delays of the logic the expansion adds around each circuit node.
+///
+/// Acknowledge merging and completion detection are trees of `⌈log₂(degree)⌉` levels, each
+/// costing the delay of one level. The default model uses `DEFAULT_REGISTER_DELAY` per level
+/// and the circuit nodes' base costs and channel delays as they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayModel {
+    /// Delay of one level of the C-element tree merging the acknowledges of a node's
+    /// outgoing channels.
+    pub acknowledge: f64,
+    /// Delay of one level of the completion detection of a node's incoming channels.
+    pub completion: f64,
+    /// Delay of a register whose base cost is [`DEFAULT_REGISTER_COST`];
+    /// other registers scale it by their cost. `None` uses the base costs themselves.
+    pub register: Option<f64>,
+    /// The model's delay per unit of the channels' virtual delays and delay attributes,
+    /// which are scaled by it; `1.0` when they are in the model's unit already.
+    pub virtual_unit: f64,
+}
+
+impl Default for DelayModel {
+    fn default() -> Self {
+        Self {
+            acknowledge: DEFAULT_REGISTER_DELAY,
+            completion: DEFAULT_REGISTER_DELAY,
+            register: None,
+            virtual_unit: 1.0,
+        }
+    }
+}
+
+impl DelayModel {
+    /// Delay of a circuit node of the given base cost: nothing for a port.
+    fn base_delay(&self, base_cost: usize) -> f64 {
+        match self.register {
+            Some(delay) => delay * base_cost as f64 / DEFAULT_REGISTER_COST as f64,
+            None => base_cost as f64,
+        }
+    }
+}
+
 /// Converts a structural graph to a Half-Buffer Channel Network.
 ///
 /// This function performs the core transformation from a structural circuit representation
@@ -160,6 +209,11 @@ const DEFAULT_REGISTER_DELAY: f64 = 10.0;
 /// - Where `backward_cost = DEFAULT_REGISTER_DELAY * log₂(outgoing_edges)` and
 ///   `dst_base_cost` is the base cost of the destination circuit node.
 ///
+/// A channel's `forward_delay` or `backward_delay` replaces the corresponding weight
+/// outright, and its `min_delay` becomes the places' `min_delay`, the lower bound of the
+/// `(min, max)` pair whose max is the weight. Parsing rejects a `min` above an explicit
+/// weight; a modelled weight is kept even below the `min` (see [`min_delay_excesses`]).
+///
 /// # Example
 ///
 /// ```
@@ -181,6 +235,49 @@ const DEFAULT_REGISTER_DELAY: f64 = 10.0;
 pub fn from_structural_graph(
     g: &StructuralGraph,
     forward_completion: bool,
+) -> Option<StructuralHBCN> {
+    from_structural_graph_with_delays(g, forward_completion, &DelayModel::default())
+}
+
+/// Converts a structural graph to a Half-Buffer Channel Network, pricing the logic around
+/// each circuit node with `delays`.
+///
+/// Behaves like [`from_structural_graph`], except that the `DEFAULT_REGISTER_DELAY` of the
+/// acknowledge and completion trees and the base costs of the nodes are replaced by the
+/// delays of `delays`, and the channels' virtual delays and delay attributes are scaled to
+/// the model's unit by its `virtual_unit`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::{DelayModel, from_structural_graph_with_delays};
+/// use hbcn::structural_graph::parse;
+///
+/// let graph = parse(r#"
+///     Port "a" [("r", 2.0), ("s", 2.0)]
+///     NullReg "r" [("b", 0.25)]
+///     NullReg "s" [("b", 0.25)]
+///     Port "b" []
+/// "#).unwrap();
+/// // Channel delays are given in units of half the model's
+/// let delays = DelayModel {
+///     acknowledge: 0.125,
+///     completion: 0.25,
+///     register: Some(0.5),
+///     virtual_unit: 0.5,
+/// };
+///
+/// let hbcn = from_structural_graph_with_delays(&graph, true, &delays).unwrap();
+/// let weights: Vec<f64> = hbcn.edge_indices().map(|ie| hbcn[ie].weight).collect();
+/// // a merges the acknowledges of two channels, then waits for the register r
+/// assert_eq!(&weights[..4], &[1.0, 1.0, 0.75, 0.75]);
+/// // b completes two channels after the register r, outweighing the virtual delay
+/// assert_eq!(&weights[8..12], &[1.0, 1.0, 0.125, 0.125]);
+/// ```
+pub fn from_structural_graph_with_delays(
+    g: &StructuralGraph,
+    forward_completion: bool,
+    delays: &DelayModel,
 ) -> Option<StructuralHBCN> {
     let mut ret = StructuralHBCN::new();
     struct VertexItem {
@@ -197,11 +294,11 @@ pub fn from_structural_graph(
             let circuit_node = CircuitNode::from(val.clone());
             let token = ret.add_node(Transition::Data(circuit_node.clone()));
             let spacer = ret.add_node(Transition::Spacer(circuit_node));
-            let base_cost = val.base_cost() as f64;
-            let backward_cost = DEFAULT_REGISTER_DELAY
+            let base_cost = delays.base_delay(val.base_cost());
+            let backward_cost = delays.acknowledge
                 * clog2(g.edges_directed(ix, Direction::Outgoing).count()) as f64;
-            let forward_cost = DEFAULT_REGISTER_DELAY
-                * clog2(g.edges_directed(ix, Direction::Incoming).count()) as f64;
+            let forward_cost =
+                delays.completion * clog2(g.edges_directed(ix, Direction::Incoming).count()) as f64;
             (
                 ix,
                 VertexItem {
@@ -236,15 +333,26 @@ pub fn from_structural_graph(
         let Channel {
             initial_phase,
             virtual_delay,
+            forward_delay,
+            backward_delay,
+            min_delay,
             ..
         } = g[ix];
+        let scale = |delay: f64| delay * delays.virtual_unit;
+        let (virtual_delay, forward_delay, backward_delay, min_delay) = (
+            scale(virtual_delay),
+            forward_delay.map(scale),
+            backward_delay.map(scale),
+            min_delay.map(scale),
+        );
 
-        let forward_cost = if forward_completion {
+        // Fixed delays from channel attributes take precedence over the cost model
+        let forward_cost = forward_delay.unwrap_or(if forward_completion {
             virtual_delay.max(*forward_cost + *src_base_cost)
         } else {
             virtual_delay
-        };
-        let backward_cost = *backward_cost + *dst_base_cost;
+        });
+        let backward_cost = backward_delay.unwrap_or(*backward_cost + *dst_base_cost);
 
         ret.add_edge(
             *src_token,
@@ -255,6 +363,7 @@ pub fn from_structural_graph(
                     is_internal,
                 },
                 weight: forward_cost,
+                min_delay,
             },
         );
         ret.add_edge(
@@ -266,6 +375,7 @@ pub fn from_structural_graph(
                     is_internal,
                 },
                 weight: forward_cost,
+                min_delay,
             },
         );
         ret.add_edge(
@@ -277,6 +387,7 @@ pub fn from_structural_graph(
                     is_internal,
                 },
                 weight: backward_cost,
+                min_delay,
             },
         );
         ret.add_edge(
@@ -288,6 +399,7 @@ pub fn from_structural_graph(
                     is_internal,
                 },
                 weight: backward_cost,
+                min_delay,
             },
         );
     }
@@ -304,10 +416,40 @@ pub fn from_structural_graph(
     Some(ret)
 }
 
+/// The places whose `min_delay` exceeds their weight, the max delay the conversion modelled
+/// for them.
+///
+/// Such a place has no delay that meets both bounds, so the subcommands warn about them.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::hbcn::{from_structural_graph, min_delay_excesses};
+/// use hbcn::structural_graph::parse;
+///
+/// let graph = parse(r#"
+///     Port "a" [("b", 100, min = 60)]
+///     Port "b" []
+/// "#).unwrap();
+/// let hbcn = from_structural_graph(&graph, false).unwrap();
+///
+/// // The forward places weigh 100, the modelled backward places less than the min
+/// assert_eq!(min_delay_excesses(&hbcn).len(), 2);
+/// ```
+pub fn min_delay_excesses(hbcn: &StructuralHBCN) -> Vec<EdgeIndex> {
+    hbcn.edge_indices()
+        .filter(|&ie| hbcn[ie].min_delay.is_some_and(|min| min > hbcn[ie].weight))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Named, structural_graph::parse};
+    use crate::{
+        Named,
+        hbcn::{DelayPair, DelayedPlace},
+        structural_graph::parse,
+    };
 
     /// Helper function to create a validated test HBCN
     fn create_test_hbcn(input: &str, forward_completion: bool) -> StructuralHBCN {
@@ -527,6 +669,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_channel_attributes() {
+        let input = r#"
+            Port "a" [("b", 150, phase = AckData, forward = 120, backward = 45, min = 40)]
+            Port "b" []
+        "#;
+        let hbcn = create_test_hbcn(input, true);
+
+        for edge_idx in hbcn.edge_indices() {
+            let place = &hbcn[edge_idx];
+            let (src, dst) = hbcn.edge_endpoints(edge_idx).unwrap();
+            let src_transition = hbcn[src].as_ref();
+            let dst_transition = hbcn[dst].as_ref();
+
+            if is_backward_place(src_transition, dst_transition) {
+                assert_eq!(place.weight, 45.0);
+                assert_eq!(place.min_delay, Some(40.0));
+                // AckData marks the place acknowledging data: Data(b) -> Spacer(a)
+                let marks_ack_data = matches!(dst_transition, Transition::Spacer(_));
+                assert_eq!(place.place.token, marks_ack_data);
+            } else {
+                // Fixed forward delay ignores forward completion and the virtual delay
+                assert_eq!(place.weight, 120.0);
+                assert_eq!(place.min_delay, Some(40.0));
+                assert!(!place.place.token);
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_max_pair_round_trips() {
+        let input = r#"
+            Port "a" [("b", 10, min = 4, max = 25)]
+            Port "b" []
+        "#;
+        let graph = parse(input).unwrap();
+        let reparsed =
+            parse(&crate::structural_graph::serialise_structural_graph(&graph).unwrap()).unwrap();
+        let channel =
+            |g: &crate::structural_graph::StructuralGraph| g[g.edge_indices().next().unwrap()];
+        assert_eq!(channel(&reparsed), channel(&graph));
+
+        // Every place carries the pair, its max as the weight
+        let hbcn = from_structural_graph(&graph, true).unwrap();
+        for edge_idx in hbcn.edge_indices() {
+            let delay = DelayedPlace::from(hbcn[edge_idx].clone()).delay;
+            assert_eq!(delay, DelayPair::new(Some(4.0), 25.0));
+        }
+        assert!(min_delay_excesses(&hbcn).is_empty());
+    }
+
+    #[test]
+    fn test_min_delay_keeps_modelled_weight() {
+        let input = r#"
+            Port "a" [("b", 100, min = 60)]
+            Port "b" []
+        "#;
+        let hbcn = create_test_hbcn(input, false);
+
+        let mut backward = Vec::new();
+        for edge_idx in hbcn.edge_indices() {
+            let place = &hbcn[edge_idx];
+            let (src, dst) = hbcn.edge_endpoints(edge_idx).unwrap();
+            assert_eq!(place.min_delay, Some(60.0));
+            if is_backward_place(hbcn[src].as_ref(), hbcn[dst].as_ref()) {
+                assert!(place.weight < 60.0);
+                backward.push(edge_idx);
+            } else {
+                assert_eq!(place.weight, 100.0);
+            }
+        }
+        assert_eq!(min_delay_excesses(&hbcn), backward);
+    }
+
     #[test]
     fn test_weight_calculations() {
         let input = r#"
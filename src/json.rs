@@ -0,0 +1,885 @@
+//! Versioned JSON import and export of structural graphs and HBCNs.
+//!
+//! Flow scripts can exchange every graph type of the library as JSON instead of scraping
+//! the text reports. A document is a single object:
+//!
+//! ```json
+//! {
+//!   "format": "hbcn",
+//!   "version": 1,
+//!   "graph": "solved_hbcn",
+//!   "cycle_time": 12.5,
+//!   "declarations": [
+//!     { "kind": "port", "name": "a", "direction": "input" },
+//!     { "kind": "register", "name": "r", "cost": 10 }
+//!   ],
+//!   "nodes": [
+//!     { "kind": "port", "name": "a", "transition": "data", "time": 0.0 },
+//!     { "kind": "register", "name": "r", "transition": "data", "time": 4.5 }
+//!   ],
+//!   "edges": [
+//!     { "source": 0, "target": 1, "token": false, "internal": false,
+//!       "max_delay": 4.5, "min_delay": 1.0, "slack": 0.0 }
+//!   ]
+//! }
+//! ```
+//!
+//! `graph` selects what the nodes and edges hold:
+//!
+//! | `graph`            | Type                   | Nodes                               | Edges |
+//! |--------------------|------------------------|-------------------------------------|-------|
+//! | `structural_graph` | [`StructuralGraph`]    | `kind`, `name`, `cost` (registers)  | `phase`, `internal`, `virtual_delay`, optional `forward_delay`, `backward_delay`, `min_delay` |
+//! | `structural_hbcn`  | [`StructuralHBCN`]     | `kind`, `name`, `transition`        | `token`, `internal`, `weight`, optional `min_delay` |
+//! | `solved_hbcn`      | [`SolvedHBCN`]         | as above, plus the arrival `time`   | `token`, `internal`, `max_delay`, optional `min_delay`, `slack` |
+//!
+//! `kind` is `port` or `register`, `transition` is `data` or `spacer`, and `source`/`target`
+//! index the `nodes` array. As in the text formats, a node may be listed only once and two
+//! nodes are joined by at most one edge in each direction. A solved HBCN may carry the analysed `cycle_time` and the
+//! constrainer's `pseudoclock_period` ([`SolvedSummary`]).
+//!
+//! Both HBCN documents may carry the node declarations of the `.hbcn` format (see
+//! [`declarations`](crate::hbcn::declarations)) in an optional `declarations` array: each
+//! entry has the `kind` and `name` of a node of the graph, an optional `direction` (`input`,
+//! `output` or `inout`) for a port or `class` (`data` or `control`) for a register, and an
+//! optional `cost`. They are written and read by the `_with_declarations` functions, so an
+//! HBCN keeps its declarations when it is exchanged as JSON.
+//!
+//! [`SCHEMA_VERSION`] is bumped whenever a field changes meaning or a required field is
+//! added; readers reject documents of any other version. Unknown fields are ignored.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::json::{structural_graph_from_json, structural_graph_to_json};
+//! use hbcn::structural_graph::parse;
+//!
+//! let graph = parse(r#"
+//!     Port "a" [("r", 10)]
+//!     DataReg "r" [("a", 20)]
+//! "#).unwrap();
+//!
+//! let json = structural_graph_to_json(&graph);
+//! let read_back = structural_graph_from_json(&json).unwrap();
+//! assert_eq!(read_back.node_count(), graph.node_count());
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    Symbol,
+    hbcn::{
+        CircuitNode, DelayPair, DelayedPlace, HBCN, Place, SolvedHBCN, StructuralHBCN, Transition,
+        TransitionEvent, WeightedPlace,
+        declarations::{NodeDeclaration, NodeKind, PortDirection, RegisterClass, misdirected_port},
+        validate_hbcn,
+    },
+    structural_graph::{self, Channel, ChannelPhase, StructuralGraph},
+};
+
+/// Version of the JSON schema written by this library.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Value of the `format` field identifying an hbcn JSON document.
+const FORMAT: &str = "hbcn";
+
+/// The graph type held by a JSON document, from its `graph` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphKind {
+    /// A [`StructuralGraph`].
+    StructuralGraph,
+    /// A [`StructuralHBCN`].
+    StructuralHbcn,
+    /// A [`SolvedHBCN`].
+    SolvedHbcn,
+}
+
+impl std::fmt::Display for GraphKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GraphKind::StructuralGraph => "structural_graph",
+            GraphKind::StructuralHbcn => "structural_hbcn",
+            GraphKind::SolvedHbcn => "solved_hbcn",
+        })
+    }
+}
+
+/// Whole-graph timing results stored alongside a solved HBCN.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SolvedSummary {
+    /// Cycle time found by `analyse` (the critical depth with `--depth`), or the cycle-time
+    /// constraint passed to `constrain`.
+    pub cycle_time: Option<f64>,
+    /// Pseudo-clock period computed by `constrain`.
+    pub pseudoclock_period: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    graph: GraphKind,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document<N, E> {
+    format: String,
+    version: u32,
+    graph: GraphKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cycle_time: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pseudoclock_period: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    declarations: Vec<DeclarationJson>,
+    nodes: Vec<N>,
+    edges: Vec<Edge<E>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Edge<E> {
+    source: usize,
+    target: usize,
+    #[serde(flatten)]
+    data: E,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum DeclarationJson {
+    Port {
+        name: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        direction: Option<PortDirection>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cost: Option<usize>,
+    },
+    Register {
+        name: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        class: Option<RegisterClass>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cost: Option<usize>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum StructuralNodeJson {
+    Port { name: Symbol },
+    Register { name: Symbol, cost: usize },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChannelJson {
+    phase: ChannelPhase,
+    internal: bool,
+    virtual_delay: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forward_delay: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backward_delay: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_delay: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum HbcnNodeJson {
+    Port { name: Symbol },
+    Register { name: Symbol },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum TransitionKind {
+    Data,
+    Spacer,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransitionJson {
+    #[serde(flatten)]
+    node: HbcnNodeJson,
+    transition: TransitionKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WeightedPlaceJson {
+    token: bool,
+    internal: bool,
+    weight: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_delay: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DelayedPlaceJson {
+    token: bool,
+    internal: bool,
+    max_delay: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_delay: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slack: Option<f64>,
+}
+
+impl From<&Transition> for TransitionJson {
+    fn from(transition: &Transition) -> Self {
+        let (kind, node) = match transition {
+            Transition::Data(node) => (TransitionKind::Data, node),
+            Transition::Spacer(node) => (TransitionKind::Spacer, node),
+        };
+        let node = match node {
+            CircuitNode::Port(name) => HbcnNodeJson::Port { name: name.clone() },
+            CircuitNode::Register(name) => HbcnNodeJson::Register { name: name.clone() },
+        };
+        TransitionJson {
+            node,
+            transition: kind,
+            time: None,
+        }
+    }
+}
+
+impl From<TransitionJson> for Transition {
+    fn from(json: TransitionJson) -> Self {
+        let node = match json.node {
+            HbcnNodeJson::Port { name } => CircuitNode::Port(name),
+            HbcnNodeJson::Register { name } => CircuitNode::Register(name),
+        };
+        match json.transition {
+            TransitionKind::Data => Transition::Data(node),
+            TransitionKind::Spacer => Transition::Spacer(node),
+        }
+    }
+}
+
+impl From<&NodeDeclaration> for DeclarationJson {
+    fn from(declaration: &NodeDeclaration) -> Self {
+        let (name, cost) = (declaration.name.clone(), declaration.cost);
+        match declaration.kind {
+            NodeKind::Port(direction) => DeclarationJson::Port {
+                name,
+                direction,
+                cost,
+            },
+            NodeKind::Register(class) => DeclarationJson::Register { name, class, cost },
+        }
+    }
+}
+
+impl From<DeclarationJson> for NodeDeclaration {
+    fn from(json: DeclarationJson) -> Self {
+        let (name, kind, cost) = match json {
+            DeclarationJson::Port {
+                name,
+                direction,
+                cost,
+            } => (name, NodeKind::Port(direction), cost),
+            DeclarationJson::Register { name, class, cost } => {
+                (name, NodeKind::Register(class), cost)
+            }
+        };
+        NodeDeclaration { name, kind, cost }
+    }
+}
+
+impl From<&WeightedPlace> for WeightedPlaceJson {
+    fn from(place: &WeightedPlace) -> Self {
+        WeightedPlaceJson {
+            token: place.place.token,
+            internal: place.place.is_internal,
+            weight: place.weight,
+            min_delay: place.min_delay,
+        }
+    }
+}
+
+impl From<WeightedPlaceJson> for WeightedPlace {
+    fn from(json: WeightedPlaceJson) -> Self {
+        WeightedPlace {
+            place: Place {
+                token: json.token,
+                is_internal: json.internal,
+            },
+            weight: json.weight,
+            min_delay: json.min_delay,
+        }
+    }
+}
+
+impl From<&DelayedPlace> for DelayedPlaceJson {
+    fn from(place: &DelayedPlace) -> Self {
+        DelayedPlaceJson {
+            token: place.place.token,
+            internal: place.place.is_internal,
+            max_delay: place.delay.max,
+            min_delay: place.delay.min,
+            slack: place.slack,
+        }
+    }
+}
+
+impl From<DelayedPlaceJson> for DelayedPlace {
+    fn from(json: DelayedPlaceJson) -> Self {
+        DelayedPlace {
+            place: Place {
+                token: json.token,
+                is_internal: json.internal,
+            },
+            delay: DelayPair::new(json.min_delay, json.max_delay),
+            slack: json.slack,
+        }
+    }
+}
+
+/// Returns `true` if `input` looks like a JSON document rather than `.graph`/`.hbcn` text.
+///
+/// Neither text format can start with `{`, so the first non-blank character decides.
+pub fn is_json(input: &str) -> bool {
+    input.trim_start().starts_with('{')
+}
+
+/// Read the `graph` field of a JSON document, checking its format and version.
+pub fn graph_kind(input: &str) -> Result<GraphKind> {
+    let header: Header = serde_json::from_str(input).context("Failed to parse JSON input")?;
+    if header.format != FORMAT {
+        bail!(
+            "Unsupported JSON format '{}' (expected '{}')",
+            header.format,
+            FORMAT
+        );
+    }
+    if header.version != SCHEMA_VERSION {
+        bail!(
+            "Unsupported JSON schema version {} (expected {})",
+            header.version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(header.graph)
+}
+
+fn to_json<T, P, N: Serialize, E: Serialize>(
+    graph: &StableGraph<T, P>,
+    kind: GraphKind,
+    summary: SolvedSummary,
+    declarations: &[NodeDeclaration],
+    node: impl Fn(&T) -> N,
+    edge: impl Fn(&P) -> E,
+) -> String {
+    // Stable graphs may have holes in their indices; the document numbers nodes densely
+    let index: HashMap<NodeIndex, usize> = graph
+        .node_indices()
+        .enumerate()
+        .map(|(i, ni)| (ni, i))
+        .collect();
+
+    let document = Document {
+        format: FORMAT.to_string(),
+        version: SCHEMA_VERSION,
+        graph: kind,
+        cycle_time: summary.cycle_time,
+        pseudoclock_period: summary.pseudoclock_period,
+        declarations: declarations.iter().map(DeclarationJson::from).collect(),
+        nodes: graph.node_indices().map(|ni| node(&graph[ni])).collect(),
+        edges: graph
+            .edge_indices()
+            .map(|ie| {
+                let (source, target) = graph.edge_endpoints(ie).unwrap();
+                Edge {
+                    source: index[&source],
+                    target: index[&target],
+                    data: edge(&graph[ie]),
+                }
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&document).expect("graph documents always serialise")
+}
+
+/// Read a document of the given kind, rejecting repeated nodes, by the `key` of their weights,
+/// and repeated edges between the same pair of nodes, as the text parsers do.
+fn from_json<T, P, N: DeserializeOwned, E: DeserializeOwned, K: Hash + Eq + Display>(
+    input: &str,
+    kind: GraphKind,
+    node: impl Fn(N) -> Result<T>,
+    key: impl Fn(&T) -> K,
+    edge: impl Fn(E) -> P,
+) -> Result<(StableGraph<T, P>, SolvedSummary, Vec<NodeDeclaration>)> {
+    let found = graph_kind(input)?;
+    if found != kind {
+        bail!("Expected a {} JSON document, found {}", kind, found);
+    }
+    let document: Document<N, E> =
+        serde_json::from_str(input).context("Failed to parse JSON input")?;
+
+    let mut graph = StableGraph::new();
+    let mut keys = HashMap::with_capacity(document.nodes.len());
+    let nodes = document
+        .nodes
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let weight = node(n)?;
+            if let Some(first) = keys.insert(key(&weight), i) {
+                bail!("Node {} repeats node {} ({})", i, first, key(&weight));
+            }
+            Ok(graph.add_node(weight))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut endpoints = HashSet::with_capacity(document.edges.len());
+    for (
+        i,
+        Edge {
+            source,
+            target,
+            data,
+        },
+    ) in document.edges.into_iter().enumerate()
+    {
+        let endpoint = |index: usize| {
+            nodes.get(index).copied().ok_or_else(|| {
+                anyhow!(
+                    "Edge {} refers to node {}, but there are only {} nodes",
+                    i,
+                    index,
+                    nodes.len()
+                )
+            })
+        };
+        let (source_node, target_node) = (endpoint(source)?, endpoint(target)?);
+        if !endpoints.insert((source, target)) {
+            bail!(
+                "Edge {} repeats an edge from node {} to node {}",
+                i,
+                source,
+                target
+            );
+        }
+        graph.add_edge(source_node, target_node, edge(data));
+    }
+
+    let summary = SolvedSummary {
+        cycle_time: document.cycle_time,
+        pseudoclock_period: document.pseudoclock_period,
+    };
+    let declarations = document
+        .declarations
+        .into_iter()
+        .map(NodeDeclaration::from)
+        .collect();
+    Ok((graph, summary, declarations))
+}
+
+/// Write a structural graph as a `structural_graph` JSON document.
+pub fn structural_graph_to_json(graph: &StructuralGraph) -> String {
+    to_json(
+        graph,
+        GraphKind::StructuralGraph,
+        SolvedSummary::default(),
+        &[],
+        |node| match node {
+            structural_graph::CircuitNode::Port(name) => {
+                StructuralNodeJson::Port { name: name.clone() }
+            }
+            structural_graph::CircuitNode::Register { name, cost } => {
+                StructuralNodeJson::Register {
+                    name: name.clone(),
+                    cost: *cost,
+                }
+            }
+        },
+        |channel| ChannelJson {
+            phase: channel.initial_phase,
+            internal: channel.is_internal,
+            virtual_delay: channel.virtual_delay,
+            forward_delay: channel.forward_delay,
+            backward_delay: channel.backward_delay,
+            min_delay: channel.min_delay,
+        },
+    )
+}
+
+/// Read a structural graph from a `structural_graph` JSON document.
+pub fn structural_graph_from_json(input: &str) -> Result<StructuralGraph> {
+    let (graph, _, _) = from_json(
+        input,
+        GraphKind::StructuralGraph,
+        |node| {
+            Ok(match node {
+                StructuralNodeJson::Port { name } => structural_graph::CircuitNode::Port(name),
+                StructuralNodeJson::Register { name, cost } => {
+                    structural_graph::CircuitNode::Register { name, cost }
+                }
+            })
+        },
+        |node| node.name().clone(),
+        |channel: ChannelJson| Channel {
+            initial_phase: channel.phase,
+            is_internal: channel.internal,
+            virtual_delay: channel.virtual_delay,
+            forward_delay: channel.forward_delay,
+            backward_delay: channel.backward_delay,
+            min_delay: channel.min_delay,
+        },
+    )?;
+    Ok(graph)
+}
+
+/// Write a structural HBCN as a `structural_hbcn` JSON document.
+pub fn structural_hbcn_to_json(hbcn: &StructuralHBCN) -> String {
+    structural_hbcn_to_json_with_declarations(hbcn, &[])
+}
+
+/// Write a structural HBCN as a `structural_hbcn` JSON document carrying the node
+/// declarations, as [`serialise_hbcn_with_declarations`] does for the text format.
+///
+/// [`serialise_hbcn_with_declarations`]: crate::hbcn::serialisation::serialise_hbcn_with_declarations
+pub fn structural_hbcn_to_json_with_declarations(
+    hbcn: &StructuralHBCN,
+    declarations: &[NodeDeclaration],
+) -> String {
+    to_json(
+        hbcn,
+        GraphKind::StructuralHbcn,
+        SolvedSummary::default(),
+        declarations,
+        |transition| TransitionJson::from(transition),
+        |place| WeightedPlaceJson::from(place),
+    )
+}
+
+fn read_structural_hbcn(input: &str) -> Result<(StructuralHBCN, Vec<NodeDeclaration>)> {
+    let (hbcn, _, declarations) = from_json(
+        input,
+        GraphKind::StructuralHbcn,
+        |node: TransitionJson| Ok(Transition::from(node)),
+        |transition| transition.to_string(),
+        |place: WeightedPlaceJson| WeightedPlace::from(place),
+    )?;
+    validate_hbcn(&hbcn)?;
+    Ok((hbcn, declarations))
+}
+
+/// Read a structural HBCN from a `structural_hbcn` JSON document.
+///
+/// The HBCN is checked with [`validate_hbcn`], as when it is read from text. Node
+/// declarations are only read by [`hbcn_from_json_with_declarations`].
+pub fn structural_hbcn_from_json(input: &str) -> Result<StructuralHBCN> {
+    Ok(read_structural_hbcn(input)?.0)
+}
+
+/// Write a solved HBCN, with its arrival times, delays and slacks, as a `solved_hbcn` JSON
+/// document.
+pub fn solved_hbcn_to_json(hbcn: &SolvedHBCN, summary: SolvedSummary) -> String {
+    solved_hbcn_to_json_with_declarations(hbcn, summary, &[])
+}
+
+/// Write a solved HBCN as a `solved_hbcn` JSON document carrying the node declarations.
+pub fn solved_hbcn_to_json_with_declarations(
+    hbcn: &SolvedHBCN,
+    summary: SolvedSummary,
+    declarations: &[NodeDeclaration],
+) -> String {
+    to_json(
+        hbcn,
+        GraphKind::SolvedHbcn,
+        summary,
+        declarations,
+        |event| TransitionJson {
+            time: Some(event.time),
+            ..TransitionJson::from(&event.transition)
+        },
+        |place| DelayedPlaceJson::from(place),
+    )
+}
+
+fn read_solved_hbcn(input: &str) -> Result<(SolvedHBCN, SolvedSummary, Vec<NodeDeclaration>)> {
+    let (hbcn, summary, declarations) = from_json(
+        input,
+        GraphKind::SolvedHbcn,
+        |node: TransitionJson| {
+            let time = node.time;
+            let transition = Transition::from(node);
+            let time = time.ok_or_else(|| anyhow!("Transition {} has no time", transition))?;
+            Ok(TransitionEvent { time, transition })
+        },
+        |event| event.transition.to_string(),
+        |place: DelayedPlaceJson| DelayedPlace::from(place),
+    )?;
+    validate_hbcn(&hbcn)?;
+    Ok((hbcn, summary, declarations))
+}
+
+/// Read a solved HBCN and its timing summary from a `solved_hbcn` JSON document.
+///
+/// The HBCN is checked with [`validate_hbcn`], as when it is read from text. Node
+/// declarations are only read by [`hbcn_from_json_with_declarations`].
+pub fn solved_hbcn_from_json(input: &str) -> Result<(SolvedHBCN, SolvedSummary)> {
+    let (hbcn, summary, _) = read_solved_hbcn(input)?;
+    Ok((hbcn, summary))
+}
+
+/// Read either kind of HBCN JSON document as the HBCN `analyse` and `constrain` work on.
+///
+/// A `structural_hbcn` document reads like the output of `expand`; a `solved_hbcn` document
+/// keeps its delays and drops its arrival times.
+pub fn hbcn_from_json(input: &str) -> Result<HBCN<Transition, DelayedPlace>> {
+    Ok(hbcn_from_json_with_declarations(input)?.0)
+}
+
+/// Read either kind of HBCN JSON document, as [`hbcn_from_json`] does, together with its node
+/// declarations.
+///
+/// The declarations are checked as in an `.hbcn` file: each must name a node of its kind
+/// that appears in the graph, at most once, and a port's direction must agree with its
+/// channels.
+pub fn hbcn_from_json_with_declarations(
+    input: &str,
+) -> Result<(HBCN<Transition, DelayedPlace>, Vec<NodeDeclaration>)> {
+    let (hbcn, declarations) = match graph_kind(input)? {
+        GraphKind::StructuralHbcn => {
+            let (hbcn, declarations) = read_structural_hbcn(input)?;
+            let hbcn = hbcn.map(
+                |_, transition| transition.clone(),
+                |_, place| DelayedPlace::from(place.clone()),
+            );
+            (hbcn, declarations)
+        }
+        GraphKind::SolvedHbcn => {
+            let (hbcn, _, declarations) = read_solved_hbcn(input)?;
+            let hbcn = hbcn.map(
+                |_, event| event.transition.clone(),
+                |_, place| place.clone(),
+            );
+            (hbcn, declarations)
+        }
+        GraphKind::StructuralGraph => bail!(
+            "Expected an HBCN JSON document, found a structural_graph (read it with --structural)"
+        ),
+    };
+
+    let nodes: HashSet<&CircuitNode> = hbcn.node_indices().map(|ni| hbcn[ni].as_ref()).collect();
+    let mut declared = HashSet::new();
+    for declaration in &declarations {
+        if !declared.insert(&declaration.name) {
+            bail!("node '{}' declared more than once", declaration.name);
+        }
+        let node = declaration.circuit_node();
+        if !nodes.contains(&node) {
+            let kind = match node {
+                CircuitNode::Port(_) => "port",
+                CircuitNode::Register(_) => "register",
+            };
+            bail!(
+                "declared {} '{}' is not a node of the graph",
+                kind,
+                declaration.name
+            );
+        }
+    }
+    if let Some((_, message)) = misdirected_port(&hbcn, &declarations) {
+        bail!(message);
+    }
+    Ok((hbcn, declarations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        from_structural_graph, hbcn::declarations::node_declarations, structural_graph::parse,
+    };
+
+    const INPUT: &str = r#"
+        Port "a" [("r", 10, min = 2)]
+        DataReg "r" [("a", 20, backward = 5)]
+    "#;
+
+    #[test]
+    fn structural_graph_round_trip() {
+        let graph = parse(INPUT).unwrap();
+        let read_back = structural_graph_from_json(&structural_graph_to_json(&graph)).unwrap();
+
+        let contents = |g: &StructuralGraph| {
+            g.edge_indices()
+                .map(|ie| {
+                    let (s, t) = g.edge_endpoints(ie).unwrap();
+                    (g[s].clone(), g[t].clone(), g[ie])
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(contents(&graph), contents(&read_back));
+    }
+
+    #[test]
+    fn solved_hbcn_round_trip() {
+        let hbcn = from_structural_graph(&parse(INPUT).unwrap(), false).unwrap();
+        let solved: SolvedHBCN = hbcn.map(
+            |ni, transition| TransitionEvent {
+                time: ni.index() as f64,
+                transition: transition.clone(),
+            },
+            |ie, place| DelayedPlace {
+                slack: Some(ie.index() as f64 / 2.0),
+                ..place.clone().into()
+            },
+        );
+        let summary = SolvedSummary {
+            cycle_time: Some(12.5),
+            pseudoclock_period: None,
+        };
+
+        let json = solved_hbcn_to_json(&solved, summary);
+        assert!(json.contains("\"cycle_time\": 12.5"));
+        assert!(!json.contains("pseudoclock_period"));
+
+        let (read_back, read_summary) = solved_hbcn_from_json(&json).unwrap();
+        assert_eq!(read_summary, summary);
+        assert_eq!(read_back.node_count(), solved.node_count());
+        for ie in solved.edge_indices() {
+            assert_eq!(read_back[ie].delay, solved[ie].delay);
+            assert_eq!(read_back[ie].slack, solved[ie].slack);
+            assert_eq!(read_back[ie].place, solved[ie].place);
+        }
+        for ni in solved.node_indices() {
+            assert_eq!(read_back[ni], solved[ni]);
+        }
+
+        // Both HBCN document kinds read as an analysable HBCN
+        let from_structural = hbcn_from_json(&structural_hbcn_to_json(&hbcn)).unwrap();
+        let from_solved = hbcn_from_json(&json).unwrap();
+        assert_eq!(from_structural.edge_count(), from_solved.edge_count());
+    }
+
+    #[test]
+    fn declarations_round_trip() {
+        let graph = parse(INPUT).unwrap();
+        let hbcn = from_structural_graph(&graph, false).unwrap();
+        let mut declarations = node_declarations(&graph);
+        declarations[1].kind = NodeKind::Register(Some(RegisterClass::Control));
+
+        let json = structural_hbcn_to_json_with_declarations(&hbcn, &declarations);
+        assert!(json.contains("\"direction\": \"inout\""));
+        assert!(json.contains("\"class\": \"control\""));
+        let (_, read_back) = hbcn_from_json_with_declarations(&json).unwrap();
+        assert_eq!(read_back, declarations);
+
+        // Documents without declarations read back with none
+        let (_, read_back) =
+            hbcn_from_json_with_declarations(&structural_hbcn_to_json(&hbcn)).unwrap();
+        assert!(read_back.is_empty());
+    }
+
+    #[test]
+    fn rejects_inconsistent_declarations() {
+        let hbcn = from_structural_graph(&parse(INPUT).unwrap(), false).unwrap();
+        let declare = |name: &str, kind| NodeDeclaration {
+            name: name.into(),
+            kind,
+            cost: None,
+        };
+        for (declarations, message) in [
+            (
+                vec![declare("a", NodeKind::Register(None))],
+                "declared register 'a' is not a node of the graph",
+            ),
+            (
+                vec![declare("b", NodeKind::Port(None))],
+                "declared port 'b' is not a node of the graph",
+            ),
+            (
+                vec![
+                    declare("a", NodeKind::Port(None)),
+                    declare("a", NodeKind::Port(None)),
+                ],
+                "node 'a' declared more than once",
+            ),
+            (
+                vec![declare("a", NodeKind::Port(Some(PortDirection::Output)))],
+                "output port 'a' drives a channel to 'r'",
+            ),
+        ] {
+            let json = structural_hbcn_to_json_with_declarations(&hbcn, &declarations);
+            let err = hbcn_from_json_with_declarations(&json).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn rejects_foreign_documents() {
+        let graph = structural_graph_to_json(&parse(INPUT).unwrap());
+
+        let err = structural_hbcn_from_json(&graph).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected a structural_hbcn JSON document, found structural_graph"
+        );
+        assert!(hbcn_from_json(&graph).is_err());
+
+        let future = graph.replace("\"version\": 1", "\"version\": 2");
+        let err = structural_graph_from_json(&future).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported JSON schema version 2 (expected 1)"
+        );
+
+        let dangling = r#"{"format": "hbcn", "version": 1, "graph": "structural_graph",
+            "nodes": [{"kind": "port", "name": "a"}],
+            "edges": [{"source": 0, "target": 3, "phase": "AckNull", "internal": false,
+                       "virtual_delay": 1}]}"#;
+        let err = structural_graph_from_json(dangling).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Edge 0 refers to node 3, but there are only 1 nodes"
+        );
+
+        assert!(is_json(&graph));
+        assert!(!is_json(INPUT));
+    }
+
+    #[test]
+    fn rejects_repeated_nodes_and_edges() {
+        let repeated_node = r#"{"format": "hbcn", "version": 1, "graph": "structural_graph",
+            "nodes": [{"kind": "port", "name": "a"}, {"kind": "register", "name": "a",
+                       "cost": 10}],
+            "edges": []}"#;
+        let err = structural_graph_from_json(repeated_node).unwrap_err();
+        assert_eq!(err.to_string(), "Node 1 repeats node 0 (a)");
+
+        let repeated_edge = r#"{"format": "hbcn", "version": 1, "graph": "structural_graph",
+            "nodes": [{"kind": "port", "name": "a"}, {"kind": "port", "name": "b"}],
+            "edges": [{"source": 0, "target": 1, "phase": "AckNull", "internal": false,
+                       "virtual_delay": 1},
+                      {"source": 0, "target": 1, "phase": "AckNull", "internal": false,
+                       "virtual_delay": 2}]}"#;
+        let err = structural_graph_from_json(repeated_edge).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Edge 1 repeats an edge from node 0 to node 1"
+        );
+
+        let repeated_transition = r#"{"format": "hbcn", "version": 1,
+            "graph": "structural_hbcn",
+            "nodes": [{"kind": "port", "name": "a", "transition": "data"},
+                      {"kind": "port", "name": "a", "transition": "data"}],
+            "edges": []}"#;
+        let err = hbcn_from_json(repeated_transition).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node 1 repeats node 0 (Data at Port \"a\")"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Hand-written lexer shared by the `.graph`, component library and `.hbcn` grammars.
+//!
+//! Large inputs run to millions of lines, and LALRPOP's built-in regex lexer spends most of
+//! the parse matching every pattern at every token. This lexer recognises the tokens of all
+//! three grammars in a single forward scan; each grammar maps them to its terminals, so a
+//! token a grammar does not use is reported there as unexpected.
+//!
+//! Whitespace and comments are skipped between tokens: `#` and `//` run to the end of the
+//! line, `/* ... */` may span lines (and does not nest). Inside a quoted or braced name they
+//! are ordinary characters.
+
+use crate::diagnostic::{Span, UserError};
+use std::fmt;
+
+/// A token, borrowing its text from the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tok<'input> {
+    /// Punctuation: one of `* : + - ( ) [ ] { } , ; =`, or `=>` or `->`.
+    Punct(&'input str),
+    /// A word, `[A-Za-z_][A-Za-z0-9_]*`: a keyword or an identifier.
+    Word(&'input str),
+    /// A double-quoted string, without the quotes.
+    Str(&'input str),
+    /// A braced name, without the braces and with its `\{`/`\}` escapes still in place.
+    Name(&'input str),
+    /// A numeric literal, `[0-9]+(\.[0-9]+)?`, with its sign where the syntax allows one.
+    Num(&'input str),
+}
+
+impl fmt::Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Punct(text) | Tok::Word(text) | Tok::Num(text) => f.write_str(text),
+            Tok::Str(text) => write!(f, "\"{}\"", text),
+            Tok::Name(text) => write!(f, "{{{}}}", text),
+        }
+    }
+}
+
+pub(crate) type Spanned<'input> = Result<(usize, Tok<'input>, usize), UserError>;
+
+/// Iterator over the tokens of an input.
+pub(crate) struct Lexer<'input> {
+    input: &'input str,
+    pos: usize,
+    /// `{` opens a braced name rather than being punctuation (`.hbcn`).
+    braced_names: bool,
+    /// A `-` directly followed by a digit starts a negative number (`.hbcn`).
+    signed_numbers: bool,
+}
+
+impl<'input> Lexer<'input> {
+    /// A lexer for the `.graph` and component library syntax.
+    pub(crate) fn structural(input: &'input str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            braced_names: false,
+            signed_numbers: false,
+        }
+    }
+
+    /// A lexer for the `.hbcn` syntax, with braced names and signed numbers.
+    pub(crate) fn hbcn(input: &'input str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            braced_names: true,
+            signed_numbers: true,
+        }
+    }
+
+    /// Skip whitespace and comments; fails on an unterminated block comment.
+    fn skip(&mut self) -> Result<(), UserError> {
+        loop {
+            let rest = &self.input[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with('#') || trimmed.starts_with("//") {
+                self.pos += trimmed.find(['\n', '\r']).unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => return Err(error("unterminated block comment", self.pos, 2)),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Lex a token delimited by `open` and `close`, whose text excludes the delimiters.
+    fn delimited(
+        &mut self,
+        start: usize,
+        close: u8,
+        escapes: bool,
+        token: fn(&'input str) -> Tok<'input>,
+        unterminated: &'static str,
+    ) -> Spanned<'input> {
+        let bytes = self.input.as_bytes();
+        let mut i = start + 1;
+        loop {
+            match bytes.get(i) {
+                None => return Err(error(unterminated, start, 1)),
+                Some(b'\\') if escapes && matches!(bytes.get(i + 1), Some(b'{' | b'}')) => i += 2,
+                Some(&b) if b == close => break,
+                Some(_) => i += 1,
+            }
+        }
+        self.pos = i + 1;
+        Ok((start, token(&self.input[start + 1..i]), self.pos))
+    }
+}
+
+fn error(message: &'static str, start: usize, len: usize) -> UserError {
+    UserError {
+        message,
+        span: Span::new(start, start + len),
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned<'input>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.skip().and_then(|()| {
+            let start = self.pos;
+            let bytes = self.input.as_bytes();
+            let Some(&first) = bytes.get(start) else {
+                return Ok(None);
+            };
+            let next = bytes.get(start + 1).copied();
+            let take = |len: usize, tok: fn(&'input str) -> Tok<'input>| {
+                Ok(Some((
+                    start,
+                    tok(&self.input[start..start + len]),
+                    start + len,
+                )))
+            };
+            let run = |from: usize, pred: fn(&u8) -> bool| {
+                from + bytes[from..].iter().take_while(|b| pred(b)).count()
+            };
+
+            match first {
+                b'=' | b'-' if next == Some(b'>') => take(2, Tok::Punct),
+                b'-' if self.signed_numbers && next.is_some_and(|b| b.is_ascii_digit()) => {
+                    take(number_end(bytes, start + 1) - start, Tok::Num)
+                }
+                b'0'..=b'9' => take(number_end(bytes, start) - start, Tok::Num),
+                b'{' if self.braced_names => self
+                    .delimited(start, b'}', true, Tok::Name, "unterminated name")
+                    .map(Some),
+                b'"' => self
+                    .delimited(start, b'"', false, Tok::Str, "unterminated string")
+                    .map(Some),
+                b'*' | b':' | b'+' | b'-' | b'(' | b')' | b'[' | b']' | b'{' | b'}' | b','
+                | b';' | b'=' => take(1, Tok::Punct),
+                b'A'..=b'Z' | b'a'..=b'z' | b'_' => take(
+                    run(start + 1, |b| b.is_ascii_alphanumeric() || *b == b'_') - start,
+                    Tok::Word,
+                ),
+                _ => {
+                    let len = self.input[start..].chars().next().map_or(1, char::len_utf8);
+                    Err(error("invalid token", start, len))
+                }
+            }
+        });
+
+        match token {
+            Ok(Some(token)) => {
+                self.pos = self.pos.max(token.2);
+                Some(Ok(token))
+            }
+            Ok(None) => None,
+            Err(error) => {
+                // Nothing sensible follows an error, so stop after reporting it
+                self.pos = self.input.len();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// End of the digits, and of the optional fraction, of a number whose digits start at `i`.
+fn number_end(bytes: &[u8], i: usize) -> usize {
+    let digits = |i: usize| i + bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let end = digits(i);
+    if bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        digits(end + 1)
+    } else {
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(lexer: Lexer<'_>) -> Vec<Tok<'_>> {
+        lexer.map(|t| t.unwrap().1).collect()
+    }
+
+    #[test]
+    fn lexes_hbcn() {
+        use Tok::*;
+        assert_eq!(
+            tokens(Lexer::hbcn(
+                "port {a} input cost 0\n* +{a} => -{b\\}c} : (-1.5, 2) # note"
+            )),
+            [
+                Word("port"),
+                Name("a"),
+                Word("input"),
+                Word("cost"),
+                Num("0"),
+                Punct("*"),
+                Punct("+"),
+                Name("a"),
+                Punct("=>"),
+                Punct("-"),
+                Name("b\\}c"),
+                Punct(":"),
+                Punct("("),
+                Num("-1.5"),
+                Punct(","),
+                Num("2"),
+                Punct(")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexes_structural() {
+        use Tok::*;
+        assert_eq!(
+            tokens(Lexer::structural(
+                "module M(\"a\") { DataReg \"r/*x*/\" [(\"a\", 1.5, min=-1)] }\n\
+                 /* note */ channel self -> \"s0\" (10);"
+            )),
+            [
+                Word("module"),
+                Word("M"),
+                Punct("("),
+                Str("a"),
+                Punct(")"),
+                Punct("{"),
+                Word("DataReg"),
+                Str("r/*x*/"),
+                Punct("["),
+                Punct("("),
+                Str("a"),
+                Punct(","),
+                Num("1.5"),
+                Punct(","),
+                Word("min"),
+                Punct("="),
+                Punct("-"),
+                Num("1"),
+                Punct(")"),
+                Punct("]"),
+                Punct("}"),
+                Word("channel"),
+                Word("self"),
+                Punct("->"),
+                Str("s0"),
+                Punct("("),
+                Num("10"),
+                Punct(")"),
+                Punct(";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_lexical_errors() {
+        for (lexer, message, span) in [
+            (
+                Lexer::hbcn("+{a} => +{b} : 1 /* open"),
+                "unterminated block comment",
+                (17, 19),
+            ),
+            (Lexer::hbcn("+{a"), "unterminated name", (1, 2)),
+            (
+                Lexer::structural("Port \"a [)"),
+                "unterminated string",
+                (5, 6),
+            ),
+            (Lexer::structural("Port \"a\" @"), "invalid token", (9, 10)),
+        ] {
+            let error = lexer.filter_map(Result::err).next().expect("should fail");
+            assert_eq!(error.message, message);
+            assert_eq!((error.span.start, error.span.end), span, "{}", message);
+        }
+    }
+}
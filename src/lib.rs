@@ -11,11 +11,15 @@
 //!
 //! # Main Workflows
 //!
-//! The library supports three main operations:
+//! The library supports five main operations:
 //!
 //! 1. **Expansion** ([`expand`]): Convert structural graphs to HBCN representation
 //! 2. **Analysis** ([`analyse`]): Estimate cycle times and identify critical paths (supports depth analysis with `--depth` flag)
 //! 3. **Constraint Generation** ([`constrain`]): Generate SDC timing constraints for synthesis
+//! 4. **Back-annotation** ([`backannotate`]): Characterise an HBCN with the path delays of a
+//!    synthesised netlist's SDF file or of the synthesis tool's timing reports, or with the
+//!    delays measured in a gate-level simulation's VCD waveform
+//! 5. **Import** ([`netlist`]): Derive a structural graph from a gate-level Verilog netlist
 //!
 //! # Usage Example
 //!
@@ -44,6 +48,12 @@
 //! - **[`expand`]**: Conversion of structural graphs to HBCN representation and serialisation
 //! - **[`analyse`]**: Cycle time analysis and critical path identification
 //! - **[`constrain`]**: Timing constraint generation using LP optimisation
+//! - **[`backannotate`]**: Back-annotation of SDF or timing-report path delays onto an HBCN
+//! - **[`netlist`]**: Import of gate-level Verilog netlists as structural graphs
+//! - **[`liberty`]**: Liberty cell libraries pricing the expansion in characterised delays
+//! - **[`diagnostic`]**: Source spans and caret-annotated error reports for both input formats
+//! - **[`json`]**: Versioned JSON import/export of structural graphs and HBCNs
+//! - **[`cache`]**: Binary cache of parsed and solved HBCNs, keyed by a hash of their sources
 //!
 //! # Re-exports
 //!
@@ -53,22 +63,31 @@
 //!   [`Transition`], [`Place`], etc. These are re-exported through `pub use hbcn::*`.
 //! - [`Symbol`] type is re-exported from [`structural_graph`] for convenient use
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Parser;
 use std::{error::Error, fmt, fs, path::Path};
 
 pub mod analyse;
+pub mod backannotate;
+pub mod cache;
 pub mod constrain;
+pub mod diagnostic;
 pub mod expand;
 pub mod hbcn;
+pub mod json;
+mod lexer;
+pub mod liberty;
+pub mod netlist;
 pub mod structural_graph;
 pub mod verbose;
 
 // Re-export the main functions for easy access
 pub use analyse::{AnalyseArgs, analyse_main};
+pub use backannotate::{BackannotateArgs, backannotate_main};
 pub use constrain::{ConstrainArgs, constrain_main};
 pub use expand::{ExpandArgs, expand_main};
 pub use hbcn::*;
+pub use netlist::{ImportArgs, import_main};
 pub use structural_graph::Symbol;
 
 /// Application-level errors that can occur during HBCN processing.
@@ -103,6 +122,8 @@ impl Error for AppError {}
 /// # Returns
 ///
 /// Returns the parsed structural graph, or an error if the file cannot be read or parsed.
+/// Parse errors are reported as a [`diagnostic::Diagnostic`] naming the file, line and column.
+/// A file starting with `{` is read as a `structural_graph` JSON document (see [`json`]).
 ///
 /// # Example
 ///
@@ -117,7 +138,192 @@ impl Error for AppError {}
 /// ```
 pub fn read_file(file_name: &Path) -> Result<structural_graph::StructuralGraph> {
     let file = fs::read_to_string(file_name)?;
-    Ok(structural_graph::parse(&file)?)
+    if json::is_json(&file) {
+        return json::structural_graph_from_json(&file);
+    }
+    structural_graph::parse(&file).map_err(|e| e.to_diagnostic(&file).with_file(file_name).into())
+}
+
+/// Reads and parses a structural graph from a file, expanding register kinds from `library`.
+///
+/// The counterpart of [`read_file`] for graphs that use register kinds declared in a
+/// component library (see [`structural_graph::library`]). JSON documents hold flattened
+/// graphs, so they are read as by [`read_file`] and the library is not needed.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hbcn::{read_file_with_library, read_library_file};
+/// use std::path::Path;
+///
+/// let library = read_library_file(Path::new("cells.lib"))?;
+/// let graph = read_file_with_library(Path::new("circuit.graph"), &library)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_file_with_library(
+    file_name: &Path,
+    library: &structural_graph::ComponentLibrary,
+) -> Result<structural_graph::StructuralGraph> {
+    let file = fs::read_to_string(file_name)?;
+    if json::is_json(&file) {
+        return json::structural_graph_from_json(&file);
+    }
+    structural_graph::parse_with_library(&file, library)
+        .map_err(|e| e.to_diagnostic(&file).with_file(file_name).into())
+}
+
+/// Reads and parses a component library file on top of the built-in register kinds.
+///
+/// Parse errors are reported as a [`diagnostic::Diagnostic`] naming the file, line and column.
+pub fn read_library_file(file_name: &Path) -> Result<structural_graph::ComponentLibrary> {
+    let file = fs::read_to_string(file_name)?;
+    structural_graph::ComponentLibrary::parse(&file)
+        .map_err(|e| e.to_diagnostic(&file).with_file(file_name).into())
+}
+
+/// Read a structural graph for a subcommand, with the optional `--library` file.
+pub(crate) fn read_structural_input(
+    file_name: &Path,
+    library: Option<&Path>,
+) -> Result<structural_graph::StructuralGraph> {
+    match library {
+        Some(library) => read_file_with_library(file_name, &read_library_file(library)?),
+        None => read_file(file_name),
+    }
+}
+
+/// Reads and parses a Liberty cell library.
+///
+/// Parse errors are reported as a [`diagnostic::Diagnostic`] naming the file, line and column.
+pub fn read_liberty_file(file_name: &Path) -> Result<liberty::Liberty> {
+    let file = fs::read_to_string(file_name)?;
+    liberty::parse_liberty(&file).map_err(|e| match e.downcast::<diagnostic::Diagnostic>() {
+        Ok(diag) => diag.with_file(file_name).into(),
+        Err(e) => e,
+    })
+}
+
+/// The expansion delay model for a subcommand, from the optional `--liberty` file, the names
+/// of its cells and the `--virtual-unit` of the channel delays.
+///
+/// Liberty delays are in nanoseconds and channel delays in virtual units, so one is not
+/// given without the other.
+pub(crate) fn read_delay_model(
+    liberty: Option<&Path>,
+    cells: &liberty::LibertyCells,
+    virtual_unit: Option<f64>,
+) -> Result<DelayModel> {
+    if let Some(unit) = virtual_unit
+        && !(unit.is_finite() && unit > 0.0)
+    {
+        bail!(
+            "--virtual-unit must be a positive number of nanoseconds, not {}",
+            unit
+        );
+    }
+    match (liberty, virtual_unit) {
+        (Some(liberty), Some(virtual_unit)) => read_liberty_file(liberty)?
+            .delay_model(cells, virtual_unit)
+            .map_err(|e| e.context(format!("In {}", liberty.display()))),
+        (Some(_), None) => bail!(
+            "--liberty prices the expansion in nanoseconds: give --virtual-unit, the \
+             nanoseconds of a unit of the channel delays"
+        ),
+        (None, Some(_)) => bail!("--virtual-unit needs a --liberty library to scale to"),
+        (None, None) => Ok(DelayModel::default()),
+    }
+}
+
+/// The settings besides the `--liberty` file that [`read_delay_model`] depends on, as
+/// `(name, value)` pairs for [`cache::load_or_build`]; none without a Liberty file.
+pub(crate) fn delay_model_settings(
+    liberty: Option<&Path>,
+    cells: &liberty::LibertyCells,
+    virtual_unit: Option<f64>,
+) -> Vec<(&'static str, String)> {
+    if liberty.is_none() {
+        return Vec::new();
+    }
+    vec![
+        ("liberty-c-element", cells.c_element.clone()),
+        ("liberty-completion", cells.completion.clone()),
+        ("liberty-register", cells.register.clone()),
+        ("virtual-unit", format!("{:?}", virtual_unit)),
+    ]
+}
+
+/// Warn about the places of an expanded HBCN whose min delay exceeds the max delay modelled
+/// for them (see [`hbcn::min_delay_excesses`]), listing them with `--verbose`.
+pub(crate) fn warn_min_delay_excesses(hbcn: &StructuralHBCN) {
+    let excesses = hbcn::min_delay_excesses(hbcn);
+    if excesses.is_empty() {
+        return;
+    }
+    eprintln!(
+        "warning: {} places have a min delay above their modelled max delay",
+        excesses.len()
+    );
+    if verbose::is_verbose() {
+        for ie in excesses {
+            let (source, target) = hbcn.edge_endpoints(ie).unwrap();
+            eprintln!(
+                "  {} => {}: min {} > max {}",
+                hbcn[source],
+                hbcn[target],
+                hbcn[ie].min_delay.unwrap(),
+                hbcn[ie].weight
+            );
+        }
+    }
+}
+
+/// Reads and parses an HBCN from a file.
+///
+/// The file-based counterpart of [`hbcn::parser::parse_hbcn`]: parse and validation errors
+/// are reported as a [`diagnostic::Diagnostic`] naming the file, line and column. A file
+/// starting with `{` is read as a `structural_hbcn` or `solved_hbcn` JSON document (see
+/// [`json::hbcn_from_json`]), and one starting with a `.` directive as a signal transition
+/// graph in the `.g` format (see [`hbcn::petri::parse_stg`]).
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hbcn::read_hbcn_file;
+/// use std::path::Path;
+///
+/// let hbcn = read_hbcn_file(Path::new("circuit.hbcn"))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_hbcn_file(file_name: &Path) -> Result<HBCN<Transition, DelayedPlace>> {
+    Ok(read_hbcn_file_with_declarations(file_name)?.0)
+}
+
+/// Reads and parses an HBCN from a file, as [`read_hbcn_file`] does, together with its node
+/// declarations (see [`hbcn::parser::parse_hbcn_with_declarations`] and
+/// [`json::hbcn_from_json_with_declarations`]). A signal transition graph has none.
+pub fn read_hbcn_file_with_declarations(
+    file_name: &Path,
+) -> Result<(
+    HBCN<Transition, DelayedPlace>,
+    Vec<hbcn::declarations::NodeDeclaration>,
+)> {
+    let file = fs::read_to_string(file_name)?;
+    if json::is_json(&file) {
+        return json::hbcn_from_json_with_declarations(&file);
+    }
+    let parsed = if hbcn::petri::is_stg(&file) {
+        hbcn::petri::parse_stg(&file).map(|hbcn| (hbcn, Vec::new()))
+    } else {
+        hbcn::parser::parse_hbcn_with_declarations(&file)
+    };
+    parsed.map_err(|e| match e.downcast::<diagnostic::Diagnostic>() {
+        Ok(diag) => diag.with_file(file_name).into(),
+        Err(e) => e,
+    })
 }
 
 /// Command-line interface arguments for the HBCN tools.
@@ -126,6 +332,9 @@ pub fn read_file(file_name: &Path) -> Result<structural_graph::StructuralGraph>
 /// - `Expand`: Convert structural graphs to HBCN representation
 /// - `Analyse`: Estimate cycle time and analyse critical paths
 /// - `Constrain`: Generate timing constraints for synthesis
+/// - `Backannotate`: Characterise an HBCN with post-synthesis delays from an SDF file, a
+///   timing report or a gate-level simulation's VCD
+/// - `Import`: Derive a structural graph from a gate-level Verilog netlist
 #[derive(Debug, Parser)]
 #[clap(
     name = "HBCN Tools",
@@ -150,4 +359,11 @@ pub enum CLICommand {
     Analyse(AnalyseArgs),
     /// Constrain the cycle-time using continous proportional constraints.
     Constrain(ConstrainArgs),
+    /// Back-annotate an HBCN with the path delays of a synthesised netlist's SDF file or
+    /// timing report, or with the delays measured in a gate-level simulation's VCD, checking
+    /// them against the budgets of a constrained HBCN.
+    Backannotate(BackannotateArgs),
+    /// Derive a structural graph from a gate-level Verilog netlist, tracing the
+    /// combinational cones between its registers and ports.
+    Import(ImportArgs),
 }
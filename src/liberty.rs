@@ -0,0 +1,487 @@
+//! Reader for Liberty (`.lib`) cell libraries, and the expansion delay model they give.
+//!
+//! Only what is needed to price the logic the expansion adds around each circuit node is
+//! read: the library's `time_unit` and, for every `cell`, the delays of the `timing` groups
+//! of its pins. A cell's delay is the largest entry of its `cell_rise`/`cell_fall` (or
+//! `rise_propagation`/`fall_propagation`) tables, whatever its input slew and load, and of
+//! its `intrinsic_rise`/`intrinsic_fall` attributes: the expansion prices the max delays of
+//! places, so the worst case bounds them. Everything else (power, area, pin capacitances,
+//! timing checks) is skipped.
+//!
+//! # Delay Model
+//!
+//! [`Liberty::delay_model`] builds the [`DelayModel`] of the expansion from three cells of
+//! the library, named by [`LibertyCells`]:
+//!
+//! - the **C-element**, one level of the tree merging a node's acknowledges;
+//! - the **completion detector**, one level of the completion detection of a node's inputs;
+//! - the **register** cell, the delay of a register of the default cost.
+//!
+//! Delays are converted to nanoseconds, the unit of the SDC constraints. The channel delays of
+//! the structural graph are in virtual units of their own, so the model scales them by the
+//! nanoseconds a virtual unit stands for rather than mixing the two.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::liberty::{LibertyCells, parse_liberty};
+//!
+//! let liberty = parse_liberty(r#"
+//!     library (async) {
+//!       time_unit : "1ps" ;
+//!       cell (C2) {
+//!         pin (Q) {
+//!           direction : output ;
+//!           timing () {
+//!             related_pin : "A" ;
+//!             cell_rise (delay_template) {
+//!               index_1 ("0.01, 0.1") ; index_2 ("0.001, 0.01") ;
+//!               values ("45, 60", "50, 70") ;
+//!             }
+//!             cell_fall (delay_template) { values ("40, 55", "48, 66") ; }
+//!           }
+//!         }
+//!       }
+//!       cell (OR2) { pin (Y) { timing () { intrinsic_rise : 30 ; intrinsic_fall : 25 ; } } }
+//!       cell (C2R) { pin (Q) { timing () { intrinsic_rise : 80 ; } } }
+//!     }
+//! "#).unwrap();
+//!
+//! assert_eq!(liberty.cell_delay("C2"), Some(0.07));
+//! // Channel delays in units of 10ps
+//! let model = liberty.delay_model(&LibertyCells::default(), 0.01).unwrap();
+//! assert_eq!((model.acknowledge, model.completion, model.register), (0.07, 0.03, Some(0.08)));
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    diagnostic::{Diagnostic, Span},
+    hbcn::DelayModel,
+};
+
+/// Names of the cells of a Liberty library that price the expansion.
+///
+/// The defaults are the cells of the Pulsar cell library: the two-input C-element `C2`, the
+/// rail completion gate `OR2` and the resettable C-element `C2R` of its registers. The
+/// subcommands taking `--liberty` pick others with `--liberty-c-element`,
+/// `--liberty-completion` and `--liberty-register`.
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct LibertyCells {
+    /// Liberty cell of one level of the C-element tree merging acknowledges
+    #[clap(long = "liberty-c-element", value_name = "CELL", default_value = "C2")]
+    pub c_element: String,
+    /// Liberty cell of one level of completion detection
+    #[clap(
+        long = "liberty-completion",
+        value_name = "CELL",
+        default_value = "OR2"
+    )]
+    pub completion: String,
+    /// Liberty cell of a register of the default cost
+    #[clap(long = "liberty-register", value_name = "CELL", default_value = "C2R")]
+    pub register: String,
+}
+
+impl Default for LibertyCells {
+    fn default() -> Self {
+        Self {
+            c_element: "C2".into(),
+            completion: "OR2".into(),
+            register: "C2R".into(),
+        }
+    }
+}
+
+/// The cells of a Liberty library and their delays, in nanoseconds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Liberty {
+    cells: HashMap<String, f64>,
+}
+
+impl Liberty {
+    /// Delay of a cell, if the library has a timed cell of that name.
+    pub fn cell_delay(&self, name: &str) -> Option<f64> {
+        self.cells.get(name).copied()
+    }
+
+    /// Names of the timed cells of the library.
+    pub fn cell_names(&self) -> impl Iterator<Item = &str> {
+        self.cells.keys().map(String::as_str)
+    }
+
+    /// The expansion delay model of the cells `cells` of this library, for channel delays in
+    /// units of `virtual_unit` nanoseconds.
+    ///
+    /// Fails if the library has no timed cell of one of the names.
+    pub fn delay_model(&self, cells: &LibertyCells, virtual_unit: f64) -> Result<DelayModel> {
+        let delay = |name: &str, role: &str| {
+            self.cell_delay(name)
+                .ok_or_else(|| anyhow!("Liberty library has no timed {} cell `{}`", role, name))
+        };
+        Ok(DelayModel {
+            acknowledge: delay(&cells.c_element, "C-element")?,
+            completion: delay(&cells.completion, "completion detector")?,
+            register: Some(delay(&cells.register, "register")?),
+            virtual_unit,
+        })
+    }
+}
+
+/// Parse a Liberty library into the delays of its cells.
+///
+/// Errors are reported as a [`Diagnostic`] pointing into `input`.
+pub fn parse_liberty(input: &str) -> Result<Liberty> {
+    read_liberty(input).map_err(|(message, span)| {
+        Diagnostic::new(
+            input,
+            span,
+            format!("Failed to parse Liberty input: {}", message),
+        )
+        .into()
+    })
+}
+
+/// A Liberty group, such as `cell (C2) { ... }`.
+#[derive(Debug, Default)]
+struct Group<'a> {
+    name: &'a str,
+    args: Vec<&'a str>,
+    span: Span,
+    /// Simple (`name : value ;`) and complex (`name (values) ;`) attributes, in order.
+    attributes: Vec<(&'a str, Vec<&'a str>, Span)>,
+    groups: Vec<Group<'a>>,
+}
+
+impl<'a> Group<'a> {
+    fn attribute(&self, name: &str) -> Option<&(&'a str, Vec<&'a str>, Span)> {
+        self.attributes.iter().find(|(n, ..)| *n == name)
+    }
+}
+
+/// A token of a Liberty file: a word or quoted string, or punctuation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tok<'a> {
+    Word(&'a str),
+    Punct(char),
+}
+
+/// Split a Liberty file into tokens. Quoted strings are words without their quotes, and a
+/// backslash before a line break continues the line.
+fn tokenise(input: &str) -> Result<Vec<(Tok<'_>, Span)>, (String, Span)> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'\\' => i += 1,
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match input[i + 2..].find("*/") {
+                Some(end) => i += end + 4,
+                None => return Err(("unterminated comment".into(), Span::new(i, i + 2))),
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += input[i..].find('\n').unwrap_or(input.len() - i);
+            }
+            b'"' => {
+                let end = input[i + 1..]
+                    .find('"')
+                    .ok_or_else(|| ("unterminated string".to_string(), Span::new(i, i + 1)))?;
+                i += end + 2;
+                tokens.push((Tok::Word(&input[start + 1..i - 1]), Span::new(start, i)));
+            }
+            b if b"(){}:;,".contains(&b) => {
+                i += 1;
+                tokens.push((Tok::Punct(b as char), Span::new(start, i)));
+            }
+            _ => {
+                while i < bytes.len()
+                    && !bytes[i].is_ascii_whitespace()
+                    && !b"(){}:;,\"\\".contains(&bytes[i])
+                {
+                    i += 1;
+                }
+                tokens.push((Tok::Word(&input[start..i]), Span::new(start, i)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse the statements of a group body up to its closing `}`, or to the end of the input
+/// at the top level.
+fn read_body<'a>(
+    tokens: &[(Tok<'a>, Span)],
+    pos: &mut usize,
+    group: &mut Group<'a>,
+    end: Span,
+) -> Result<(), (String, Span)> {
+    let at_top = group.name.is_empty();
+    loop {
+        let Some(&(tok, span)) = tokens.get(*pos) else {
+            return if at_top {
+                Ok(())
+            } else {
+                Err((format!("unclosed group `{}`", group.name), group.span))
+            };
+        };
+        *pos += 1;
+        let name = match tok {
+            Tok::Punct('}') if !at_top => return Ok(()),
+            Tok::Punct(';') => continue,
+            Tok::Word(name) => name,
+            Tok::Punct(c) => return Err((format!("unexpected `{}`", c), span)),
+        };
+
+        match tokens.get(*pos).map(|(tok, _)| *tok) {
+            // Simple attribute: values up to the `;`, or the end of the line
+            Some(Tok::Punct(':')) => {
+                *pos += 1;
+                let mut values = Vec::new();
+                while let Some(&(Tok::Word(value), _)) = tokens.get(*pos) {
+                    // The `;` may be left out, then the next statement ends the value
+                    if !values.is_empty() && starts_statement(tokens, *pos) {
+                        break;
+                    }
+                    values.push(value);
+                    *pos += 1;
+                }
+                group.attributes.push((name, values, span));
+            }
+            // Group or complex attribute
+            Some(Tok::Punct('(')) => {
+                *pos += 1;
+                let mut args = Vec::new();
+                loop {
+                    match tokens.get(*pos) {
+                        Some((Tok::Word(arg), _)) => args.push(*arg),
+                        Some((Tok::Punct(','), _)) => {}
+                        Some((Tok::Punct(')'), _)) => break,
+                        Some((Tok::Punct(c), span)) => {
+                            return Err((format!("unexpected `{}`", c), *span));
+                        }
+                        None => return Err(("unclosed `(`".into(), span)),
+                    }
+                    *pos += 1;
+                }
+                *pos += 1;
+                if tokens.get(*pos).map(|(tok, _)| *tok) == Some(Tok::Punct('{')) {
+                    *pos += 1;
+                    let mut child = Group {
+                        name,
+                        args,
+                        span,
+                        ..Group::default()
+                    };
+                    read_body(tokens, pos, &mut child, end)?;
+                    group.groups.push(child);
+                } else {
+                    group.attributes.push((name, args, span));
+                }
+            }
+            _ => {
+                let span = tokens.get(*pos).map_or(end, |(_, span)| *span);
+                return Err((format!("expected `:` or `(` after `{}`", name), span));
+            }
+        }
+    }
+}
+
+/// Whether the word at `pos` starts a statement, being followed by `:` or `(`.
+fn starts_statement(tokens: &[(Tok, Span)], pos: usize) -> bool {
+    matches!(
+        tokens.get(pos + 1).map(|(tok, _)| *tok),
+        Some(Tok::Punct(':' | '('))
+    )
+}
+
+fn read_liberty(input: &str) -> Result<Liberty, (String, Span)> {
+    let tokens = tokenise(input)?;
+    let end = Span::new(input.len(), input.len());
+    let mut root = Group::default();
+    read_body(&tokens, &mut 0, &mut root, end)?;
+
+    let library = match root.groups.as_slice() {
+        [library] if library.name == "library" => library,
+        [library, extra, ..] if library.name == "library" => {
+            return Err(("extra input after library".into(), extra.span));
+        }
+        [other, ..] => return Err(("expected `library (...) { ... }`".into(), other.span)),
+        [] => return Err(("expected `library (...) { ... }`".into(), end)),
+    };
+
+    let scale = match library.attribute("time_unit") {
+        Some((_, value, span)) => time_unit(&value.concat(), *span)?,
+        // The Liberty default
+        None => 1.0,
+    };
+
+    let mut cells = HashMap::new();
+    for cell in library.groups.iter().filter(|g| g.name == "cell") {
+        let Some(name) = cell.args.first() else {
+            return Err(("cell without a name".into(), cell.span));
+        };
+        let mut delay: Option<f64> = None;
+        for timing in cell
+            .groups
+            .iter()
+            .filter(|g| matches!(g.name, "pin" | "bus" | "bundle"))
+            .flat_map(|pin| pin.groups.iter())
+            .filter(|g| g.name == "timing")
+        {
+            for value in timing_delays(timing)? {
+                delay = Some(delay.map_or(value, |d| d.max(value)));
+            }
+        }
+        if let Some(delay) = delay {
+            cells.insert(name.to_string(), delay * scale);
+        }
+    }
+    Ok(Liberty { cells })
+}
+
+/// The delays of a `timing` group: every entry of each delay table and the intrinsic delays,
+/// in the library's time unit.
+fn timing_delays(timing: &Group) -> Result<Vec<f64>, (String, Span)> {
+    let number = |text: &str, span: Span| {
+        text.trim()
+            .parse::<f64>()
+            .map_err(|_| (format!("invalid delay `{}`", text.trim()), span))
+    };
+    let mut delays = Vec::new();
+    for (name, values, span) in &timing.attributes {
+        if matches!(*name, "intrinsic_rise" | "intrinsic_fall") {
+            let value = values.first().copied().unwrap_or_default();
+            delays.push(number(value, *span)?);
+        }
+    }
+    for table in timing.groups.iter().filter(|g| {
+        matches!(
+            g.name,
+            "cell_rise" | "cell_fall" | "rise_propagation" | "fall_propagation"
+        )
+    }) {
+        let Some((_, values, span)) = table.attribute("values") else {
+            return Err((format!("`{}` has no values", table.name), table.span));
+        };
+        if values.is_empty() {
+            return Err((format!("`{}` has no values", table.name), *span));
+        }
+        for entry in values.iter().flat_map(|row| row.split(',')) {
+            delays.push(number(entry, *span)?);
+        }
+    }
+    Ok(delays)
+}
+
+/// Nanoseconds per unit of a `time_unit`, such as `1ns`, `10ps` or `1us`.
+fn time_unit(text: &str, span: Span) -> Result<f64, (String, Span)> {
+    let split = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = if number.is_empty() {
+        1.0
+    } else {
+        number
+            .parse()
+            .map_err(|_| (format!("invalid time_unit `{}`", text), span))?
+    };
+    let unit = match unit {
+        "s" => 1e9,
+        "ms" => 1e6,
+        "us" => 1e3,
+        "ns" => 1.0,
+        "ps" => 1e-3,
+        "fs" => 1e-6,
+        _ => return Err((format!("unknown time_unit `{}`", text), span)),
+    };
+    Ok(number * unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBERTY: &str = r#"
+        /* A Liberty library in the layout the characterisation tools write */
+        library (async_cells) {
+          delay_model : table_lookup ;
+          time_unit : "100ps" ;
+          lu_table_template (delay_template) {
+            variable_1 : input_net_transition ;
+            index_1 ("0.01, 0.1") ;
+          }
+          cell (C2) {
+            area : 4.5 ;
+            pin (A) { direction : input ; capacitance : 0.002 ; }
+            pin (Q) {
+              direction : output ;
+              function : "(A&B)|(A&Q)|(B&Q)"
+              timing () {
+                related_pin : "A" ;
+                cell_rise (delay_template) { values ( \
+                  "0.4, 0.6", \
+                  "0.5, 0.8") ; }
+                cell_fall (delay_template) { values ("0.5, 0.7", "0.6, 0.9") ; }
+                rise_transition (delay_template) { values ("9, 9", "9, 9") ; }
+              }
+              timing () { related_pin : "B" ; intrinsic_rise : 0.45 ; }
+            }
+          }
+          cell (TIE) { pin (Y) { direction : output ; } }
+        }
+    "#;
+
+    #[test]
+    fn reads_worst_table_entry() {
+        let liberty = parse_liberty(LIBERTY).unwrap();
+        // The slowest corner of cell_fall, 0.9, above intrinsic_rise in units of 100ps
+        assert!((liberty.cell_delay("C2").unwrap() - 0.09).abs() < 1e-12);
+        // Cells without timing have no delay
+        assert_eq!(liberty.cell_delay("TIE"), None);
+        assert_eq!(liberty.cell_names().collect::<Vec<_>>(), ["C2"]);
+
+        let error = liberty
+            .delay_model(&LibertyCells::default(), 1.0)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Liberty library has no timed completion detector cell `OR2`"
+        );
+        let cells = LibertyCells {
+            c_element: "C2".into(),
+            completion: "C2".into(),
+            register: "C2".into(),
+        };
+        let model = liberty.delay_model(&cells, 1.0).unwrap();
+        assert_eq!(model.register, liberty.cell_delay("C2"));
+    }
+
+    #[test]
+    fn reports_malformed_libraries() {
+        for (input, message) in [
+            ("library (l) { cell (a) {", "unclosed group `cell`"),
+            ("cell (a) { }", "expected `library (...) { ... }`"),
+            (
+                "library (l) { time_unit : \"1min\" ; }",
+                "unknown time_unit `1min`",
+            ),
+            (
+                "library (l) { cell (a) { pin (y) { timing () { intrinsic_rise : x ; } } } }",
+                "invalid delay `x`",
+            ),
+            (
+                "library (l) { cell (a) { pin y } }",
+                "expected `:` or `(` after `pin`",
+            ),
+        ] {
+            let error = parse_liberty(input).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", input, error);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
-use hbcn::{CLIArguments, analyse_main, constrain_main, expand_main};
+use hbcn::{
+    CLIArguments, analyse_main, backannotate_main, constrain_main, expand_main, import_main,
+};
 
 fn main() -> Result<()> {
     let args = CLIArguments::parse();
@@ -12,5 +14,7 @@ fn main() -> Result<()> {
         hbcn::CLICommand::Expand(args) => expand_main(args),
         hbcn::CLICommand::Analyse(args) => analyse_main(args),
         hbcn::CLICommand::Constrain(args) => constrain_main(args),
+        hbcn::CLICommand::Backannotate(args) => backannotate_main(args),
+        hbcn::CLICommand::Import(args) => import_main(args),
     }
 }
@@ -0,0 +1,885 @@
+//! Import of gate-level Verilog netlists as structural graphs.
+//!
+//! A netlist that was edited by hand after synthesis no longer matches the `.graph` file
+//! `syn_rtl` wrote for it. [`import_netlist`] derives the structural graph again from the
+//! netlist itself: it finds the half-buffer registers and ports by the names
+//! [`crate::constrain::sdc`] gives them, and traces the combinational cones between them
+//! into channels whose virtual delay comes from a per-cell [`CellWeights`] table.
+//!
+//! # Components
+//!
+//! - A **port** is a group of top-level ports named by its rails, `name_*` (`name_t`,
+//!   `name_f`, ...), or `name_*[i]` for bit `i` of a bus port `name[i]`. The logic of the
+//!   port's instance `inst:name` (see `port_instance`) belongs to the port, and an
+//!   `inst:` instance without top-level ports is the port of a sub-module (`inst:m/ip`
+//!   is `port:m/p`).
+//! - A **register** is the instance holding a sequential cell of the weight table, such as
+//!   `r` for the cell `r/u1`, or a top-level sequential cell itself. Instances of a
+//!   register nested in another, such as `r/s0`, are its stages and belong to it. The
+//!   register's kind is the one its module is named after (`DataReg` or a uniquified
+//!   `DataReg_3`); otherwise the kind of the library whose stages are the register's
+//!   nested instances, the cheapest if several match.
+//!
+//! # Channels
+//!
+//! A channel joins two components when a combinational cone leads from the outputs of one
+//! to the inputs of the other, or to a port's output rails. Its virtual delay is the
+//! largest sum of the weights of the cells along any path of the cone. Acknowledges run
+//! the other way along every channel and are told apart by name: paths through a net
+//! named `ack` or `*_ack` (such as `name_ack`, or the `ack` port of a register module)
+//! are not channels. Combinational loops are broken where a path would revisit a net.
+//!
+//! # Weight Table
+//!
+//! Each line of a weight table describes a cell type: its name, its weight, its output
+//! pins separated by commas, and `sequential` for the cells holding a register's state.
+//! Every other pin is an input, and `#` or `//` start a comment:
+//!
+//! ```text
+//! # cell  weight  outputs  [sequential]
+//! INV     1       Y
+//! AO22    2       Y
+//! C2      1       Q        sequential
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::netlist::{CellWeights, import_netlist, verilog::parse_verilog};
+//! use hbcn::structural_graph::ComponentLibrary;
+//!
+//! let weights = CellWeights::parse("INV 1 Y\nC2 1 Q sequential").unwrap();
+//! let source = r#"
+//!     module top (a_t, a_f, a_ack, b_t, b_f, b_ack);
+//!       input a_t, a_f, b_ack; output a_ack, b_t, b_f;
+//!       C2 \r/ct (.A(a_t), .B(ack_n), .Q(t));
+//!       C2 \r/cf (.A(a_f), .B(ack_n), .Q(f));
+//!       INV i0 (.A(t), .Y(b_t));
+//!       INV i1 (.A(f), .Y(nf));
+//!       INV i2 (.A(nf), .Y(b_f));
+//!       INV i3 (.A(b_ack), .Y(ack_n));
+//!       INV \r/cd (.A(t), .Y(a_ack));
+//!     endmodule
+//! "#;
+//!
+//! let flat = parse_verilog(source).unwrap().flatten(None).unwrap();
+//! let graph = import_netlist(&flat, source, &weights, &ComponentLibrary::default()).unwrap();
+//! // a, r and b, with the deepest cone from r to b
+//! assert_eq!(graph.node_count(), 3);
+//! let delays: Vec<f64> = graph.edge_indices().map(|ie| graph[ie].virtual_delay).collect();
+//! assert_eq!(delays, [0.0, 2.0]);
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    rc::Rc,
+};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::{
+    constrain::sdc::port_instance,
+    diagnostic::{Diagnostic, Span},
+    hbcn::serialisation::write_comment_header,
+    json,
+    structural_graph::{
+        self, Component, ComponentLibrary, StructuralGraph, Symbol,
+        serialise_structural_graph_with_library,
+    },
+};
+
+pub mod verilog;
+
+use verilog::{Direction, FlatNetlist};
+
+/// Command-line arguments for the import command.
+#[derive(Parser, Debug)]
+pub struct ImportArgs {
+    /// Gate-level Verilog netlist
+    pub input: PathBuf,
+
+    /// Cell weight table: each cell's virtual delay, outputs and whether it is sequential
+    #[clap(short, long)]
+    pub weights: PathBuf,
+
+    /// Top module, if the netlist has more than one
+    #[clap(long)]
+    pub top: Option<String>,
+
+    /// Component library declaring additional register kinds
+    #[clap(long)]
+    pub library: Option<PathBuf>,
+
+    /// Structural graph output file
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// JSON output file with the StructuralGraph
+    #[clap(long)]
+    pub json: Option<PathBuf>,
+}
+
+/// A cell type of a [`CellWeights`] table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellType {
+    /// Virtual delay of a path through the cell.
+    pub weight: f64,
+    /// Output pins; bus pins match by name without their index.
+    pub outputs: Vec<String>,
+    /// Whether the cell holds a register's state.
+    pub sequential: bool,
+}
+
+impl CellType {
+    /// Whether `pin`, possibly a bit of a bus such as `Q[1]`, is an output.
+    pub fn is_output(&self, pin: &str) -> bool {
+        let name = pin.split_once('[').map_or(pin, |(name, _)| name);
+        self.outputs.iter().any(|output| output == name)
+    }
+}
+
+/// The cell types of a netlist, by name; see the [module documentation](self) for the file
+/// format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellWeights {
+    cells: HashMap<String, CellType>,
+}
+
+impl CellWeights {
+    /// Parse a weight table.
+    ///
+    /// Errors are reported as a [`Diagnostic`] pointing into `input`.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut cells = HashMap::new();
+        let mut offset = 0;
+        for line in input.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let text = line
+                .split_once('#')
+                .map_or(line, |(text, _)| text)
+                .split("//")
+                .next()
+                .unwrap_or_default();
+            let mut fields = text.split_whitespace();
+            let Some(name) = fields.next() else { continue };
+            let span = Span::new(start, start + line.trim_end().len());
+            let error = |message: String| -> anyhow::Error {
+                Diagnostic::new(input, span, format!("Invalid cell weight: {}", message)).into()
+            };
+
+            let weight = fields
+                .next()
+                .ok_or_else(|| error(format!("`{}` has no weight", name)))?;
+            let weight: f64 = weight
+                .parse()
+                .ok()
+                .filter(|w: &f64| w.is_finite() && *w >= 0.0)
+                .ok_or_else(|| error(format!("invalid weight `{}`", weight)))?;
+            let outputs = fields
+                .next()
+                .ok_or_else(|| error(format!("`{}` has no output pins", name)))?;
+            let sequential = match fields.next() {
+                None => false,
+                Some("sequential") => true,
+                Some(other) => return Err(error(format!("unexpected `{}`", other))),
+            };
+            if let Some(extra) = fields.next() {
+                return Err(error(format!("unexpected `{}`", extra)));
+            }
+            let cell = CellType {
+                weight,
+                outputs: outputs.split(',').map(str::to_string).collect(),
+                sequential,
+            };
+            if cells.insert(name.to_string(), cell).is_some() {
+                return Err(error(format!("`{}` listed more than once", name)));
+            }
+        }
+        Ok(Self { cells })
+    }
+
+    /// Look up a cell type by name.
+    pub fn get(&self, name: &str) -> Option<&CellType> {
+        self.cells.get(name)
+    }
+
+    /// Add a cell type, replacing any of the same name.
+    pub fn insert(&mut self, name: impl Into<String>, cell: CellType) {
+        self.cells.insert(name.into(), cell);
+    }
+}
+
+/// Derive the structural graph of a flattened netlist.
+///
+/// See the [module documentation](self) for how ports, registers and channels are found.
+/// Errors are reported as a [`Diagnostic`] pointing at the offending cell in `input`, the
+/// Verilog source of `netlist`, or at its start when no single cell is to blame.
+pub fn import_netlist(
+    netlist: &FlatNetlist,
+    input: &str,
+    weights: &CellWeights,
+    library: &ComponentLibrary,
+) -> Result<StructuralGraph> {
+    import_components(netlist, weights, library).map_err(|(message, span)| {
+        Diagnostic::new(
+            input,
+            span,
+            format!("Failed to import netlist: {}", message),
+        )
+        .into()
+    })
+}
+
+/// [`import_netlist`], with errors as a message and the [`Span`] of the offending cell.
+fn import_components(
+    netlist: &FlatNetlist,
+    weights: &CellWeights,
+    library: &ComponentLibrary,
+) -> Result<StructuralGraph, (String, Span)> {
+    let types: Vec<&CellType> = netlist
+        .cells
+        .iter()
+        .map(|cell| {
+            weights.get(&cell.kind).ok_or_else(|| {
+                (
+                    format!("cell type `{}` is not in the weight table", cell.kind),
+                    cell.span,
+                )
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut components = Components::default();
+
+    // Ports from their top-level data rails; acknowledges belong to no port
+    let mut sources: Vec<Vec<usize>> = Vec::new();
+    let mut sinks: HashMap<usize, usize> = HashMap::new();
+    let mut instances: HashMap<String, usize> = HashMap::new();
+    for port in &netlist.ports {
+        let Some(name) = port_name(&port.name) else {
+            continue;
+        };
+        let c = components.add("Port", &name);
+        if c == sources.len() {
+            sources.push(Vec::new());
+            instances.insert(port_instance(&name), c);
+        }
+        match port.direction {
+            Direction::Input => sources[c].push(port.net),
+            Direction::Output => {
+                sinks.insert(port.net, c);
+            }
+            Direction::Inout => {
+                sources[c].push(port.net);
+                sinks.insert(port.net, c);
+            }
+        }
+    }
+
+    // The instances of sequential cells, outside port instances
+    let groups: HashSet<&str> = netlist
+        .cells
+        .iter()
+        .zip(&types)
+        .filter(|(cell, cell_type)| cell_type.sequential && !cell.name.starts_with("inst:"))
+        .map(|(cell, _)| {
+            cell.name
+                .rsplit_once('/')
+                .map_or(&cell.name[..], |(i, _)| i)
+        })
+        .collect();
+
+    // Every cell's owner: its port, its outermost register, or none for logic
+    let mut stages: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut owner: Vec<Option<usize>> = Vec::with_capacity(netlist.cells.len());
+    for cell in &netlist.cells {
+        let name = cell.name.as_str();
+        let ancestors = name
+            .match_indices('/')
+            .map(|(i, _)| &name[..i])
+            .chain(std::iter::once(name));
+        if name.starts_with("inst:") {
+            let port = ancestors
+                .clone()
+                .find_map(|ancestor| instances.get(ancestor).copied());
+            owner.push(Some(match port {
+                Some(port) => port,
+                None => components.add("Port", &instance_port(name)),
+            }));
+            continue;
+        }
+        let Some(group) = ancestors.clone().find(|ancestor| groups.contains(ancestor)) else {
+            owner.push(None);
+            continue;
+        };
+        let c = components.index.get(group).copied().unwrap_or_else(|| {
+            stages.insert(group, Vec::new());
+            components.add("", group)
+        });
+        if let Some(stage) = ancestors
+            .skip_while(|ancestor| *ancestor != group)
+            .skip(1)
+            .find(|ancestor| groups.contains(ancestor))
+        {
+            let stage = &stage[group.len() + 1..];
+            let list = stages.get_mut(group).unwrap();
+            if !list.contains(&stage) {
+                list.push(stage);
+            }
+        }
+        owner.push(Some(c));
+    }
+    let mut components = components.list;
+    sources.resize(components.len(), Vec::new());
+
+    for (kind, name) in components.iter_mut() {
+        if kind.is_empty() {
+            *kind = register_kind(netlist, library, name, &stages[name.as_ref()])?;
+        }
+    }
+
+    // Readers and outputs of every net, and the nets named as acknowledges
+    let mut readers: Vec<Vec<usize>> = vec![Vec::new(); netlist.net_count()];
+    let mut outputs: Vec<Vec<usize>> = vec![Vec::new(); netlist.cells.len()];
+    for (k, cell) in netlist.cells.iter().enumerate() {
+        for (pin, net) in &cell.pins {
+            if types[k].is_output(pin) {
+                outputs[k].push(*net);
+            } else {
+                readers[*net].push(k);
+            }
+        }
+    }
+    let ack: Vec<bool> = (0..netlist.net_count())
+        .map(|net| netlist.net_names(net).iter().any(|name| is_ack(name)))
+        .collect();
+
+    // A register's outputs are the nets its cells drive outside it
+    for (k, nets) in outputs.iter().enumerate() {
+        let Some(c) = owner[k] else { continue };
+        if components[c].0.as_ref() == "Port" {
+            continue;
+        }
+        for &net in nets {
+            let external = sinks.get(&net).is_some_and(|&p| p != c)
+                || readers[net].iter().any(|&r| owner[r] != Some(c));
+            if external && !sources[c].contains(&net) {
+                sources[c].push(net);
+            }
+        }
+    }
+
+    let mut tracer = Tracer {
+        types: &types,
+        owner: &owner,
+        readers: &readers,
+        outputs: &outputs,
+        sinks: &sinks,
+        ack: &ack,
+        memo: vec![None; netlist.net_count()],
+        depth: vec![None; netlist.net_count()],
+    };
+    let channels: Vec<Vec<(Symbol, f64)>> = sources
+        .iter()
+        .enumerate()
+        .map(|(c, nets)| {
+            let mut channels: BTreeMap<usize, f64> = BTreeMap::new();
+            for &net in nets {
+                for (&target, &delay) in tracer.reach(net).iter() {
+                    if target != c {
+                        let entry = channels.entry(target).or_insert(delay);
+                        *entry = entry.max(delay);
+                    }
+                }
+            }
+            channels
+                .into_iter()
+                .map(|(target, delay)| (components[target].1.clone(), delay))
+                .collect()
+        })
+        .collect();
+
+    let components = components
+        .into_iter()
+        .zip(channels)
+        .map(|((kind, name), channels)| Component {
+            kind,
+            name,
+            channels,
+        })
+        .collect();
+    structural_graph::from_components(components, library)
+        .map_err(|e| (e.to_string(), Span::default()))
+}
+
+/// The components of a netlist, as `(kind, name)`, and their indices by name.
+#[derive(Default)]
+struct Components {
+    list: Vec<(Symbol, Symbol)>,
+    index: HashMap<String, usize>,
+}
+
+impl Components {
+    /// The component named `name`, added with `kind` if new.
+    fn add(&mut self, kind: &str, name: &str) -> usize {
+        *self.index.entry(name.to_string()).or_insert_with(|| {
+            self.list.push((kind.into(), name.into()));
+            self.list.len() - 1
+        })
+    }
+}
+
+/// Longest paths from nets to the components their cones reach.
+struct Tracer<'a> {
+    types: &'a [&'a CellType],
+    owner: &'a [Option<usize>],
+    readers: &'a [Vec<usize>],
+    outputs: &'a [Vec<usize>],
+    sinks: &'a HashMap<usize, usize>,
+    ack: &'a [bool],
+    memo: Vec<Option<Rc<HashMap<usize, f64>>>>,
+    /// Depth of each net on the path being traced, or `None` off the path.
+    depth: Vec<Option<usize>>,
+}
+
+impl Tracer<'_> {
+    /// The components reached from `net`, with the largest weight of a path to each.
+    fn reach(&mut self, net: usize) -> Rc<HashMap<usize, f64>> {
+        self.trace(net, 0).0
+    }
+
+    /// [`Tracer::reach`] from `net` at `depth` on the traced path, and the smallest depth
+    /// of a net whose revisit cut the trace short.
+    ///
+    /// A trace cut at a net above `net` misses the paths through that net, so only traces
+    /// whose cuts stay at or below `net` are memoized.
+    fn trace(&mut self, net: usize, depth: usize) -> (Rc<HashMap<usize, f64>>, usize) {
+        if let Some(reach) = &self.memo[net] {
+            return (reach.clone(), usize::MAX);
+        }
+        let mut reach: HashMap<usize, f64> = HashMap::new();
+        let mut cut = usize::MAX;
+        if !self.ack[net] {
+            let (types, owner, readers, outputs) =
+                (self.types, self.owner, self.readers, self.outputs);
+            self.depth[net] = Some(depth);
+            if let Some(&port) = self.sinks.get(&net) {
+                reach.insert(port, 0.0);
+            }
+            for &k in &readers[net] {
+                let mut merge = |target: usize, delay: f64| {
+                    let entry = reach.entry(target).or_insert(delay);
+                    *entry = entry.max(delay);
+                };
+                if let Some(c) = owner[k] {
+                    merge(c, 0.0);
+                    continue;
+                }
+                for &next in &outputs[k] {
+                    if let Some(revisit) = self.depth[next] {
+                        cut = cut.min(revisit);
+                        continue;
+                    }
+                    let (next_reach, next_cut) = self.trace(next, depth + 1);
+                    cut = cut.min(next_cut);
+                    for (&target, &delay) in next_reach.iter() {
+                        merge(target, delay + types[k].weight);
+                    }
+                }
+            }
+            self.depth[net] = None;
+        }
+        let reach = Rc::new(reach);
+        if cut >= depth {
+            self.memo[net] = Some(reach.clone());
+        }
+        (reach, cut)
+    }
+}
+
+/// The HBCN port a top-level port bit is a data rail of: `a` for `a_t`, `a[3]` for
+/// `a_t[3]`. Acknowledges (`a_ack`) are no data rail.
+fn port_name(bit: &str) -> Option<String> {
+    let (base, index) = match bit.split_once('[') {
+        Some((base, index)) => (base, Some(index)),
+        None => (bit, None),
+    };
+    let (name, rail) = base.rsplit_once('_').unwrap_or((base, ""));
+    if rail == "ack" {
+        return None;
+    }
+    Some(match index {
+        Some(index) => format!("{}[{}", name, index),
+        None => name.to_string(),
+    })
+}
+
+/// The port a port instance holds the logic of, where no top-level port names it:
+/// `port:m/p` for `inst:m/ip/...`, otherwise `name` for `inst:name/...`.
+fn instance_port(cell: &str) -> String {
+    let path = &cell["inst:".len()..];
+    let mut parts = path.split('/');
+    let first = parts.next().unwrap_or_default();
+    match (parts.next(), parts.next()) {
+        (Some(port), Some(_)) if port.len() > 1 && port.starts_with('i') => {
+            format!("port:{}/{}", first, &port[1..])
+        }
+        _ => first.to_string(),
+    }
+}
+
+/// Whether a net name, such as `a_ack` or `r/ack[0]`, names an acknowledge: its local name
+/// is `ack` or ends in an `_ack` component, unlike `feedback` or `stack`.
+fn is_ack(name: &str) -> bool {
+    let local = name.rsplit('/').next().unwrap_or(name);
+    let local = local
+        .split_once('[')
+        .map_or(local, |(local, _)| local)
+        .to_ascii_lowercase();
+    local == "ack" || local.ends_with("_ack")
+}
+
+/// The register kind of `register`: the kind its module is named after, or else the
+/// cheapest kind whose stages are `stages`.
+fn register_kind(
+    netlist: &FlatNetlist,
+    library: &ComponentLibrary,
+    register: &str,
+    stages: &[&str],
+) -> Result<Symbol, (String, Span)> {
+    if let Some(module) = netlist.instances.get(register) {
+        let named = library
+            .kind_names()
+            .filter(|kind| {
+                module == kind.as_ref()
+                    || module
+                        .strip_prefix(kind.as_ref())
+                        .is_some_and(|rest| rest.starts_with('_'))
+            })
+            .max_by_key(|kind| kind.len());
+        if let Some(kind) = named {
+            return Ok(kind.clone());
+        }
+    }
+
+    let mut wanted: Vec<&str> = stages.to_vec();
+    wanted.sort_unstable();
+    library
+        .kind_names()
+        .filter_map(|name| {
+            let kind = library.get(name)?;
+            let mut suffixes: Vec<&str> = kind.stages.iter().map(|s| s.suffix.as_ref()).collect();
+            suffixes.sort_unstable();
+            (suffixes == wanted).then_some((kind.cost, name))
+        })
+        .min()
+        .map(|(_, name)| name.clone())
+        .ok_or_else(|| {
+            (
+                format!(
+                    "no register kind has the stages [{}] of register `{}`",
+                    stages.join(", "),
+                    register
+                ),
+                Span::default(),
+            )
+        })
+}
+
+/// Import a Verilog netlist and write its structural graph.
+///
+/// The output is behind a `#` comment header naming the generator version and the input
+/// files, and registers fold into the kinds of the `--library` file, if any.
+pub fn import_main(args: ImportArgs) -> Result<()> {
+    use crate::verbose::is_verbose;
+
+    let ImportArgs {
+        input,
+        weights,
+        top,
+        library,
+        output,
+        json,
+    } = args;
+
+    let with_file = |path: &PathBuf| {
+        let path = path.clone();
+        move |e: anyhow::Error| match e.downcast::<Diagnostic>() {
+            Ok(diag) => diag.with_file(&path).into(),
+            Err(e) => e,
+        }
+    };
+
+    if is_verbose() {
+        eprintln!("Reading weight table: {:?}", weights);
+    }
+    let weights_text =
+        fs::read_to_string(&weights).with_context(|| format!("Failed to read {:?}", weights))?;
+    let cell_weights = CellWeights::parse(&weights_text).map_err(with_file(&weights))?;
+    let component_library = match &library {
+        Some(library) => crate::read_library_file(library)?,
+        None => ComponentLibrary::default(),
+    };
+
+    if is_verbose() {
+        eprintln!("Reading netlist: {:?}", input);
+    }
+    let text = fs::read_to_string(&input)?;
+    let netlist = verilog::parse_verilog(&text).map_err(with_file(&input))?;
+    let flat = netlist
+        .flatten_diagnostic(&text, top.as_deref())
+        .map_err(with_file(&input))?;
+    if is_verbose() {
+        eprintln!(
+            "Flattened {} cells and {} nets",
+            flat.cells.len(),
+            flat.net_count()
+        );
+    }
+    let graph = import_netlist(&flat, &text, &cell_weights, &component_library)
+        .map_err(with_file(&input))?;
+    if is_verbose() {
+        eprintln!(
+            "Imported {} nodes and {} channels",
+            graph.node_count(),
+            graph.edge_count()
+        );
+    }
+
+    let header = [
+        format!("Generated by hbcn {}", env!("CARGO_PKG_VERSION")),
+        format!("Source: {}", input.display()),
+        format!("Weights: {}", weights.display()),
+    ];
+    let mut serialised = String::new();
+    // Infallible for String
+    let _ = write_comment_header(&header, &mut serialised);
+    serialised.push_str(&serialise_structural_graph_with_library(
+        &graph,
+        &component_library,
+    )?);
+    fs::write(&output, serialised)?;
+
+    if let Some(json) = json {
+        fs::write(json, json::structural_graph_to_json(&graph))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structural_graph::CircuitNode;
+
+    const WEIGHTS: &str = "
+        # Combinational cells
+        INV  1 Y
+        AO22 2 Y   // two levels
+        # Register cells
+        C2   1 Q sequential
+        DFF  0 Q,QN sequential
+    ";
+
+    /// Two dual-rail registers, `r` with stages `r/s0` and `r/s1`, between the ports `a`
+    /// and `b`; the acknowledges run back through `*_ack` nets.
+    const NETLIST: &str = r#"
+        module top (a_t, a_f, a_ack, b_t, b_f, b_ack);
+          input a_t, a_f, b_ack;
+          output a_ack, b_t, b_f;
+          wire r_ack, q_ack;
+
+          C2 \r/c (.A(a_t), .B(r_ack), .Q(x0));
+          C2 \r/s0/c (.A(x0), .B(r_ack), .Q(x1));
+          C2 \r/s1/c (.A(x1), .B(r_ack), .Q(x2));
+          C2 \r/cf (.A(a_f), .B(r_ack), .Q(y2));
+          INV \r/cd (.A(q_ack), .Y(r_ack));
+          assign a_ack = r_ack;
+
+          AO22 g0 (.A(x2), .B(y2), .Y(n0));
+          INV g1 (.A(n0), .Y(n1));
+          DataReg_1 q (.d0(n1), .d1(y2), .ack_in(b_ack), .ack(q_ack), .q0(b_t), .q1(b_f));
+        endmodule
+
+        module DataReg_1 (d0, d1, ack_in, ack, q0, q1);
+          input d0, d1, ack_in; output ack, q0, q1;
+          DFF l0 (.D(d0), .Q(q0), .QN());
+          DFF l1 (.D(d1), .Q(q1), .QN());
+          INV ci (.A(ack_in), .Y(ack));
+        endmodule
+    "#;
+
+    fn import(netlist: &str) -> StructuralGraph {
+        let weights = CellWeights::parse(WEIGHTS).unwrap();
+        let flat = verilog::parse_verilog(netlist)
+            .unwrap()
+            .flatten(None)
+            .unwrap();
+        import_netlist(&flat, netlist, &weights, &ComponentLibrary::default()).unwrap()
+    }
+
+    fn channels(graph: &StructuralGraph) -> Vec<(String, String, f64)> {
+        let mut channels: Vec<_> = graph
+            .edge_indices()
+            .map(|ie| {
+                let (s, t) = graph.edge_endpoints(ie).unwrap();
+                (
+                    graph[s].name().to_string(),
+                    graph[t].name().to_string(),
+                    graph[ie].virtual_delay,
+                )
+            })
+            .collect();
+        channels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        channels
+    }
+
+    #[test]
+    fn traces_cones_between_registers_and_ports() {
+        let graph = import(NETLIST);
+        let registers: Vec<(String, usize)> = graph
+            .node_indices()
+            .filter_map(|ni| match &graph[ni] {
+                CircuitNode::Register { name, cost } => Some((name.to_string(), *cost)),
+                CircuitNode::Port(_) => None,
+            })
+            .collect();
+        // `r` folds its stages into a DataReg, and `q` is named after one
+        assert_eq!(
+            registers,
+            [
+                ("r".to_string(), 10),
+                ("r/s0".to_string(), 10),
+                ("r/s1".to_string(), 10),
+                ("q".to_string(), 10),
+                ("q/s0".to_string(), 10),
+                ("q/s1".to_string(), 10),
+            ]
+        );
+
+        // The deepest path from r to q is AO22 then INV; acknowledges are no channels
+        let external: Vec<_> = channels(&graph)
+            .into_iter()
+            .filter(|(s, t, _)| s.split('/').next() != t.split('/').next())
+            .collect();
+        assert_eq!(
+            external,
+            [
+                ("a".to_string(), "r".to_string(), 0.0),
+                ("q/s1".to_string(), "b".to_string(), 0.0),
+                ("r/s1".to_string(), "q".to_string(), 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn assigns_port_instances_and_cheapest_kind() {
+        let graph = import(
+            r#"
+            module top (a_t, b_t, b_ack);
+              input a_t, b_ack; output b_t;
+              C2 \r/u (.A(a_t), .B(b_ack), .Q(x));
+              INV \inst:b/u (.A(x), .Y(b_t));
+              INV \inst:m/ip/u (.A(x), .Y(y));
+            endmodule
+        "#,
+        );
+        assert_eq!(
+            channels(&graph),
+            [
+                ("a".to_string(), "r".to_string(), 0.0),
+                ("r".to_string(), "b".to_string(), 0.0),
+                ("r".to_string(), "port:m/p".to_string(), 0.0),
+            ]
+        );
+        let r = graph
+            .node_indices()
+            .find(|&ni| graph[ni].name().as_ref() == "r");
+        assert!(matches!(
+            graph[r.unwrap()],
+            CircuitNode::Register { cost: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn traces_shared_loops_from_every_entry() {
+        // `a` enters the loop n3 -> n4 -> n2 -> n3 at g1 and `b` at g2; the trace from `a`
+        // is cut at n3, which must not hide the path from `b` through g1 to `c`
+        let graph = import(
+            r#"
+            module top (a_t, b_t, c_t);
+              input a_t, b_t; output c_t;
+              INV i0 (.A(a_t), .Y(n1));
+              AO22 g1 (.A(n1), .B(n2), .Y(n3));
+              INV i1 (.A(n3), .Y(c_t));
+              INV i2 (.A(n3), .Y(n4));
+              AO22 g2 (.A(n4), .B(b_t), .Y(n2));
+            endmodule
+        "#,
+        );
+        assert_eq!(
+            channels(&graph),
+            [
+                ("a".to_string(), "c".to_string(), 4.0),
+                ("b".to_string(), "c".to_string(), 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_acknowledges_by_whole_name() {
+        for name in ["ack", "a_ack", "r/ack[0]", "r/s0/Q_ACK"] {
+            assert!(is_ack(name), "{}", name);
+        }
+        for name in ["feedback", "stack", "r/nack", "ack_n", "back[1]"] {
+            assert!(!is_ack(name), "{}", name);
+        }
+
+        // A cone through `feedback` and `stack` is still a channel
+        let graph = import(
+            r#"
+            module top (a_t, b_t);
+              input a_t; output b_t;
+              INV i0 (.A(a_t), .Y(feedback));
+              INV i1 (.A(feedback), .Y(stack));
+              INV i2 (.A(stack), .Y(b_t));
+            endmodule
+        "#,
+        );
+        assert_eq!(channels(&graph), [("a".to_string(), "b".to_string(), 3.0)]);
+    }
+
+    #[test]
+    fn reports_unknown_cells_and_bad_weights() {
+        let weights = CellWeights::parse(WEIGHTS).unwrap();
+        let input = "module top (a_t); input a_t; NAND2 u (.A(a_t)); endmodule";
+        let flat = verilog::parse_verilog(input)
+            .unwrap()
+            .flatten(None)
+            .unwrap();
+        let error = import_netlist(&flat, input, &weights, &ComponentLibrary::default())
+            .unwrap_err()
+            .downcast::<Diagnostic>()
+            .unwrap();
+        assert_eq!(
+            error.message,
+            "Failed to import netlist: cell type `NAND2` is not in the weight table"
+        );
+        assert_eq!(&input[error.span.start..error.span.end], "u");
+
+        for (table, message) in [
+            ("INV", "`INV` has no weight"),
+            ("INV x Y", "invalid weight `x`"),
+            ("INV 1", "has no output pins"),
+            ("INV 1 Y combinational", "unexpected `combinational`"),
+            ("INV 1 Y\nINV 2 Y", "listed more than once"),
+        ] {
+            let error = CellWeights::parse(table).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", table, error);
+        }
+    }
+}
@@ -0,0 +1,1098 @@
+//! Reader for structural (gate-level) Verilog netlists.
+//!
+//! Only the structural subset a synthesis tool writes is read: `module` definitions with
+//! ANSI or non-ANSI port lists, `input`/`output`/`inout` and net declarations with constant
+//! ranges, continuous `assign`s between nets, and instances of cells or of other modules
+//! with named or positional connections. Connections may be nets, bit- and part-selects,
+//! concatenations, replications and constants. Parameters, attributes (`(* ... *)`) and
+//! compiler directives are skipped; behavioural constructs are rejected.
+//!
+//! [`Netlist::flatten`] elaborates the hierarchy below a top module into the leaf cells of
+//! the design and the bit-level nets joining their pins. Cells and nets are named by their
+//! hierarchical path with `/` as the divider, as in SDF, and escaped identifiers keep their
+//! text without the leading backslash, so `\inst:a/u1 ` is the cell `inst:a/u1`.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::diagnostic::{Diagnostic, Span};
+
+/// Direction of a module port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+    Inout,
+}
+
+/// A parsed Verilog file: its modules, in order.
+#[derive(Debug, Default)]
+pub struct Netlist {
+    modules: Vec<Module>,
+    index: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct Module {
+    name: String,
+    span: Span,
+    /// Port names, in port-list order.
+    ports: Vec<String>,
+    decls: HashMap<String, Decl>,
+    instances: Vec<Instance>,
+    assigns: Vec<(Expr, Expr)>,
+}
+
+#[derive(Debug, Default)]
+struct Decl {
+    direction: Option<Direction>,
+    range: Option<(i64, i64)>,
+}
+
+#[derive(Debug)]
+struct Instance {
+    module: String,
+    name: String,
+    span: Span,
+    connections: Connections,
+}
+
+#[derive(Debug)]
+enum Connections {
+    Named(Vec<(String, Option<Expr>, Span)>),
+    Positional(Vec<Option<Expr>>),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Net(String),
+    Select(String, i64, i64),
+    Concat(Vec<Expr>),
+    /// A constant, by its width in bits.
+    Constant(usize),
+}
+
+/// A leaf cell of a flattened netlist.
+#[derive(Debug, Clone)]
+pub struct FlatCell {
+    /// Full hierarchical name of the instance, e.g. `r/u1`.
+    pub name: String,
+    /// Cell type.
+    pub kind: String,
+    /// Connected pins and their nets; bus pins are named by bit, e.g. `A[1]`.
+    pub pins: Vec<(String, usize)>,
+    /// Span of the instance in the Verilog source.
+    pub span: Span,
+}
+
+/// A top-level port bit of a flattened netlist.
+#[derive(Debug, Clone)]
+pub struct FlatPort {
+    /// Name of the bit, e.g. `a_t` or `a_t[3]`.
+    pub name: String,
+    /// Direction of the port.
+    pub direction: Direction,
+    /// The net the port connects to.
+    pub net: usize,
+}
+
+/// The leaf cells of a design and the bit-level nets between them.
+#[derive(Debug, Default)]
+pub struct FlatNetlist {
+    /// Top-level port bits, in port-list order.
+    pub ports: Vec<FlatPort>,
+    /// Leaf cells, in elaboration order.
+    pub cells: Vec<FlatCell>,
+    /// Module of each hierarchical instance, by full instance name.
+    pub instances: HashMap<String, String>,
+    nets: Vec<Vec<String>>,
+}
+
+impl FlatNetlist {
+    /// Number of nets.
+    pub fn net_count(&self) -> usize {
+        self.nets.len()
+    }
+
+    /// Every full name of a net: one per module and `assign` it passes through.
+    pub fn net_names(&self, net: usize) -> &[String] {
+        &self.nets[net]
+    }
+}
+
+/// Parse a Verilog netlist.
+///
+/// Errors are reported as a [`Diagnostic`] pointing into `input`.
+///
+/// # Example
+///
+/// ```
+/// use hbcn::netlist::verilog::parse_verilog;
+///
+/// let netlist = parse_verilog(r#"
+///     module top (a, y);
+///       input a; output y;
+///       wire n;
+///       INV u1 (.A(a), .Y(n));
+///       INV u2 (.A(n), .Y(y));
+///     endmodule
+/// "#).unwrap();
+///
+/// let flat = netlist.flatten(None).unwrap();
+/// assert_eq!(flat.cells.len(), 2);
+/// assert_eq!(flat.cells[0].pins[1].1, flat.cells[1].pins[0].1);
+/// ```
+pub fn parse_verilog(input: &str) -> Result<Netlist> {
+    read_verilog(input).map_err(|(message, span)| {
+        Diagnostic::new(
+            input,
+            span,
+            format!("Failed to parse Verilog input: {}", message),
+        )
+        .into()
+    })
+}
+
+impl Netlist {
+    /// Names of the modules, in order.
+    pub fn module_names(&self) -> impl Iterator<Item = &str> {
+        self.modules.iter().map(|m| m.name.as_str())
+    }
+
+    /// Flatten the hierarchy below `top`, or below the only module no other module
+    /// instantiates.
+    ///
+    /// Instances of modules the netlist does not define are leaf cells. Errors carry the
+    /// [`Span`] of the offending instance; see [`Netlist::flatten_diagnostic`].
+    pub fn flatten(&self, top: Option<&str>) -> Result<FlatNetlist, (String, Span)> {
+        let top = match top {
+            Some(name) => self
+                .module(name)
+                .ok_or_else(|| (format!("no module named `{}`", name), Span::default()))?,
+            None => {
+                let mut roots = self.modules.iter().filter(|m| {
+                    !self
+                        .modules
+                        .iter()
+                        .flat_map(|parent| parent.instances.iter())
+                        .any(|instance| instance.module == m.name)
+                });
+                match (roots.next(), roots.next()) {
+                    (Some(top), None) => top,
+                    (None, _) => return Err(("no top module".into(), Span::default())),
+                    (Some(_), Some(other)) => {
+                        return Err(("more than one top module, select one".into(), other.span));
+                    }
+                }
+            }
+        };
+
+        let mut builder = Builder::default();
+        let mut ports = Vec::new();
+        for port in &top.ports {
+            let decl = &top.decls[port];
+            let Some(direction) = decl.direction else {
+                return Err((format!("port `{}` has no direction", port), top.span));
+            };
+            for bit in bit_names(port, decl.range) {
+                ports.push((bit.clone(), direction, builder.net(bit)));
+            }
+        }
+        self.elaborate(top, "", &mut vec![top.name.as_str()], &mut builder)?;
+
+        let (nets, root) = builder.finish();
+        Ok(FlatNetlist {
+            ports: ports
+                .into_iter()
+                .map(|(name, direction, net)| FlatPort {
+                    name,
+                    direction,
+                    net: root[net],
+                })
+                .collect(),
+            cells: builder
+                .cells
+                .into_iter()
+                .map(|mut cell| {
+                    for (_, net) in cell.pins.iter_mut() {
+                        *net = root[*net];
+                    }
+                    cell
+                })
+                .collect(),
+            instances: builder.instances,
+            nets,
+        })
+    }
+
+    /// [`Netlist::flatten`], reporting errors as a [`Diagnostic`] pointing into `input`,
+    /// the source of this netlist.
+    pub fn flatten_diagnostic(&self, input: &str, top: Option<&str>) -> Result<FlatNetlist> {
+        self.flatten(top).map_err(|(message, span)| {
+            Diagnostic::new(
+                input,
+                span,
+                format!("Failed to flatten netlist: {}", message),
+            )
+            .into()
+        })
+    }
+
+    fn module(&self, name: &str) -> Option<&Module> {
+        self.index.get(name).map(|&i| &self.modules[i])
+    }
+
+    /// Add the cells and nets of `module`, instantiated as `prefix`, to `builder`.
+    fn elaborate<'a>(
+        &'a self,
+        module: &'a Module,
+        prefix: &str,
+        stack: &mut Vec<&'a str>,
+        builder: &mut Builder,
+    ) -> Result<(), (String, Span)> {
+        let join = |name: &str| {
+            if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", prefix, name)
+            }
+        };
+
+        for (lhs, rhs) in &module.assigns {
+            let lhs = bits(module, lhs);
+            let rhs = bits(module, rhs);
+            for (l, r) in lhs.iter().rev().zip(rhs.iter().rev()) {
+                if let (Some(l), Some(r)) = (l, r) {
+                    let (l, r) = (builder.net(join(l)), builder.net(join(r)));
+                    builder.union(l, r);
+                }
+            }
+        }
+
+        for instance in &module.instances {
+            let name = join(&instance.name);
+            let Some(child) = self.module(&instance.module) else {
+                // A leaf cell: bus pins are named by bit, LSB first
+                let Connections::Named(connections) = &instance.connections else {
+                    return Err((
+                        format!(
+                            "cell `{}` of undefined module `{}` needs named connections",
+                            instance.name, instance.module
+                        ),
+                        instance.span,
+                    ));
+                };
+                let mut pins = Vec::new();
+                for (pin, expr, _) in connections {
+                    let Some(expr) = expr else { continue };
+                    let expr_bits = bits(module, expr);
+                    let width = expr_bits.len();
+                    for (i, bit) in expr_bits.into_iter().enumerate() {
+                        let Some(bit) = bit else { continue };
+                        let pin = if width == 1 {
+                            pin.clone()
+                        } else {
+                            format!("{}[{}]", pin, width - 1 - i)
+                        };
+                        pins.push((pin, builder.net(join(&bit))));
+                    }
+                }
+                builder.cells.push(FlatCell {
+                    name,
+                    kind: instance.module.clone(),
+                    pins,
+                    span: instance.span,
+                });
+                continue;
+            };
+
+            if stack.contains(&child.name.as_str()) {
+                return Err((
+                    format!("module `{}` instantiates itself", child.name),
+                    instance.span,
+                ));
+            }
+            let connections: Vec<(&str, &Expr, Span)> = match &instance.connections {
+                Connections::Named(connections) => connections
+                    .iter()
+                    .filter_map(|(port, expr, span)| Some((port.as_str(), expr.as_ref()?, *span)))
+                    .collect(),
+                Connections::Positional(exprs) => {
+                    if exprs.len() > child.ports.len() {
+                        return Err((
+                            format!(
+                                "module `{}` has {} ports, but {} are connected",
+                                child.name,
+                                child.ports.len(),
+                                exprs.len()
+                            ),
+                            instance.span,
+                        ));
+                    }
+                    child
+                        .ports
+                        .iter()
+                        .zip(exprs)
+                        .filter_map(|(port, expr)| {
+                            Some((port.as_str(), expr.as_ref()?, instance.span))
+                        })
+                        .collect()
+                }
+            };
+            for (port, expr, span) in connections {
+                if !child.ports.iter().any(|p| p == port) {
+                    return Err((
+                        format!("module `{}` has no port `{}`", child.name, port),
+                        span,
+                    ));
+                }
+                let ports = bit_names(port, child.decls[port].range);
+                let expr = bits(module, expr);
+                // Connections align at their least significant bit
+                for (p, e) in ports.iter().rev().zip(expr.iter().rev()) {
+                    if let Some(e) = e {
+                        let (p, e) = (builder.net(format!("{}/{}", name, p)), builder.net(join(e)));
+                        builder.union(p, e);
+                    }
+                }
+            }
+
+            builder.instances.insert(name.clone(), child.name.clone());
+            stack.push(&child.name);
+            self.elaborate(child, &name, stack, builder)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Names of the bits of a net, most significant first.
+fn bit_names(name: &str, range: Option<(i64, i64)>) -> Vec<String> {
+    match range {
+        None => vec![name.to_string()],
+        Some((msb, lsb)) => select(msb, lsb)
+            .map(|i| format!("{}[{}]", name, i))
+            .collect(),
+    }
+}
+
+/// Indices from `msb` to `lsb`, inclusive, in either direction.
+fn select(msb: i64, lsb: i64) -> Box<dyn Iterator<Item = i64>> {
+    if msb >= lsb {
+        Box::new((lsb..=msb).rev())
+    } else {
+        Box::new(msb..=lsb)
+    }
+}
+
+/// Local names of the bits of an expression, most significant first; `None` for
+/// constant bits.
+fn bits(module: &Module, expr: &Expr) -> Vec<Option<String>> {
+    match expr {
+        Expr::Net(name) => {
+            let range = module.decls.get(name).and_then(|decl| decl.range);
+            bit_names(name, range).into_iter().map(Some).collect()
+        }
+        Expr::Select(name, msb, lsb) => select(*msb, *lsb)
+            .map(|i| Some(format!("{}[{}]", name, i)))
+            .collect(),
+        Expr::Concat(items) => items.iter().flat_map(|item| bits(module, item)).collect(),
+        Expr::Constant(width) => vec![None; *width],
+    }
+}
+
+/// Nets under construction: a union-find over every name a net is known by.
+#[derive(Default)]
+struct Builder {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    parent: Vec<usize>,
+    cells: Vec<FlatCell>,
+    instances: HashMap<String, String>,
+}
+
+impl Builder {
+    fn net(&mut self, name: String) -> usize {
+        if let Some(&net) = self.index.get(&name) {
+            return net;
+        }
+        let net = self.names.len();
+        self.index.insert(name.clone(), net);
+        self.names.push(name);
+        self.parent.push(net);
+        net
+    }
+
+    fn find(&mut self, mut net: usize) -> usize {
+        while self.parent[net] != net {
+            self.parent[net] = self.parent[self.parent[net]];
+            net = self.parent[net];
+        }
+        net
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    /// The names of each joined net, and the joined net of every name.
+    fn finish(&mut self) -> (Vec<Vec<String>>, Vec<usize>) {
+        let mut nets: Vec<Vec<String>> = Vec::new();
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut root = Vec::with_capacity(self.names.len());
+        for net in 0..self.names.len() {
+            let r = self.find(net);
+            let id = *ids.entry(r).or_insert_with(|| {
+                nets.push(Vec::new());
+                nets.len() - 1
+            });
+            nets[id].push(std::mem::take(&mut self.names[net]));
+            root.push(id);
+        }
+        (nets, root)
+    }
+}
+
+/// A token of a Verilog file.
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    Punct(char),
+}
+
+impl Tok {
+    fn describe(&self) -> String {
+        match self {
+            Tok::Ident(name) => format!("`{}`", name),
+            Tok::Number(text) => format!("`{}`", text),
+            Tok::Punct(c) => format!("`{}`", c),
+        }
+    }
+}
+
+/// Split a Verilog file into tokens, skipping comments, attributes and compiler
+/// directives.
+fn tokenise(input: &str) -> Result<Vec<(Tok, Span)>, (String, Span)> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b if b.is_ascii_whitespace() => i += 1,
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += input[i..].find('\n').unwrap_or(input.len() - i);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match input[i + 2..].find("*/") {
+                Some(end) => i += end + 4,
+                None => return Err(("unterminated comment".into(), Span::new(i, i + 2))),
+            },
+            b'(' if bytes.get(i + 1) == Some(&b'*') && bytes.get(i + 2) != Some(&b')') => {
+                match input[i + 2..].find("*)") {
+                    Some(end) => i += end + 4,
+                    None => return Err(("unterminated attribute".into(), Span::new(i, i + 2))),
+                }
+            }
+            b'`' => i += input[i..].find('\n').unwrap_or(input.len() - i),
+            b'\\' => {
+                i += 1;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                if i == start + 1 {
+                    return Err(("empty escaped identifier".into(), Span::new(start, i)));
+                }
+                tokens.push((
+                    Tok::Ident(input[start + 1..i].to_string()),
+                    Span::new(start, i),
+                ));
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || b"_$".contains(&bytes[i]))
+                {
+                    i += 1;
+                }
+                tokens.push((Tok::Ident(input[start..i].to_string()), Span::new(start, i)));
+            }
+            b if b.is_ascii_digit() || b == b'\'' => {
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                if bytes.get(i) == Some(&b'\'') {
+                    i += 1;
+                    if matches!(bytes.get(i), Some(b's' | b'S')) {
+                        i += 1;
+                    }
+                    if !matches!(
+                        bytes.get(i),
+                        Some(b'b' | b'B' | b'o' | b'O' | b'd' | b'D' | b'h' | b'H')
+                    ) {
+                        return Err(("invalid number base".into(), Span::new(start, i + 1)));
+                    }
+                    i += 1;
+                    while i < bytes.len()
+                        && (bytes[i].is_ascii_alphanumeric() || b"_?".contains(&bytes[i]))
+                    {
+                        i += 1;
+                    }
+                }
+                tokens.push((
+                    Tok::Number(input[start..i].to_string()),
+                    Span::new(start, i),
+                ));
+            }
+            b if b"()[]{},;:.=#".contains(&b) => {
+                i += 1;
+                tokens.push((Tok::Punct(b as char), Span::new(start, i)));
+            }
+            _ => {
+                let c = input[i..].chars().next().unwrap();
+                return Err((
+                    format!("unexpected character `{}`", c),
+                    Span::new(i, i + c.len_utf8()),
+                ));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the tokens of a Verilog file.
+struct Parser {
+    tokens: Vec<(Tok, Span)>,
+    pos: usize,
+    end: Span,
+}
+
+type ParseResult<T> = Result<T, (String, Span)>;
+
+const NET_TYPES: &[&str] = &["wire", "tri", "reg", "supply0", "supply1", "wand", "wor"];
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, span)| *span)
+    }
+
+    fn next(&mut self) -> ParseResult<(Tok, Span)> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ("unexpected end of input".to_string(), self.end))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> ParseResult<T> {
+        match self.tokens.get(self.pos) {
+            Some((tok, span)) => Err((
+                format!("unexpected token {}, expected {}", tok.describe(), expected),
+                *span,
+            )),
+            None => Err((
+                format!("unexpected end of input, expected {}", expected),
+                self.end,
+            )),
+        }
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Tok::Punct(c))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(name)) if name == keyword)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.is_punct(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> ParseResult<Span> {
+        if self.is_punct(c) {
+            self.pos += 1;
+            Ok(self.tokens[self.pos - 1].1)
+        } else {
+            self.unexpected(&format!("`{}`", c))
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<(String, Span)> {
+        match self.peek() {
+            Some(Tok::Ident(_)) => match self.next()? {
+                (Tok::Ident(name), span) => Ok((name, span)),
+                _ => unreachable!(),
+            },
+            _ => self.unexpected("an identifier"),
+        }
+    }
+
+    fn integer(&mut self) -> ParseResult<i64> {
+        match self.next()? {
+            (Tok::Number(text), span) => text
+                .replace('_', "")
+                .parse()
+                .map_err(|_| (format!("expected an integer, found `{}`", text), span)),
+            (tok, span) => Err((
+                format!("unexpected token {}, expected an integer", tok.describe()),
+                span,
+            )),
+        }
+    }
+
+    /// Skip a balanced parenthesised group, such as a parameter list.
+    fn skip_group(&mut self) -> ParseResult<()> {
+        self.expect('(')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()?.0 {
+                Tok::Punct('(') => depth += 1,
+                Tok::Punct(')') => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_statement(&mut self) -> ParseResult<()> {
+        while self.next()?.0 != Tok::Punct(';') {}
+        Ok(())
+    }
+
+    /// An optional `[msb:lsb]` range.
+    fn range(&mut self) -> ParseResult<Option<(i64, i64)>> {
+        if !self.eat('[') {
+            return Ok(None);
+        }
+        let msb = self.integer()?;
+        self.expect(':')?;
+        let lsb = self.integer()?;
+        self.expect(']')?;
+        Ok(Some((msb, lsb)))
+    }
+
+    fn module(&mut self) -> ParseResult<Module> {
+        let (_, start) = self.ident()?;
+        let (name, _) = self.ident()?;
+        let mut module = Module {
+            name,
+            span: start,
+            ports: Vec::new(),
+            decls: HashMap::new(),
+            instances: Vec::new(),
+            assigns: Vec::new(),
+        };
+        if self.eat('#') {
+            self.skip_group()?;
+        }
+        if self.eat('(') && !self.eat(')') {
+            // ANSI declarations carry on with the previous direction and range
+            let mut ansi: Option<(Direction, Option<(i64, i64)>)> = None;
+            loop {
+                if let Some(direction) = self.direction() {
+                    self.pos += 1;
+                    while NET_TYPES.iter().any(|t| self.is_keyword(t)) || self.is_keyword("signed")
+                    {
+                        self.pos += 1;
+                    }
+                    ansi = Some((direction, self.range()?));
+                }
+                let (port, span) = self.ident()?;
+                if let Some((direction, range)) = ansi {
+                    module.decls.insert(
+                        port.clone(),
+                        Decl {
+                            direction: Some(direction),
+                            range,
+                        },
+                    );
+                }
+                if module.ports.contains(&port) {
+                    return Err((format!("port `{}` listed more than once", port), span));
+                }
+                module.ports.push(port);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(')')?;
+        }
+        self.expect(';')?;
+
+        loop {
+            match self.peek() {
+                Some(Tok::Ident(keyword)) => match keyword.as_str() {
+                    "endmodule" => {
+                        self.pos += 1;
+                        break;
+                    }
+                    "input" | "output" | "inout" => self.declaration(&mut module)?,
+                    "assign" => {
+                        self.pos += 1;
+                        self.assignments(&mut module)?;
+                    }
+                    "parameter" | "localparam" | "defparam" | "timeunit" | "timeprecision" => {
+                        self.skip_statement()?
+                    }
+                    "always" | "initial" | "function" | "task" | "generate" | "specify"
+                    | "module" => {
+                        return Err((
+                            format!("`{}` is not supported in a structural netlist", keyword),
+                            self.span(),
+                        ));
+                    }
+                    k if NET_TYPES.contains(&k) => self.declaration(&mut module)?,
+                    _ => self.instances(&mut module)?,
+                },
+                _ => return self.unexpected("a declaration, instance or `endmodule`"),
+            }
+        }
+
+        for port in &module.ports {
+            module.decls.entry(port.clone()).or_default();
+        }
+        Ok(module)
+    }
+
+    fn direction(&self) -> Option<Direction> {
+        match self.peek() {
+            Some(Tok::Ident(k)) if k == "input" => Some(Direction::Input),
+            Some(Tok::Ident(k)) if k == "output" => Some(Direction::Output),
+            Some(Tok::Ident(k)) if k == "inout" => Some(Direction::Inout),
+            _ => None,
+        }
+    }
+
+    /// A port or net declaration; a net declaration may assign its nets.
+    fn declaration(&mut self, module: &mut Module) -> ParseResult<()> {
+        let direction = self.direction();
+        self.pos += 1;
+        while NET_TYPES.iter().any(|t| self.is_keyword(t)) || self.is_keyword("signed") {
+            self.pos += 1;
+        }
+        let range = self.range()?;
+        loop {
+            let (name, span) = self.ident()?;
+            if direction.is_some() && !module.ports.contains(&name) {
+                return Err((format!("`{}` is not in the port list", name), span));
+            }
+            let decl = module.decls.entry(name.clone()).or_default();
+            decl.direction = decl.direction.or(direction);
+            decl.range = decl.range.or(range);
+            if self.eat('=') {
+                let value = self.expr()?;
+                module.assigns.push((Expr::Net(name), value));
+            }
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(';')?;
+        Ok(())
+    }
+
+    fn assignments(&mut self, module: &mut Module) -> ParseResult<()> {
+        loop {
+            let lhs = self.expr()?;
+            self.expect('=')?;
+            let rhs = self.expr()?;
+            module.assigns.push((lhs, rhs));
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(';')?;
+        Ok(())
+    }
+
+    /// Instances of one cell or module, such as `INV u1 (.A(a), .Y(y)), u2 (...);`.
+    fn instances(&mut self, module: &mut Module) -> ParseResult<()> {
+        let (cell, _) = self.ident()?;
+        if self.eat('#') {
+            self.skip_group()?;
+        }
+        loop {
+            let (name, span) = self.ident()?;
+            if self.is_punct('[') {
+                return Err(("instance arrays are not supported".into(), self.span()));
+            }
+            self.expect('(')?;
+            let connections = if self.is_punct('.') {
+                let mut named = Vec::new();
+                loop {
+                    self.expect('.')?;
+                    let (pin, span) = self.ident()?;
+                    self.expect('(')?;
+                    let expr = if self.is_punct(')') {
+                        None
+                    } else {
+                        Some(self.expr()?)
+                    };
+                    self.expect(')')?;
+                    named.push((pin, expr, span));
+                    if !self.eat(',') {
+                        break;
+                    }
+                }
+                Connections::Named(named)
+            } else {
+                let mut positional = Vec::new();
+                if !self.is_punct(')') {
+                    loop {
+                        if self.is_punct(',') || self.is_punct(')') {
+                            positional.push(None);
+                        } else {
+                            positional.push(Some(self.expr()?));
+                        }
+                        if !self.eat(',') {
+                            break;
+                        }
+                    }
+                }
+                Connections::Positional(positional)
+            };
+            self.expect(')')?;
+            module.instances.push(Instance {
+                module: cell.clone(),
+                name,
+                span,
+                connections,
+            });
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(';')?;
+        Ok(())
+    }
+
+    fn expr(&mut self) -> ParseResult<Expr> {
+        match self.peek() {
+            Some(Tok::Ident(_)) => {
+                let (name, _) = self.ident()?;
+                if !self.eat('[') {
+                    return Ok(Expr::Net(name));
+                }
+                let msb = self.integer()?;
+                let lsb = if self.eat(':') { self.integer()? } else { msb };
+                self.expect(']')?;
+                Ok(Expr::Select(name, msb, lsb))
+            }
+            Some(Tok::Number(_)) => {
+                let (tok, span) = self.next()?;
+                let Tok::Number(text) = tok else {
+                    unreachable!()
+                };
+                // A replication `{n{...}}` starts with a number too
+                if self.is_punct('{') {
+                    let count = text
+                        .parse::<usize>()
+                        .map_err(|_| (format!("invalid replication count `{}`", text), span))?;
+                    self.expect('{')?;
+                    let item = self.concat()?;
+                    return Ok(Expr::Concat(vec![item; count]));
+                }
+                Ok(Expr::Constant(constant_width(&text)))
+            }
+            Some(Tok::Punct('{')) => {
+                self.pos += 1;
+                if matches!(self.peek(), Some(Tok::Number(_))) {
+                    let replication = self.expr()?;
+                    if self.eat('}') {
+                        return Ok(replication);
+                    }
+                    let mut items = vec![replication];
+                    self.expect(',')?;
+                    items.extend(self.concat_items()?);
+                    return Ok(Expr::Concat(items));
+                }
+                self.concat()
+            }
+            _ => self.unexpected("a net, constant or concatenation"),
+        }
+    }
+
+    /// The items of a concatenation after its `{`, up to and including its `}`.
+    fn concat(&mut self) -> ParseResult<Expr> {
+        Ok(Expr::Concat(self.concat_items()?))
+    }
+
+    fn concat_items(&mut self) -> ParseResult<Vec<Expr>> {
+        let mut items = Vec::new();
+        loop {
+            items.push(self.expr()?);
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect('}')?;
+        Ok(items)
+    }
+}
+
+/// Width of a constant: its size, or one bit for an unsized constant.
+fn constant_width(text: &str) -> usize {
+    match text.split_once('\'') {
+        Some((size, _)) if !size.is_empty() => size.replace('_', "").parse().unwrap_or(1),
+        _ => 1,
+    }
+}
+
+fn read_verilog(input: &str) -> Result<Netlist, (String, Span)> {
+    let tokens = tokenise(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: Span::new(input.len(), input.len()),
+    };
+    let mut netlist = Netlist::default();
+    while parser.peek().is_some() {
+        if !parser.is_keyword("module") && !parser.is_keyword("macromodule") {
+            return parser.unexpected("`module`");
+        }
+        let span = parser.span();
+        let module = parser.module()?;
+        if netlist
+            .index
+            .insert(module.name.clone(), netlist.modules.len())
+            .is_some()
+        {
+            return Err((
+                format!("module `{}` defined more than once", module.name),
+                span,
+            ));
+        }
+        netlist.modules.push(module);
+    }
+    Ok(netlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETLIST: &str = r#"
+        `timescale 1ns/1ps
+        // A register module instantiated twice, and a port instance
+        module reg2 (input [1:0] d, output [1:0] q);
+          (* keep *) LATCH l0 (.D(d[0]), .Q(q[0]));
+          LATCH l1 (.D(d[1]), .Q(q[1]));
+        endmodule
+
+        module top (a, b);
+          input [1:0] a;
+          output [1:0] b;
+          wire [1:0] n, m;
+          reg2 r0 (.d(a), .q(n));
+          reg2 r1 (n, m);
+          BUF \inst:b/u0 (.A(m[0]), .Y(b[0]));
+          assign b[1] = m[1];
+          TIE t (.Y(), .A(1'b0));
+        endmodule
+    "#;
+
+    #[test]
+    fn flattens_hierarchy_into_bit_level_nets() {
+        let netlist = parse_verilog(NETLIST).unwrap();
+        assert_eq!(netlist.module_names().collect::<Vec<_>>(), ["reg2", "top"]);
+        let flat = netlist.flatten(None).unwrap();
+
+        let names: Vec<&str> = flat.cells.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["r0/l0", "r0/l1", "r1/l0", "r1/l1", "inst:b/u0", "t"]
+        );
+        assert_eq!(flat.instances["r1"], "reg2");
+
+        let ports: Vec<&str> = flat.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(ports, ["a[1]", "a[0]", "b[1]", "b[0]"]);
+        assert_eq!(flat.ports[2].direction, Direction::Output);
+
+        let pin = |cell: usize, pin: &str| {
+            flat.cells[cell]
+                .pins
+                .iter()
+                .find(|(name, _)| name == pin)
+                .unwrap()
+                .1
+        };
+        // a[0] reaches r0/l0 through the port d[0]
+        assert_eq!(flat.ports[1].net, pin(0, "D"));
+        assert!(flat.net_names(pin(0, "D")).contains(&"r0/d[0]".to_string()));
+        // Positional connections and the assign to b[1]
+        assert_eq!(pin(1, "Q"), pin(3, "D"));
+        assert_eq!(pin(3, "Q"), flat.ports[2].net);
+        assert_eq!(pin(4, "Y"), flat.ports[3].net);
+        // The tie cell has no connected pins
+        assert!(flat.cells[5].pins.is_empty());
+    }
+
+    #[test]
+    fn names_bus_pins_of_leaf_cells_by_bit() {
+        let netlist = parse_verilog(
+            "module top (input [2:0] a, output y); AND3 u (.A({a[0], a[2:1]}), .Y(y)); endmodule",
+        )
+        .unwrap();
+        let flat = netlist.flatten(Some("top")).unwrap();
+        let pins: Vec<&str> = flat.cells[0].pins.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(pins, ["A[2]", "A[1]", "A[0]", "Y"]);
+        assert_eq!(flat.cells[0].pins[0].1, flat.ports[2].net);
+    }
+
+    #[test]
+    fn reports_malformed_netlists() {
+        for (input, message) in [
+            ("module top (a); input a;", "unexpected end of input"),
+            (
+                "module top; always begin end endmodule",
+                "`always` is not supported",
+            ),
+            (
+                "module top (a); input a, b; endmodule",
+                "`b` is not in the port list",
+            ),
+            (
+                "module top; INV u1 (a, y); endmodule",
+                "needs named connections",
+            ),
+            (
+                "module top; m u (); endmodule module m; top t (); endmodule",
+                "no top module",
+            ),
+            (
+                "module a; endmodule module b; endmodule",
+                "more than one top module",
+            ),
+            (
+                "module top; INV u1 (.A(a + b)); endmodule",
+                "unexpected character `+`",
+            ),
+            (
+                "module top; m u (.x(a)); endmodule module m; endmodule",
+                "has no port `x`",
+            ),
+        ] {
+            let error = parse_verilog(input)
+                .and_then(|netlist| netlist.flatten_diagnostic(input, None))
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(message), "{}: {}", input, error);
+        }
+    }
+}
@@ -8,58 +8,340 @@
 //!
 //! The AST consists of:
 //!
-//! - **[`EntryType`]**: The type of circuit component (Port, DataReg, etc.)
-//! - **[`Entry`]**: A complete circuit component definition with its name and connections
+//! - **[`Entry`]**: A complete circuit component definition with its kind, name and connections
+//! - **[`Adjacency`]**: One outgoing channel of an entry
+//! - **[`ChannelAttribute`]**: An optional `key = value` setting on an adjacency tuple
+//! - **[`Item`]**: A top-level item of a graph: an entry, a module definition or an instance
+//! - **[`ModuleDef`]** / **[`Instance`]** / **[`Binding`]**: Module definitions and instantiation
+//! - **[`Channels`]**: Extra channels driven by a component declared elsewhere (a module port)
+//! - **[`KindDef`]** / **[`KindItem`]**: A register kind declared in a component library
+//!
+//! Every entry and adjacency tuple keeps the byte [`Span`] it was parsed from, so errors
+//! detected after parsing (undefined targets, duplicate names) can still point at the source.
 //!
 //! This AST is an implementation detail of the parser and is not typically used
 //! by external code.
 
-use super::Symbol;
+use super::{ChannelPhase, Symbol};
+use crate::diagnostic::Span;
+
+/// The setting carried by a [`ChannelAttribute`].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AttributeKind {
+    /// `phase = <ChannelPhase>`: initial handshake phase.
+    Phase(ChannelPhase),
+    /// `internal` or `internal = <bool>`: whether the channel is internal.
+    Internal(bool),
+    /// `forward = <num>`: fixed weight of the two forward places.
+    Forward(f64),
+    /// `backward = <num>`: fixed weight of the two backward places.
+    Backward(f64),
+    /// `min = <num>`: minimum delay of the channel's places.
+    Min(f64),
+    /// `max = <num>`: maximum delay, and so fixed weight, of all four places.
+    Max(f64),
+}
+
+impl AttributeKind {
+    /// The attribute key as written in the source.
+    pub fn key(&self) -> &'static str {
+        match self {
+            AttributeKind::Phase(_) => "phase",
+            AttributeKind::Internal(_) => "internal",
+            AttributeKind::Forward(_) => "forward",
+            AttributeKind::Backward(_) => "backward",
+            AttributeKind::Min(_) => "min",
+            AttributeKind::Max(_) => "max",
+        }
+    }
+}
 
-/// Type of circuit component entry in the structural graph AST.
-#[derive(PartialEq, Eq, Debug)]
-pub enum EntryType {
-    /// External port component.
-    Port,
-    /// Data register with internal structure.
-    DataReg,
-    /// Null register (simple register).
-    NullReg,
-    /// Control register (higher cost).
-    ControlReg,
-    /// Unsafe register with internal structure.
-    UnsafeReg,
+/// AST node representing one `key = value` attribute of an adjacency tuple.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ChannelAttribute {
+    /// The attribute and its value.
+    pub kind: AttributeKind,
+    /// Source location of the attribute.
+    pub span: Span,
+}
+
+impl ChannelAttribute {
+    /// Create a new channel attribute.
+    pub fn new(kind: AttributeKind, span: Span) -> ChannelAttribute {
+        ChannelAttribute { kind, span }
+    }
+}
+
+/// AST node representing one `("target", delay, attributes...)` tuple of an adjacency list.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Adjacency {
+    /// Name of the component this channel drives.
+    pub target: Symbol,
+    /// Virtual delay of the channel.
+    pub virtual_delay: f64,
+    /// Optional attributes overriding the channel defaults.
+    pub attributes: Vec<ChannelAttribute>,
+    /// Source location of the whole tuple.
+    pub span: Span,
+}
+
+impl Adjacency {
+    /// Create a new adjacency tuple.
+    pub fn new(
+        target: Symbol,
+        virtual_delay: f64,
+        attributes: Vec<ChannelAttribute>,
+        span: Span,
+    ) -> Adjacency {
+        Adjacency {
+            target,
+            virtual_delay,
+            attributes,
+            span,
+        }
+    }
 }
 
 /// AST node representing a circuit component definition.
 ///
 /// This captures the parsed structure of a single line in the structural graph format:
-/// `<Type> <Name> [<adjacency_list>]`
+/// `<Kind> <Name> [<adjacency_list>]`
 ///
 /// # Fields
 ///
-/// - `entry_type`: The component type
+/// - `kind`: The component kind (`Port` or a register kind of the component library)
 /// - `name`: Component identifier (symbol/interned string)
-/// - `adjacency_list`: List of connections as `(target_name, delay)` tuples
-#[derive(PartialEq, Debug)]
+/// - `adjacency_list`: List of outgoing channels
+/// - `kind_span`/`name_span`/`span`: Source locations of the kind, the name and the whole entry
+#[derive(PartialEq, Debug, Clone)]
 pub struct Entry {
-    /// The type of circuit component.
-    pub entry_type: EntryType,
+    /// The kind of circuit component.
+    pub kind: Symbol,
     /// The name/identifier of the component.
     pub name: Symbol,
-    /// List of connections: `(target_component_name, virtual_delay)`.
-    pub adjacency_list: Vec<(Symbol, f64)>,
+    /// List of outgoing channels.
+    pub adjacency_list: Vec<Adjacency>,
+    /// Source location of the kind.
+    pub kind_span: Span,
+    /// Source location of the quoted name.
+    pub name_span: Span,
+    /// Source location of the whole entry.
+    pub span: Span,
 }
 
 impl Entry {
     /// Create a new AST entry.
     ///
     /// This is used internally by the parser to construct AST nodes.
-    pub fn new(entry_type: EntryType, name: Symbol, adjacency_list: Vec<(Symbol, f64)>) -> Entry {
+    pub fn new(
+        kind: Symbol,
+        name: Symbol,
+        adjacency_list: Vec<Adjacency>,
+        kind_span: Span,
+        name_span: Span,
+        span: Span,
+    ) -> Entry {
         Entry {
-            entry_type,
+            kind,
             name,
             adjacency_list,
+            kind_span,
+            name_span,
+            span,
+        }
+    }
+}
+
+/// AST node representing extra channels driven by a component declared elsewhere:
+/// `"<name>" [<adjacency_list>]`
+///
+/// Inside a module body the name is usually one of the module's ports, giving the channels
+/// driven by whichever component the port is bound to.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Channels {
+    /// Name of the component driving the channels.
+    pub source: Symbol,
+    /// List of outgoing channels.
+    pub adjacency_list: Vec<Adjacency>,
+    /// Source location of the quoted name.
+    pub source_span: Span,
+    /// Source location of the whole item.
+    pub span: Span,
+}
+
+impl Channels {
+    /// Create a new channel list.
+    pub fn new(
+        source: Symbol,
+        adjacency_list: Vec<Adjacency>,
+        source_span: Span,
+        span: Span,
+    ) -> Channels {
+        Channels {
+            source,
+            adjacency_list,
+            source_span,
+            span,
+        }
+    }
+}
+
+/// AST node binding one port of a module [`Instance`] to a component.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Binding {
+    /// `"<component>"`: binds the port at the same position in the module header.
+    Positional { actual: Symbol, span: Span },
+    /// `"<port>" = "<component>"`: binds the named port.
+    Named {
+        port: Symbol,
+        actual: Symbol,
+        span: Span,
+    },
+}
+
+/// AST node representing a module instance: `<Module> "<name>" (<bindings>)`
+#[derive(PartialEq, Debug, Clone)]
+pub struct Instance {
+    /// Name of the instantiated module.
+    pub module: Symbol,
+    /// Instance name, prefixed with `/` to every component of the module.
+    pub name: Symbol,
+    /// Port bindings, either all positional or all named.
+    pub bindings: Vec<Binding>,
+    /// Source location of the module name.
+    pub module_span: Span,
+    /// Source location of the quoted instance name.
+    pub name_span: Span,
+    /// Source location of the whole instance.
+    pub span: Span,
+}
+
+impl Instance {
+    /// Create a new module instance.
+    pub fn new(
+        module: Symbol,
+        name: Symbol,
+        bindings: Vec<Binding>,
+        module_span: Span,
+        name_span: Span,
+        span: Span,
+    ) -> Instance {
+        Instance {
+            module,
+            name,
+            bindings,
+            module_span,
+            name_span,
+            span,
+        }
+    }
+}
+
+/// AST node representing a module definition: `module <Name> (<ports>) { <items> }`
+#[derive(PartialEq, Debug)]
+pub struct ModuleDef {
+    /// The module name, used in place of a component type to instantiate it.
+    pub name: Symbol,
+    /// Port names and their source locations, in declaration order.
+    pub ports: Vec<(Symbol, Span)>,
+    /// Entries, channel lists and nested instances of the module.
+    pub body: Vec<Item>,
+    /// Source location of the module name.
+    pub name_span: Span,
+    /// Source location of the whole definition.
+    pub span: Span,
+}
+
+impl ModuleDef {
+    /// Create a new module definition.
+    pub fn new(
+        name: Symbol,
+        ports: Vec<(Symbol, Span)>,
+        body: Vec<Item>,
+        name_span: Span,
+        span: Span,
+    ) -> ModuleDef {
+        ModuleDef {
+            name,
+            ports,
+            body,
+            name_span,
+            span,
+        }
+    }
+}
+
+/// AST node representing one item of a structural graph or of a module body.
+///
+/// Module definitions are only accepted at the top level.
+#[derive(PartialEq, Debug)]
+pub enum Item {
+    /// A component definition.
+    Entry(Entry),
+    /// Extra channels driven by a component declared elsewhere.
+    Channels(Channels),
+    /// A module instance.
+    Instance(Instance),
+    /// A module definition.
+    Module(ModuleDef),
+}
+
+/// AST node representing one item in the body of a register kind declaration.
+#[derive(PartialEq, Debug)]
+pub enum KindItem {
+    /// `stage "<name>" [cost <n>];`: an internal sub-stage node.
+    Stage {
+        name: Symbol,
+        cost: Option<usize>,
+        span: Span,
+    },
+    /// `channel <from> -> <to> (<delay>, attributes...);`: an internal channel.
+    Channel {
+        from: Option<Symbol>,
+        to: Option<Symbol>,
+        delay: f64,
+        attributes: Vec<ChannelAttribute>,
+        span: Span,
+    },
+    /// `output <stage> [(attributes...)];`: the node driving the register's adjacency list.
+    Output {
+        stage: Option<Symbol>,
+        attributes: Vec<ChannelAttribute>,
+        span: Span,
+    },
+}
+
+/// AST node representing a register kind declared in a component library:
+/// `register <Name> [cost <n>] { <items> }`
+#[derive(PartialEq, Debug)]
+pub struct KindDef {
+    /// The kind name, as used in place of `DataReg` etc. in a structural graph.
+    pub name: Symbol,
+    /// Base cost of the register's nodes, if given.
+    pub cost: Option<usize>,
+    /// Stages, internal channels and output of the kind.
+    pub items: Vec<KindItem>,
+    /// Source location of the kind name.
+    pub name_span: Span,
+    /// Source location of the whole declaration.
+    pub span: Span,
+}
+
+impl KindDef {
+    /// Create a new register kind declaration.
+    pub fn new(
+        name: Symbol,
+        cost: Option<usize>,
+        items: Vec<KindItem>,
+        name_span: Span,
+        span: Span,
+    ) -> KindDef {
+        KindDef {
+            name,
+            cost,
+            items,
+            name_span,
+            span,
         }
     }
 }
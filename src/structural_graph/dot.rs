@@ -0,0 +1,180 @@
+//! Graphviz rendering of structural graphs.
+//!
+//! [`structural_graph_to_dot`] writes a structural graph in the DOT language. Registers are
+//! matched to the kinds of a [`ComponentLibrary`] as by the `.graph` serialiser, and each
+//! register drawn with the kind it folds into: a kind with stages is a cluster labelled
+//! `DataReg "r"` around its stage nodes, a stage-less one a single box. Ports are ellipses.
+//! Channels are labelled with their virtual delay and any fixed `forward`, `backward` or `min`
+//! delay, and internal channels are dashed.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::structural_graph::{ComponentLibrary, parse, structural_graph_to_dot};
+//!
+//! let graph = parse(r#"
+//!     Port "a" [("r", 10)]
+//!     DataReg "r" [("b", 20, min = 5)]
+//!     Port "b" []
+//! "#).unwrap();
+//!
+//! let dot = structural_graph_to_dot(&graph, &ComponentLibrary::default());
+//! assert!(dot.starts_with("digraph structural_graph {"));
+//! assert!(dot.contains("label=\"DataReg \\\"r\\\"\";"));
+//! assert!(dot.contains("label=\"20\\nmin 5\""));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use petgraph::stable_graph::NodeIndex;
+
+use super::{CircuitNode, ComponentLibrary, StructuralGraph, serialisation::component_kinds};
+
+/// Render a structural graph in the DOT language, folding registers into the kinds of
+/// `library`.
+///
+/// A register that matches no kind is drawn as a plain register box.
+pub fn structural_graph_to_dot(graph: &StructuralGraph, library: &ComponentLibrary) -> String {
+    let mut out = String::new();
+    // Infallible for String
+    let _ = write_structural_dot(graph, library, &mut out);
+    out
+}
+
+/// Escape a string for a double-quoted DOT identifier.
+pub(crate) fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_structural_dot<W: Write>(
+    graph: &StructuralGraph,
+    library: &ComponentLibrary,
+    w: &mut W,
+) -> fmt::Result {
+    let kinds = component_kinds(graph, library).unwrap_or_default();
+
+    writeln!(w, "digraph structural_graph {{")?;
+    writeln!(w, "  rankdir=LR;")?;
+    writeln!(w, "  node [fontname=\"Helvetica\"];")?;
+    writeln!(w, "  edge [fontname=\"Helvetica\"];")?;
+
+    let node_label = |ix: NodeIndex| match &graph[ix] {
+        CircuitNode::Port(name) => format!("{}", name),
+        CircuitNode::Register { name, cost } => format!("{}\\ncost {}", name, cost),
+    };
+    let node_line = |w: &mut W, ix: NodeIndex, indent: &str, label: String| {
+        let shape = match graph[ix] {
+            CircuitNode::Port(_) => "ellipse",
+            CircuitNode::Register { .. } => "box",
+        };
+        writeln!(
+            w,
+            "{}n{} [label=\"{}\", shape={}];",
+            indent,
+            ix.index(),
+            dot_escape(&label).replace("\\\\n", "\\n"),
+            shape
+        )
+    };
+
+    // Stage nodes are drawn inside the cluster of their register
+    let stage_of: HashMap<NodeIndex, NodeIndex> = kinds
+        .iter()
+        .flat_map(|(&main, (_, stages))| stages.iter().map(move |&stage| (stage, main)))
+        .collect();
+
+    for ix in graph.node_indices() {
+        if stage_of.contains_key(&ix) {
+            continue;
+        }
+        match kinds.get(&ix) {
+            Some((kind, stages)) if !stages.is_empty() => {
+                writeln!(w, "  subgraph \"cluster_{}\" {{", ix.index())?;
+                writeln!(
+                    w,
+                    "    label=\"{}\";",
+                    dot_escape(&format!("{} \"{}\"", kind, graph[ix].name()))
+                )?;
+                writeln!(w, "    style=rounded;")?;
+                for &node in std::iter::once(&ix).chain(stages) {
+                    node_line(w, node, "    ", node_label(node))?;
+                }
+                writeln!(w, "  }}")?;
+            }
+            Some((kind, _)) => {
+                let label = match &graph[ix] {
+                    CircuitNode::Port(name) => format!("{} \"{}\"", kind, name),
+                    CircuitNode::Register { name, cost } => {
+                        format!("{} \"{}\"\\ncost {}", kind, name, cost)
+                    }
+                };
+                node_line(w, ix, "  ", label)?;
+            }
+            None => node_line(w, ix, "  ", node_label(ix))?,
+        }
+    }
+
+    for ie in graph.edge_indices() {
+        let (src, dst) = graph
+            .edge_endpoints(ie)
+            .expect("Edge should have valid endpoints");
+        let channel = &graph[ie];
+        let mut label = format!("{}", channel.virtual_delay);
+        for (name, delay) in [
+            ("forward", channel.forward_delay),
+            ("backward", channel.backward_delay),
+            ("min", channel.min_delay),
+        ] {
+            if let Some(delay) = delay {
+                label += &format!("\\n{} {}", name, delay);
+            }
+        }
+        write!(
+            w,
+            "  n{} -> n{} [label=\"{}\"",
+            src.index(),
+            dst.index(),
+            label
+        )?;
+        if channel.is_internal {
+            write!(w, ", style=dashed")?;
+        }
+        writeln!(w, "];")?;
+    }
+
+    writeln!(w, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structural_graph::parse;
+
+    #[test]
+    fn renders_kinds_and_channels() {
+        let graph = parse(
+            r#"
+            Port "a" [("r", 10)]
+            DataReg "r" [("n", 20)]
+            NullReg "n" [("b", 5, backward = 2)]
+            Port "b" []
+            "#,
+        )
+        .unwrap();
+        let dot = structural_graph_to_dot(&graph, &ComponentLibrary::default());
+
+        // The DataReg stages are clustered, the NullReg is a single box
+        let cluster = dot.find("subgraph").unwrap();
+        let cluster_end = cluster + dot[cluster..].find("  }").unwrap();
+        let body = &dot[cluster..cluster_end];
+        assert!(body.contains("label=\"DataReg \\\"r\\\"\";"));
+        assert_eq!(body.matches("shape=box").count(), 3);
+        assert!(dot.contains("[label=\"NullReg \\\"n\\\"\\ncost 10\", shape=box];"));
+        assert!(dot.contains("[label=\"Port \\\"a\\\"\", shape=ellipse];"));
+
+        // Internal channels between the stages are dashed
+        assert_eq!(dot.matches("style=dashed").count(), 2);
+        assert!(dot.contains("[label=\"5\\nbackward 2\"];"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Elaboration of module definitions and instances into a flat list of entries.
+//!
+//! A module is a named group of entries with a list of ports. Instantiating it copies its
+//! entries into the enclosing scope, prefixing every component name with `<instance>/`, while
+//! references to a port resolve to the component the instance binds it to. Nested instances
+//! stack their prefixes, so a register `acc` of instance `mac` inside instance `lane0` becomes
+//! `lane0/mac/acc`, the same naming Pulsar already uses for flattened hierarchy.
+
+use super::ast::{Adjacency, Binding, Channels, Entry, Instance, Item, ModuleDef};
+use super::{ComponentLibrary, ParseError, Symbol};
+use crate::diagnostic::Span;
+use std::collections::HashMap;
+
+/// An item of the elaborated graph, with every name resolved to its flattened form.
+pub(super) enum FlatItem {
+    Entry(Entry),
+    Channels(Channels),
+}
+
+/// Naming scope of one module instance (or of the top level).
+#[derive(Default)]
+struct Scope {
+    /// Prefix of the components declared in this scope, `None` at the top level.
+    prefix: Option<Symbol>,
+    /// Flattened name of the component bound to each port of the module.
+    ports: HashMap<Symbol, Symbol>,
+}
+
+impl Scope {
+    /// Flattened name of a component declared in this scope.
+    fn local(&self, name: &Symbol) -> Symbol {
+        match &self.prefix {
+            None => name.clone(),
+            Some(prefix) => format!("{}/{}", prefix, name).into(),
+        }
+    }
+
+    /// Flattened name of a component referenced in this scope: a port or a local component.
+    fn resolve(&self, name: &Symbol) -> Symbol {
+        self.ports
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| self.local(name))
+    }
+
+    fn adjacency(&self, adjacency_list: &[Adjacency]) -> Vec<Adjacency> {
+        adjacency_list
+            .iter()
+            .map(|adjacency| Adjacency {
+                target: self.resolve(&adjacency.target),
+                ..adjacency.clone()
+            })
+            .collect()
+    }
+}
+
+struct Elaborator {
+    modules: HashMap<Symbol, ModuleDef>,
+}
+
+/// Flatten the module instances of a parsed graph.
+///
+/// Module definitions are collected first, so a module may be instantiated before it is
+/// defined. Entries keep their source spans, so errors found while building the graph point
+/// at the entry inside the module definition.
+pub(super) fn elaborate(
+    items: Vec<Item>,
+    library: &ComponentLibrary,
+) -> Result<Vec<FlatItem>, ParseError> {
+    let mut modules: HashMap<Symbol, ModuleDef> = HashMap::new();
+    let mut top = Vec::new();
+
+    for item in items {
+        match item {
+            Item::Module(module) => {
+                if module.name.as_ref() == "Port" || library.get(module.name.as_ref()).is_some() {
+                    return Err(invalid(
+                        format!("module '{}' shadows a component type", module.name),
+                        module.name_span,
+                    ));
+                }
+                if modules.contains_key(&module.name) {
+                    return Err(invalid(
+                        format!("module '{}' defined more than once", module.name),
+                        module.name_span,
+                    ));
+                }
+                for (i, (port, span)) in module.ports.iter().enumerate() {
+                    if module.ports[..i].iter().any(|(other, _)| other == port) {
+                        return Err(invalid(
+                            format!("port '{}' declared more than once", port),
+                            *span,
+                        ));
+                    }
+                }
+                modules.insert(module.name.clone(), module);
+            }
+            item => top.push(item),
+        }
+    }
+
+    // Top-level entries and channel lists need no renaming, so they are moved rather than
+    // copied; only the bodies of instantiated modules are
+    let elaborator = Elaborator { modules };
+    let mut flat = Vec::with_capacity(top.len());
+    for item in top {
+        match item {
+            Item::Entry(entry) => flat.push(FlatItem::Entry(entry)),
+            Item::Channels(channels) => flat.push(FlatItem::Channels(channels)),
+            item => elaborator.expand(
+                std::slice::from_ref(&item),
+                &Scope::default(),
+                &mut Vec::new(),
+                &mut flat,
+            )?,
+        }
+    }
+    Ok(flat)
+}
+
+fn invalid(message: String, span: Span) -> ParseError {
+    ParseError::InvalidModule { message, span }
+}
+
+impl Elaborator {
+    /// Expand `items` in `scope` into `flat`; `stack` holds the modules being instantiated,
+    /// innermost last, to reject recursive instantiation.
+    fn expand(
+        &self,
+        items: &[Item],
+        scope: &Scope,
+        stack: &mut Vec<Symbol>,
+        flat: &mut Vec<FlatItem>,
+    ) -> Result<(), ParseError> {
+        for item in items {
+            match item {
+                Item::Entry(entry) => {
+                    if let Some(module) = stack
+                        .last()
+                        .filter(|_| scope.ports.contains_key(&entry.name))
+                    {
+                        return Err(invalid(
+                            format!(
+                                "component '{}' shadows a port of module '{}'",
+                                entry.name, module
+                            ),
+                            entry.name_span,
+                        ));
+                    }
+                    flat.push(FlatItem::Entry(Entry {
+                        name: scope.local(&entry.name),
+                        adjacency_list: scope.adjacency(&entry.adjacency_list),
+                        ..entry.clone()
+                    }));
+                }
+                Item::Channels(channels) => flat.push(FlatItem::Channels(Channels {
+                    source: scope.resolve(&channels.source),
+                    adjacency_list: scope.adjacency(&channels.adjacency_list),
+                    ..channels.clone()
+                })),
+                Item::Instance(instance) => self.instantiate(instance, scope, stack, flat)?,
+                // The grammar only accepts module definitions at the top level, and
+                // `elaborate` has already taken them out.
+                Item::Module(_) => unreachable!("nested module definition"),
+            }
+        }
+        Ok(())
+    }
+
+    fn instantiate(
+        &self,
+        instance: &Instance,
+        scope: &Scope,
+        stack: &mut Vec<Symbol>,
+        flat: &mut Vec<FlatItem>,
+    ) -> Result<(), ParseError> {
+        let Some(module) = self.modules.get(&instance.module) else {
+            return Err(ParseError::UnknownComponentType {
+                name: instance.module.clone(),
+                span: instance.module_span,
+            });
+        };
+        if stack.contains(&module.name) {
+            return Err(invalid(
+                format!("recursive instantiation of module '{}'", module.name),
+                instance.span,
+            ));
+        }
+
+        let ports = bind_ports(module, instance, scope)?;
+        let inner = Scope {
+            prefix: Some(scope.local(&instance.name)),
+            ports,
+        };
+
+        stack.push(module.name.clone());
+        self.expand(&module.body, &inner, stack, flat)?;
+        stack.pop();
+        Ok(())
+    }
+}
+
+/// Resolve the bindings of `instance` to the flattened name bound to each port of `module`.
+fn bind_ports(
+    module: &ModuleDef,
+    instance: &Instance,
+    scope: &Scope,
+) -> Result<HashMap<Symbol, Symbol>, ParseError> {
+    let mut ports = HashMap::new();
+
+    let named = instance
+        .bindings
+        .iter()
+        .filter(|b| matches!(b, Binding::Named { .. }))
+        .count();
+    if named == 0 {
+        if instance.bindings.len() != module.ports.len() {
+            return Err(invalid(
+                format!(
+                    "module '{}' has {} port(s) but instance '{}' binds {}",
+                    module.name,
+                    module.ports.len(),
+                    instance.name,
+                    instance.bindings.len()
+                ),
+                instance.span,
+            ));
+        }
+        for ((port, _), binding) in module.ports.iter().zip(instance.bindings.iter()) {
+            if let Binding::Positional { actual, .. } = binding {
+                ports.insert(port.clone(), scope.resolve(actual));
+            }
+        }
+        return Ok(ports);
+    }
+
+    for binding in instance.bindings.iter() {
+        match binding {
+            Binding::Positional { span, .. } => {
+                return Err(invalid(
+                    "positional and named port bindings cannot be mixed".to_string(),
+                    *span,
+                ));
+            }
+            Binding::Named { port, actual, span } => {
+                if !module.ports.iter().any(|(p, _)| p == port) {
+                    return Err(invalid(
+                        format!("module '{}' has no port '{}'", module.name, port),
+                        *span,
+                    ));
+                }
+                if ports.insert(port.clone(), scope.resolve(actual)).is_some() {
+                    return Err(invalid(
+                        format!("port '{}' bound more than once", port),
+                        *span,
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some((port, _)) = module.ports.iter().find(|(p, _)| !ports.contains_key(p)) {
+        return Err(invalid(
+            format!(
+                "port '{}' of module '{}' is not bound by instance '{}'",
+                port, module.name, instance.name
+            ),
+            instance.span,
+        ));
+    }
+    Ok(ports)
+}
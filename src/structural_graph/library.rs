@@ -0,0 +1,429 @@
+//! Component libraries: register kinds and their expansion into internal stages.
+//!
+//! Every non-port entry of a structural graph names a *register kind*. A kind describes how
+//! one register expands into circuit nodes: the main node (the one other components drive),
+//! any number of internal sub-stages named `<register>/<stage>`, the internal channels
+//! joining them, and which node drives the register's own adjacency list. [`parse`] looks
+//! kinds up in a [`ComponentLibrary`] and expands them generically.
+//!
+//! The built-in kinds are themselves declared in the library format:
+//!
+//! ```text
+//! register NullReg cost 10 {}
+//! register ControlReg cost 50 {}
+//!
+//! register DataReg cost 10 {
+//!     stage "s0";
+//!     stage "s1";
+//!     channel self -> "s0" (10, phase = ReqNull);
+//!     channel "s0" -> "s1" (10, phase = ReqData);
+//!     output "s1";
+//! }
+//!
+//! register UnsafeReg cost 10 {
+//!     stage "s0";
+//!     channel self -> "s0" (10, phase = ReqNull);
+//!     output "s0" (phase = ReqData, internal);
+//! }
+//! ```
+//!
+//! # Library Format
+//!
+//! - `register <Kind> [cost <n>] { ... }` declares a kind; `cost` (default 10) is the base
+//!   cost of its nodes.
+//! - `stage "<name>" [cost <n>];` adds a sub-stage node, costing the register's cost unless
+//!   given.
+//! - `channel <from> -> <to> (<delay>, attributes...);` adds an internal channel, where
+//!   `self` names the main node and a quoted name a stage. It takes the same attributes as a
+//!   structural graph adjacency tuple; internal channels default to `internal` and
+//!   `phase = AckNull`.
+//! - `output <node> [(attributes...)];` selects the node that drives the register's
+//!   adjacency list (default: the last stage, or `self` if there are none). Its `phase` and
+//!   `internal` attributes set the defaults of those outgoing channels.
+//!
+//! A library file is loaded on top of the built-in kinds, so it may add kinds or redefine
+//! built-in ones; `Port` is reserved.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::structural_graph::{ComponentLibrary, parse_with_library};
+//!
+//! let library = ComponentLibrary::parse(r#"
+//!     register CReg cost 20 {
+//!         stage "c";
+//!         channel self -> "c" (5, phase = ReqData);
+//!     }
+//! "#).unwrap();
+//!
+//! let graph = parse_with_library(r#"
+//!     Port "a" [("r", 10)]
+//!     CReg "r" [("b", 10)]
+//!     Port "b" []
+//! "#, &library).unwrap();
+//!
+//! // a, r, r/c and b
+//! assert_eq!(graph.node_count(), 4);
+//! ```
+//!
+//! [`parse`]: super::parse
+
+use super::{
+    Channel, ChannelPhase, ParseError, Symbol,
+    ast::{AttributeKind, ChannelAttribute, KindDef, KindItem},
+    parser,
+};
+use crate::diagnostic::Span;
+use crate::lexer::Lexer;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+/// Cost of a register node whose kind or stage declares none.
+pub const DEFAULT_REGISTER_COST: usize = 10;
+
+/// Library source of the built-in register kinds.
+const BUILTIN_LIBRARY: &str = r#"
+register NullReg cost 10 {}
+register ControlReg cost 50 {}
+
+register DataReg cost 10 {
+    stage "s0";
+    stage "s1";
+    channel self -> "s0" (10, phase = ReqNull);
+    channel "s0" -> "s1" (10, phase = ReqData);
+    output "s1";
+}
+
+register UnsafeReg cost 10 {
+    stage "s0";
+    channel self -> "s0" (10, phase = ReqNull);
+    output "s0" (phase = ReqData, internal);
+}
+"#;
+
+lazy_static! {
+    static ref BUILTIN: ComponentLibrary = {
+        let mut library = ComponentLibrary {
+            kinds: HashMap::new(),
+        };
+        library
+            .load(BUILTIN_LIBRARY)
+            .expect("built-in component library should be valid");
+        library
+    };
+}
+
+/// An internal sub-stage node of a register kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    /// Suffix appended to the register name: the stage node is `<register>/<suffix>`.
+    pub suffix: Symbol,
+    /// Base cost of the stage node.
+    pub cost: usize,
+}
+
+/// A channel between two nodes of a register kind.
+///
+/// Endpoints index [`RegisterKind::stages`]; `None` is the register's main node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternalChannel {
+    /// Source node of the channel.
+    pub from: Option<usize>,
+    /// Destination node of the channel.
+    pub to: Option<usize>,
+    /// Phase, delay and attributes of the channel.
+    pub channel: Channel,
+}
+
+/// How one register kind expands into circuit nodes and channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterKind {
+    /// Base cost of the register's main node.
+    pub cost: usize,
+    /// Internal sub-stages, in declaration order.
+    pub stages: Vec<Stage>,
+    /// Internal channels, in declaration order.
+    pub channels: Vec<InternalChannel>,
+    /// Node driving the register's adjacency list (`None` for the main node).
+    pub output: Option<usize>,
+    /// Initial phase of the register's outgoing channels, unless overridden.
+    pub output_phase: ChannelPhase,
+    /// Whether the register's outgoing channels are internal, unless overridden.
+    pub output_internal: bool,
+}
+
+/// A set of register kinds, keyed by the name used in structural graphs.
+///
+/// [`ComponentLibrary::default`] holds the built-in kinds (`NullReg`, `ControlReg`,
+/// `DataReg` and `UnsafeReg`); [`ComponentLibrary::parse`] adds the kinds of a library file
+/// to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentLibrary {
+    kinds: HashMap<Symbol, RegisterKind>,
+}
+
+impl Default for ComponentLibrary {
+    fn default() -> Self {
+        BUILTIN.clone()
+    }
+}
+
+impl ComponentLibrary {
+    /// The built-in register kinds, shared without cloning.
+    pub(super) fn builtin() -> &'static ComponentLibrary {
+        &BUILTIN
+    }
+
+    /// Parse a component library file on top of the built-in kinds.
+    ///
+    /// Errors carry the [`Span`] of the offending declaration; see
+    /// [`ParseError::to_diagnostic`].
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut library = Self::default();
+        library.load(input)?;
+        Ok(library)
+    }
+
+    /// Look up a register kind by name.
+    pub fn get(&self, name: &str) -> Option<&RegisterKind> {
+        self.kinds.get(&Symbol::from(name))
+    }
+
+    /// Add a register kind, replacing any kind of the same name.
+    pub fn insert(&mut self, name: Symbol, kind: RegisterKind) {
+        self.kinds.insert(name, kind);
+    }
+
+    /// Iterate over the names of all kinds in the library.
+    pub fn kind_names(&self) -> impl Iterator<Item = &Symbol> {
+        self.kinds.keys()
+    }
+
+    /// Parse `input` and add its kinds to this library.
+    fn load(&mut self, input: &str) -> Result<(), ParseError> {
+        let defs = parser::LibraryParser::new().parse(Lexer::structural(input))?;
+        let mut declared: HashMap<Symbol, Span> = HashMap::new();
+
+        for def in defs {
+            if def.name.as_ref() == "Port" {
+                return Err(invalid(
+                    "'Port' is reserved and cannot be declared as a register kind".to_string(),
+                    def.name_span,
+                ));
+            }
+            if declared.insert(def.name.clone(), def.name_span).is_some() {
+                return Err(invalid(
+                    format!("register kind '{}' declared more than once", def.name),
+                    def.name_span,
+                ));
+            }
+            let name = def.name.clone();
+            let kind = RegisterKind::from_def(def)?;
+            self.kinds.insert(name, kind);
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String, span: Span) -> ParseError {
+    ParseError::InvalidComponentDefinition { message, span }
+}
+
+impl RegisterKind {
+    fn from_def(def: KindDef) -> Result<Self, ParseError> {
+        let cost = def.cost.unwrap_or(DEFAULT_REGISTER_COST);
+        let mut stages: Vec<Stage> = Vec::new();
+        let mut stage_index: HashMap<Symbol, usize> = HashMap::new();
+
+        // Stages first, so channels and the output may refer to stages declared later
+        for item in def.items.iter() {
+            if let KindItem::Stage {
+                name,
+                cost: stage_cost,
+                span,
+            } = item
+            {
+                if name.is_empty() {
+                    return Err(invalid("stage name must not be empty".to_string(), *span));
+                }
+                if stage_index.insert(name.clone(), stages.len()).is_some() {
+                    return Err(invalid(
+                        format!("stage '{}' declared more than once", name),
+                        *span,
+                    ));
+                }
+                stages.push(Stage {
+                    suffix: name.clone(),
+                    cost: stage_cost.unwrap_or(cost),
+                });
+            }
+        }
+
+        let resolve = |stage: &Option<Symbol>, span: Span| -> Result<Option<usize>, ParseError> {
+            match stage {
+                None => Ok(None),
+                Some(name) => stage_index
+                    .get(name)
+                    .map(|&i| Some(i))
+                    .ok_or_else(|| invalid(format!("undefined stage '{}'", name), span)),
+            }
+        };
+
+        let mut channels = Vec::new();
+        let mut output = None;
+        for item in def.items {
+            match item {
+                KindItem::Stage { .. } => {}
+                KindItem::Channel {
+                    from,
+                    to,
+                    delay,
+                    attributes,
+                    span,
+                } => {
+                    let from = resolve(&from, span)?;
+                    let to = resolve(&to, span)?;
+                    if from == to {
+                        return Err(invalid(
+                            "internal channel connects a node to itself".to_string(),
+                            span,
+                        ));
+                    }
+                    let channel = Channel::new(ChannelPhase::AckNull, true, delay)
+                        .with_attributes(attributes)?;
+                    channels.push(InternalChannel { from, to, channel });
+                }
+                KindItem::Output {
+                    stage,
+                    attributes,
+                    span,
+                } => {
+                    if output.is_some() {
+                        return Err(invalid("output declared more than once".to_string(), span));
+                    }
+                    output = Some((resolve(&stage, span)?, attributes));
+                }
+            }
+        }
+
+        let (output, output_attributes) =
+            output.unwrap_or_else(|| (stages.len().checked_sub(1), Vec::new()));
+        let mut output_phase = ChannelPhase::AckNull;
+        let mut output_internal = false;
+        for ChannelAttribute { kind, span } in output_attributes {
+            match kind {
+                AttributeKind::Phase(phase) => output_phase = phase,
+                AttributeKind::Internal(internal) => output_internal = internal,
+                other => {
+                    return Err(invalid(
+                        format!(
+                            "'{}' cannot be set on the output; only 'phase' and 'internal' can",
+                            other.key()
+                        ),
+                        span,
+                    ));
+                }
+            }
+        }
+
+        Ok(RegisterKind {
+            cost,
+            stages,
+            channels,
+            output,
+            output_phase,
+            output_internal,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_kinds() {
+        let library = ComponentLibrary::default();
+        let mut names: Vec<_> = library.kind_names().map(|n| n.to_string()).collect();
+        names.sort();
+        assert_eq!(names, ["ControlReg", "DataReg", "NullReg", "UnsafeReg"]);
+
+        let data_reg = library.get("DataReg").unwrap();
+        assert_eq!(data_reg.stages.len(), 2);
+        assert_eq!(data_reg.output, Some(1));
+        assert_eq!(
+            data_reg.channels[0].channel,
+            Channel::new(ChannelPhase::ReqNull, true, 10.0)
+        );
+
+        let unsafe_reg = library.get("UnsafeReg").unwrap();
+        assert_eq!(unsafe_reg.output_phase, ChannelPhase::ReqData);
+        assert!(unsafe_reg.output_internal);
+
+        assert_eq!(library.get("ControlReg").unwrap().cost, 50);
+    }
+
+    #[test]
+    fn parse_adds_to_builtins() {
+        let library = ComponentLibrary::parse(
+            r#"
+            register Q4Reg cost 15 {
+                stage "c" cost 30;
+                stage "r";
+                channel self -> "c" (12, phase = ReqNull, backward = 5);
+                channel "c" -> "r" (12, phase = ReqData);
+                output "r" (phase = AckData);
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert!(library.get("DataReg").is_some());
+        let q4 = library.get("Q4Reg").unwrap();
+        assert_eq!(q4.cost, 15);
+        assert_eq!(q4.stages[0].cost, 30);
+        assert_eq!(q4.stages[1].cost, 15);
+        assert_eq!(q4.channels[0].channel.backward_delay, Some(5.0));
+        assert_eq!(q4.output, Some(1));
+        assert_eq!(q4.output_phase, ChannelPhase::AckData);
+        assert!(!q4.output_internal);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("register Port {}", "'Port' is reserved"),
+            ("register A {}\nregister A {}", "declared more than once"),
+            (
+                "register A { stage \"s\"; stage \"s\"; }",
+                "stage 's' declared more than once",
+            ),
+            (
+                "register A { channel self -> \"x\" (1); }",
+                "undefined stage 'x'",
+            ),
+            (
+                "register A { channel self -> self (1); }",
+                "connects a node to itself",
+            ),
+            (
+                "register A { stage \"s\"; output \"s\" (min = 1); }",
+                "only 'phase' and 'internal'",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = ComponentLibrary::parse(input).unwrap_err();
+            assert!(
+                err.to_string().contains(expected),
+                "{:?}: expected '{}', got '{}'",
+                input,
+                expected,
+                err
+            );
+        }
+
+        let err = ComponentLibrary::parse("register A cost 1.5 {}").unwrap_err();
+        assert!(matches!(err, ParseError::SyntaxError { .. }));
+        assert_eq!(err.to_diagnostic("register A cost 1.5 {}").column, 17);
+    }
+}
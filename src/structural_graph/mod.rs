@@ -19,9 +19,11 @@
 //!
 //! The format syntax is:
 //! - `<Type> <Name> [<adjacency_list>]`
-//! - `<Type>`: One of `Port`, `DataReg`, `NullReg`, `ControlReg`, or `UnsafeReg`
+//! - `<Type>`: `Port` or a register kind: `DataReg`, `NullReg`, `ControlReg`, `UnsafeReg`, or
+//!   one declared in a component library (see [`library`])
 //! - `<Name>`: A quoted string identifier
-//! - `<adjacency_list>`: A comma-separated list of `("target", delay)` tuples
+//! - `<adjacency_list>`: A comma-separated list of `("target", delay)` tuples, each
+//!   optionally followed by channel attributes (see below)
 //!
 //! # Component Types
 //!
@@ -41,6 +43,51 @@
 //!
 //! The initial phase affects how tokens are distributed when converting to HBCN.
 //!
+//! # Channel Attributes
+//!
+//! An adjacency tuple may carry `key = value` attributes after its delay, overriding the
+//! defaults `parse` would otherwise pick for the channel:
+//!
+//! ```text
+//! Port "a" [("b", 10, phase = ReqData, internal, forward = 12, backward = 30, min = 4)]
+//! Port "c" [("b", 10, min = 4, max = 25)]
+//! ```
+//!
+//! - `phase = AckNull | ReqData | AckData | ReqNull`: initial handshake phase
+//! - `internal` / `internal = true | false`: mark the channel internal
+//! - `forward = <num>`: fixed weight of the two forward places
+//! - `backward = <num>`: fixed weight of the two backward places
+//! - `max = <num>`: maximum delay of the channel's places, fixing the weight of all four;
+//!   it replaces `forward` and `backward`, so may not be given with either
+//! - `min = <num>`: minimum delay of the channel's places, the `min` of each place's
+//!   `(min, max)` pair whose `max` is the place's weight. It may not exceed an explicit
+//!   `max`, `forward` or `backward`; the subcommands warn about places whose modelled
+//!   weight it exceeds (see [`min_delay_excesses`](crate::hbcn::min_delay_excesses))
+//!
+//! # Modules
+//!
+//! Repeated blocks can be described once as a module and instantiated, binding each of
+//! the module's ports to a component of the enclosing scope, by position or by name:
+//!
+//! ```text
+//! module Lane ("in", "out") {
+//!     "in" [("acc", 10)]
+//!     DataReg "acc" [("acc", 5), ("out", 20)]
+//! }
+//!
+//! Lane "lane0" ("a", "b")
+//! Lane "lane1" ("in" = "a", "out" = "b")
+//! ```
+//!
+//! Parsing flattens the instances, naming each component `<instance>/<name>`
+//! (`lane0/acc`). Inside the module, a port name refers to the component bound to it, and
+//! `"<port>" [...]` lists channels driven by that component.
+//!
+//! # Serialisation
+//!
+//! [`serialise_structural_graph`] writes a graph back to this format, folding register
+//! sub-stages (`/s0`, `/s1`) into their register's entry; see [`serialisation`].
+//!
 //! # Usage
 //!
 //! ```
@@ -62,6 +109,10 @@
 //! ```
 
 mod ast;
+pub mod dot;
+mod hierarchy;
+pub mod library;
+pub mod serialisation;
 
 // Include the generated parser with clippy warnings suppressed
 #[allow(clippy::all)]
@@ -76,8 +127,12 @@ mod parser {
     include!(concat!(env!("OUT_DIR"), "/structural_graph/parser.rs"));
 }
 
-use ast::{Entry, EntryType};
+use crate::diagnostic::{Diagnostic, Span, UserError, describe_lalrpop_error};
+use crate::lexer::{Lexer, Tok};
+use ast::{Adjacency, AttributeKind, ChannelAttribute, Channels, Entry};
+use hierarchy::FlatItem;
 use petgraph::{graph, stable_graph::StableGraph};
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, error::Error, fmt};
 use string_cache::DefaultAtom;
 
@@ -86,9 +141,11 @@ use string_cache::DefaultAtom;
 /// Symbols are interned strings using `DefaultAtom` for efficient comparison and memory usage.
 pub type Symbol = DefaultAtom;
 
-// Cost constants for different register types
-const REGISTER_COST: usize = 10;
-const CONTROL_REG_COST: usize = 50;
+pub use dot::structural_graph_to_dot;
+pub use library::{ComponentLibrary, RegisterKind};
+pub use serialisation::{
+    SerialiseError, serialise_structural_graph, serialise_structural_graph_with_library,
+};
 
 /// Represents a circuit component (port or register) in the structural graph.
 ///
@@ -183,7 +240,7 @@ impl fmt::Display for CircuitNode {
 /// // A channel starting in request data phase
 /// let phase = ChannelPhase::ReqData;
 /// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum ChannelPhase {
     /// Acknowledge null phase - acknowledgment of spacer/null propagation.
     AckNull,
@@ -215,21 +272,28 @@ pub enum ChannelPhase {
 /// - **`virtual_delay`**: The delay (in time units) associated with traversing this channel.
 ///   This is used as the base weight for forward places in the HBCN conversion.
 ///
+/// - **`forward_delay`** / **`backward_delay`**: Fixed weights for the forward and backward
+///   places, overriding the cost model of the HBCN conversion when set. The `max`
+///   attribute sets both.
+///
+/// - **`min_delay`**: Minimum delay carried onto each of the channel's places.
+///
+/// The last three are only set by channel attributes in the input (see the module docs).
+///
 /// # Example
 ///
 /// ```
 /// use hbcn::structural_graph::{Channel, ChannelPhase};
 ///
-/// let external_channel = Channel {
-///     initial_phase: ChannelPhase::AckNull,
-///     is_internal: false,
-///     virtual_delay: 100.0,
-/// };
+/// let external_channel = Channel::new(ChannelPhase::AckNull, false, 100.0);
 ///
 /// let internal_channel = Channel {
 ///     initial_phase: ChannelPhase::ReqData,
 ///     is_internal: true,
 ///     virtual_delay: 10.0,
+///     forward_delay: None,
+///     backward_delay: Some(20.0),
+///     min_delay: None,
 /// };
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -240,6 +304,94 @@ pub struct Channel {
     pub is_internal: bool,
     /// Virtual delay for traversing this channel.
     pub virtual_delay: f64,
+    /// Fixed weight of the forward places, overriding `virtual_delay` and forward completion.
+    pub forward_delay: Option<f64>,
+    /// Fixed weight of the backward places, overriding the handshake cost model.
+    pub backward_delay: Option<f64>,
+    /// Minimum delay of the channel's places, whose maximum is their weight.
+    pub min_delay: Option<f64>,
+}
+
+impl Channel {
+    /// Create a channel with the given phase, kind and virtual delay, and no overrides.
+    pub fn new(initial_phase: ChannelPhase, is_internal: bool, virtual_delay: f64) -> Self {
+        Channel {
+            initial_phase,
+            is_internal,
+            virtual_delay,
+            forward_delay: None,
+            backward_delay: None,
+            min_delay: None,
+        }
+    }
+
+    /// Apply the attributes of an adjacency tuple on top of the defaults in `self`.
+    fn with_attributes(mut self, attributes: Vec<ChannelAttribute>) -> Result<Self, ParseError> {
+        let mut seen: Vec<&'static str> = Vec::new();
+        let mut min_span = Span::default();
+        let mut max_span = None;
+        for ChannelAttribute { kind, span } in attributes {
+            if seen.contains(&kind.key()) {
+                return Err(ParseError::InvalidAttribute {
+                    message: format!("'{}' given more than once", kind.key()),
+                    span,
+                });
+            }
+            seen.push(kind.key());
+
+            match kind {
+                AttributeKind::Phase(phase) => self.initial_phase = phase,
+                AttributeKind::Internal(internal) => self.is_internal = internal,
+                AttributeKind::Forward(delay) => self.forward_delay = Some(delay),
+                AttributeKind::Backward(delay) => self.backward_delay = Some(delay),
+                AttributeKind::Min(delay) => {
+                    self.min_delay = Some(delay);
+                    min_span = span;
+                }
+                AttributeKind::Max(delay) => {
+                    self.forward_delay = Some(delay);
+                    self.backward_delay = Some(delay);
+                    max_span = Some(span);
+                }
+            }
+        }
+
+        if let Some(span) = max_span
+            && let Some(key) = ["forward", "backward"]
+                .into_iter()
+                .find(|key| seen.contains(key))
+        {
+            return Err(ParseError::InvalidAttribute {
+                message: format!("'max' cannot be given with '{}'", key),
+                span,
+            });
+        }
+
+        // The places' weights are their max delays. Only explicit ones are known here; the
+        // conversion keeps modelled weights as they are, whatever the min
+        let maxima = if max_span.is_some() {
+            [("max", self.forward_delay), ("max", None)]
+        } else {
+            [
+                ("forward", self.forward_delay),
+                ("backward", self.backward_delay),
+            ]
+        };
+        if let Some(min) = self.min_delay {
+            for (direction, max) in maxima {
+                if let Some(max) = max.filter(|&max| min > max) {
+                    return Err(ParseError::InvalidAttribute {
+                        message: format!(
+                            "min delay {} exceeds the {} delay {}",
+                            min, direction, max
+                        ),
+                        span: min_span,
+                    });
+                }
+            }
+        }
+        Ok(self)
+    }
 }
 
 /// Type alias for the structural graph representation.
@@ -252,7 +404,7 @@ pub struct Channel {
 /// conversion to an HBCN. It can be parsed from a text description using [`parse`].
 pub type StructuralGraph = StableGraph<CircuitNode, Channel>;
 
-type LarlPopError<'a> = lalrpop_util::ParseError<usize, parser::Token<'a>, &'static str>;
+type LarlPopError<'a> = lalrpop_util::ParseError<usize, Tok<'a>, UserError>;
 
 /// Errors that can occur when parsing a structural graph.
 ///
@@ -261,6 +413,9 @@ type LarlPopError<'a> = lalrpop_util::ParseError<usize, parser::Token<'a>, &'sta
 /// - A circuit component is defined multiple times
 /// - A referenced component in an adjacency list doesn't exist
 ///
+/// Every variant carries the byte [`Span`] of the offending input; use
+/// [`ParseError::to_diagnostic`] to resolve it to a line/column with a source snippet.
+///
 /// # Example
 ///
 /// ```
@@ -271,8 +426,9 @@ type LarlPopError<'a> = lalrpop_util::ParseError<usize, parser::Token<'a>, &'sta
 /// "#;
 ///
 /// match parse(input) {
-///     Err(ParseError::UndefinedElement(name)) => {
-///         assert_eq!(name.as_ref(), "undefined");
+///     Err(err @ ParseError::UndefinedElement { .. }) => {
+///         let diag = err.to_diagnostic(input);
+///         assert_eq!((diag.line, diag.column), (2, 15));
 ///     },
 ///     _ => panic!("Expected undefined element error"),
 /// }
@@ -280,29 +436,60 @@ type LarlPopError<'a> = lalrpop_util::ParseError<usize, parser::Token<'a>, &'sta
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParseError {
     /// Syntax error in the input text (malformed input format).
-    SyntaxError(String),
+    SyntaxError { message: String, span: Span },
     /// A circuit component was defined multiple times.
-    MultipleDefinitions(CircuitNode),
+    MultipleDefinitions { node: CircuitNode, span: Span },
     /// A component referenced in an adjacency list was never defined.
-    UndefinedElement(Symbol),
-    /// A non-port component used the reserved `port:` name prefix, which the HBCN
-    /// format reserves to identify ports (see [`crate::hbcn::parser`]).
-    ReservedNamePrefix(Symbol),
+    UndefinedElement { name: Symbol, span: Span },
+    /// A channel attribute was repeated or has an inconsistent value.
+    InvalidAttribute { message: String, span: Span },
+    /// A component's type is neither `Port` nor a register kind of the component library.
+    UnknownComponentType { name: Symbol, span: Span },
+    /// A register kind in a component library file is malformed.
+    InvalidComponentDefinition { message: String, span: Span },
+    /// A module definition or instance is malformed: duplicate or shadowing names, bad port
+    /// bindings, or recursive instantiation.
+    InvalidModule { message: String, span: Span },
+}
+
+impl ParseError {
+    /// The source location this error refers to.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::SyntaxError { span, .. }
+            | ParseError::MultipleDefinitions { span, .. }
+            | ParseError::UndefinedElement { span, .. }
+            | ParseError::InvalidAttribute { span, .. }
+            | ParseError::UnknownComponentType { span, .. }
+            | ParseError::InvalidComponentDefinition { span, .. }
+            | ParseError::InvalidModule { span, .. } => *span,
+        }
+    }
+
+    /// Resolve this error against the parsed `source` into a located [`Diagnostic`].
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        Diagnostic::new(source, self.span(), self.to_string())
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::SyntaxError(err) => write!(f, "{}", err),
-            ParseError::MultipleDefinitions(node) => {
+            ParseError::SyntaxError { message, .. } => write!(f, "{}", message),
+            ParseError::MultipleDefinitions { node, .. } => {
                 write!(f, "Multiple Definitions of {}", node.name())
             }
-            ParseError::UndefinedElement(name) => write!(f, "Undefined Element: {}", name),
-            ParseError::ReservedNamePrefix(name) => write!(
-                f,
-                "Reserved name prefix: non-port component '{}' may not start with 'port:'",
-                name
-            ),
+            ParseError::UndefinedElement { name, .. } => write!(f, "Undefined Element: {}", name),
+            ParseError::InvalidAttribute { message, .. } => {
+                write!(f, "Invalid channel attribute: {}", message)
+            }
+            ParseError::UnknownComponentType { name, .. } => {
+                write!(f, "Unknown component type: {}", name)
+            }
+            ParseError::InvalidComponentDefinition { message, .. } => {
+                write!(f, "Invalid component definition: {}", message)
+            }
+            ParseError::InvalidModule { message, .. } => write!(f, "Invalid module: {}", message),
         }
     }
 }
@@ -311,7 +498,8 @@ impl Error for ParseError {}
 
 impl From<LarlPopError<'_>> for ParseError {
     fn from(err: LarlPopError) -> Self {
-        ParseError::SyntaxError(format!("{}", err))
+        let (message, span) = describe_lalrpop_error(&err);
+        ParseError::SyntaxError { message, span }
     }
 }
 
@@ -328,32 +516,45 @@ impl From<LarlPopError<'_>> for ParseError {
 /// ```
 ///
 /// Where:
-/// - `<Type>` is one of: `Port`, `DataReg`, `NullReg`, `ControlReg`, `UnsafeReg`
+/// - `<Type>` is `Port` or a register kind (`DataReg`, `NullReg`, `ControlReg`, `UnsafeReg`)
 /// - `<Name>` is a quoted string identifier
 /// - `<adjacency_list>` is a comma-separated list of `("target", delay)` tuples
 ///
 /// # Component Type Handling
 ///
-/// Different component types are handled specially:
+/// `Port` is a simple node with no internal structure. Every other type is a register kind
+/// of the built-in [`ComponentLibrary`], expanded generically:
 ///
-/// - **Port**: Simple node with no internal structure
 /// - **DataReg**: Expanded into 3 nodes (main + s0 + s1) with internal channels
 /// - **NullReg**: Simple register node with cost 10
 /// - **ControlReg**: Register node with cost 50
 /// - **UnsafeReg**: Expanded into 2 nodes (main + s0) with internal channels
 ///
+/// Use [`parse_with_library`] to expand register kinds declared in a library file.
+///
+/// Module instances are flattened first: every component of an instance `i` is named
+/// `i/<name>`, and the module's ports resolve to the components the instance binds.
+///
 /// # Channel Initialization
 ///
 /// Channels are initialised with phases based on their type:
 /// - **External channels** (non-UnsafeReg): `AckNull` phase
 /// - **Internal channels** (UnsafeReg or DataReg internal): `ReqData` or `ReqNull` phase
 ///
+/// Channel attributes on an adjacency tuple override these defaults.
+///
 /// # Errors
 ///
 /// Returns an error if:
 /// - The input syntax is malformed (`SyntaxError`)
+/// - A component's type is neither `Port` nor a known register kind (`UnknownComponentType`)
 /// - A component is defined multiple times (`MultipleDefinitions`)
 /// - An adjacency list references an undefined component (`UndefinedElement`)
+/// - A channel attribute is repeated, `max` is given with `forward` or `backward`, or `min`
+///   exceeds an explicit max delay (`InvalidAttribute`)
+/// - A module definition or instance is malformed, e.g. a port is left unbound (`InvalidModule`)
+///
+/// Each error carries the [`Span`] of the offending entry or adjacency tuple.
 ///
 /// # Example
 ///
@@ -375,157 +576,222 @@ impl From<LarlPopError<'_>> for ParseError {
 /// }
 /// ```
 pub fn parse(input: &str) -> Result<StructuralGraph, ParseError> {
-    let nodes = parser::GraphParser::new().parse(input)?;
+    parse_with_library(input, ComponentLibrary::builtin())
+}
+
+/// Parses a structural graph, expanding register kinds from `library`.
+///
+/// Behaves like [`parse`], except that component types are looked up in `library` (see
+/// [`library`] for the file format), so a graph may use register kinds declared there.
+pub fn parse_with_library(
+    input: &str,
+    library: &ComponentLibrary,
+) -> Result<StructuralGraph, ParseError> {
+    let items = parser::GraphParser::new().parse(Lexer::structural(input))?;
+    let items = hierarchy::elaborate(items, library)?;
+    build_graph(&items, library)
+}
 
-    let mut ret = StructuralGraph::new();
-    let mut lut = HashMap::new();
+/// A component of a graph derived from something other than `.graph` text, such as a
+/// netlist: its type (`Port` or a register kind), its name and its outgoing channels as
+/// `(target, virtual delay)` pairs.
+pub(crate) struct Component {
+    pub kind: Symbol,
+    pub name: Symbol,
+    pub channels: Vec<(Symbol, f64)>,
+}
 
-    let mut adjacency: Vec<(graph::NodeIndex, Vec<(Symbol, Channel)>)> = Vec::new();
+/// Builds a structural graph from components, expanding their register kinds from
+/// `library` as [`parse_with_library`] does. Errors carry empty spans, as there is no
+/// source text to point into.
+pub(crate) fn from_components(
+    components: Vec<Component>,
+    library: &ComponentLibrary,
+) -> Result<StructuralGraph, ParseError> {
+    let span = Span::default();
+    let items: Vec<FlatItem> = components
+        .into_iter()
+        .map(
+            |Component {
+                 kind,
+                 name,
+                 channels,
+             }| {
+                let adjacency_list = channels
+                    .into_iter()
+                    .map(|(target, delay)| Adjacency::new(target, delay, Vec::new(), span))
+                    .collect();
+                FlatItem::Entry(Entry::new(kind, name, adjacency_list, span, span, span))
+            },
+        )
+        .collect();
+    build_graph(&items, library)
+}
 
-    for Entry {
-        entry_type,
-        name,
-        adjacency_list,
-    } in nodes.into_iter()
-    {
-        // `port:` is reserved to mark ports in the HBCN text format; a register carrying it
-        // would round-trip (serialise then re-parse) as a port. Reject it up front.
-        if entry_type != EntryType::Port && name.as_ref().starts_with("port:") {
-            return Err(ParseError::ReservedNamePrefix(name));
+/// Builds the graph of elaborated items, expanding register kinds from `library`.
+fn build_graph(
+    items: &[FlatItem],
+    library: &ComponentLibrary,
+) -> Result<StructuralGraph, ParseError> {
+    // Resolve every component's kind up front, which also sizes the graph: one node per
+    // component and stage, one edge per adjacency tuple and internal channel
+    let mut node_count = 0;
+    let mut edge_count = 0;
+    let mut kinds = Vec::new();
+    for item in items.iter() {
+        match item {
+            FlatItem::Entry(entry) => {
+                let register = if entry.kind.as_ref() == "Port" {
+                    None
+                } else {
+                    Some(library.get(entry.kind.as_ref()).ok_or_else(|| {
+                        ParseError::UnknownComponentType {
+                            name: entry.kind.clone(),
+                            span: entry.kind_span,
+                        }
+                    })?)
+                };
+                node_count += 1 + register.map_or(0, |r| r.stages.len());
+                edge_count += entry.adjacency_list.len() + register.map_or(0, |r| r.channels.len());
+                kinds.push(register);
+            }
+            FlatItem::Channels(channels) => edge_count += channels.adjacency_list.len(),
         }
+    }
 
-        let c = match entry_type {
-            EntryType::DataReg => {
-                let s0: Symbol = format!("{}/s0", name.as_ref()).into();
-                let s1: Symbol = format!("{}/s1", name.as_ref()).into();
+    let mut ret = StructuralGraph::with_capacity(node_count, edge_count);
+    let mut lut = HashMap::with_capacity(node_count);
 
-                let cn = CircuitNode::Register {
-                    name: name.clone(),
-                    cost: REGISTER_COST,
-                };
-                let cni = ret.add_node(cn.clone());
-                if lut.insert(name.clone(), cni).is_some() {
-                    return Err(ParseError::MultipleDefinitions(cn));
-                }
+    // Main node of each entry, in order, and the output node and default channel of each
+    // component, for the adjacency tuples of its entry and of channel lists declared apart
+    // from it (module ports)
+    let mut mains = Vec::with_capacity(kinds.len());
+    let mut outputs = HashMap::with_capacity(kinds.len());
 
-                let s0n = CircuitNode::Register {
-                    name: s0.clone(),
-                    cost: REGISTER_COST,
-                };
-                let s0i = ret.add_node(s0n.clone());
-                if lut.insert(s0.clone(), s0i).is_some() {
-                    return Err(ParseError::MultipleDefinitions(s0n));
-                }
-                adjacency.push((
-                    cni,
-                    vec![(
-                        s0,
-                        Channel {
-                            initial_phase: ChannelPhase::ReqNull,
-                            is_internal: true,
-                            virtual_delay: 10.0,
-                        },
-                    )],
-                ));
-                adjacency.push((
-                    s0i,
-                    vec![(
-                        s1.clone(),
-                        Channel {
-                            initial_phase: ChannelPhase::ReqData,
-                            is_internal: true,
-                            virtual_delay: 10.0,
-                        },
-                    )],
-                ));
-
-                CircuitNode::Register {
-                    name: s1,
-                    cost: REGISTER_COST,
-                }
+    let entries = items.iter().filter_map(|item| match item {
+        FlatItem::Entry(entry) => Some(entry),
+        FlatItem::Channels(_) => None,
+    });
+    for (entry, register) in entries.zip(kinds.iter()) {
+        let Entry {
+            name, name_span, ..
+        } = entry;
+
+        let mut add_node = |node: CircuitNode| {
+            let ni = ret.add_node(node.clone());
+            if lut.insert(node.name().clone(), ni).is_some() {
+                Err(ParseError::MultipleDefinitions {
+                    node,
+                    span: *name_span,
+                })
+            } else {
+                Ok(ni)
             }
-            EntryType::UnsafeReg => {
-                let s0: Symbol = format!("{}/s0", name.as_ref()).into();
+        };
 
-                let cn = CircuitNode::Register {
+        let (main, output, default_channel) = match register {
+            None => {
+                let port = add_node(CircuitNode::Port(name.clone()))?;
+                (port, port, Channel::new(ChannelPhase::AckNull, false, 0.0))
+            }
+            Some(register) => {
+                // Main node, then one node per stage, named `<name>/<stage>`
+                let main = add_node(CircuitNode::Register {
                     name: name.clone(),
-                    cost: REGISTER_COST,
-                };
-                let cni = ret.add_node(cn.clone());
-                if lut.insert(name.clone(), cni).is_some() {
-                    return Err(ParseError::MultipleDefinitions(cn));
-                }
-
-                adjacency.push((
-                    cni,
-                    vec![(
-                        s0.clone(),
-                        Channel {
-                            initial_phase: ChannelPhase::ReqNull,
-                            is_internal: true,
-                            virtual_delay: 10.0,
-                        },
-                    )],
-                ));
-
-                CircuitNode::Register {
-                    name: s0,
-                    cost: REGISTER_COST,
+                    cost: register.cost,
+                })?;
+                for stage in register.stages.iter() {
+                    add_node(CircuitNode::Register {
+                        name: format!("{}/{}", name.as_ref(), stage.suffix.as_ref()).into(),
+                        cost: stage.cost,
+                    })?;
                 }
+                (
+                    main,
+                    stage_node(main, register.output),
+                    Channel::new(register.output_phase, register.output_internal, 0.0),
+                )
             }
-            EntryType::Port => CircuitNode::Port(name),
-            EntryType::NullReg => CircuitNode::Register {
-                name,
-                cost: REGISTER_COST,
-            },
-            EntryType::ControlReg => CircuitNode::Register {
-                name,
-                cost: CONTROL_REG_COST,
-            },
         };
-        let ni = ret.add_node(c.clone());
-        if lut.insert(c.name().clone(), ni).is_some() {
-            return Err(ParseError::MultipleDefinitions(c));
-        }
-        adjacency.push((
-            ni,
-            adjacency_list
-                .into_iter()
-                .map(|(s, n)| {
-                    (
-                        s,
-                        if entry_type == EntryType::UnsafeReg {
-                            Channel {
-                                initial_phase: ChannelPhase::ReqData,
-                                is_internal: true,
-                                virtual_delay: n,
-                            }
-                        } else {
-                            Channel {
-                                initial_phase: ChannelPhase::AckNull,
-                                is_internal: false,
-                                virtual_delay: n,
-                            }
-                        },
-                    )
-                })
-                .collect(),
-        ));
+
+        mains.push(main);
+        outputs.insert(name.clone(), (output, default_channel));
     }
 
-    for (ni, adjacency_list) in adjacency.into_iter() {
-        for (x, channel) in adjacency_list.into_iter() {
-            if let Some(xi) = lut.get(&x) {
-                ret.add_edge(ni, *xi, channel);
-            } else {
-                return Err(ParseError::UndefinedElement(x.clone()));
+    let add_edges = |ret: &mut StructuralGraph,
+                     output: graph::NodeIndex,
+                     adjacency_list: &[Adjacency],
+                     default_channel: Channel| {
+        for adjacency in adjacency_list.iter() {
+            let Some(&target) = lut.get(&adjacency.target) else {
+                return Err(ParseError::UndefinedElement {
+                    name: adjacency.target.clone(),
+                    span: adjacency.span,
+                });
+            };
+            let channel = Channel {
+                virtual_delay: adjacency.virtual_delay,
+                ..default_channel
+            };
+            ret.add_edge(
+                output,
+                target,
+                channel.with_attributes(adjacency.attributes.clone())?,
+            );
+        }
+        Ok(())
+    };
+
+    // Each entry's internal channels and adjacency tuples, then the channel lists
+    let mut channel_lists = Vec::new();
+    let mut registers = mains.into_iter().zip(kinds.iter());
+    for item in items.iter() {
+        let entry = match item {
+            FlatItem::Entry(entry) => entry,
+            FlatItem::Channels(channels) => {
+                channel_lists.push(channels);
+                continue;
+            }
+        };
+        let (main, register) = registers.next().expect("one main node per entry");
+        if let Some(register) = register {
+            for internal in register.channels.iter() {
+                ret.add_edge(
+                    stage_node(main, internal.from),
+                    stage_node(main, internal.to),
+                    internal.channel,
+                );
             }
         }
+        let (output, default_channel) = outputs[&entry.name];
+        add_edges(&mut ret, output, &entry.adjacency_list, default_channel)?;
+    }
+
+    for Channels {
+        source,
+        adjacency_list,
+        source_span,
+        ..
+    } in channel_lists.into_iter()
+    {
+        let Some(&(output, default_channel)) = outputs.get(source) else {
+            return Err(ParseError::UndefinedElement {
+                name: source.clone(),
+                span: *source_span,
+            });
+        };
+        add_edges(&mut ret, output, adjacency_list, default_channel)?;
     }
 
     Ok(ret)
 }
 
+/// Node of stage `stage` of a register whose main node is `main`, or the main node for
+/// `None`: the stage nodes are added right after the main node, in order.
+fn stage_node(main: graph::NodeIndex, stage: Option<usize>) -> graph::NodeIndex {
+    stage.map_or(main, |i| graph::NodeIndex::new(main.index() + 1 + i))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -547,6 +813,219 @@ mod tests {
         assert_eq!(g.node_count(), 7);
     }
 
+    #[test]
+    fn parse_comments() {
+        let input = r#"
+            # Accumulator loop
+            Port "a" [("result", 10)]   // input operand
+            Port "b" [("result", 20)]
+            /* NullReg "old" [("acc", 5)]
+               kept for reference */
+            NullReg "result" [("acc", 30), /* ("b", 1), */ ("output", 40)]
+            DataReg "acc" [("result", 50)]
+            Port "output" []
+            "#;
+        let g = parse(input).expect("comments should be skipped");
+        assert_eq!(g.node_count(), 7);
+        assert_eq!(g.edge_count(), 7);
+    }
+
+    #[test]
+    fn parse_comment_markers_inside_names() {
+        let input = r#"
+            Port "a#1" [("b//x/*y", 10)]
+            Port "b//x/*y" []
+            "#;
+        let g = parse(input).expect("names with comment markers should parse");
+        let names: Vec<_> = g.node_indices().map(|n| g[n].name().to_string()).collect();
+        assert!(names.contains(&"a#1".to_string()));
+        assert!(names.contains(&"b//x/*y".to_string()));
+    }
+
+    #[test]
+    fn parse_channel_attributes() {
+        let input = r#"
+            Port "a" [("b", 10, phase = ReqData, internal, backward = 30, min = 4), ("c", 5,)]
+            Port "b" [("c", 7, internal = false, forward = 9)]
+            Port "c" []
+            "#;
+        let g = parse(input).unwrap();
+        let channel = |from: &str, to: &str| {
+            let ix = |name: &str| g.node_indices().find(|&n| g[n].name().as_ref() == name);
+            g[g.find_edge(ix(from).unwrap(), ix(to).unwrap()).unwrap()]
+        };
+
+        assert_eq!(
+            channel("a", "b"),
+            Channel {
+                initial_phase: ChannelPhase::ReqData,
+                is_internal: true,
+                virtual_delay: 10.0,
+                forward_delay: None,
+                backward_delay: Some(30.0),
+                min_delay: Some(4.0),
+            }
+        );
+        assert_eq!(
+            channel("a", "c"),
+            Channel::new(ChannelPhase::AckNull, false, 5.0)
+        );
+        assert_eq!(channel("b", "c").forward_delay, Some(9.0));
+        assert!(!channel("b", "c").is_internal);
+
+        // `max` fixes both weights, and a `min` above the virtual delay is only a lower bound
+        let g = parse("Port \"a\" [(\"b\", 10, min = 12, max = 15)]\nPort \"b\" []").unwrap();
+        let channel = g[g.edge_indices().next().unwrap()];
+        assert_eq!(
+            (
+                channel.forward_delay,
+                channel.backward_delay,
+                channel.min_delay
+            ),
+            (Some(15.0), Some(15.0), Some(12.0))
+        );
+    }
+
+    #[test]
+    fn parse_err_channel_attributes() {
+        let input = "Port \"a\" [(\"b\", 10, min = 1, min = 2)]\nPort \"b\" []";
+        let err = parse(input).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidAttribute { .. }));
+        assert_eq!(&input[err.span().start..err.span().end], "min = 2");
+
+        let input = "Port \"a\" [(\"b\", 10, min = 8, forward = 6)]\nPort \"b\" []";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid channel attribute: min delay 8 exceeds the forward delay 6"
+        );
+
+        let input = "Port \"a\" [(\"b\", 150, backward = 35, min = 40)]\nPort \"b\" []";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid channel attribute: min delay 40 exceeds the backward delay 35"
+        );
+        assert_eq!(&input[err.span().start..err.span().end], "min = 40");
+
+        let input = "Port \"a\" [(\"b\", 10, max = 20, min = 25)]\nPort \"b\" []";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid channel attribute: min delay 25 exceeds the max delay 20"
+        );
+
+        let input = "Port \"a\" [(\"b\", 10, backward = 5, max = 20)]\nPort \"b\" []";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid channel attribute: 'max' cannot be given with 'backward'"
+        );
+        assert_eq!(&input[err.span().start..err.span().end], "max = 20");
+
+        let input = "Port \"a\" [(\"b\", 10, phase = Idle)]\nPort \"b\" []";
+        assert!(matches!(parse(input), Err(ParseError::SyntaxError { .. })));
+    }
+
+    #[test]
+    fn parse_with_custom_register_kind() {
+        let library = ComponentLibrary::parse(
+            r#"
+            register CElemReg cost 30 {
+                stage "c" cost 40;
+                stage "o";
+                channel self -> "c" (7, phase = ReqNull);
+                channel "c" -> "o" (8, phase = ReqData);
+                output "o" (phase = AckData);
+            }
+            "#,
+        )
+        .unwrap();
+        let input = r#"
+            Port "a" [("r", 10)]
+            CElemReg "r" [("b", 20)]
+            Port "b" []
+            "#;
+        let g = parse_with_library(input, &library).unwrap();
+        let ix = |name: &str| {
+            g.node_indices()
+                .find(|&n| g[n].name().as_ref() == name)
+                .unwrap()
+        };
+
+        assert_eq!(g.node_count(), 5);
+        assert_eq!(g[ix("r")].base_cost(), 30);
+        assert_eq!(g[ix("r/c")].base_cost(), 40);
+        assert_eq!(g[ix("r/o")].base_cost(), 30);
+
+        let internal = g[g.find_edge(ix("r/c"), ix("r/o")).unwrap()];
+        assert_eq!(internal, Channel::new(ChannelPhase::ReqData, true, 8.0));
+        let output = g[g.find_edge(ix("r/o"), ix("b")).unwrap()];
+        assert_eq!(output, Channel::new(ChannelPhase::AckData, false, 20.0));
+
+        // The built-in parser does not know the kind
+        let err = parse(input).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown component type: CElemReg");
+        assert_eq!(
+            (
+                err.to_diagnostic(input).line,
+                err.to_diagnostic(input).column
+            ),
+            (3, 13)
+        );
+    }
+
+    #[test]
+    fn builtin_kinds_expand_as_before() {
+        let g = parse(
+            r#"
+            DataReg "d" [("u", 5)]
+            UnsafeReg "u" [("d", 6)]
+            "#,
+        )
+        .unwrap();
+        let names: Vec<_> = g.node_indices().map(|n| g[n].name().to_string()).collect();
+        assert_eq!(names, ["d", "d/s0", "d/s1", "u", "u/s0"]);
+
+        let channels: Vec<_> = g
+            .edge_indices()
+            .map(|e| {
+                let (s, t) = g.edge_endpoints(e).unwrap();
+                (g[s].name().to_string(), g[t].name().to_string(), g[e])
+            })
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                (
+                    "d".into(),
+                    "d/s0".into(),
+                    Channel::new(ChannelPhase::ReqNull, true, 10.0)
+                ),
+                (
+                    "d/s0".into(),
+                    "d/s1".into(),
+                    Channel::new(ChannelPhase::ReqData, true, 10.0)
+                ),
+                (
+                    "d/s1".into(),
+                    "u".into(),
+                    Channel::new(ChannelPhase::AckNull, false, 5.0)
+                ),
+                (
+                    "u".into(),
+                    "u/s0".into(),
+                    Channel::new(ChannelPhase::ReqNull, true, 10.0)
+                ),
+                (
+                    "u/s0".into(),
+                    "d".into(),
+                    Channel::new(ChannelPhase::ReqData, true, 6.0)
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn parse_err_undefined() {
         let input = r#"
@@ -556,12 +1035,35 @@ mod tests {
             DataReg "acc" [("result", 50)]
             "#;
         let result = parse(input);
-        assert!(matches!(result, Err(ParseError::UndefinedElement(_))));
-        if let Err(ParseError::UndefinedElement(node)) = result {
-            assert_eq!(node.as_ref(), "output");
+        assert!(matches!(result, Err(ParseError::UndefinedElement { .. })));
+        if let Err(ParseError::UndefinedElement { name, .. }) = result {
+            assert_eq!(name.as_ref(), "output");
         }
     }
 
+    #[test]
+    fn parse_err_undefined_location() {
+        let input = "Port \"a\" [(\"result\", 10)]\nNullReg \"result\" [(\"output\", 40)]\n";
+        let err = parse(input).unwrap_err();
+        let diag = err.to_diagnostic(input);
+
+        // Points at the offending adjacency tuple
+        assert_eq!((diag.line, diag.column), (2, 19));
+        assert_eq!(&input[err.span().start..err.span().end], "(\"output\", 40)");
+        assert_eq!(diag.message, "Undefined Element: output");
+    }
+
+    #[test]
+    fn parse_err_multiple_definitions_location() {
+        let input = "Port \"a\" []\nPort \"b\" []\nNullReg \"a\" []\n";
+        let err = parse(input).unwrap_err();
+        assert!(matches!(err, ParseError::MultipleDefinitions { .. }));
+
+        // Points at the name of the second definition
+        assert_eq!(err.to_diagnostic(input).line, 3);
+        assert_eq!(&input[err.span().start..err.span().end], "\"a\"");
+    }
+
     #[test]
     fn parse_err_syntax() {
         let input = r#"
@@ -572,7 +1074,10 @@ mod tests {
             Port "output" []
             "#;
         let result = parse(input);
-        assert!(matches!(result, Err(ParseError::SyntaxError(_))));
+        assert!(matches!(result, Err(ParseError::SyntaxError { .. })));
+
+        let diag = result.unwrap_err().to_diagnostic(input);
+        assert_eq!((diag.line, diag.column), (4, 43));
     }
 
     #[test]
@@ -667,16 +1172,19 @@ Port "b" []"#;
     }
 
     #[test]
-    fn parse_err_reserved_port_prefix() {
-        // A non-port component must not use the reserved `port:` prefix (it would
-        // round-trip through the HBCN format as a port).
+    fn parse_register_with_port_prefix() {
+        // The HBCN format declares the nodes `expand` writes, so a register may carry the
+        // `port:` prefix that marks undeclared ports
         let input = r#"
             Port "a" [("port:b", 10)]
             NullReg "port:b" [("out", 20)]
             Port "out" []
             "#;
-        let result = parse(input);
-        assert!(matches!(result, Err(ParseError::ReservedNamePrefix(_))));
+        let g = parse(input).unwrap();
+        assert!(g.node_indices().any(|idx| matches!(
+            &g[idx],
+            CircuitNode::Register { name, .. } if name.as_ref() == "port:b"
+        )));
     }
 
     #[test]
@@ -689,7 +1197,10 @@ Port "b" []"#;
             Port "out" []
             "#;
         let result = parse(input);
-        assert!(matches!(result, Err(ParseError::MultipleDefinitions(_))));
+        assert!(matches!(
+            result,
+            Err(ParseError::MultipleDefinitions { .. })
+        ));
     }
 
     #[test]
@@ -699,6 +1210,149 @@ Port "b" []"#;
         let huge = "9".repeat(350);
         let input = format!("Port \"a\" [(\"b\", {huge})]\nPort \"b\" []");
         let result = parse(&input);
-        assert!(matches!(result, Err(ParseError::SyntaxError(_))));
+        assert!(matches!(result, Err(ParseError::SyntaxError { .. })));
+    }
+
+    /// Name pairs of every channel, for comparing graphs with different node orders.
+    fn channel_names(g: &StructuralGraph) -> Vec<(String, String, f64)> {
+        let mut channels: Vec<_> = g
+            .edge_indices()
+            .map(|e| {
+                let (s, t) = g.edge_endpoints(e).unwrap();
+                (
+                    g[s].name().to_string(),
+                    g[t].name().to_string(),
+                    g[e].virtual_delay,
+                )
+            })
+            .collect();
+        channels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        channels
+    }
+
+    #[test]
+    fn parse_module_instances() {
+        let hierarchical = parse(
+            r#"
+            module Lane ("in", "out") {
+                "in" [("mul", 10)]
+                NullReg "mul" [("acc", 20)]
+                DataReg "acc" [("acc", 5), ("out", 30)]
+            }
+
+            Port "a" [("b", 1)]
+            Lane "lane0" ("a", "b")
+            Lane "lane1" ("out" = "b", "in" = "a")
+            Port "b" []
+            "#,
+        )
+        .unwrap();
+
+        let flat = parse(
+            r#"
+            Port "a" [("b", 1), ("lane0/mul", 10), ("lane1/mul", 10)]
+            NullReg "lane0/mul" [("lane0/acc", 20)]
+            DataReg "lane0/acc" [("lane0/acc", 5), ("b", 30)]
+            NullReg "lane1/mul" [("lane1/acc", 20)]
+            DataReg "lane1/acc" [("lane1/acc", 5), ("b", 30)]
+            Port "b" []
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(hierarchical.node_count(), flat.node_count());
+        assert_eq!(channel_names(&hierarchical), channel_names(&flat));
+    }
+
+    #[test]
+    fn parse_nested_modules() {
+        let g = parse(
+            r#"
+            Top "top" ("x")
+            Port "x" []
+
+            module Top ("p") {
+                Leaf "l0" ("p")
+                Leaf "l1" ("p")
+            }
+
+            module Leaf ("q") {
+                "q" [("r", 3)]
+                UnsafeReg "r" [("q", 4)]
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut names: Vec<_> = g.node_indices().map(|n| g[n].name().to_string()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["top/l0/r", "top/l0/r/s0", "top/l1/r", "top/l1/r/s0", "x"]
+        );
+
+        // A channel driven by a module port takes the defaults of the bound component
+        let port_channel = g
+            .edge_indices()
+            .find(|&e| g[g.edge_endpoints(e).unwrap().1].name().as_ref() == "top/l1/r")
+            .unwrap();
+        assert_eq!(
+            g[port_channel],
+            Channel::new(ChannelPhase::AckNull, false, 3.0)
+        );
+    }
+
+    #[test]
+    fn parse_module_errors() {
+        let cases = [
+            (
+                "module M (\"a\") {}\nM \"m\" (\"x\", \"y\")\nPort \"x\" []\nPort \"y\" []",
+                "Invalid module: module 'M' has 1 port(s) but instance 'm' binds 2",
+                (2, 1),
+            ),
+            (
+                "module M (\"a\") {}\nM \"m\" (\"b\" = \"x\")\nPort \"x\" []",
+                "Invalid module: module 'M' has no port 'b'",
+                (2, 8),
+            ),
+            (
+                "module M (\"a\", \"b\") {}\nM \"m\" (\"a\" = \"x\")\nPort \"x\" []",
+                "Invalid module: port 'b' of module 'M' is not bound by instance 'm'",
+                (2, 1),
+            ),
+            (
+                "module M (\"a\") {\n    M \"inner\" (\"a\")\n}\nPort \"x\" []\nM \"m\" (\"x\")",
+                "Invalid module: recursive instantiation of module 'M'",
+                (2, 5),
+            ),
+            (
+                "module M (\"a\") {\n    NullReg \"a\" []\n}\nPort \"x\" []\nM \"m\" (\"x\")",
+                "Invalid module: component 'a' shadows a port of module 'M'",
+                (2, 13),
+            ),
+            (
+                "module DataReg () {}",
+                "Invalid module: module 'DataReg' shadows a component type",
+                (1, 8),
+            ),
+            (
+                "module M () {}\nmodule M () {}",
+                "Invalid module: module 'M' defined more than once",
+                (2, 8),
+            ),
+            ("N \"n\" ()", "Unknown component type: N", (1, 1)),
+            (
+                "module M (\"a\") {\n    \"a\" [(\"nowhere\", 1)]\n}\nPort \"x\" []\nM \"m\" (\"x\")",
+                "Undefined Element: m/nowhere",
+                (2, 10),
+            ),
+        ];
+
+        for (input, message, location) in cases {
+            let err = parse(input).unwrap_err();
+            let diag = err.to_diagnostic(input);
+            assert_eq!(err.to_string(), message, "{}", input);
+            assert_eq!((diag.line, diag.column), location, "{}", input);
+        }
     }
 }
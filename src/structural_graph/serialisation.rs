@@ -0,0 +1,519 @@
+//! Serialisation of structural graphs back to the `.graph` text format.
+//!
+//! [`serialise_structural_graph`] writes one entry per component, in node order, such that
+//! [`parse`](super::parse) reads back an equivalent graph: the same nodes (name, type and
+//! cost) and the same channels (endpoints, phase, delays and attributes).
+//!
+//! A structural graph does not record which register kind a node was expanded from, so
+//! registers are matched against the kinds of a [`ComponentLibrary`]: a register `r` folds
+//! back into a single `DataReg "r"` entry when its `r/s0` and `r/s1` stages and the internal
+//! channels between them are exactly those `DataReg` expands to. Kinds with more stages are
+//! tried first; a register that matches none of them is written as a stage-less kind of the
+//! same cost (`NullReg`, `ControlReg`). Channels that differ from the defaults of their source
+//! are written with channel attributes.
+//!
+//! # Example
+//!
+//! ```
+//! use hbcn::structural_graph::{parse, serialise_structural_graph};
+//!
+//! let graph = parse(r#"
+//!     Port "a" [("r", 10)]
+//!     DataReg "r" [("b", 20, min = 5)]
+//!     Port "b" []
+//! "#).unwrap();
+//!
+//! let text = serialise_structural_graph(&graph).unwrap();
+//! assert_eq!(
+//!     text,
+//!     "Port \"a\" [(\"r\", 10)]\nDataReg \"r\" [(\"b\", 20, min = 5)]\nPort \"b\" []\n"
+//! );
+//! ```
+
+use super::{Channel, ChannelPhase, CircuitNode, ComponentLibrary, StructuralGraph, Symbol};
+use petgraph::{
+    stable_graph::{EdgeIndex, NodeIndex},
+    visit::EdgeRef,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+/// Errors that prevent a structural graph from being written as `.graph` text.
+#[derive(Debug, PartialEq)]
+pub enum SerialiseError {
+    /// A register matches no kind of the library, not even a stage-less kind of its cost.
+    UnknownRegister { name: Symbol, cost: usize },
+    /// A node name contains a double quote, which the format has no way to escape.
+    InvalidName { name: Symbol },
+    /// A channel delay is negative or not finite, which the format cannot express.
+    InvalidDelay {
+        from: Symbol,
+        to: Symbol,
+        delay: f64,
+    },
+}
+
+impl fmt::Display for SerialiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerialiseError::UnknownRegister { name, cost } => write!(
+                f,
+                "Register \"{}\" with cost {} matches no register kind",
+                name, cost
+            ),
+            SerialiseError::InvalidName { name } => {
+                write!(f, "Name '{}' contains a double quote", name)
+            }
+            SerialiseError::InvalidDelay { from, to, delay } => write!(
+                f,
+                "Channel \"{}\" -> \"{}\" has unrepresentable delay {}",
+                from, to, delay
+            ),
+        }
+    }
+}
+
+impl Error for SerialiseError {}
+
+/// A component to write: its type, name, output node and default output channel.
+struct Component<'a> {
+    kind: &'a str,
+    output: NodeIndex,
+    default_channel: Channel,
+    /// Internal channels of a folded register, left out of its adjacency list.
+    internal: Vec<EdgeIndex>,
+    /// Stage nodes of a folded register.
+    stages: Vec<NodeIndex>,
+}
+
+/// Serialise a structural graph to `.graph` text, folding registers into the built-in kinds.
+///
+/// See the [module documentation](self) for how registers are matched to kinds.
+pub fn serialise_structural_graph(graph: &StructuralGraph) -> Result<String, SerialiseError> {
+    serialise_structural_graph_with_library(graph, ComponentLibrary::builtin())
+}
+
+/// Serialise a structural graph to `.graph` text, folding registers into the kinds of
+/// `library`.
+///
+/// The counterpart of [`parse_with_library`](super::parse_with_library).
+pub fn serialise_structural_graph_with_library(
+    graph: &StructuralGraph,
+    library: &ComponentLibrary,
+) -> Result<String, SerialiseError> {
+    let mut out = String::new();
+    serialise_structural_graph_to(graph, library, &mut out)?;
+    Ok(out)
+}
+
+/// Serialise a structural graph into the provided writer.
+///
+/// See [`serialise_structural_graph_with_library`].
+pub fn serialise_structural_graph_to<W: fmt::Write>(
+    graph: &StructuralGraph,
+    library: &ComponentLibrary,
+    writer: &mut W,
+) -> Result<(), SerialiseError> {
+    let components = fold_registers(graph, library)?;
+
+    // Outgoing channels of each node, in edge order
+    let mut outgoing: HashMap<NodeIndex, Vec<EdgeIndex>> = HashMap::new();
+    for edge in graph.edge_indices() {
+        let (source, _) = graph.edge_endpoints(edge).unwrap();
+        outgoing.entry(source).or_default().push(edge);
+    }
+
+    for node in graph.node_indices() {
+        let Some(component) = components.get(&node) else {
+            continue;
+        };
+        let name = checked_name(graph[node].name())?;
+
+        let mut adjacency = Vec::new();
+        for &edge in outgoing.get(&component.output).into_iter().flatten() {
+            if component.internal.contains(&edge) {
+                continue;
+            }
+            let (_, target) = graph.edge_endpoints(edge).unwrap();
+            let target = checked_name(graph[target].name())?;
+            adjacency.push(adjacency_tuple(
+                name,
+                target,
+                &graph[edge],
+                &component.default_channel,
+            )?);
+        }
+
+        // Infallible for String; other writers only fail on I/O, which has no error variant
+        let _ = writeln!(
+            writer,
+            "{} \"{}\" [{}]",
+            component.kind,
+            name,
+            adjacency.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The kind each component of a structural graph is written as, keyed by its main node, with
+/// the stage nodes of folded registers.
+///
+/// Registers are matched to the kinds of `library` as for
+/// [`serialise_structural_graph_with_library`].
+pub(crate) fn component_kinds<'a>(
+    graph: &StructuralGraph,
+    library: &'a ComponentLibrary,
+) -> Result<HashMap<NodeIndex, (&'a str, Vec<NodeIndex>)>, SerialiseError> {
+    Ok(fold_registers(graph, library)?
+        .into_iter()
+        .map(|(node, component)| (node, (component.kind, component.stages)))
+        .collect())
+}
+
+fn checked_name(name: &Symbol) -> Result<&Symbol, SerialiseError> {
+    if name.contains('"') {
+        Err(SerialiseError::InvalidName { name: name.clone() })
+    } else {
+        Ok(name)
+    }
+}
+
+/// Format one `("target", delay, attributes...)` tuple relative to the source's defaults.
+fn adjacency_tuple(
+    from: &Symbol,
+    to: &Symbol,
+    channel: &Channel,
+    default: &Channel,
+) -> Result<String, SerialiseError> {
+    let delay = |delay: f64| {
+        if delay.is_finite() && delay >= 0.0 {
+            Ok(delay)
+        } else {
+            Err(SerialiseError::InvalidDelay {
+                from: from.clone(),
+                to: to.clone(),
+                delay,
+            })
+        }
+    };
+
+    let mut tuple = format!("(\"{}\", {}", to, delay(channel.virtual_delay)?);
+    if channel.initial_phase != default.initial_phase {
+        tuple += &format!(", phase = {}", phase_name(channel.initial_phase));
+    }
+    if channel.is_internal != default.is_internal {
+        tuple += if channel.is_internal {
+            ", internal"
+        } else {
+            ", internal = false"
+        };
+    }
+    if let Some(forward) = channel.forward_delay {
+        tuple += &format!(", forward = {}", delay(forward)?);
+    }
+    if let Some(backward) = channel.backward_delay {
+        tuple += &format!(", backward = {}", delay(backward)?);
+    }
+    if let Some(min) = channel.min_delay {
+        tuple += &format!(", min = {}", delay(min)?);
+    }
+    tuple.push(')');
+    Ok(tuple)
+}
+
+fn phase_name(phase: ChannelPhase) -> &'static str {
+    match phase {
+        ChannelPhase::AckNull => "AckNull",
+        ChannelPhase::ReqData => "ReqData",
+        ChannelPhase::AckData => "AckData",
+        ChannelPhase::ReqNull => "ReqNull",
+    }
+}
+
+/// Match every node to the component it is written as; stage nodes of folded registers
+/// have no entry.
+fn fold_registers<'a>(
+    graph: &StructuralGraph,
+    library: &'a ComponentLibrary,
+) -> Result<HashMap<NodeIndex, Component<'a>>, SerialiseError> {
+    let lut: HashMap<&Symbol, NodeIndex> = graph
+        .node_indices()
+        .map(|node| (graph[node].name(), node))
+        .collect();
+
+    // Kinds with more stages first, so a DataReg is not mistaken for three NullRegs
+    let mut kinds: Vec<_> = library
+        .kind_names()
+        .map(|name| (name.as_ref(), library.get(name).unwrap()))
+        .collect();
+    kinds.sort_by(|(a_name, a), (b_name, b)| {
+        b.stages
+            .len()
+            .cmp(&a.stages.len())
+            .then_with(|| a_name.cmp(b_name))
+    });
+
+    // A register's stages are named after it, so visiting names in order sees every
+    // register before its stages, whatever the node order.
+    let mut nodes: Vec<NodeIndex> = graph.node_indices().collect();
+    nodes.sort_by(|&a, &b| graph[a].name().cmp(graph[b].name()));
+
+    let mut components = HashMap::new();
+    let mut stage_nodes: HashSet<NodeIndex> = HashSet::new();
+
+    for main in nodes {
+        if stage_nodes.contains(&main) {
+            continue;
+        }
+        let (name, cost) = match &graph[main] {
+            CircuitNode::Port(_) => {
+                components.insert(
+                    main,
+                    Component {
+                        kind: "Port",
+                        output: main,
+                        default_channel: Channel::new(ChannelPhase::AckNull, false, 0.0),
+                        internal: Vec::new(),
+                        stages: Vec::new(),
+                    },
+                );
+                continue;
+            }
+            CircuitNode::Register { name, cost } => (name, *cost),
+        };
+
+        let component = kinds
+            .iter()
+            .find_map(|&(kind_name, kind)| {
+                if kind.cost != cost {
+                    return None;
+                }
+
+                // Every stage must be an unclaimed register of the stage's cost
+                let stages = kind
+                    .stages
+                    .iter()
+                    .map(|stage| {
+                        let name: Symbol = format!("{}/{}", name, stage.suffix).into();
+                        lut.get(&name).copied().filter(|&node| {
+                            !stage_nodes.contains(&node)
+                                && !components.contains_key(&node)
+                                && graph[node]
+                                    == CircuitNode::Register {
+                                        name: name.clone(),
+                                        cost: stage.cost,
+                                    }
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                let node = |stage: Option<usize>| stage.map_or(main, |i| stages[i]);
+
+                // Every internal channel must be present, once each
+                let mut internal: Vec<EdgeIndex> = Vec::new();
+                for channel in kind.channels.iter() {
+                    let edge = graph.edges(node(channel.from)).find(|edge| {
+                        edge.target() == node(channel.to)
+                            && !internal.contains(&edge.id())
+                            && *edge.weight() == channel.channel
+                    })?;
+                    internal.push(edge.id());
+                }
+
+                // Only the output node may drive anything beyond the internal channels
+                let output = node(kind.output);
+                let stray = std::iter::once(main)
+                    .chain(stages.iter().copied())
+                    .any(|n| {
+                        n != output && graph.edges(n).any(|edge| !internal.contains(&edge.id()))
+                    });
+                if stray {
+                    return None;
+                }
+
+                Some(Component {
+                    kind: kind_name,
+                    output,
+                    default_channel: Channel::new(kind.output_phase, kind.output_internal, 0.0),
+                    internal,
+                    stages,
+                })
+            })
+            .ok_or_else(|| SerialiseError::UnknownRegister {
+                name: name.clone(),
+                cost,
+            })?;
+
+        stage_nodes.extend(component.stages.iter().copied());
+        components.insert(main, component);
+    }
+
+    Ok(components)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structural_graph::{parse, parse_with_library};
+
+    /// Nodes and channels of a graph by name, independent of node and edge order.
+    fn contents(graph: &StructuralGraph) -> (Vec<CircuitNode>, Vec<String>) {
+        let mut nodes: Vec<_> = graph.node_indices().map(|n| graph[n].clone()).collect();
+        nodes.sort();
+        let mut channels: Vec<_> = graph
+            .edge_indices()
+            .map(|edge| {
+                let (s, t) = graph.edge_endpoints(edge).unwrap();
+                format!(
+                    "{} -> {}: {:?}",
+                    graph[s].name(),
+                    graph[t].name(),
+                    graph[edge]
+                )
+            })
+            .collect();
+        channels.sort();
+        (nodes, channels)
+    }
+
+    fn assert_round_trip(graph: &StructuralGraph) -> String {
+        let text = serialise_structural_graph(graph).unwrap();
+        let reparsed = parse(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(contents(graph), contents(&reparsed), "{}", text);
+        text
+    }
+
+    #[test]
+    fn round_trip_examples() {
+        let examples = [
+            include_str!("../../examples/structural_graphs/ARV.graph"),
+            include_str!("../../examples/structural_graphs/cyclic.graph"),
+            include_str!("../../examples/structural_graphs/loop.graph"),
+            include_str!("../../examples/structural_graphs/mac4.graph"),
+            include_str!("../../examples/structural_graphs/mac5.graph"),
+            include_str!("../../examples/structural_graphs/mac6.graph"),
+            include_str!("../../examples/structural_graphs/test.graph"),
+            include_str!("../../examples/structural_graphs/lanes.graph"),
+        ];
+        for example in examples {
+            let graph = parse(example).unwrap();
+            let text = assert_round_trip(&graph);
+            // Sub-stages fold back into their register instead of being written out
+            assert!(!text.contains("/s0\" ["), "{}", text);
+        }
+    }
+
+    #[test]
+    fn folds_sub_stages() {
+        let graph = parse(
+            r#"
+            Port "a" [("d", 10), ("u", 5, phase = ReqNull, forward = 7)]
+            DataReg "d" [("c", 20), ("d/s0", 3)]
+            UnsafeReg "u" [("c", 15, internal = false)]
+            ControlReg "c" [("a", 1)]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            assert_round_trip(&graph),
+            concat!(
+                "Port \"a\" [(\"d\", 10), (\"u\", 5, phase = ReqNull, forward = 7)]\n",
+                "DataReg \"d\" [(\"c\", 20), (\"d/s0\", 3)]\n",
+                "UnsafeReg \"u\" [(\"c\", 15, internal = false)]\n",
+                "ControlReg \"c\" [(\"a\", 1)]\n",
+            )
+        );
+    }
+
+    #[test]
+    fn modified_registers_are_not_folded() {
+        let mut graph = parse(
+            r#"
+            DataReg "d" [("d", 10)]
+            "#,
+        )
+        .unwrap();
+
+        // A channel leaving a non-output stage cannot be written on a DataReg entry; the
+        // register and its first stage still fold as an UnsafeReg
+        let s0 = graph
+            .node_indices()
+            .find(|&n| graph[n].name().as_ref() == "d/s0")
+            .unwrap();
+        let main = graph
+            .node_indices()
+            .find(|&n| graph[n].name().as_ref() == "d")
+            .unwrap();
+        graph.add_edge(s0, main, Channel::new(ChannelPhase::AckNull, false, 4.0));
+
+        assert_eq!(
+            assert_round_trip(&graph),
+            concat!(
+                "UnsafeReg \"d\" [(\"d/s1\", 10), (\"d\", 4, phase = AckNull, internal = false)]\n",
+                "NullReg \"d/s1\" [(\"d\", 10)]\n",
+            )
+        );
+    }
+
+    #[test]
+    fn custom_library_kinds() {
+        let library = ComponentLibrary::parse(
+            r#"
+            register CReg cost 20 {
+                stage "c";
+                channel self -> "c" (5, phase = ReqData);
+                output "c" (phase = AckData);
+            }
+            "#,
+        )
+        .unwrap();
+        let input = r#"
+            Port "a" [("r", 10)]
+            CReg "r" [("a", 10), ("b", 2, phase = AckNull)]
+            Port "b" []
+            "#;
+        let graph = parse_with_library(input, &library).unwrap();
+
+        let text = serialise_structural_graph_with_library(&graph, &library).unwrap();
+        assert!(
+            text.contains("CReg \"r\" [(\"a\", 10), (\"b\", 2, phase = AckNull)]"),
+            "{}",
+            text
+        );
+        let reparsed = parse_with_library(&text, &library).unwrap();
+        assert_eq!(contents(&graph), contents(&reparsed));
+
+        // The built-in kinds have no register of cost 20
+        assert_eq!(
+            serialise_structural_graph(&graph),
+            Err(SerialiseError::UnknownRegister {
+                name: "r".into(),
+                cost: 20
+            })
+        );
+    }
+
+    #[test]
+    fn unrepresentable_graphs() {
+        let mut graph = StructuralGraph::new();
+        let a = graph.add_node(CircuitNode::Port("a".into()));
+        let b = graph.add_node(CircuitNode::Port("b\"".into()));
+        graph.add_edge(a, b, Channel::new(ChannelPhase::AckNull, false, 1.0));
+        assert_eq!(
+            serialise_structural_graph(&graph),
+            Err(SerialiseError::InvalidName { name: "b\"".into() })
+        );
+
+        let mut graph = StructuralGraph::new();
+        let a = graph.add_node(CircuitNode::Port("a".into()));
+        graph.add_edge(a, a, Channel::new(ChannelPhase::AckNull, false, -1.0));
+        assert!(matches!(
+            serialise_structural_graph(&graph),
+            Err(SerialiseError::InvalidDelay { .. })
+        ));
+    }
+}
@@ -3,6 +3,8 @@
 //! These tests use the library API directly instead of calling cargo run,
 //! which is much faster and more efficient.
 
+use hbcn::analyse::hbcn::Engine;
+use hbcn::analyse::vcd::TimescaleUnit;
 use hbcn::{AnalyseArgs, ConstrainArgs, analyse_main, constrain_main};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -40,10 +42,21 @@ fn run_hbcn_constrain(
         csv: csv.map(|p| p.to_path_buf()),
         rpt: rpt.map(|p| p.to_path_buf()),
         vcd: vcd.map(|p| p.to_path_buf()),
+        vcd_periods: 1,
+        vcd_timescale: TimescaleUnit::PS,
+        vcd_channels: false,
         no_proportional,
         no_forward_completion,
         forward_margin,
         backward_margin,
+        library: None,
+        liberty: None,
+        liberty_cells: Default::default(),
+        virtual_unit: None,
+        json: None,
+        cache: None,
+        html: None,
+        report_tcl: None,
     };
 
     constrain_main(args).map_err(|e| e.into())
@@ -72,10 +85,21 @@ fn run_hbcn_constrain_hbcn_format(
         csv: csv.map(|p| p.to_path_buf()),
         rpt: rpt.map(|p| p.to_path_buf()),
         vcd: vcd.map(|p| p.to_path_buf()),
+        vcd_periods: 1,
+        vcd_timescale: TimescaleUnit::PS,
+        vcd_channels: false,
         no_proportional,
         no_forward_completion: true, // HBCN format doesn't use forward_completion option
         forward_margin,
         backward_margin,
+        library: None,
+        liberty: None,
+        liberty_cells: Default::default(),
+        virtual_unit: None,
+        json: None,
+        cache: None,
+        html: None,
+        report_tcl: None,
     };
 
     constrain_main(args).map_err(|e| e.into())
@@ -93,9 +117,27 @@ fn run_hbcn_analyse(
         input: input.to_path_buf(),
         structural,
         depth: false,
+        engine: Engine::Lp,
+        integer_cycle_time: false,
+        corners: false,
+        monte_carlo: None,
+        sigma: None,
+        seed: 0,
+        top_k: None,
+        slack_window: None,
         report: report.map(|p| p.to_path_buf()),
         vcd: vcd.map(|p| p.to_path_buf()),
+        vcd_periods: 1,
+        vcd_timescale: TimescaleUnit::PS,
+        vcd_channels: false,
         dot: dot.map(|p| p.to_path_buf()),
+        library: None,
+        liberty: None,
+        liberty_cells: Default::default(),
+        virtual_unit: None,
+        json: None,
+        cache: None,
+        html: None,
     };
 
     analyse_main(args).map_err(|e| e.into())
@@ -107,9 +149,27 @@ fn run_hbcn_depth(input: &Path, report: Option<&Path>) -> Result<(), Box<dyn std
         input: input.to_path_buf(),
         structural: true,
         depth: true,
+        engine: Engine::Lp,
+        integer_cycle_time: false,
+        corners: false,
+        monte_carlo: None,
+        sigma: None,
+        seed: 0,
+        top_k: None,
+        slack_window: None,
         report: report.map(|p| p.to_path_buf()),
         vcd: None,
+        vcd_periods: 1,
+        vcd_timescale: TimescaleUnit::PS,
+        vcd_channels: false,
         dot: None,
+        library: None,
+        liberty: None,
+        liberty_cells: Default::default(),
+        virtual_unit: None,
+        json: None,
+        cache: None,
+        html: None,
     };
 
     analyse_main(args).map_err(|e| e.into())
@@ -864,6 +924,322 @@ Port "c" []
         assert!(!dot_content.is_empty(), "DOT file should not be empty");
     }
 
+    /// Test analysis with an HTML report listing every critical cycle
+    #[test]
+    fn test_analyse_with_html_output() {
+        let graph_content = r#"Port "a" [("b", 20)]
+DataReg "b" [("c", 15)]
+Port "c" []
+"#;
+
+        let (_temp_dir, input_path) = create_test_file(graph_content);
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+        let html_path = temp_output_dir.path().join("test.html");
+        let log_path = temp_output_dir.path().join("test.log");
+
+        let result = analyse_main(AnalyseArgs {
+            input: input_path,
+            structural: true,
+            depth: false,
+            engine: Engine::Lp,
+            integer_cycle_time: false,
+            corners: false,
+            monte_carlo: None,
+            sigma: None,
+            seed: 0,
+            top_k: None,
+            slack_window: None,
+            report: Some(log_path.clone()),
+            vcd: None,
+            vcd_periods: 1,
+            vcd_timescale: TimescaleUnit::PS,
+            vcd_channels: false,
+            dot: None,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            cache: None,
+            html: Some(html_path.clone()),
+        });
+        assert!(
+            result.is_ok(),
+            "Analysis with HTML should succeed: {:?}",
+            result
+        );
+
+        let log = fs::read_to_string(&log_path).expect("Failed to read report");
+        let html = fs::read_to_string(&html_path).expect("Failed to read HTML report");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<th class=\"name\">Worst virtual cycle-time</th>"));
+        // One expandable row per cycle of the text report
+        assert_eq!(
+            html.matches("<tbody class=\"cycle\">").count(),
+            log.matches("\nCycle ").count()
+        );
+    }
+
+    /// The maximum cycle ratio engine analyses the loop example without an LP solver.
+    #[test]
+    fn test_analyse_with_mcr_engine() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+        let log_path = temp_output_dir.path().join("loop.log");
+
+        analyse_main(AnalyseArgs {
+            input: root.join("examples/hbcn/loop.hbcn"),
+            structural: false,
+            depth: false,
+            engine: Engine::Mcr,
+            integer_cycle_time: false,
+            corners: false,
+            monte_carlo: None,
+            sigma: None,
+            seed: 0,
+            top_k: None,
+            slack_window: None,
+            report: Some(log_path.clone()),
+            vcd: None,
+            vcd_periods: 1,
+            vcd_timescale: TimescaleUnit::PS,
+            vcd_channels: false,
+            dot: None,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            cache: None,
+            html: None,
+        })
+        .expect("Analysis with the MCR engine should succeed");
+
+        let log = fs::read_to_string(&log_path).expect("Failed to read report");
+        assert!(log.starts_with("Worst cycle-time: 140\n"), "{}", log);
+        assert!(log.contains("\nCycle 0: delay - slack = 140 "), "{}", log);
+    }
+
+    /// Corner analysis reports each corner's cycle time and critical cycle
+    #[test]
+    fn test_analyse_with_corners() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+        let log_path = temp_output_dir.path().join("corners.log");
+
+        analyse_main(AnalyseArgs {
+            input: root.join("examples/hbcn/corners.hbcn"),
+            structural: false,
+            depth: false,
+            engine: Engine::Mcr,
+            integer_cycle_time: false,
+            corners: true,
+            monte_carlo: None,
+            sigma: None,
+            seed: 0,
+            top_k: None,
+            slack_window: None,
+            report: Some(log_path.clone()),
+            vcd: None,
+            vcd_periods: 1,
+            vcd_timescale: TimescaleUnit::PS,
+            vcd_channels: false,
+            dot: None,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            cache: None,
+            html: None,
+        })
+        .expect("Corner analysis should succeed");
+
+        let log = fs::read_to_string(&log_path).expect("Failed to read report");
+        // The default analysis still prices every place at its max delay
+        assert!(log.starts_with("Worst cycle-time: 36\n"), "{}", log);
+        assert!(log.contains("\nCorner cycle-times:\n"), "{}", log);
+        for (corner, cycle_time) in [("min", 20), ("mixed", 22), ("max", 36)] {
+            assert!(
+                log.contains(&format!("| {:<6} | {:<10} |", corner, cycle_time)),
+                "{}",
+                log
+            );
+            assert!(
+                log.contains(&format!(
+                    "\n{} corner critical cycle: delay - slack = {} ",
+                    corner, cycle_time
+                )),
+                "{}",
+                log
+            );
+        }
+    }
+
+    /// Monte Carlo analysis reports its statistics and is reproducible from its seed
+    #[test]
+    fn test_analyse_monte_carlo() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+
+        let run = |name: &str, seed: u64| {
+            let log_path = temp_output_dir.path().join(name);
+            analyse_main(AnalyseArgs {
+                input: root.join("examples/hbcn/corners.hbcn"),
+                structural: false,
+                depth: false,
+                engine: Engine::Mcr,
+                integer_cycle_time: false,
+                corners: false,
+                monte_carlo: Some(500),
+                sigma: None,
+                seed,
+                top_k: None,
+                slack_window: None,
+                report: Some(log_path.clone()),
+                vcd: None,
+                vcd_periods: 1,
+                vcd_timescale: TimescaleUnit::PS,
+                vcd_channels: false,
+                dot: None,
+                library: None,
+                liberty: None,
+                liberty_cells: Default::default(),
+                virtual_unit: None,
+                json: None,
+                cache: None,
+                html: None,
+            })
+            .expect("Monte Carlo analysis should succeed");
+            fs::read_to_string(&log_path).expect("Failed to read report")
+        };
+
+        let log = run("first.log", 1);
+        assert!(
+            log.contains("\nMonte Carlo cycle-time (500 samples, min/max ranges, seed 1):\n"),
+            "{}",
+            log
+        );
+        for label in ["Mean", "Std dev", "Median", "P95", "Max"] {
+            assert!(log.contains(&format!("| {} ", label)), "{}", log);
+        }
+        assert!(
+            log.contains("\nMonte Carlo cycle-time histogram:\n"),
+            "{}",
+            log
+        );
+        // Either channel sets the cycle time in some of the samples
+        assert!(log.contains("| +{a} => +{b} |"), "{}", log);
+        assert!(log.contains("| +{b} => +{c} |"), "{}", log);
+
+        assert_eq!(log, run("second.log", 1));
+        assert_ne!(log, run("third.log", 2));
+    }
+
+    /// Fractional delays report the exact cycle time, rounded up only on request
+    #[test]
+    fn test_analyse_integer_cycle_time() {
+        let (_temp_dir, input_path) = create_test_file(
+            "* +{port:a} => +{r} : 1.5\n\
+             +{r} => -{port:a} : 2\n\
+             -{port:a} => -{r} : 3\n\
+             -{r} => +{port:a} : 1.25\n",
+        );
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+
+        for (integer_cycle_time, expected) in [(false, "7.75"), (true, "8")] {
+            let log_path = temp_output_dir.path().join("ring.log");
+            analyse_main(AnalyseArgs {
+                input: input_path.clone(),
+                structural: false,
+                depth: false,
+                engine: Engine::Mcr,
+                integer_cycle_time,
+                corners: false,
+                monte_carlo: None,
+                sigma: None,
+                seed: 0,
+                top_k: None,
+                slack_window: None,
+                report: Some(log_path.clone()),
+                vcd: None,
+                vcd_periods: 1,
+                vcd_timescale: TimescaleUnit::PS,
+                vcd_channels: false,
+                dot: None,
+                library: None,
+                liberty: None,
+                liberty_cells: Default::default(),
+                virtual_unit: None,
+                json: None,
+                cache: None,
+                html: None,
+            })
+            .expect("Analysis should succeed");
+
+            let log = fs::read_to_string(&log_path).expect("Failed to read report");
+            assert!(
+                log.starts_with(&format!("Worst cycle-time: {}\n", expected)),
+                "{}",
+                log
+            );
+            // The critical cycle's delays stay those of the places either way
+            assert!(log.contains("\nCycle 0: delay - slack = 7.75 "), "{}", log);
+        }
+    }
+
+    /// Test the top-K cycle enumeration on the corners example
+    #[test]
+    fn test_analyse_top_k_cycles() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_output_dir = TempDir::new().expect("Failed to create temp dir");
+        let log_path = temp_output_dir.path().join("corners.log");
+
+        analyse_main(AnalyseArgs {
+            input: root.join("examples/hbcn/corners.hbcn"),
+            structural: false,
+            depth: false,
+            engine: Engine::Mcr,
+            integer_cycle_time: false,
+            corners: false,
+            monte_carlo: None,
+            sigma: None,
+            seed: 0,
+            top_k: Some(3),
+            slack_window: None,
+            report: Some(log_path.clone()),
+            vcd: None,
+            vcd_periods: 1,
+            vcd_timescale: TimescaleUnit::PS,
+            vcd_channels: false,
+            dot: None,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            cache: None,
+            html: None,
+        })
+        .expect("Analysis should succeed");
+
+        let log = fs::read_to_string(&log_path).expect("Failed to read report");
+        assert!(log.contains("\nLeast-slack cycles:\n"), "{}", log);
+        assert!(
+            log.contains("| 0     | 36    | 1      | 36    | 0     |"),
+            "{}",
+            log
+        );
+        assert!(
+            log.contains("| 2     | 28    | 1      | 28    | 8     |"),
+            "{}",
+            log
+        );
+        // One detailed table follows per enumerated cycle
+        assert!(log.contains("\nCycle 2: delay - slack = 28 "), "{}", log);
+        assert!(!log.contains("\nCycle 3:"), "{}", log);
+    }
+
     /// Test analysis with multiple outputs
     #[test]
     fn test_analyse_with_multiple_outputs() {
@@ -1376,6 +1752,25 @@ mod hbcn_format_integration_tests {
         assert!(result.is_err(), "Should fail with malformed HBCN input");
     }
 
+    /// Test that HBCN errors name the file, line and column of the offending input
+    #[test]
+    fn test_hbcn_format_error_location() {
+        let hbcn_content = "* +{port:a} => +{b} : 10\n+{b} => -{port:a} 20\n";
+
+        let (_temp_dir, input_path) = create_hbcn_test_file(hbcn_content);
+        let result = run_hbcn_analyse(&input_path, None, None, None, false);
+        let message = result
+            .expect_err("Should fail with malformed HBCN input")
+            .to_string();
+
+        assert!(
+            message.contains(&format!("{}:2:19", input_path.display())),
+            "error should point at line 2, column 19: {}",
+            message
+        );
+        assert!(message.contains("2 | +{b} => -{port:a} 20"));
+    }
+
     /// Test analysis with HBCN format - register node (not port: prefix)
     #[test]
     fn test_hbcn_format_with_register() {
@@ -2592,3 +2987,549 @@ Port "b" []
         assert!(result.is_ok(), "Analysis should succeed: {:?}", result);
     }
 }
+
+mod expand_integration_tests {
+    use super::*;
+    use hbcn::{ExpandArgs, expand_main, liberty::LibertyCells};
+
+    /// The custom-kind example expands with its component library, and is rejected without it.
+    #[test]
+    fn test_expand_with_component_library() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let input = root.join("examples/structural_graphs/custom.graph");
+        let library = root.join("examples/components/cells.components");
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("custom.hbcn");
+
+        expand_main(ExpandArgs {
+            input: input.clone(),
+            output: output.clone(),
+            forward_completion: false,
+            library: Some(library),
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect("expansion with the component library should succeed");
+
+        let hbcn = hbcn::hbcn::parser::parse_hbcn(&fs::read_to_string(&output).unwrap())
+            .expect("expanded output should parse");
+        // a, q, q/cd, q/o, c, c/c, b
+        assert_eq!(hbcn.node_count(), 14);
+
+        let err = expand_main(ExpandArgs {
+            input,
+            output,
+            forward_completion: false,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect_err("unknown register kinds should be rejected");
+        assert!(err.to_string().contains("Unknown component type: Q4Reg"));
+    }
+
+    /// The hierarchical example flattens its module instances into `/`-joined names.
+    #[test]
+    fn test_expand_module_instances() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("lanes.hbcn");
+
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/lanes.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect("expansion of module instances should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        let hbcn = hbcn::hbcn::parser::parse_hbcn(&content).expect("expanded output should parse");
+        // 6 ports, plus coeff_q (3 nodes), mac/mul (1) and mac/acc (3) per lane
+        assert_eq!(hbcn.node_count(), 2 * (6 + 4 * 7));
+        assert!(content.contains("lane3/mac/acc/s1"));
+        assert!(content.contains("lane2/coeff_q"));
+    }
+
+    /// `expand --json` writes a versioned document that reads back as the same HBCN, with the
+    /// same node declarations.
+    #[test]
+    fn test_expand_json_output() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("lanes.hbcn");
+        let json = temp_dir.path().join("lanes.json");
+
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/lanes.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: Some(json.clone()),
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect("expansion with JSON output should succeed");
+
+        let document = fs::read_to_string(&json).unwrap();
+        assert!(document.contains("\"format\": \"hbcn\""));
+        assert!(document.contains("\"graph\": \"structural_hbcn\""));
+
+        // JSON input is recognised by the HBCN reader, like the text output
+        let (from_json, json_declarations) =
+            hbcn::read_hbcn_file_with_declarations(&json).expect("JSON output should read back");
+        let (from_text, text_declarations) =
+            hbcn::read_hbcn_file_with_declarations(&output).unwrap();
+        assert_eq!(from_json.node_count(), from_text.node_count());
+        assert_eq!(from_json.edge_count(), from_text.edge_count());
+        // ...with the same node declarations
+        assert!(!json_declarations.is_empty());
+        assert_eq!(json_declarations, text_declarations);
+
+        // ...but not as a structural graph
+        assert!(hbcn::read_file(&json).is_err());
+    }
+
+    /// `expand --pnml --stg` writes the lanes example as Petri nets with one transition per
+    /// HBCN transition, one place per HBCN place and the hierarchical names made signals.
+    #[test]
+    fn test_expand_petri_net_outputs() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("lanes.hbcn");
+        let pnml = temp_dir.path().join("lanes.pnml");
+        let stg = temp_dir.path().join("lanes.g");
+
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/lanes.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: Some(pnml.clone()),
+            stg: Some(stg.clone()),
+            dot: None,
+        })
+        .expect("expansion with Petri-net outputs should succeed");
+
+        let hbcn = hbcn::read_hbcn_file(&output).unwrap();
+        let tokens = hbcn
+            .edge_indices()
+            .filter(|&ie| hbcn[ie].place.token)
+            .count();
+
+        let pnml = fs::read_to_string(&pnml).unwrap();
+        assert!(pnml.contains("<name><text>lanes</text></name>"));
+        assert_eq!(pnml.matches("<transition ").count(), hbcn.node_count());
+        assert_eq!(pnml.matches("<place ").count(), hbcn.edge_count());
+        assert_eq!(pnml.matches("<initialMarking>").count(), tokens);
+        assert_eq!(pnml.matches("<delay ").count(), hbcn.edge_count());
+
+        let stg = fs::read_to_string(&stg).unwrap();
+        assert!(stg.starts_with("# Generated by hbcn"));
+        assert!(stg.contains(".model lanes\n"));
+        assert!(stg.contains("# Signal lane3_mac_acc_s1 is node {lane3/mac/acc/s1}\n"));
+        let marking = stg.lines().find(|l| l.starts_with(".marking")).unwrap();
+        assert_eq!(marking.matches('<').count(), tokens);
+        assert!(stg.ends_with(".end\n"));
+    }
+
+    /// `expand --dot` draws each custom register of the component-library example as a
+    /// cluster of its stages, labelled with its kind.
+    #[test]
+    fn test_expand_dot_output() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("custom.hbcn");
+        let dot = temp_dir.path().join("custom.dot");
+
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/custom.graph"),
+            output,
+            forward_completion: false,
+            library: Some(root.join("examples/components/cells.components")),
+            liberty: None,
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: Some(dot.clone()),
+        })
+        .expect("expansion with a DOT output should succeed");
+
+        let dot = fs::read_to_string(&dot).unwrap();
+        assert!(dot.starts_with("digraph structural_graph {"));
+        assert_eq!(dot.matches("subgraph").count(), 2);
+        assert!(dot.contains("label=\"Q4Reg \\\"q\\\"\";"));
+        assert!(dot.contains("label=\"CElemReg \\\"c\\\"\";"));
+        assert_eq!(dot.matches("shape=ellipse").count(), 2);
+        assert!(dot.contains("label=\"q/cd\\ncost 25\""));
+    }
+
+    /// With the example Liberty library, acknowledges cost one `C2` level plus the
+    /// destination's `C2R` register delay, in nanoseconds, while forward places keep their
+    /// virtual delay, scaled to nanoseconds by `--virtual-unit`.
+    #[test]
+    fn test_expand_with_liberty() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("loop.hbcn");
+        let liberty = root.join("examples/liberty/async.lib");
+
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/loop.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: Some(liberty.clone()),
+            liberty_cells: Default::default(),
+            virtual_unit: Some(0.01),
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect("expansion with the Liberty library should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.contains(&format!("liberty={}", liberty.display())));
+        assert!(content.contains("virtual_unit=0.01"));
+        let hbcn = hbcn::hbcn::parser::parse_hbcn(&content).expect("expanded output should parse");
+        let mut delays: Vec<f64> = hbcn.edge_indices().map(|ie| hbcn[ie].delay.max).collect();
+        delays.sort_by(f64::total_cmp);
+        delays.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        // The worst table entries, C2 106ps and C2R 131ps, between the virtual delays of 10
+        // and 50 units of 10ps
+        let expected = [0.1, 0.237, 0.5];
+        assert_eq!(delays.len(), 3);
+        assert!(
+            delays
+                .iter()
+                .zip(expected)
+                .all(|(d, e)| (d - e).abs() < 1e-9),
+            "{:?}",
+            delays
+        );
+
+        // Without a virtual unit, nanoseconds and virtual units would mix
+        let err = expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/loop.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: Some(liberty.clone()),
+            liberty_cells: Default::default(),
+            virtual_unit: None,
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect_err("a Liberty library without a virtual unit should be rejected");
+        assert!(err.to_string().contains("give --virtual-unit"));
+
+        // A library without the priced cells is rejected, naming the file
+        let incomplete = temp_dir.path().join("incomplete.lib");
+        fs::write(&incomplete, "library (l) { time_unit : \"1ns\" ; }").unwrap();
+        let err = expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/loop.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: Some(incomplete),
+            liberty_cells: Default::default(),
+            virtual_unit: Some(1.0),
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect_err("a library without a C2 cell should be rejected");
+        assert!(format!("{:#}", err).contains("no timed C-element cell `C2`"));
+
+        // Other cells of the library price the acknowledges: OR2 70ps and C2R 131ps
+        expand_main(ExpandArgs {
+            input: root.join("examples/structural_graphs/loop.graph"),
+            output: output.clone(),
+            forward_completion: false,
+            library: None,
+            liberty: Some(liberty.clone()),
+            liberty_cells: LibertyCells {
+                c_element: "OR2".into(),
+                ..Default::default()
+            },
+            virtual_unit: Some(1.0),
+            json: None,
+            pnml: None,
+            stg: None,
+            dot: None,
+        })
+        .expect("expansion with other Liberty cells should succeed");
+        let content = fs::read_to_string(&output).unwrap();
+        let hbcn = hbcn::hbcn::parser::parse_hbcn(&content).expect("expanded output should parse");
+        assert!(
+            hbcn.edge_indices()
+                .any(|ie| (hbcn[ie].delay.max - 0.201).abs() < 1e-9)
+        );
+    }
+}
+
+mod backannotate_integration_tests {
+    use super::*;
+    use hbcn::{BackannotateArgs, CircuitNode, Transition, backannotate_main};
+
+    /// The loop example annotated with its SDF: every place takes the delay of its netlist
+    /// path, and the declarations of the input are kept.
+    #[test]
+    fn test_backannotate_loop_example() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("loop.hbcn");
+
+        backannotate_main(BackannotateArgs {
+            input: root.join("examples/hbcn/loop.hbcn"),
+            sdf: Some(root.join("examples/sdf/loop.sdf")),
+            timing_report: None,
+            vcd: None,
+            vcd_scope: None,
+            output: output.clone(),
+        })
+        .expect("back-annotation should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.contains("# Back-annotated from: "));
+        assert!(content.contains("register {a/s0} cost 10"));
+
+        let hbcn = hbcn::hbcn::parser::parse_hbcn(&content).expect("output should parse");
+        let transition = |name: &str| {
+            let node = CircuitNode::Register(name[1..].into());
+            if name.starts_with('+') {
+                Transition::Data(node)
+            } else {
+                Transition::Spacer(node)
+            }
+        };
+        let delay = |source: &str, target: &str| {
+            let (source, target) = (transition(source), transition(target));
+            let ie = hbcn
+                .edge_indices()
+                .find(|&ie| {
+                    let (s, t) = hbcn.edge_endpoints(ie).unwrap();
+                    hbcn[s] == source && hbcn[t] == target
+                })
+                .unwrap();
+            let delay = &hbcn[ie].delay;
+            (delay.min.unwrap(), delay.max)
+        };
+        // The forward wires, and the acknowledges through an inverter
+        assert_eq!(delay("+a", "+a/s0"), (0.012, 0.018));
+        assert_eq!(delay("-a/s1", "-a"), (0.025, 0.04));
+        assert_eq!(delay("+a/s0", "-a"), (0.017, 0.028));
+        assert_eq!(delay("+a", "-a/s1"), (0.017, 0.028));
+    }
+
+    /// A timing report replaces the delays of the places it times and flags those over
+    /// their budget, without the LP solver.
+    #[test]
+    fn test_backannotate_timing_report() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let report = temp_dir.path().join("timing.rpt");
+        let output = temp_dir.path().join("loop.hbcn");
+        fs::write(
+            &report,
+            "hbcn_place max +{a/s1} => +{a}\n          Data Path:-   61000\n\
+             hbcn_place min +{a/s1} => +{a}\n          Data Path:-   42000\n\
+             hbcn_place max +{a} => +{a/s0}\n          Data Path:-    8000\n",
+        )
+        .unwrap();
+
+        backannotate_main(BackannotateArgs {
+            input: root.join("examples/hbcn/loop.hbcn"),
+            sdf: None,
+            timing_report: Some(report),
+            vcd: None,
+            vcd_scope: None,
+            output: output.clone(),
+        })
+        .expect("back-annotation should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.contains("+{a/s1} => +{a} : (42,61)"));
+        assert!(content.contains("+{a} => +{a/s0} : 8"));
+        assert!(content.contains("-{a/s1} => -{a} : 50"));
+    }
+
+    /// The simulated loop example: each place takes the time its target followed its
+    /// source, within the budgets of the loop example, without the LP solver.
+    #[test]
+    fn test_backannotate_simulation() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("loop.hbcn");
+
+        backannotate_main(BackannotateArgs {
+            input: root.join("examples/hbcn/loop.hbcn"),
+            sdf: None,
+            timing_report: None,
+            vcd: Some(root.join("examples/vcd/loop.vcd")),
+            vcd_scope: Some("tb.dut".into()),
+            output: output.clone(),
+        })
+        .expect("back-annotation should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.contains("# Back-annotated from: "));
+        assert!(content.contains("  +{a} => +{a/s0} : (0.14,0.143)"));
+        assert!(content.contains("  -{a/s0} => +{a} : (0.07,0.07)"));
+        assert!(content.contains("* -{a} => +{a/s1} : (0.07,0.07)"));
+
+        // Outside the design's scope no signal is a rail
+        let err = backannotate_main(BackannotateArgs {
+            input: root.join("examples/hbcn/loop.hbcn"),
+            sdf: None,
+            timing_report: None,
+            vcd: Some(root.join("examples/vcd/loop.vcd")),
+            vcd_scope: Some("tb.missing".into()),
+            output,
+        })
+        .expect_err("a missing scope should be rejected");
+        assert!(format!("{:#}", err).contains("VCD has no scope tb.missing"));
+    }
+}
+
+mod import_integration_tests {
+    use super::*;
+    use hbcn::{ImportArgs, import_main};
+
+    /// The pipeline example imports as a `.graph` that re-reads to the same components, with
+    /// the deepest cone between `r` and `q` as their channel's delay.
+    #[test]
+    fn test_import_pipeline_example() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let output = temp_dir.path().join("pipeline.graph");
+
+        import_main(ImportArgs {
+            input: root.join("examples/netlist/pipeline.v"),
+            weights: root.join("examples/netlist/cells.weights"),
+            top: None,
+            library: None,
+            output: output.clone(),
+            json: None,
+        })
+        .expect("import should succeed");
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.starts_with("# Generated by hbcn "));
+        assert!(content.contains("Port \"a\" [(\"r\", 0)]"));
+        assert!(content.contains("DataReg \"r\" [(\"q\", 3)]"));
+        assert!(content.contains("DataReg \"q\" [(\"b\", 0)]"));
+
+        let graph = hbcn::read_file(&output).expect("imported graph should parse");
+        // a, r (3 nodes), q (3 nodes) and b
+        assert_eq!(graph.node_count(), 8);
+        assert!(hbcn::from_structural_graph(&graph, false).is_some());
+    }
+
+    /// A cell missing from the weight table is reported at its instance in the netlist.
+    #[test]
+    fn test_import_unknown_cell() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let weights = temp_dir.path().join("partial.weights");
+        fs::write(&weights, "INV 1 Y\nC2 1 Q sequential\n").unwrap();
+
+        let err = import_main(ImportArgs {
+            input: root.join("examples/netlist/pipeline.v"),
+            weights,
+            top: None,
+            library: None,
+            output: temp_dir.path().join("pipeline.graph"),
+            json: None,
+        })
+        .expect_err("unknown cells should be rejected");
+        let message = err.to_string();
+        assert!(message.contains("cell type `NOR2` is not in the weight table"));
+        assert!(message.contains("pipeline.v:27:8"));
+    }
+}
+
+mod stg_import_integration_tests {
+    use super::*;
+    use hbcn::{CircuitNode, Transition};
+
+    /// The ring example is read as an HBCN: the `.inputs`/`.outputs` signals become ports,
+    /// the `.internal` ones registers, and each channel keeps the token of its marking.
+    #[test]
+    fn test_read_stg_ring_example() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let hbcn = hbcn::read_hbcn_file(&root.join("examples/stg/ring.g"))
+            .expect("the ring STG should read as an HBCN");
+
+        assert_eq!(hbcn.node_count(), 8);
+        assert_eq!(hbcn.edge_count(), 12);
+        let tokens = hbcn
+            .edge_indices()
+            .filter(|&ie| hbcn[ie].place.token)
+            .count();
+        assert_eq!(tokens, 3);
+
+        let ports: Vec<String> = hbcn
+            .node_indices()
+            .filter_map(|ix| match &hbcn[ix] {
+                Transition::Data(CircuitNode::Port(name)) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ports, ["in", "out"]);
+        assert!(
+            hbcn.edge_indices()
+                .all(|ie| hbcn[ie].delay.max == 1.0 && hbcn[ie].delay.min.is_none())
+        );
+    }
+
+    /// A choice place is reported at its line in the file.
+    #[test]
+    fn test_read_stg_rejects_choice() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let input = temp_dir.path().join("choice.g");
+        fs::write(
+            &input,
+            ".inputs a\n.outputs b c\n.graph\na+ p0\np0 b+ c+\n.end\n",
+        )
+        .unwrap();
+
+        let message = hbcn::read_hbcn_file(&input).unwrap_err().to_string();
+        assert!(message.contains(
+            "place `p0` is a choice between transitions `b+`, `c+`, which a marked graph \
+             cannot represent"
+        ));
+        assert!(message.contains("choice.g:4:4"));
+    }
+}